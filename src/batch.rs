@@ -0,0 +1,324 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Batch payments: paying many recipients with a single transaction.
+
+use std::str::FromStr;
+
+use bpstd::{Address, AddressNetwork, Network, Sats};
+use psbt::{Beneficiary, Payment};
+
+/// Errors parsing or composing a batch of payments.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum BatchError {
+    /// record {0} of the batch must have form `<address>,<amount in satoshis>`.
+    InvalidRecord(usize),
+
+    /// record {0} of the batch contains invalid address '{1}'.
+    InvalidAddress(usize, String),
+
+    /// record {0} of the batch contains invalid amount '{1}'; the amount must be given in
+    /// satoshis.
+    InvalidAmount(usize, String),
+
+    /// payment to {0} has zero amount.
+    ZeroAmount(Address),
+
+    /// address {0} is repeated in the batch.
+    DuplicateAddress(Address),
+
+    /// several outputs pay the same amount to {0}.
+    DuplicateOutput(Address),
+
+    /// total amount of the batch payments overflows.
+    Overflow,
+
+    /// batch contains no payments.
+    Empty,
+
+    /// batch pays to {found:?} network address {address}, while the wallet uses {expected:?}.
+    NetworkMismatch {
+        address: Address,
+        expected: AddressNetwork,
+        found: AddressNetwork,
+    },
+
+    /// invalid JSON batch: {0}.
+    #[cfg(feature = "serde_json")]
+    Json(String),
+}
+
+/// Handling of the batch payments repeating the same address.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Display)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+pub enum DuplicatePolicy {
+    /// Fail on the first repeated address
+    #[default]
+    #[display("reject")]
+    Reject,
+
+    /// Pay the sum of all amounts for the address with a single output
+    #[display("merge")]
+    Merge,
+}
+
+/// List of payments to different addresses, made with a single transaction.
+///
+/// Payments are kept in the order they were added; repeated addresses are handled according to
+/// the [`DuplicatePolicy`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Batch {
+    policy: DuplicatePolicy,
+    payments: Vec<(Address, Sats)>,
+}
+
+impl Batch {
+    pub fn new(policy: DuplicatePolicy) -> Self {
+        Batch {
+            policy,
+            payments: vec![],
+        }
+    }
+
+    /// Constructs batch from the `(address, amount)` pairs.
+    pub fn with(
+        payments: impl IntoIterator<Item = (Address, Sats)>,
+        policy: DuplicatePolicy,
+    ) -> Result<Self, BatchError> {
+        let mut batch = Batch::new(policy);
+        for (address, amount) in payments {
+            batch.push(address, amount)?;
+        }
+        Ok(batch)
+    }
+
+    /// Parses batch from CSV lines of the form `<address>,<amount in satoshis>`.
+    ///
+    /// Empty lines, lines starting with `#` and a header line starting with `address` are
+    /// skipped.
+    pub fn from_csv(s: &str, policy: DuplicatePolicy) -> Result<Self, BatchError> {
+        let mut batch = Batch::new(policy);
+        for (no, line) in s.lines().enumerate() {
+            let no = no + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if no == 1 && line.to_lowercase().starts_with("address") {
+                continue;
+            }
+            let (address, amount) = line.split_once(',').ok_or(BatchError::InvalidRecord(no))?;
+            let address = parse_address(no, address.trim())?;
+            let amount = parse_amount(no, amount.trim())?;
+            batch.push(address, amount)?;
+        }
+        batch.check_non_empty()
+    }
+
+    /// Parses batch from a JSON array of objects like
+    /// `{ "address": "<address>", "amount": <satoshis> }`.
+    #[cfg(feature = "serde_json")]
+    pub fn from_json(s: &str, policy: DuplicatePolicy) -> Result<Self, BatchError> {
+        use serde_json::Value;
+
+        let value =
+            serde_json::from_str::<Value>(s).map_err(|e| BatchError::Json(e.to_string()))?;
+        let records = value
+            .as_array()
+            .ok_or_else(|| BatchError::Json(s!("batch must be an array of payments")))?;
+        let mut batch = Batch::new(policy);
+        for (no, record) in records.iter().enumerate() {
+            let no = no + 1;
+            let address = record["address"].as_str().ok_or(BatchError::InvalidRecord(no))?;
+            let address = parse_address(no, address)?;
+            let amount = match &record["amount"] {
+                Value::Number(amount) => amount
+                    .as_u64()
+                    .ok_or_else(|| BatchError::InvalidAmount(no, amount.to_string()))?,
+                Value::String(amount) => parse_amount(no, amount)?.sats(),
+                _ => return Err(BatchError::InvalidRecord(no)),
+            };
+            batch.push(address, Sats(amount))?;
+        }
+        batch.check_non_empty()
+    }
+
+    /// Adds payment to the batch.
+    pub fn push(&mut self, address: Address, amount: Sats) -> Result<(), BatchError> {
+        if amount == Sats::ZERO {
+            return Err(BatchError::ZeroAmount(address));
+        }
+        match self.payments.iter_mut().find(|(a, _)| *a == address) {
+            None => self.payments.push((address, amount)),
+            Some(_) if self.policy == DuplicatePolicy::Reject => {
+                return Err(BatchError::DuplicateAddress(address));
+            }
+            Some((_, sats)) => *sats = sats.checked_add(amount).ok_or(BatchError::Overflow)?,
+        }
+        Ok(())
+    }
+
+    /// Checks that all the batch addresses belong to the `network`.
+    pub fn check_network(&self, network: Network) -> Result<(), BatchError> {
+        let expected = AddressNetwork::from(network);
+        match self.payments.iter().find(|(address, _)| address.network != expected) {
+            Some((address, _)) => Err(BatchError::NetworkMismatch {
+                address: *address,
+                expected,
+                found: address.network,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn len(&self) -> usize { self.payments.len() }
+
+    pub fn is_empty(&self) -> bool { self.payments.is_empty() }
+
+    pub fn payments(&self) -> impl Iterator<Item = (Address, Sats)> + '_ {
+        self.payments.iter().copied()
+    }
+
+    /// Total amount paid by the batch.
+    pub fn total(&self) -> Result<Sats, BatchError> {
+        self.payments
+            .iter()
+            .try_fold(Sats::ZERO, |total, (_, amount)| total.checked_add(*amount))
+            .ok_or(BatchError::Overflow)
+    }
+
+    /// Transaction beneficiaries in the order of the batch payments.
+    pub fn beneficiaries(&self) -> Vec<Beneficiary> {
+        self.payments
+            .iter()
+            .map(|(address, amount)| Beneficiary::new(*address, Payment::Fixed(*amount)))
+            .collect()
+    }
+
+    fn check_non_empty(self) -> Result<Self, BatchError> {
+        if self.is_empty() {
+            return Err(BatchError::Empty);
+        }
+        Ok(self)
+    }
+}
+
+/// Checks that none of the `beneficiaries` repeats another one with the same address and amount,
+/// which is likely a mistake. Payments of different amounts to the same address are allowed.
+pub fn check_duplicate_outputs(beneficiaries: &[Beneficiary]) -> Result<(), BatchError> {
+    for (no, beneficiary) in beneficiaries.iter().enumerate() {
+        if beneficiaries[..no].contains(beneficiary) {
+            return Err(BatchError::DuplicateOutput(beneficiary.address));
+        }
+    }
+    Ok(())
+}
+
+fn parse_address(no: usize, s: &str) -> Result<Address, BatchError> {
+    Address::from_str(s).map_err(|_| BatchError::InvalidAddress(no, s.to_owned()))
+}
+
+fn parse_amount(no: usize, s: &str) -> Result<Sats, BatchError> {
+    u64::from_str(s).map(Sats).map_err(|_| BatchError::InvalidAmount(no, s.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR1: &str = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+    const ADDR2: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+    #[test]
+    fn test_batch_csv() {
+        let csv =
+            format!("address,amount\n# payroll\n{ADDR1},1000\n\n{ADDR2}, 2500\n{ADDR1},500\n");
+        assert_eq!(
+            Batch::from_csv(&csv, DuplicatePolicy::Reject),
+            Err(BatchError::DuplicateAddress(Address::from_str(ADDR1).unwrap()))
+        );
+
+        let batch = Batch::from_csv(&csv, DuplicatePolicy::Merge).unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.total(), Ok(Sats(4000)));
+        let payments = batch.payments().collect::<Vec<_>>();
+        assert_eq!(payments[0], (Address::from_str(ADDR1).unwrap(), Sats(1500)));
+        assert_eq!(payments[1], (Address::from_str(ADDR2).unwrap(), Sats(2500)));
+        assert!(batch.check_network(Network::Mainnet).is_ok());
+        assert!(matches!(
+            batch.check_network(Network::Testnet3),
+            Err(BatchError::NetworkMismatch { .. })
+        ));
+
+        assert_eq!(Batch::from_csv("", DuplicatePolicy::Merge), Err(BatchError::Empty));
+        assert_eq!(
+            Batch::from_csv(ADDR1, DuplicatePolicy::Merge),
+            Err(BatchError::InvalidRecord(1))
+        );
+        assert_eq!(
+            Batch::from_csv(&format!("{ADDR1},0.1"), DuplicatePolicy::Merge),
+            Err(BatchError::InvalidAmount(1, s!("0.1")))
+        );
+        assert_eq!(
+            Batch::from_csv(&format!("{ADDR1},0"), DuplicatePolicy::Merge),
+            Err(BatchError::ZeroAmount(Address::from_str(ADDR1).unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_duplicate_outputs() {
+        let addr1 = Address::from_str(ADDR1).unwrap();
+        let addr2 = Address::from_str(ADDR2).unwrap();
+        let mut beneficiaries = vec![
+            Beneficiary::new(addr1, Payment::Fixed(Sats(1000))),
+            Beneficiary::new(addr2, Payment::Fixed(Sats(1000))),
+            Beneficiary::new(addr1, Payment::Fixed(Sats(2000))),
+        ];
+        assert_eq!(check_duplicate_outputs(&beneficiaries), Ok(()));
+        beneficiaries.push(Beneficiary::new(addr1, Payment::Fixed(Sats(1000))));
+        assert_eq!(
+            check_duplicate_outputs(&beneficiaries),
+            Err(BatchError::DuplicateOutput(addr1))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde_json")]
+    fn test_batch_json() {
+        let json = format!(
+            r#"[{{ "address": "{ADDR1}", "amount": 1000 }}, {{ "address": "{ADDR2}", "amount": "20" }}]"#
+        );
+        let batch = Batch::from_json(&json, DuplicatePolicy::Reject).unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.total(), Ok(Sats(1020)));
+
+        assert_eq!(
+            Batch::from_json(r#"[{ "address": "xyz", "amount": 1 }]"#, DuplicatePolicy::Reject),
+            Err(BatchError::InvalidAddress(1, s!("xyz")))
+        );
+        assert!(matches!(
+            Batch::from_json("{}", DuplicatePolicy::Reject),
+            Err(BatchError::Json(_))
+        ));
+    }
+}
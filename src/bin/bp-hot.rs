@@ -26,9 +26,8 @@ extern crate serde_crate as serde;
 
 use std::process::ExitCode;
 
-use bpwallet::cli::LogLevel;
+use bpwallet::cli::{parse_args, LogLevel};
 use bpwallet::hot::{DataError, HotArgs};
-use clap::Parser;
 
 fn main() -> ExitCode {
     if let Err(err) = run() {
@@ -40,7 +39,7 @@ fn main() -> ExitCode {
 }
 
 fn run() -> Result<(), DataError> {
-    let args = HotArgs::parse();
+    let args = parse_args::<HotArgs>();
     LogLevel::from_verbosity_flag_count(args.verbose).apply();
     trace!("Command-line arguments: {:#?}", &args);
 
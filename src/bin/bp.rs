@@ -26,8 +26,7 @@ extern crate serde_crate as serde;
 
 use std::process::ExitCode;
 
-use bpwallet::cli::{Args, BpCommand, Config, DescrStdOpts, Exec, ExecError, LogLevel};
-use clap::Parser;
+use bpwallet::cli::{parse_args, Args, BpCommand, Config, DescrStdOpts, Exec, ExecError, LogLevel};
 
 fn main() -> ExitCode {
     if let Err(err) = run() {
@@ -39,7 +38,7 @@ fn main() -> ExitCode {
 }
 
 fn run() -> Result<(), ExecError> {
-    let mut args = Args::<BpCommand, DescrStdOpts>::parse();
+    let mut args = parse_args::<Args<BpCommand, DescrStdOpts>>();
     args.process();
     LogLevel::from_verbosity_flag_count(args.verbose).apply();
     trace!("Command-line arguments: {:#?}", &args);
@@ -47,8 +46,9 @@ fn run() -> Result<(), ExecError> {
     eprintln!("BP: command-line wallet for bitcoin protocol");
     eprintln!("    by LNP/BP Standards Association\n");
 
-    // TODO: Update arguments basing on the configuration
     let conf = Config::load(&args.conf_path("bp"));
+    args.configure(&conf);
     debug!("Executing command: {}", args.command);
-    args.exec(conf, "bp")
+    let format = args.general.format;
+    args.exec_print(conf, "bp", format)
 }
@@ -0,0 +1,275 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Payment request URIs as defined in BIP-21.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bpstd::{Address, AddressNetwork, Network, Sats};
+use psbt::{Beneficiary, Payment};
+
+/// URI scheme of the payment requests.
+pub const BIP21_SCHEME: &str = "bitcoin";
+
+/// Errors parsing or using BIP-21 payment request URI.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum InvoiceError {
+    /// payment request must be a URI with `bitcoin:` scheme.
+    NoScheme,
+
+    /// payment request contains invalid address '{0}'.
+    InvalidAddress(String),
+
+    /// payment request contains invalid amount '{0}'; the amount must be given in bitcoins with
+    /// no more than 8 decimal places.
+    InvalidAmount(String),
+
+    /// payment request contains invalid percent-encoded string '{0}'.
+    InvalidEncoding(String),
+
+    /// payment request parameter '{0}' is repeated.
+    RepeatedParam(String),
+
+    /// payment request requires support of '{0}' parameter, which is not supported.
+    UnsupportedRequirement(String),
+
+    /// payment request address is for {found:?} network, while the wallet uses {expected:?}.
+    NetworkMismatch {
+        expected: AddressNetwork,
+        found: AddressNetwork,
+    },
+
+    /// payment request to {0} doesn't specify the amount.
+    NoAmount(Address),
+}
+
+/// Payment request parsed from a BIP-21 URI like `bitcoin:<address>?amount=0.001&label=Shop`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Invoice {
+    pub address: Address,
+    pub amount: Option<Sats>,
+    /// Label for the payment recipient, which may be stored with the transaction.
+    pub label: Option<String>,
+    /// Message describing the payment.
+    pub message: Option<String>,
+}
+
+impl Invoice {
+    /// Constructs invoice paying to the `address` with no amount, label or message.
+    pub fn new(address: Address) -> Self {
+        Invoice {
+            address,
+            amount: None,
+            label: None,
+            message: None,
+        }
+    }
+
+    /// Checks that the invoice address is valid on the `network`.
+    pub fn check_network(&self, network: Network) -> Result<(), InvoiceError> {
+        let expected = AddressNetwork::from(network);
+        if self.address.network != expected {
+            return Err(InvoiceError::NetworkMismatch {
+                expected,
+                found: self.address.network,
+            });
+        }
+        Ok(())
+    }
+
+    /// Converts invoice into a beneficiary of a transaction, failing if the invoice has no
+    /// amount.
+    pub fn to_beneficiary(&self) -> Result<Beneficiary, InvoiceError> {
+        let amount = self.amount.ok_or(InvoiceError::NoAmount(self.address))?;
+        Ok(Beneficiary::new(self.address, Payment::Fixed(amount)))
+    }
+}
+
+impl FromStr for Invoice {
+    type Err = InvoiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s.split_once(':').ok_or(InvoiceError::NoScheme)?;
+        if !scheme.eq_ignore_ascii_case(BIP21_SCHEME) {
+            return Err(InvoiceError::NoScheme);
+        }
+        let (addr, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let address =
+            Address::from_str(addr).map_err(|_| InvoiceError::InvalidAddress(addr.to_owned()))?;
+        let mut invoice = Invoice::new(address);
+
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let slot = match key {
+                "amount" => {
+                    if invoice.amount.is_some() {
+                        return Err(InvoiceError::RepeatedParam(key.to_owned()));
+                    }
+                    invoice.amount = Some(parse_btc(value)?);
+                    continue;
+                }
+                "label" => &mut invoice.label,
+                "message" => &mut invoice.message,
+                key if key.starts_with("req-") => {
+                    return Err(InvoiceError::UnsupportedRequirement(key.to_owned()));
+                }
+                // Unknown optional parameters are ignored
+                _ => continue,
+            };
+            if slot.is_some() {
+                return Err(InvoiceError::RepeatedParam(key.to_owned()));
+            }
+            *slot = Some(percent_decode(value)?);
+        }
+        Ok(invoice)
+    }
+}
+
+impl Display for Invoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{BIP21_SCHEME}:{}", self.address)?;
+        let mut sep = '?';
+        if let Some(amount) = self.amount {
+            let sats = amount.sats();
+            let frac = format!("{:08}", sats % 100_000_000);
+            let frac = frac.trim_end_matches('0');
+            write!(f, "{sep}amount={}", sats / 100_000_000)?;
+            if !frac.is_empty() {
+                write!(f, ".{frac}")?;
+            }
+            sep = '&';
+        }
+        for (key, value) in [("label", &self.label), ("message", &self.message)] {
+            if let Some(value) = value {
+                write!(f, "{sep}{key}={}", percent_encode(value))?;
+                sep = '&';
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses amount in bitcoins, like `0.001`, into satoshis.
+fn parse_btc(s: &str) -> Result<Sats, InvoiceError> {
+    let err = || InvoiceError::InvalidAmount(s.to_owned());
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if (int.is_empty() && frac.is_empty())
+        || frac.len() > 8
+        || !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit())
+    {
+        return Err(err());
+    }
+    let int = if int.is_empty() { 0 } else { int.parse::<u64>().map_err(|_| err())? };
+    let frac = format!("{frac:0<8}").parse::<u64>().map_err(|_| err())?;
+    int.checked_mul(100_000_000).and_then(|sats| sats.checked_add(frac)).map(Sats).ok_or_else(err)
+}
+
+fn percent_decode(s: &str) -> Result<String, InvoiceError> {
+    let err = || InvoiceError::InvalidEncoding(s.to_owned());
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'%' => {
+                let hex = [iter.next().ok_or_else(err)?, iter.next().ok_or_else(err)?];
+                let hex = std::str::from_utf8(&hex).map_err(|_| err())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| err())?);
+            }
+            b'+' => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| err())
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: &str = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+
+    #[test]
+    fn test_parse_invoice() {
+        let invoice = Invoice::from_str(&format!(
+            "bitcoin:{ADDR}?amount=0.0015&label=Luke-Jr&message=Donation%20for%20project+xyz"
+        ))
+        .unwrap();
+        assert_eq!(invoice.address.to_string(), ADDR);
+        assert_eq!(invoice.amount, Some(Sats(150_000)));
+        assert_eq!(invoice.label.as_deref(), Some("Luke-Jr"));
+        assert_eq!(invoice.message.as_deref(), Some("Donation for project xyz"));
+        assert!(invoice.check_network(Network::Mainnet).is_ok());
+        assert!(matches!(
+            invoice.check_network(Network::Testnet3),
+            Err(InvoiceError::NetworkMismatch { .. })
+        ));
+        assert_eq!(invoice, Invoice::from_str(&invoice.to_string()).unwrap());
+
+        let invoice =
+            Invoice::from_str(&format!("BITCOIN:{ADDR}?somethingyoudontunderstand=50")).unwrap();
+        assert_eq!(invoice.amount, None);
+        assert!(invoice.to_beneficiary().is_err());
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_btc("20.3"), Ok(Sats(2_030_000_000)));
+        assert_eq!(parse_btc("1"), Ok(Sats(100_000_000)));
+        assert_eq!(parse_btc(".00000001"), Ok(Sats(1)));
+        assert!(parse_btc("0.000000001").is_err());
+        assert!(parse_btc("1,5").is_err());
+        assert!(parse_btc("-1").is_err());
+        assert!(parse_btc("").is_err());
+    }
+
+    #[test]
+    fn test_reject_invoice() {
+        assert_eq!(Invoice::from_str(ADDR), Err(InvoiceError::NoScheme));
+        assert_eq!(
+            Invoice::from_str(&format!("bitcoin:{ADDR}?req-somethingyoudontunderstand=50")),
+            Err(InvoiceError::UnsupportedRequirement(s!("req-somethingyoudontunderstand")))
+        );
+        assert_eq!(
+            Invoice::from_str(&format!("bitcoin:{ADDR}?label=a&label=b")),
+            Err(InvoiceError::RepeatedParam(s!("label")))
+        );
+        assert!(matches!(
+            Invoice::from_str("bitcoin:notanaddress"),
+            Err(InvoiceError::InvalidAddress(_))
+        ));
+    }
+}
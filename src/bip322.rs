@@ -0,0 +1,362 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! BIP-322 generic signed messages.
+//!
+//! Only the "simple" signatures, consisting of the witness of the virtual `to_sign`
+//! transaction, are supported, for P2WPKH and P2TR (key path) addresses. Verification is a pure
+//! function, requiring neither a wallet nor network access.
+
+use base64::Engine;
+use bpstd::secp256k1::{ecdsa, schnorr as bip340, PublicKey, Secp256k1, XOnlyPublicKey};
+use bpstd::{Address, CompressedPk, ScriptPubkey, Sighash, TapSighash, WPubkeyHash};
+#[cfg(feature = "signers")]
+use bpstd::{InternalKeypair, KeyOrigin, XprivAccount};
+use sha2::{Digest, Sha256};
+
+const MESSAGE_TAG: &str = "BIP0322-signed-message";
+const SIGHASH_TAG: &str = "TapSighash";
+
+const SIGHASH_DEFAULT: u8 = 0x00;
+const SIGHASH_ALL: u8 = 0x01;
+
+/// Errors signing and verifying BIP-322 messages.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum Bip322Error {
+    /// address {0} has a type which is not supported for message signing; only P2WPKH and P2TR
+    /// addresses can be used.
+    UnsupportedAddress(Address),
+
+    /// signature is not a valid base64 string.
+    Base64,
+
+    /// signature contains invalid witness data.
+    InvalidWitness,
+
+    /// signature uses unsupported sighash type {0:#04x}.
+    SighashType(u8),
+
+    /// public key in the signature doesn't match the address.
+    KeyMismatch,
+
+    /// signature is not valid for the given address and message.
+    InvalidSignature,
+}
+
+/// Computes BIP-322 hash of the message.
+pub fn message_hash(msg: &[u8]) -> [u8; 32] { tagged_hash(MESSAGE_TAG, msg) }
+
+/// Verifies BIP-322 simple signature, encoded in base64, of the message `msg` by the `address`.
+pub fn verify_message(address: &Address, msg: &[u8], signature: &str) -> Result<(), Bip322Error> {
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(signature.trim())
+        .map_err(|_| Bip322Error::Base64)?;
+    let witness = decode_witness(&data).ok_or(Bip322Error::InvalidWitness)?;
+    let script = address.script_pubkey();
+    let secp = Secp256k1::verification_only();
+    if script.is_p2wpkh() {
+        let [sig, pk] = witness.as_slice() else {
+            return Err(Bip322Error::InvalidWitness);
+        };
+        let (sighash_type, sig) = sig.split_last().ok_or(Bip322Error::InvalidWitness)?;
+        if *sighash_type != SIGHASH_ALL {
+            return Err(Bip322Error::SighashType(*sighash_type));
+        }
+        let pk = PublicKey::from_slice(pk).map_err(|_| Bip322Error::KeyMismatch)?;
+        if ScriptPubkey::p2wpkh(WPubkeyHash::from(CompressedPk::from(pk))) != script {
+            return Err(Bip322Error::KeyMismatch);
+        }
+        let sig = ecdsa::Signature::from_der(sig).map_err(|_| Bip322Error::InvalidSignature)?;
+        let sighash = Sighash::from(segwit_v0_sighash(&script, msg));
+        secp.verify_ecdsa(&sighash.into(), &sig, &pk).map_err(|_| Bip322Error::InvalidSignature)
+    } else if script.is_p2tr() {
+        let [sig] = witness.as_slice() else {
+            return Err(Bip322Error::InvalidWitness);
+        };
+        let sighash_type = match sig.len() {
+            64 => SIGHASH_DEFAULT,
+            65 if sig[64] == SIGHASH_ALL => SIGHASH_ALL,
+            65 => return Err(Bip322Error::SighashType(sig[64])),
+            _ => return Err(Bip322Error::InvalidWitness),
+        };
+        let pk = XOnlyPublicKey::from_slice(&script.as_slice()[2..])
+            .map_err(|_| Bip322Error::KeyMismatch)?;
+        let sig =
+            bip340::Signature::from_slice(&sig[..64]).map_err(|_| Bip322Error::InvalidSignature)?;
+        let sighash = TapSighash::from(taproot_sighash(&script, msg, sighash_type));
+        secp.verify_schnorr(&sig, sighash.as_ref(), &pk).map_err(|_| Bip322Error::InvalidSignature)
+    } else {
+        Err(Bip322Error::UnsupportedAddress(*address))
+    }
+}
+
+/// Signs the message `msg` by the `address` with BIP-322 simple signature, encoded in base64.
+///
+/// The signing key is derived from the `account` using key `origin`, and must correspond to the
+/// address, which is checked before signing.
+#[cfg(feature = "signers")]
+pub fn sign_message(
+    account: &XprivAccount,
+    origin: &KeyOrigin,
+    address: &Address,
+    msg: &[u8],
+) -> Result<String, Bip322Error> {
+    if !account.origin().is_subset_of(origin) {
+        return Err(Bip322Error::KeyMismatch);
+    }
+    let xpriv =
+        account.xpriv().derive_priv(&origin.as_derivation()[account.origin().derivation().len()..]);
+    let script = address.script_pubkey();
+    let witness = if script.is_p2wpkh() {
+        let pk = xpriv.to_compr_pk();
+        if ScriptPubkey::p2wpkh(WPubkeyHash::from(pk)) != script {
+            return Err(Bip322Error::KeyMismatch);
+        }
+        let sighash = Sighash::from(segwit_v0_sighash(&script, msg));
+        let mut sig = xpriv.to_private_ecdsa().sign_ecdsa(sighash.into()).serialize_der().to_vec();
+        sig.push(SIGHASH_ALL);
+        vec![sig, pk.to_byte_array().to_vec()]
+    } else if script.is_p2tr() {
+        let output_pair =
+            InternalKeypair::from(xpriv.to_keypair_bip340()).to_output_keypair(None).0;
+        if output_pair.x_only_public_key().0.serialize() != script.as_slice()[2..] {
+            return Err(Bip322Error::KeyMismatch);
+        }
+        let sighash = TapSighash::from(taproot_sighash(&script, msg, SIGHASH_DEFAULT));
+        vec![output_pair.sign_schnorr(sighash.as_ref()).to_byte_array().to_vec()]
+    } else {
+        return Err(Bip322Error::UnsupportedAddress(*address));
+    };
+    Ok(base64::engine::general_purpose::STANDARD.encode(encode_witness(&witness)))
+}
+
+fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag = Sha256::digest(tag.as_bytes());
+    let mut engine = Sha256::new();
+    engine.update(tag);
+    engine.update(tag);
+    engine.update(data);
+    engine.finalize().into()
+}
+
+fn sha256d(data: &[u8]) -> [u8; 32] { Sha256::digest(Sha256::digest(data)).into() }
+
+fn push_var_int(data: &mut Vec<u8>, len: usize) {
+    match len {
+        0..=0xFC => data.push(len as u8),
+        0xFD..=0xFFFF => {
+            data.push(0xFD);
+            data.extend((len as u16).to_le_bytes());
+        }
+        _ => {
+            data.push(0xFE);
+            data.extend((len as u32).to_le_bytes());
+        }
+    }
+}
+
+fn read_var_int(data: &mut &[u8]) -> Option<usize> {
+    let (first, rest) = data.split_first()?;
+    let (len, size) = match first {
+        0xFD => (u16::from_le_bytes(rest.get(..2)?.try_into().ok()?) as usize, 2),
+        0xFE => (u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize, 4),
+        0xFF => return None,
+        len => (*len as usize, 0),
+    };
+    *data = &rest[size..];
+    Some(len)
+}
+
+#[cfg(feature = "signers")]
+fn encode_witness(witness: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = vec![];
+    push_var_int(&mut data, witness.len());
+    for item in witness {
+        push_var_int(&mut data, item.len());
+        data.extend(item);
+    }
+    data
+}
+
+fn decode_witness(mut data: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let count = read_var_int(&mut data)?;
+    let mut witness = Vec::with_capacity(count.min(data.len()));
+    for _ in 0..count {
+        let len = read_var_int(&mut data)?;
+        witness.push(data.get(..len)?.to_vec());
+        data = &data[len..];
+    }
+    data.is_empty().then_some(witness)
+}
+
+/// Serializes the single output of the `to_sign` transaction, which has zero value and
+/// `OP_RETURN` script.
+fn to_sign_output() -> [u8; 10] { [0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x6A] }
+
+/// Computes id of the virtual `to_spend` transaction, in the internal byte order.
+fn to_spend_txid(script: &ScriptPubkey, msg: &[u8]) -> [u8; 32] {
+    let mut tx = vec![];
+    // Version 0 and a single input spending the null outpoint
+    tx.extend([0u8; 4]);
+    tx.push(1);
+    tx.extend([0u8; 32]);
+    tx.extend([0xFFu8; 4]);
+    // Script sig `OP_0 PUSH32[message_hash]` and zero sequence
+    tx.extend([0x22, 0x00, 0x20]);
+    tx.extend(message_hash(msg));
+    tx.extend([0u8; 4]);
+    // A single output with zero value paying to the address, and zero lock time
+    tx.push(1);
+    tx.extend([0u8; 8]);
+    push_var_int(&mut tx, script.as_slice().len());
+    tx.extend(script.as_slice());
+    tx.extend([0u8; 4]);
+    sha256d(&tx)
+}
+
+/// Computes BIP-143 `SIGHASH_ALL` signature hash for the P2WPKH input of the `to_sign`
+/// transaction.
+fn segwit_v0_sighash(script: &ScriptPubkey, msg: &[u8]) -> [u8; 32] {
+    let mut outpoint = to_spend_txid(script, msg).to_vec();
+    outpoint.extend([0u8; 4]);
+
+    let mut data = vec![];
+    data.extend([0u8; 4]);
+    data.extend(sha256d(&outpoint));
+    data.extend(sha256d(&[0u8; 4]));
+    data.extend(&outpoint);
+    // Script code of P2WPKH is P2PKH script with the witness program
+    data.extend([0x19, 0x76, 0xA9, 0x14]);
+    data.extend(&script.as_slice()[2..]);
+    data.extend([0x88, 0xAC]);
+    data.extend([0u8; 8]);
+    data.extend([0u8; 4]);
+    data.extend(sha256d(&to_sign_output()));
+    data.extend([0u8; 4]);
+    data.extend((SIGHASH_ALL as u32).to_le_bytes());
+    sha256d(&data)
+}
+
+/// Computes BIP-341 key path signature hash for the P2TR input of the `to_sign` transaction.
+fn taproot_sighash(script: &ScriptPubkey, msg: &[u8], sighash_type: u8) -> [u8; 32] {
+    let mut outpoint = to_spend_txid(script, msg).to_vec();
+    outpoint.extend([0u8; 4]);
+    let mut script_pubkeys = vec![];
+    push_var_int(&mut script_pubkeys, script.as_slice().len());
+    script_pubkeys.extend(script.as_slice());
+
+    // Sighash epoch and type, followed by zero version and lock time
+    let mut data = vec![0x00, sighash_type];
+    data.extend([0u8; 8]);
+    data.extend(Sha256::digest(&outpoint));
+    data.extend(Sha256::digest([0u8; 8]));
+    data.extend(Sha256::digest(&script_pubkeys));
+    data.extend(Sha256::digest([0u8; 4]));
+    data.extend(Sha256::digest(to_sign_output()));
+    // Key path spend without annex of the input 0
+    data.push(0x00);
+    data.extend([0u8; 4]);
+    tagged_hash(SIGHASH_TAG, &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use amplify::hex::ToHex;
+
+    use super::*;
+
+    // Test vectors from BIP-322
+    const WPKH: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const TR: &str = "bc1ppv609nr0vr25u07u95waq5lucwfm6tde4nydujnu8npg4q75mr5sxq8lt3";
+
+    #[test]
+    fn test_message_hash() {
+        assert_eq!(
+            message_hash(b"").to_hex(),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        let script = Address::from_str(WPKH).unwrap().script_pubkey();
+        let mut txid = to_spend_txid(&script, b"");
+        txid.reverse();
+        assert_eq!(
+            txid.to_hex(),
+            "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7"
+        );
+    }
+
+    #[test]
+    fn test_verify_message() {
+        let wpkh = Address::from_str(WPKH).unwrap();
+        let sig = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        assert_eq!(verify_message(&wpkh, b"", sig), Ok(()));
+        let sig = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        assert_eq!(verify_message(&wpkh, b"Hello World", sig), Ok(()));
+        assert_eq!(verify_message(&wpkh, b"Hello World!", sig), Err(Bip322Error::InvalidSignature));
+
+        let tr = Address::from_str(TR).unwrap();
+        let sig = "AUHd69PrJQEv+oKTfZ8l+WROBHuy9HKrbFCJu7U1iK2iiEy1vMU5EfMtjc+VSHM7aU0SDbak5IUZRVno2P5mjSafAQ==";
+        assert_eq!(verify_message(&tr, b"Hello World", sig), Ok(()));
+        assert_eq!(verify_message(&wpkh, b"Hello World", sig), Err(Bip322Error::InvalidWitness));
+        assert_eq!(verify_message(&tr, b"Hello World", "%%"), Err(Bip322Error::Base64));
+
+        let p2pkh = Address::from_str("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2").unwrap();
+        assert_eq!(
+            verify_message(&p2pkh, b"Hello World", sig),
+            Err(Bip322Error::UnsupportedAddress(p2pkh))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "signers")]
+    fn test_sign_message() {
+        use bpstd::XkeyOrigin;
+
+        use crate::MnemonicSeed;
+
+        let seed = MnemonicSeed::from_phrase(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+             abandon about",
+            "",
+        )
+        .unwrap();
+        let sign_verify = |account: &str, addr: &str| {
+            let account = seed.derive(&XkeyOrigin::from_str(account).unwrap(), false);
+            let origin = KeyOrigin::from_str(&format!("{}/0/0", account.origin())).unwrap();
+            let address = Address::from_str(addr).unwrap();
+            let sig = sign_message(&account, &origin, &address, b"Hello World").unwrap();
+            assert_eq!(verify_message(&address, b"Hello World", &sig), Ok(()));
+            let other = KeyOrigin::from_str(&format!("{}/0/1", account.origin())).unwrap();
+            assert_eq!(
+                sign_message(&account, &other, &address, b"Hello World"),
+                Err(Bip322Error::KeyMismatch)
+            );
+        };
+        sign_verify(
+            "73c5da0a/86h/0h/0h",
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+        );
+        sign_verify("73c5da0a/84h/0h/0h", "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+    }
+}
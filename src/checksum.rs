@@ -0,0 +1,179 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Output descriptor checksums as defined in BIP-380.
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+
+/// Errors computing descriptor checksum.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ChecksumError {
+    /// descriptor contains character '{0}' which is not allowed in descriptors.
+    InvalidChar(char),
+
+    /// descriptor checksum '{found}' doesn't match the descriptor, which has checksum
+    /// '{expected}'; the descriptor is likely to contain a typo.
+    Mismatch { expected: String, found: String },
+}
+
+fn polymod(c: u64, val: u64) -> u64 {
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7_ffff_ffff) << 5) ^ val;
+    for (i, generator) in GENERATOR.iter().enumerate() {
+        if (c0 >> i) & 1 == 1 {
+            c ^= generator;
+        }
+    }
+    c
+}
+
+/// Computes checksum of a descriptor string not containing a checksum.
+pub fn descriptor_checksum(descr: &str) -> Result<String, ChecksumError> {
+    let mut c = 1u64;
+    let mut cls = 0u64;
+    let mut cls_count = 0;
+    for ch in descr.chars() {
+        let pos = INPUT_CHARSET.find(ch).ok_or(ChecksumError::InvalidChar(ch))? as u64;
+        c = polymod(c, pos & 31);
+        cls = cls * 3 + (pos >> 5);
+        cls_count += 1;
+        if cls_count == 3 {
+            c = polymod(c, cls);
+            cls = 0;
+            cls_count = 0;
+        }
+    }
+    if cls_count > 0 {
+        c = polymod(c, cls);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+    Ok((0..8).map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char).collect())
+}
+
+/// Appends checksum to a descriptor string not containing a checksum.
+pub fn with_checksum(descr: &str) -> Result<String, ChecksumError> {
+    Ok(format!("{descr}#{}", descriptor_checksum(descr)?))
+}
+
+/// Splits a descriptor string into the descriptor and its checksum, if present, verifying that
+/// the checksum matches the descriptor.
+pub fn split_checksum(descr: &str) -> Result<(&str, Option<&str>), ChecksumError> {
+    let Some((descr, found)) = descr.rsplit_once('#') else {
+        descriptor_checksum(descr)?;
+        return Ok((descr, None));
+    };
+    let expected = descriptor_checksum(descr)?;
+    if expected != found {
+        return Err(ChecksumError::Mismatch {
+            expected,
+            found: found.to_owned(),
+        });
+    }
+    Ok((descr, Some(found)))
+}
+
+/// Replaces `'` hardened derivation markers with `h`, giving the canonical form of the
+/// descriptor. Since the checksum depends on the markers, the descriptor must not contain it.
+pub fn normalize_hardened(descr: &str) -> String { descr.replace('\'', "h") }
+
+/// Converts a descriptor string, optionally containing a checksum, into the canonical form with
+/// `h` hardened derivation markers followed by the checksum of the canonical form.
+///
+/// The checksum present in the original string is verified before the normalization.
+pub fn normalize(descr: &str) -> Result<String, ChecksumError> {
+    let (descr, _) = split_checksum(descr)?;
+    with_checksum(&normalize_hardened(descr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor_checksum() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(
+            with_checksum("addr(mkmZxiEcEd8ZqjQWVZuC6so5dFMKEFpN2j)").unwrap(),
+            "addr(mkmZxiEcEd8ZqjQWVZuC6so5dFMKEFpN2j)#02wpgw69"
+        );
+        assert_eq!(
+            descriptor_checksum(
+                "wpkh([d34db33f/84h/0h/0h]xpub6DJ2dNUysrn5Vt36jH2KLBT2i1auw1tTSSomg8PhqNiUtx8QX2SvC9nrHu81fT41fvDUnhMjEzQgXnQjKEu3oaqMSzhSrHMxyyoEAmUHQbY/0/*)"
+            )
+            .unwrap(),
+            "cjjspncu"
+        );
+        assert_eq!(descriptor_checksum("raw(dé)"), Err(ChecksumError::InvalidChar('é')));
+    }
+
+    #[test]
+    fn test_split_checksum() {
+        assert_eq!(
+            split_checksum("raw(deadbeef)#89f8spxm"),
+            Ok(("raw(deadbeef)", Some("89f8spxm")))
+        );
+        assert_eq!(split_checksum("raw(deadbeef)"), Ok(("raw(deadbeef)", None)));
+        assert_eq!(
+            split_checksum("raw(deadbeaf)#89f8spxm"),
+            Err(ChecksumError::Mismatch {
+                expected: descriptor_checksum("raw(deadbeaf)").unwrap(),
+                found: "89f8spxm".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn test_normalize_round_trip() {
+        const XPUB: &str = "xpub6DJ2dNUysrn5Vt36jH2KLBT2i1auw1tTSSomg8PhqNiUtx8QX2SvC9nrHu81fT41fvDUnhMjEzQgXnQjKEu3oaqMSzhSrHMxyyoEAmUHQbY";
+        let vectors = [
+            (format!("wpkh([d34db33f/84h/0h/0h]{XPUB}/0/*)"), "cjjspncu"),
+            (format!("sh(wpkh([d34db33f/49h/0h/0h]{XPUB}/0/*))"), "c7n29h9h"),
+            (format!("tr([d34db33f/86h/0h/0h]{XPUB}/0/*)"), "fzjmrt67"),
+            (
+                format!(
+                    "multi(2,[d34db33f/48h/0h/0h/2h]{XPUB}/0/*,[d34db33f/48h/0h/1h/2h]{XPUB}/1/*)"
+                ),
+                "wldpyhun",
+            ),
+        ];
+        for (descr, checksum) in vectors {
+            let canonical = format!("{descr}#{checksum}");
+            let apostrophe = descr.replace("h/", "'/").replace("h]", "']");
+            assert_ne!(apostrophe, descr);
+            assert_eq!(normalize(&descr).unwrap(), canonical);
+            assert_eq!(normalize(&apostrophe).unwrap(), canonical);
+            assert_eq!(normalize(&with_checksum(&apostrophe).unwrap()).unwrap(), canonical);
+            assert_eq!(normalize(&canonical).unwrap(), canonical);
+            assert_eq!(split_checksum(&canonical), Ok((descr.as_str(), Some(checksum))));
+            assert!(matches!(
+                normalize(&format!("{apostrophe}#{checksum}")),
+                Err(ChecksumError::Mismatch { .. })
+            ));
+        }
+    }
+}
@@ -20,21 +20,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fmt::Debug;
-use std::path::PathBuf;
+use std::collections::BTreeSet;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::time::Duration;
+use std::{env, fs, io};
 
-use bpstd::XpubDerivable;
+use amplify::hex::ToHex;
+use bpstd::{Keychain, Network, XpubDerivable};
 use clap::Subcommand;
 use descriptors::Descriptor;
 use strict_encoding::Ident;
 
 use crate::cli::{
-    Config, DescrStdOpts, DescriptorOpts, ExecError, GeneralOpts, ResolverOpt, WalletOpts,
+    bitcoind_rpc_port, Config, DescrStdOpts, DescriptorOpts, EffectiveConfig, ExecError,
+    GeneralOpts, OutputFormat, ResolverOpt, SettingSource, WalletOpts, WalletSettings, DATA_DIR,
+    DEFAULT_ESPLORA, DEFAULT_MEMPOOL, DEFAULT_SIGNET_MAGIC, WALLET_ENV,
 };
-use crate::fs::FsTextStore;
-use crate::indexers::esplora;
-use crate::{AnyIndexer, Layer2Empty, Wallet, WalletCache};
+use crate::fees::{FeeEstimator, FeeRate, FeeTarget};
+use crate::fs::{load_wallet, CryptError, FsTextStore, WalletDirError, WalletKey};
+use crate::indexers::{bitcoind, esplora};
+use crate::{
+    AnyIndexer, BlockHeight, Layer2Empty, SyncProgress, SyncReport, TipCheck, Txid, Wallet,
+    WalletCache,
+};
+
+/// Time to wait for a connection to SOCKS5 proxy before reporting it as unreachable.
+const PROXY_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Command-line arguments
 #[derive(Parser)]
@@ -57,9 +71,59 @@ pub struct Args<C: Clone + Eq + Debug + Subcommand, O: DescriptorOpts = DescrStd
     #[clap(long, global = true)]
     pub sync: bool,
 
+    /// Re-request the whole wallet history from the indexer, ignoring the state saved during the
+    /// previous syncs. Implies `--sync`.
+    #[clap(long, global = true, alias = "full-resync")]
+    pub rescan: bool,
+
+    /// Number of consecutive unused addresses after which the address discovery stops
+    ///
+    /// The value is saved to the wallet and applies to all its keychains, unless
+    /// `--change-gap-limit` is given. Wallets without a saved value use the gap limit of 20.
+    #[clap(long, global = true, value_name = "COUNT")]
+    pub gap_limit: Option<u32>,
+
+    /// Number of consecutive unused addresses after which the address discovery stops in the
+    /// change keychain
+    ///
+    /// The value is saved to the wallet.
+    #[clap(long, global = true, value_name = "COUNT")]
+    pub change_gap_limit: Option<u32>,
+
+    /// Restrict wallet operations (sync, balance and coin listing) to the given comma-separated
+    /// list of keychains
+    ///
+    /// By default all keychains of the wallet descriptor are used.
+    #[clap(long, global = true, value_delimiter = ',', value_name = "LIST")]
+    pub keychains: Vec<Keychain>,
+
+    /// Fee rate, in satoshis per virtual byte, used when the indexer is not available or fails
+    /// to estimate fee rate for a confirmation target
+    #[clap(long, global = true, value_name = "RATE")]
+    pub fallback_fee_rate: Option<FeeRate>,
+
+    /// Minimal fee rate, in satoshis per virtual byte, replacing lower fee rate estimations
+    #[clap(long, global = true, value_name = "RATE", default_value_t)]
+    pub min_fee_rate: FeeRate,
+
+    /// Passphrase of the encrypted wallet; if not given, it is asked interactively
+    ///
+    /// For the `create --encrypt` command, this is the passphrase for the new wallet.
+    #[clap(long, global = true, env = "BP_WALLET_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: Option<String>,
+
     #[command(flatten)]
     pub general: GeneralOpts,
 
+    /// Settings of the wallet used by the command, loaded from the wallet directory by
+    /// [`Args::configure`].
+    #[clap(skip)]
+    pub settings: WalletSettings,
+
+    /// Origin of the resolver options, detected by [`Args::configure`].
+    #[clap(skip)]
+    pub resolver_source: SettingSource,
+
     /// Command to execute.
     #[clap(subcommand)]
     pub command: C,
@@ -72,7 +136,16 @@ impl<C: Clone + Eq + Debug + Subcommand, O: DescriptorOpts> Args<C, O> {
             wallet: self.wallet.clone(),
             resolver: self.resolver.clone(),
             sync: self.sync,
+            rescan: self.rescan,
+            gap_limit: self.gap_limit,
+            change_gap_limit: self.change_gap_limit,
+            keychains: self.keychains.clone(),
+            fallback_fee_rate: self.fallback_fee_rate,
+            min_fee_rate: self.min_fee_rate,
+            passphrase: self.passphrase.clone(),
             general: self.general.clone(),
+            settings: self.settings.clone(),
+            resolver_source: self.resolver_source,
             command: cmd.clone(),
         }
     }
@@ -80,14 +153,221 @@ impl<C: Clone + Eq + Debug + Subcommand, O: DescriptorOpts> Args<C, O> {
 
 pub trait Exec {
     type Error: std::error::Error;
+    /// Result of the command execution, which can be either displayed to the user or serialized.
+    type Output: serde::Serialize + Display;
     const CONF_FILE_NAME: &'static str;
 
-    fn exec(self, config: Config, name: &'static str) -> Result<(), Self::Error>;
+    fn exec(self, config: Config, name: &'static str) -> Result<Self::Output, Self::Error>;
+
+    /// Executes the command and prints its output to STDOUT using the given `format`.
+    fn exec_print(
+        self,
+        config: Config,
+        name: &'static str,
+        format: OutputFormat,
+    ) -> Result<(), Self::Error>
+    where
+        Self: Sized,
+    {
+        let output = self.exec(config, name)?;
+        if format.is_human() {
+            print!("{output}");
+        } else {
+            format.print(&output);
+        }
+        Ok(())
+    }
+}
+
+/// Output of a command, combining a human-readable text with the structured command result.
+///
+/// The text is produced by [`Display`] and the structured data by [`serde::Serialize`], so the
+/// commands can be used both from a command-line tool and as a library.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct ExecOutput {
+    pub text: String,
+    pub data: serde_json::Value,
+}
+
+impl ExecOutput {
+    /// Constructs output with structured `data` and no text.
+    pub fn with(data: &impl serde::Serialize) -> Self {
+        ExecOutput {
+            text: none!(),
+            data: serde_json::to_value(data).expect("unable to serialize command output"),
+        }
+    }
+
+    /// Constructs output for a serializable document, using its YAML representation as the text.
+    pub fn document(data: &impl serde::Serialize) -> Self {
+        let mut output = ExecOutput::with(data);
+        output.text = serde_yaml::to_string(data).expect("unable to generate YAML representation");
+        output
+    }
+
+    /// Appends a line to the output text.
+    pub fn line(&mut self, line: impl Display) {
+        self.text.push_str(&line.to_string());
+        self.text.push('\n');
+    }
+}
+
+impl Display for ExecOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(&self.text) }
+}
+
+impl serde::Serialize for ExecOutput {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.data.serialize(serializer)
+    }
 }
 
 impl<C: Clone + Eq + Debug + Subcommand, O: DescriptorOpts> Args<C, O> {
     pub fn process(&mut self) { self.general.process(); }
 
+    /// Applies wallet settings and configuration defaults to the options which were not provided
+    /// via command-line arguments or environment variables.
+    ///
+    /// Command-line arguments take precedence over the settings of the used wallet, which take
+    /// precedence over the global configuration.
+    pub fn configure(&mut self, conf: &Config) {
+        self.settings = self
+            .wallet_dir(conf)
+            .map(|dir| {
+                WalletSettings::load(&dir).unwrap_or_else(|err| {
+                    eprintln!("Warning: ignoring wallet settings: {err}");
+                    none!()
+                })
+            })
+            .unwrap_or_default();
+
+        if self.resolver.is_some() {
+            self.resolver_source = SettingSource::CommandLine;
+        } else if self.settings.resolver.as_ref().is_some_and(ResolverOpt::is_some) {
+            self.resolver_source = SettingSource::Wallet;
+        } else if conf.resolver.is_some() {
+            self.resolver_source = SettingSource::ConfigFile;
+        }
+        if let Some(resolver) = &self.settings.resolver {
+            self.resolver.merge(resolver);
+        }
+        self.resolver.merge(&conf.resolver);
+        if self.gap_limit.is_none() {
+            self.gap_limit = self.settings.gap_limit;
+        }
+
+        if self.verbose >= 2 {
+            let resolver = &self.resolver;
+            let indexer = [
+                ("esplora", &resolver.esplora),
+                ("electrum", &resolver.electrum),
+                ("mempool", &resolver.mempool),
+                ("bitcoind", &resolver.bitcoind),
+            ]
+            .into_iter()
+            .find_map(|(name, url)| url.as_ref().map(|url| format!("{name} {url}")))
+            .unwrap_or_else(|| s!("none"));
+            let gap_limit = self.gap_limit.map(|limit| limit.to_string());
+            let fee = self.settings.fee.map(|fee| fee.to_string());
+            eprintln!("Effective settings:");
+            eprintln!("  indexer: {indexer}");
+            eprintln!("  proxy: {}", resolver.proxy.as_deref().unwrap_or("none"));
+            eprintln!("  gap limit: {}", gap_limit.as_deref().unwrap_or("wallet default"));
+            eprintln!("  fee: {}", fee.as_deref().unwrap_or("none"));
+        }
+    }
+
+    /// Merges the configuration file with the wallet settings and command-line arguments applied
+    /// by [`Args::configure`], detecting the origin of the values.
+    pub fn effective_config(&self, conf: &Config) -> EffectiveConfig {
+        let default_data_dir = shellexpand::tilde(DATA_DIR).to_string();
+        let (resolver, resolver_source) = if self.resolver.is_some() {
+            (self.resolver.clone(), self.resolver_source)
+        } else {
+            (self.resolver(), SettingSource::Default)
+        };
+        EffectiveConfig {
+            data_dir: self.general.data_dir.clone(),
+            data_dir_source: if self.general.data_dir == Path::new(&default_data_dir) {
+                SettingSource::Default
+            } else {
+                SettingSource::CommandLine
+            },
+            network: self.general.network,
+            default_wallet: conf.default_wallet.clone(),
+            default_wallet_source: if conf.default_wallet == Config::default().default_wallet {
+                SettingSource::Default
+            } else {
+                SettingSource::ConfigFile
+            },
+            wallet_dir: self.wallet_dir(conf),
+            resolver,
+            resolver_source,
+            gap_limit: self.gap_limit,
+            fee: self.settings.fee,
+        }
+    }
+
+    /// Returns directory of the wallet used by the command, unless the wallet descriptor is given
+    /// in the command-line arguments.
+    fn wallet_dir(&self, conf: &Config) -> Option<PathBuf> {
+        if self.wallet.descriptor_opts.is_some() {
+            return None;
+        }
+        if let Some(wallet_path) = self.wallet.wallet_path.clone() {
+            return Some(wallet_path);
+        }
+        Some(self.general.wallet_dir(self.wallet_name(conf)))
+    }
+
+    /// Opens the store of the wallet used by the command, locking the wallet directory and
+    /// unlocking the store if the wallet is encrypted.
+    pub fn wallet_store(&self, conf: &Config) -> Result<FsTextStore, ExecError> {
+        let path = self.wallet_dir(conf).ok_or(ExecError::NotStored)?;
+        if !path.is_dir() {
+            return Err(WalletDirError::NotFound(path.display().to_string()).into());
+        }
+        let store = FsTextStore::locked(path, Duration::from_secs(self.general.wait_lock))?;
+        self.unlock(store)
+    }
+
+    /// Unlocks the store of an encrypted wallet with the wallet passphrase; stores of the
+    /// plaintext wallets are returned as they are.
+    fn unlock(&self, mut store: FsTextStore) -> Result<FsTextStore, ExecError> {
+        if store.is_encrypted()? {
+            let passphrase = match &self.passphrase {
+                Some(passphrase) => passphrase.clone(),
+                None => rpassword::prompt_password("Wallet passphrase: ")?,
+            };
+            store.unlock(&passphrase).map_err(|err| match err {
+                CryptError::WrongPassphrase => ExecError::WrongPassphrase,
+                err => err.into(),
+            })?;
+        }
+        Ok(store)
+    }
+
+    /// Name of the wallet to use if no wallet path or descriptor is given: the one from the
+    /// command line, from `BP_WALLET` environment variable or the default one from the config.
+    pub fn wallet_name(&self, conf: &Config) -> String {
+        self.wallet
+            .name
+            .as_ref()
+            .map(Ident::to_string)
+            .or_else(|| env::var(WALLET_ENV).ok())
+            .unwrap_or(conf.default_wallet.clone())
+    }
+
+    /// Checks whether a wallet is selected by the arguments or the environment, or the default
+    /// wallet from the config exists.
+    pub fn has_wallet(&self, conf: &Config) -> bool {
+        self.wallet.name.is_some()
+            || self.wallet.wallet_path.is_some()
+            || self.wallet.descriptor_opts.is_some()
+            || env::var(WALLET_ENV).is_ok()
+            || self.general.wallet_dir(self.wallet_name(conf)).exists()
+    }
+
     pub fn conf_path(&self, name: &'static str) -> PathBuf {
         let mut conf_path = self.general.base_dir();
         conf_path.push(name);
@@ -95,26 +375,235 @@ impl<C: Clone + Eq + Debug + Subcommand, O: DescriptorOpts> Args<C, O> {
         conf_path
     }
 
+    /// Substitutes `{network}` placeholder in the esplora or mempool server URL with the path
+    /// used for the selected network by blockstream.info and mempool.space. These servers
+    /// serve mainnet from the root path, so for mainnet the placeholder is removed together with
+    /// the preceding slash.
+    fn esplora_url(&self, url: &str) -> String {
+        let network = match self.general.network {
+            Network::Mainnet => return url.replace("/{network}", "").replace("{network}", ""),
+            Network::Testnet3 => "testnet",
+            Network::Testnet4 => "testnet4",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        };
+        url.replace("{network}", network)
+    }
+
+    fn checked_esplora_url(&self, url: &str) -> Result<String, ExecError> {
+        if self.general.custom_signet().is_some() && url.contains("{network}") {
+            return Err(ExecError::CustomSignetIndexer);
+        }
+        let url = self.esplora_url(url);
+        self.check_onion(&url)?;
+        Ok(url)
+    }
+
+    fn esplora_builder(&self, url: &str) -> esplora::Builder {
+        let mut builder = esplora::Builder::new(url);
+        if let Some(timeout) = self.resolver.esplora_timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = self.resolver.socks5h_proxy() {
+            builder = builder.proxy(&proxy);
+        }
+        builder
+    }
+
+    /// Ensures that a proxy is configured if the indexer server is a Tor onion service, which
+    /// can't be reached directly.
+    fn check_onion(&self, url: &str) -> Result<(), ExecError> {
+        let host = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+        let host = host.split(['/', ':']).next().unwrap_or_default();
+        if self.resolver.proxy.is_none() && host.ends_with(".onion") {
+            return Err(ExecError::OnionWithoutProxy(url.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Checks that the configured proxy accepts connections, such that failures to reach it are
+    /// not reported as indexer errors.
+    fn check_proxy(&self) -> Result<(), ExecError> {
+        let Some(proxy) = &self.resolver.proxy else {
+            return Ok(());
+        };
+        let addr = proxy.split_once("://").map(|(_, addr)| addr).unwrap_or(proxy);
+        let err = match addr.to_socket_addrs() {
+            Ok(addrs) => {
+                let mut err = io::Error::new(io::ErrorKind::NotFound, "unknown proxy host");
+                for addr in addrs {
+                    match TcpStream::connect_timeout(&addr, PROXY_CONNECT_TIMEOUT) {
+                        Ok(_) => return Ok(()),
+                        Err(e) => err = e,
+                    }
+                }
+                err
+            }
+            Err(err) => err,
+        };
+        Err(ExecError::ProxyUnreachable(proxy.clone(), err.into()))
+    }
+
+    fn electrum_client(&self, url: &str) -> Result<electrum::Client, ExecError> {
+        self.check_onion(url)?;
+        let mut config =
+            electrum::ConfigBuilder::new().retry(self.resolver.sync_retries.unwrap_or_default());
+        if let Some(proxy) = &self.resolver.proxy {
+            let addr = proxy.split_once("://").map(|(_, addr)| addr).unwrap_or(proxy);
+            config = config.socks5(Some(electrum::Socks5Config::new(addr)));
+        }
+        Ok(electrum::Client::from_config(url, config.build())?)
+    }
+
+    /// Returns resolver options, selecting the default indexer for the network if none is given.
+    ///
+    /// The defaults are blockstream.info esplora for mainnet, testnet3 and signet, and
+    /// mempool.space for testnet4; regtest and custom signets have no default indexer.
+    fn resolver(&self) -> ResolverOpt {
+        let mut resolver = self.resolver.clone();
+        if resolver.esplora.is_some()
+            || resolver.electrum.is_some()
+            || resolver.mempool.is_some()
+            || resolver.bitcoind.is_some()
+            || self.general.custom_signet().is_some()
+        {
+            return resolver;
+        }
+        match self.general.network {
+            Network::Mainnet | Network::Testnet3 | Network::Signet => {
+                resolver.esplora = Some(DEFAULT_ESPLORA.to_owned());
+            }
+            Network::Testnet4 => resolver.mempool = Some(DEFAULT_MEMPOOL.to_owned()),
+            Network::Regtest => return resolver,
+        }
+        if self.general.format == OutputFormat::Human {
+            let url = resolver.esplora.as_ref().or(resolver.mempool.as_ref()).expect("set above");
+            eprintln!("No indexer specified, using {}", self.esplora_url(url));
+        }
+        resolver
+    }
+
     pub fn indexer(&self) -> Result<AnyIndexer, ExecError> {
-        let network = self.general.network.to_string();
-        Ok(match (&self.resolver.esplora, &self.resolver.electrum, &self.resolver.mempool) {
-            (None, Some(url), None) => AnyIndexer::Electrum(Box::new(electrum::Client::new(url)?)),
-            (Some(url), None, None) => AnyIndexer::Esplora(Box::new(esplora::Client::new_esplora(
-                &url.replace("{network}", &network),
-            )?)),
-            (None, None, Some(url)) => AnyIndexer::Mempool(Box::new(esplora::Client::new_mempool(
-                &url.replace("{network}", &network),
-            )?)),
+        if self.general.offline {
+            return Err(ExecError::OfflineRequired);
+        }
+        let resolver = self.resolver();
+        if resolver.bitcoind.is_none() {
+            self.check_proxy()?;
+        }
+        Ok(match (&resolver.esplora, &resolver.electrum, &resolver.mempool, &resolver.bitcoind) {
+            (None, Some(url), None, None) => {
+                AnyIndexer::Electrum(Box::new(self.electrum_client(url)?))
+            }
+            (Some(url), None, None, None) => AnyIndexer::Esplora(Box::new(
+                esplora::Client::with_builder(
+                    self.esplora_builder(&self.checked_esplora_url(url)?),
+                    esplora::ClientKind::Esplora,
+                )?
+                .with_retries(resolver.retry_policy())
+                .with_parallelism(resolver.sync_parallelism()),
+            )),
+            (None, None, Some(url), None) => AnyIndexer::Mempool(Box::new(
+                esplora::Client::with_builder(
+                    self.esplora_builder(&self.checked_esplora_url(url)?),
+                    esplora::ClientKind::Mempool,
+                )?
+                .with_retries(resolver.retry_policy())
+                .with_parallelism(resolver.sync_parallelism()),
+            )),
+            (None, None, None, Some(url)) => {
+                let port = bitcoind_rpc_port(self.general.network).to_string();
+                let client =
+                    bitcoind::Client::new(&url.replace("{port}", &port), resolver.rpc_auth())
+                        .with_retries(resolver.retry_policy());
+                self.check_signet_magic(&client)?;
+                AnyIndexer::Bitcoind(Box::new(client))
+            }
             _ => {
                 eprintln!(
-                    "Error: no blockchain indexer specified; use either --esplora --mempool or \
-                     --electrum argument"
+                    "Error: no blockchain indexer specified; use either --esplora --mempool \
+                     --electrum or --bitcoind argument"
                 );
                 exit(1);
             }
         })
     }
 
+    /// Checks that Bitcoin Core node runs the signet selected with `--signet-magic` argument, or
+    /// the default public signet if the argument is not given.
+    fn check_signet_magic(&self, client: &bitcoind::Client) -> Result<(), ExecError> {
+        if self.general.network != Network::Signet {
+            return Ok(());
+        }
+        let expected = self.general.signet_magic.unwrap_or(DEFAULT_SIGNET_MAGIC);
+        match client.signet_magic()? {
+            Some(found) if found != expected => Err(ExecError::SignetMismatch {
+                expected: expected.to_hex(),
+                found: found.to_hex(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Resolves the fee rate target into a fee rate in satoshis per virtual byte, requesting fee
+    /// estimation from the indexer unless the rate is given explicitly.
+    ///
+    /// If the indexer is not available, uses `--fallback-fee-rate`, if given.
+    pub fn fee_rate(&self, target: FeeTarget) -> Result<f64, ExecError> {
+        if let FeeTarget::Rate(rate) = target {
+            return Ok(rate);
+        }
+        let indexer = match (self.indexer(), self.fallback_fee_rate) {
+            (Ok(indexer), _) => indexer,
+            (Err(err), Some(fallback)) => {
+                eprintln!("Warning: {err}");
+                eprintln!("Using fallback fee rate of {fallback} ṩ/vbyte");
+                return Ok(fallback.sats_per_vbyte());
+            }
+            (Err(err), None) => return Err(err),
+        };
+        let rate = self.fee_estimator(&indexer).fee_rate(target)?;
+        eprintln!("Estimated fee rate for '{target}' target: {rate:.2} ṩ/vbyte");
+        Ok(rate)
+    }
+
+    /// Constructs fee estimator for the indexer, using the fallback and minimal fee rates from
+    /// the command-line arguments.
+    pub fn fee_estimator<'i>(&self, indexer: &'i AnyIndexer) -> FeeEstimator<'i, AnyIndexer> {
+        FeeEstimator::new(indexer)
+            .with_fallback(self.fallback_fee_rate.map(FeeRate::sats_per_vbyte))
+            .with_min_rate(self.min_fee_rate.sats_per_vbyte())
+    }
+
+    #[cfg(feature = "async")]
+    pub fn indexer_async(&self) -> Result<esplora::AsyncClient, ExecError> {
+        if self.general.offline {
+            return Err(ExecError::OfflineRequired);
+        }
+        let resolver = self.resolver();
+        self.check_proxy()?;
+        let client = match (&resolver.esplora, &resolver.mempool) {
+            (Some(url), None) => esplora::AsyncClient::with_builder(
+                self.esplora_builder(&self.checked_esplora_url(url)?),
+                esplora::ClientKind::Esplora,
+            )?,
+            (None, Some(url)) => esplora::AsyncClient::with_builder(
+                self.esplora_builder(&self.checked_esplora_url(url)?),
+                esplora::ClientKind::Mempool,
+            )?,
+            _ => {
+                eprintln!(
+                    "Error: no asynchronous blockchain indexer specified; use either --esplora or \
+                     --mempool argument"
+                );
+                exit(1);
+            }
+        };
+        Ok(client
+            .with_retries(resolver.retry_policy())
+            .with_parallelism(resolver.sync_parallelism()))
+    }
+
     #[allow(clippy::multiple_bound_locations)]
     pub fn bp_wallet<D: Descriptor>(
         &self,
@@ -123,47 +612,348 @@ impl<C: Clone + Eq + Debug + Subcommand, O: DescriptorOpts> Args<C, O> {
     where
         for<'de> D: From<O::Descr> + serde::Serialize + serde::Deserialize<'de>,
     {
-        eprint!("Loading descriptor");
-        let sync = self.sync || self.wallet.descriptor_opts.is_some();
+        let mut wallet = self.load_wallet::<D>(conf)?;
 
-        let mut wallet: Wallet<XpubDerivable, D, WalletCache<Layer2Empty>> =
-            if let Some(d) = self.wallet.descriptor_opts.descriptor() {
-                eprintln!(" from command-line argument");
+        let human = self.general.format.is_human();
+        let sync = self.sync || self.rescan || self.wallet.descriptor_opts.is_some();
+        if sync && self.general.offline {
+            if human {
+                eprintln!("Wallet sync is skipped in offline mode");
+            }
+        } else if sync {
+            if self.rescan {
+                wallet.reset_sync();
+            }
+            let indexer = self.indexer()?;
+            report_tip(wallet.check_tip(&indexer));
+            let res = if human {
                 eprint!("Syncing");
-                Wallet::new_layer1(d.into(), self.general.network)
+                let mut progress = StderrProgress::default();
+                let res = wallet.update_with_progress(&indexer, &mut progress);
+                progress.finish();
+                res
             } else {
-                let path = if let Some(wallet_path) = self.wallet.wallet_path.clone() {
-                    eprint!(" from specified wallet directory ... ");
-                    wallet_path
-                } else {
-                    let wallet_name = self
-                        .wallet
-                        .name
-                        .as_ref()
-                        .map(Ident::to_string)
-                        .unwrap_or(conf.default_wallet.clone());
-                    eprint!(" from wallet {wallet_name} ... ");
-                    self.general.wallet_dir(wallet_name)
-                };
-                let provider = FsTextStore::new(path)?;
-                let wallet = Wallet::load(provider, true)?;
-                eprintln!("success");
-                wallet
+                wallet.update(&indexer)
             };
+            report_sync(&res, human);
+            log_sync_summary(&wallet);
+        }
 
-        if sync {
-            let indexer = self.indexer()?;
-            eprint!("Syncing");
-            if let Some(errors) = wallet.update(&indexer).into_err() {
-                eprintln!(" partial, some requests has failed:");
-                for err in errors {
-                    eprintln!("- {err}");
+        Ok(wallet)
+    }
+
+    /// Asynchronous version of [`Args::bp_wallet`], syncing the wallet using an esplora or
+    /// mempool indexer without blocking the async runtime.
+    #[cfg(feature = "async")]
+    #[allow(clippy::multiple_bound_locations)]
+    pub async fn bp_wallet_async<D: Descriptor>(
+        &self,
+        conf: &Config,
+    ) -> Result<Wallet<XpubDerivable, D, WalletCache<Layer2Empty>>, ExecError>
+    where
+        for<'de> D: From<O::Descr> + serde::Serialize + serde::Deserialize<'de>,
+    {
+        let mut wallet = self.load_wallet::<D>(conf)?;
+
+        let human = self.general.format.is_human();
+        let sync = self.sync || self.rescan || self.wallet.descriptor_opts.is_some();
+        if sync && self.general.offline {
+            if human {
+                eprintln!("Wallet sync is skipped in offline mode");
+            }
+        } else if sync {
+            if self.rescan {
+                wallet.reset_sync();
+            }
+            let indexer = self.indexer_async()?;
+            report_tip(wallet.check_tip_async(&indexer).await);
+            if human {
+                eprint!("Syncing");
+            }
+            report_sync(&wallet.update_async(&indexer).await, human);
+            log_sync_summary(&wallet);
+        }
+
+        Ok(wallet)
+    }
+
+    #[allow(clippy::multiple_bound_locations)]
+    fn load_wallet<D: Descriptor>(
+        &self,
+        conf: &Config,
+    ) -> Result<Wallet<XpubDerivable, D, WalletCache<Layer2Empty>>, ExecError>
+    where
+        for<'de> D: From<O::Descr> + serde::Serialize + serde::Deserialize<'de>,
+    {
+        let human = self.general.format.is_human();
+        if human {
+            eprint!("Loading descriptor");
+        }
+        let mut wallet = if let Some(d) = self.wallet.descriptor_opts.descriptor() {
+            if human {
+                eprintln!(" from command-line argument");
+            }
+            Wallet::new_layer1(d.into(), self.general.network)
+        } else {
+            let path = if let Some(wallet_path) = self.wallet.wallet_path.clone() {
+                if human {
+                    eprint!(" from specified wallet directory ... ");
                 }
+                wallet_path
             } else {
-                eprintln!(" success");
+                let wallet_name = self.wallet_name(conf);
+                if human {
+                    eprint!(" from wallet {wallet_name} ... ");
+                }
+                self.general.wallet_dir(wallet_name)
+            };
+            let wait = Duration::from_secs(self.general.wait_lock);
+            let provider = self.unlock(FsTextStore::locked(path, wait)?)?;
+            let wallet = load_wallet(provider, true)?;
+            if human {
+                eprintln!("success");
+            }
+            wallet
+        };
+
+        self.apply_gap_limits::<D>(&mut wallet);
+        if !self.keychains.is_empty() {
+            let keychains = self.keychains.iter().copied().collect::<BTreeSet<_>>();
+            let known = wallet.keychains();
+            if let Some(keychain) = keychains.iter().find(|k| !known.contains(*k)) {
+                return Err(ExecError::UnknownKeychain(*keychain));
+            }
+            wallet.restrict_keychains(Some(keychains));
+        }
+
+        Ok(wallet)
+    }
+
+    fn apply_gap_limits<D: Descriptor>(
+        &self,
+        wallet: &mut Wallet<XpubDerivable, D, WalletCache<Layer2Empty>>,
+    ) {
+        if let Some(gap_limit) = self.gap_limit {
+            for keychain in wallet.keychains() {
+                wallet.set_gap_limit(keychain, gap_limit);
+            }
+        }
+        if let Some(gap_limit) = self.change_gap_limit {
+            wallet.set_gap_limit(1u8, gap_limit);
+        }
+    }
+
+    /// Creates a new wallet with the descriptor provided in the command-line arguments and saves
+    /// it under the given `name` into the wallet data directory.
+    ///
+    /// See [`Self::create_wallet_from`] for the details.
+    #[allow(clippy::multiple_bound_locations)]
+    pub fn create_wallet<D: Descriptor>(
+        &self,
+        name: &Ident,
+        conf: &mut Config,
+        set_default: bool,
+        force: bool,
+        encrypt: bool,
+    ) -> Result<Wallet<XpubDerivable, D, WalletCache<Layer2Empty>>, ExecError>
+    where
+        for<'de> D: From<O::Descr> + serde::Serialize + serde::Deserialize<'de>,
+    {
+        let descriptor = self.wallet.descriptor_opts.descriptor().ok_or(ExecError::NoDescriptor)?;
+        self.create_wallet_from(name, descriptor, conf, set_default, force, encrypt)
+    }
+
+    /// Creates a new wallet with the given `descriptor` and saves it under the given `name` into
+    /// the wallet data directory.
+    ///
+    /// Fails if a wallet with the same name already exists (unless `force` is set, in which case
+    /// the existing wallet is overwritten), or if the descriptor keys do not match the selected
+    /// network; in these cases nothing is written to the disk. If `set_default` is true, the
+    /// wallet is made default in `conf`, which is left to the caller to store.
+    ///
+    /// If `encrypt` is true, the wallet files are encrypted at rest with the passphrase from
+    /// `--passphrase` argument, or the one asked interactively.
+    #[allow(clippy::multiple_bound_locations)]
+    pub fn create_wallet_from<D: Descriptor>(
+        &self,
+        name: &Ident,
+        descriptor: O::Descr,
+        conf: &mut Config,
+        set_default: bool,
+        force: bool,
+        encrypt: bool,
+    ) -> Result<Wallet<XpubDerivable, D, WalletCache<Layer2Empty>>, ExecError>
+    where
+        for<'de> D: From<O::Descr> + serde::Serialize + serde::Deserialize<'de>,
+    {
+        let network = self.general.network;
+        if descriptor.xpubs().any(|xpub| xpub.xpub().is_testnet() != network.is_testnet()) {
+            return Err(ExecError::NetworkMismatch(network));
+        }
+        let name = name.to_string();
+        let dir = self.general.wallet_dir(&name);
+        let exists = dir.exists();
+        if exists && !force {
+            return Err(ExecError::WalletExists(name));
+        }
+
+        let key = if encrypt {
+            Some(WalletKey::new(&new_passphrase(self.passphrase.as_deref())?))
+        } else {
+            None
+        };
+
+        let mut wallet = Wallet::new_layer1(descriptor.into(), network);
+        self.apply_gap_limits::<D>(&mut wallet);
+        wallet.set_name(name.clone());
+        let res = FsTextStore::locked(dir.clone(), Duration::from_secs(self.general.wait_lock))
+            .map_err(ExecError::from)
+            .and_then(|mut provider| {
+                provider.key = key;
+                Ok(wallet.make_persistent(provider, true)?)
+            })
+            .and_then(|_| Ok(wallet.store()?));
+        if let Err(err) = res {
+            // Do not leave a partially written wallet behind
+            if !exists {
+                fs::remove_dir_all(&dir).ok();
             }
+            return Err(err);
         }
 
+        if set_default {
+            conf.default_wallet = name;
+        }
         Ok(wallet)
     }
 }
+
+/// Returns the `given` new wallet passphrase, or asks for it interactively, requiring to repeat
+/// it.
+pub fn new_passphrase(given: Option<&str>) -> Result<String, ExecError> {
+    if let Some(passphrase) = given {
+        return Ok(passphrase.to_owned());
+    }
+    let passphrase = rpassword::prompt_password("New wallet passphrase: ")?;
+    if rpassword::prompt_password("Repeat the passphrase: ")? != passphrase {
+        return Err(ExecError::PassphraseMismatch);
+    }
+    Ok(passphrase)
+}
+
+/// Renders wallet synchronization progress to STDERR.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub struct StderrProgress {
+    txs: usize,
+    line_len: usize,
+}
+
+impl StderrProgress {
+    /// Clears the progress information, leaving just the "Syncing" prefix in the current line.
+    pub fn finish(&mut self) {
+        eprint!("\r{:1$}\rSyncing", "", self.line_len);
+        self.line_len = 0;
+    }
+}
+
+impl SyncProgress for StderrProgress {
+    fn on_address(&mut self, index: u32, total: u32) {
+        let percent = (index + 1) * 100 / total.max(index + 1);
+        let line = format!(
+            "Syncing: {percent:>3}% (address {} of {total}), {} transactions found",
+            index + 1,
+            self.txs
+        );
+        eprint!("\r{line:width$}", width = self.line_len);
+        self.line_len = line.len();
+    }
+
+    fn on_tx(&mut self, _txid: Txid, _height: Option<BlockHeight>) { self.txs += 1; }
+}
+
+/// Reports the result of a wallet sync to STDERR; failed requests are reported even if `human`
+/// progress output is disabled.
+fn report_sync<E: Display>(report: &SyncReport<E>, human: bool) {
+    for (keychain, height) in &report.reorgs {
+        eprintln!("Warning: block {height} at which keychain {keychain} was synced is re-orged");
+    }
+    if !report.is_complete() {
+        if human {
+            eprintln!(" partial, some requests has failed:");
+        } else {
+            eprintln!("Wallet sync is partial, some requests has failed:");
+        }
+        for err in &report.failed {
+            eprintln!("- {err}");
+        }
+    } else if human {
+        eprintln!(" success");
+    }
+}
+
+/// Warns if the indexer chain is inconsistent with the chain tip stored during the previous sync.
+fn report_tip<E: Display>(res: Result<TipCheck, E>) {
+    match res {
+        Ok(check) if check.is_warning() => eprintln!("Warning: {check}"),
+        Ok(_) => {}
+        Err(err) => log::debug!("Unable to check the wallet chain tip: {err}"),
+    }
+}
+
+/// Logs summary of the synchronized wallet state at the info level.
+fn log_sync_summary<D: Descriptor>(wallet: &Wallet<XpubDerivable, D, WalletCache<Layer2Empty>>) {
+    let height = wallet.last_sync_height().map(|height| height.to_string());
+    log::info!(
+        "Wallet is synced to block {}: {} transactions, {} unspent outputs, balance {} ṩ",
+        height.as_deref().unwrap_or("unknown"),
+        wallet.transactions().count(),
+        wallet.utxos().count(),
+        wallet.balance()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::ffi::OsString;
+
+    use clap::{CommandFactory, FromArgMatches};
+
+    use super::*;
+    use crate::cli::{with_legacy_env, BpCommand};
+
+    #[test]
+    fn test_env_fallback() {
+        let vars = HashMap::from([
+            ("ESPLORA_SERVER", "https://esplora.example.com/{network}/api"),
+            ("LNPBP_NETWORK", "signet"),
+            ("MEMPOOL_SERVER", "https://mempool.example.com/api"),
+            ("BP_MEMPOOL", "https://mempool.example.org/api"),
+        ]);
+        let parse = |args: &[&str]| {
+            let command = with_legacy_env(Args::<BpCommand>::command(), |name| {
+                vars.get(name).map(OsString::from)
+            });
+            Args::<BpCommand>::from_arg_matches(&command.try_get_matches_from(args).unwrap())
+                .unwrap()
+        };
+
+        let args = parse(&["bp", "balance"]);
+        assert_eq!(
+            args.resolver.esplora.as_deref(),
+            Some("https://esplora.example.com/{network}/api")
+        );
+        assert_eq!(args.general.network, Network::Signet);
+        // The legacy variable is ignored when the current one is set
+        assert_eq!(args.resolver.mempool, None);
+
+        let args = parse(&[
+            "bp",
+            "balance",
+            "--esplora=https://other.example.com/api",
+            "--network=mainnet",
+        ]);
+        assert_eq!(args.resolver.esplora.as_deref(), Some("https://other.example.com/api"));
+        assert_eq!(args.general.network, Network::Mainnet);
+    }
+}
@@ -20,25 +20,46 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fs::File;
+use std::cmp;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
 use std::path::{Path, PathBuf};
 use std::process::exit;
-use std::{fs, io};
+use std::str::FromStr;
 
 use amplify::IoError;
-use bpstd::psbt::{Beneficiary, TxParams};
-use bpstd::{ConsensusEncode, Derive, IdxBase, Keychain, NormalIndex, Sats, Tx, XpubDerivable};
+use bpstd::psbt::Beneficiary;
+use bpstd::{
+    Address, AddressNetwork, ConsensusDecode, ConsensusEncode, Derive, IdxBase, Keychain, LockTime,
+    Network, NormalIndex, Sats, ScriptPubkey, Tx, TxOut, Weight, XpubAccount, XpubDerivable,
+};
 use colored::Colorize;
 use descriptors::Descriptor;
 use nonasync::persistence::PersistenceError;
 use psbt::{ConstructionError, Payment, Psbt, PsbtConstructor, PsbtVer, UnfinalizedInputs};
 use strict_encoding::Ident;
 
-use crate::cli::{Args, Config, DescriptorOpts, Exec};
-use crate::fs::FsTextStore;
+use crate::bip322::{self, Bip322Error};
+use crate::checksum::{self, ChecksumError};
+use crate::cli::{
+    new_passphrase, Args, Config, ConfigIssues, DescriptorArgError, DescriptorOpts, Exec,
+    ExecOutput, ResolverOpt,
+};
+use crate::coinselect::{DustPolicy, Strategy};
+use crate::export::{
+    self, CoreImport, CoreImportError, CoreTimestamp, ElectrumImportError, HistoryFilter,
+};
+use crate::fees::{FeePolicy, FeeTarget};
+use crate::finalize::{self, CombineError};
+use crate::fs::{list_wallets, migrate_wallets, CryptError, LoadError, WalletDirError, WalletKey};
+use crate::labels::Label;
 use crate::{
-    coinselect, AnyIndexerError, Indexer, Layer2Empty, OpType, Wallet, WalletAddr, WalletCache,
-    WalletUtxo,
+    check_duplicate_outputs, fees, AnyIndexer, AnyIndexerError, Batch, BatchError, BroadcastError,
+    BuildTxError, BumpFeeError, ChangePolicy, CpfpError, DuplicatePolicy, Indexer, Invoice,
+    InvoiceError, NonWalletItem, OpReturn, OpType, Outpoint, SpendParams, SweepFilter, TxOrder,
+    TxStatus, Txid, WalletAddr,
 };
 
 #[derive(Subcommand, Clone, PartialEq, Eq, Debug, Display)]
@@ -47,20 +68,76 @@ pub enum Command {
     #[display("list")]
     List,
 
-    /// Get or set default wallet
+    /// Get or set default wallet and indexer
     #[display("default")]
     Default {
         /// Name of the wallet to make it default
         default: Option<Ident>,
+
+        /// Save the indexer given by `--electrum`, `--esplora`, `--mempool` or `--bitcoind`
+        /// argument as the default one
+        #[clap(long)]
+        resolver: bool,
+    },
+
+    /// Validate and print the configuration in effect, merged from the config file, wallet
+    /// settings, environment variables and command-line arguments
+    #[display("config")]
+    Config,
+
+    /// Move wallets of the selected network from a legacy data directory into the one given by
+    /// `--data-dir`
+    ///
+    /// Wallets existing in both directories are never overwritten; in this case nothing is
+    /// moved.
+    #[display("migrate")]
+    Migrate {
+        /// List the wallets which would be moved without moving them
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Legacy data directory to move the wallets from
+        #[clap(value_hint = clap::ValueHint::DirPath)]
+        from: PathBuf,
     },
 
     /// Create a named wallet
     #[display("create")]
     Create {
+        /// Make the new wallet default
+        #[clap(long)]
+        default: bool,
+
+        /// Overwrite an existing wallet with the same name
+        #[clap(short, long)]
+        force: bool,
+
+        /// Encrypt the wallet files at rest with a passphrase, given by `--passphrase` or asked
+        /// interactively
+        #[clap(long)]
+        encrypt: bool,
+
         /// The name for the new wallet
         name: Ident,
     },
 
+    /// Encrypt the wallet files at rest with a new passphrase, or change the passphrase of an
+    /// encrypted wallet
+    ///
+    /// The encryption key is derived from the passphrase with Argon2id; the wallet descriptor,
+    /// cache and labels are encrypted with AES-256-GCM. The current passphrase of an encrypted
+    /// wallet is given by `--passphrase` or asked interactively.
+    #[display("encrypt")]
+    Encrypt {
+        /// New wallet passphrase; asked interactively if not given
+        #[clap(long, env = "BP_WALLET_NEW_PASSPHRASE", hide_env_values = true)]
+        new_passphrase: Option<String>,
+    },
+
+    /// Remove encryption of the wallet files, storing them in plaintext
+    #[display("decrypt")]
+    Decrypt,
+
     /// Generate a new wallet address(es)
     #[display("address")]
     Address {
@@ -77,14 +154,44 @@ pub enum Command {
         index: Option<NormalIndex>,
 
         /// Do not shift the last used index
-        #[clap(short = 'D', long, conflicts_with_all = ["change", "index"])]
+        #[clap(short = 'D', long, alias = "no-shift", conflicts_with = "index")]
         dry_run: bool,
 
         /// Number of addresses to generate
+        ///
+        /// Unless `--dry-run` or `--index` is given, all generated addresses are marked as used,
+        /// such that the next call generates new addresses.
         #[clap(short = 'C', long, default_value = "1")]
         count: u8,
     },
 
+    /// Verify that an address belongs to the wallet descriptor before sharing it
+    ///
+    /// Each keychain is searched up to its last used address, followed by the gap limit and
+    /// `--search-depth` more addresses.
+    #[display("verify")]
+    Verify {
+        /// Number of addresses to check on each keychain in addition to the gap limit
+        #[clap(short = 'd', long, default_value = "0")]
+        search_depth: u32,
+
+        /// Address to verify
+        address: Address,
+    },
+
+    /// Combine PSBTs of the same transaction signed by different signers, like hardware wallets,
+    /// into a single PSBT holding all their signatures
+    #[display("combine")]
+    Combine {
+        /// Name of PSBT file to save the combined PSBT to. If not given, prints PSBT to STDOUT
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// Names of PSBT files to combine
+        #[clap(required = true, num_args = 2..)]
+        psbts: Vec<PathBuf>,
+    },
+
     /// Finalize a PSBT, optionally extracting and publishing the signed transaction
     #[display("finalize")]
     Finalize {
@@ -113,6 +220,18 @@ pub enum Command {
         /// print to STDOUT.
         tx: Option<PathBuf>,
     },
+
+    /// Broadcast a signed transaction to the network via the indexer
+    #[display("broadcast")]
+    Broadcast {
+        /// Validate the transaction, finalizing PSBT if needed, but do not submit it.
+        #[clap(long)]
+        dry_run: bool,
+
+        /// File containing either signed transaction (binary or hex-encoded) or a fully-signed
+        /// PSBT (binary or Base64-encoded), which is finalized before the broadcast
+        tx: PathBuf,
+    },
 }
 
 #[derive(Subcommand, Clone, PartialEq, Eq, Debug, Display)]
@@ -131,6 +250,30 @@ pub enum BpCommand {
         /// Print information about individual UTXOs
         #[clap(short, long)]
         utxo: bool,
+
+        /// Minimal number of confirmations for a coin to be counted as confirmed
+        #[clap(long, default_value = "1")]
+        min_confirmations: u32,
+    },
+
+    /// List unspent transaction outputs of the wallet
+    #[display("utxos")]
+    Utxos {
+        /// List only UTXOs having at least this amount of satoshis
+        #[clap(long)]
+        min_amount: Option<Sats>,
+
+        /// List only UTXOs belonging to the given keychain
+        #[clap(short, long)]
+        keychain: Option<Keychain>,
+
+        /// List only UTXOs from the mined transactions
+        #[clap(long)]
+        confirmed_only: bool,
+
+        /// Order in which UTXOs are listed
+        #[clap(long, value_enum, default_value_t = UtxoSort::Height)]
+        sort: UtxoSort,
     },
 
     /// Display history of wallet operations
@@ -143,10 +286,27 @@ pub enum BpCommand {
         /// Print operation details
         #[clap(long)]
         details: bool,
+
+        /// List only transactions mined at or after the block height
+        #[clap(long, value_name = "HEIGHT")]
+        since: Option<u32>,
+
+        /// List only transactions mined at or before the block height; excludes unconfirmed
+        /// transactions
+        #[clap(long, value_name = "HEIGHT")]
+        until: Option<u32>,
     },
 
-    /// Inspect transaction
-    Tx { tx: Tx },
+    /// Show fee rates recommended by the indexer
+    #[display("fees")]
+    Fees,
+
+    /// Inspect transaction given in the raw form, or fetch it from the indexer by its id and show
+    /// its details
+    ///
+    /// The details include the transaction fee, size, confirmation depth and the label stored in
+    /// the wallet; inputs and outputs belonging to the wallet are annotated with their derivation.
+    Tx { tx: TxRef },
 
     /// Inspect PSBT file
     Inspect {
@@ -161,20 +321,572 @@ pub enum BpCommand {
         #[clap(short = '2')]
         v2: bool,
 
-        /// Bitcoin invoice in form of `<sats>@<address>`. To spend full wallet balance use
-        /// `MAX` for the amount.
+        /// Recipient of the payment in form of `<address>:<sats>` or `<sats>@<address>`. To spend
+        /// full wallet balance use `MAX` for the amount.
         ///
-        /// If multiple `MAX` addresses provided the wallet balance is split between them in equal
-        /// proportions.
+        /// May be repeated to pay several recipients with a single transaction. If multiple `MAX`
+        /// addresses provided the wallet balance is split between them in equal proportions.
+        #[clap(long, value_parser = parse_beneficiary)]
+        to: Vec<Beneficiary>,
+
+        /// BIP-21 payment request URI like `bitcoin:<address>?amount=<btc>&label=<label>`, which
+        /// must specify the amount. The request label is saved as the transaction label
+        #[clap(long, value_name = "URI")]
+        uri: Vec<Invoice>,
+
+        /// File with a batch of payments, each made to a different address. A file with `.json`
+        /// extension must contain an array of `{ "address": ..., "amount": <sats> }` objects;
+        /// other files are read as CSV with `<address>,<sats>` lines
+        #[clap(long, value_name = "FILE", conflicts_with_all = ["to", "uri"])]
+        batch: Option<PathBuf>,
+
+        /// How to handle batch payments repeating the same address
+        #[clap(long, value_enum, default_value_t = DuplicatePolicy::Reject, requires = "batch")]
+        duplicates: DuplicatePolicy,
+
+        /// Allow several outputs paying the same amount to the same address
+        #[clap(long, conflicts_with = "batch")]
+        allow_duplicate_outputs: bool,
+
+        /// Sort transaction inputs and outputs according to BIP-69 instead of keeping the order
+        /// of the selected coins and the given beneficiaries
+        #[clap(long)]
+        bip69: bool,
+
+        #[clap(flatten)]
+        select: SelectOpts,
+
+        /// Derivation index of the change address on the internal keychain. The index must not
+        /// be used or reserved yet; by default the next unused index is taken
+        #[clap(long, value_name = "INDEX")]
+        change_index: Option<NormalIndex>,
+
+        /// Allow fee rates above 1000 ṩ/vbyte or below the minimal relay fee rate
+        #[clap(long)]
+        allow_absurd_fee: bool,
+
+        /// Do not signal replaceability of the transaction (BIP-125)
         #[clap(long)]
+        no_rbf: bool,
+
+        /// Transaction lock time: block height if below 500000000, UNIX timestamp otherwise
+        #[clap(long, value_name = "HEIGHT|TIME")]
+        lock_time: Option<u32>,
+
+        /// Hex-encoded data, up to 80 bytes, to commit to in a zero-value `OP_RETURN` output
+        #[clap(long, value_name = "HEX")]
+        op_return: Option<OpReturn>,
+
+        /// Fee
+        ///
+        /// If neither fee nor `--fee-rate` is given, the fee from the wallet settings is used.
+        fee: Option<Sats>,
+
+        /// Name of a PSBT file to save. If not given, prints PSBT to STDOUT
+        psbt: Option<PathBuf>,
+    },
+
+    /// Estimate the fee and the coins spent by a payment without composing a PSBT
+    ///
+    /// The coins are selected the same way as by `construct`, but the wallet is left unchanged
+    /// and no change address is reserved.
+    #[display("estimate")]
+    Estimate {
+        /// Recipient of the payment in form of `<address>:<sats>` or `<sats>@<address>`; may be
+        /// repeated. To spend full wallet balance use `MAX` for the amount.
+        #[clap(long, required = true, value_parser = parse_beneficiary)]
         to: Vec<Beneficiary>,
 
+        /// Allow several outputs paying the same amount to the same address
+        #[clap(long)]
+        allow_duplicate_outputs: bool,
+
+        #[clap(flatten)]
+        select: SelectOpts,
+
         /// Fee
-        fee: Sats,
+        ///
+        /// If neither fee nor `--fee-rate` is given, the fee from the wallet settings is used.
+        fee: Option<Sats>,
+    },
+
+    /// Compose a PSBT spending all spendable wallet coins to a single address, without change
+    #[display("sweep")]
+    Sweep {
+        /// Encode PSBT as V2
+        #[clap(short = '2')]
+        v2: bool,
+
+        /// Address receiving the swept funds, less the fee
+        address: Address,
+
+        /// Fee rate in satoshis per virtual byte, or a confirmation target given as `fast`,
+        /// `medium`, `slow` or `<N>blocks`
+        #[clap(long)]
+        fee_rate: FeeTarget,
+
+        /// Sweep only coins belonging to the given keychain
+        #[clap(short, long)]
+        keychain: Option<Keychain>,
+
+        /// Sweep only coins with the amount below this number of satoshis, for instance to get
+        /// rid of the dust
+        #[clap(long, value_name = "SATS")]
+        below: Option<Sats>,
+
+        /// Sweep only outputs of the confirmed transactions
+        #[clap(long)]
+        confirmed_only: bool,
+
+        /// Do not signal replaceability of the transaction (BIP-125)
+        #[clap(long)]
+        no_rbf: bool,
+
+        /// Name of a PSBT file to save. If not given, prints PSBT to STDOUT
+        psbt: Option<PathBuf>,
+    },
+
+    /// Compose a PSBT consolidating small wallet coins into a single output on a new change
+    /// address, for instance during low-fee periods
+    #[display("consolidate")]
+    Consolidate {
+        /// Encode PSBT as V2
+        #[clap(short = '2')]
+        v2: bool,
+
+        /// Fee rate in satoshis per virtual byte, or a confirmation target given as `fast`,
+        /// `medium`, `slow` or `<N>blocks`
+        #[clap(long)]
+        fee_rate: FeeTarget,
+
+        /// Consolidate only coins with the amount below this number of satoshis
+        #[clap(long, value_name = "SATS")]
+        below: Option<Sats>,
+
+        /// Do not consolidate unless at least this number of coins match the criteria
+        #[clap(long, default_value = "2", value_name = "COUNT")]
+        min_inputs: usize,
+
+        /// Consolidate only outputs of the confirmed transactions
+        #[clap(long)]
+        confirmed_only: bool,
+
+        /// Name of a PSBT file to save. If not given, prints PSBT to STDOUT
+        psbt: Option<PathBuf>,
+    },
+
+    /// Compose a PSBT replacing an unconfirmed wallet transaction with a higher fee (BIP-125)
+    #[display("bump-fee")]
+    BumpFee {
+        /// Encode PSBT as V2
+        #[clap(short = '2')]
+        v2: bool,
+
+        /// Id of the transaction to replace
+        txid: Txid,
+
+        /// New fee rate in satoshis per virtual byte, or a confirmation target given as `fast`,
+        /// `medium`, `slow` or `<N>blocks`
+        #[clap(long)]
+        fee_rate: FeeTarget,
+
+        /// Name of a PSBT file to save. If not given, prints PSBT to STDOUT
+        psbt: Option<PathBuf>,
+    },
+
+    /// Compose a PSBT for a child transaction accelerating an unconfirmed wallet transaction
+    /// (CPFP)
+    #[display("cpfp")]
+    Cpfp {
+        /// Encode PSBT as V2
+        #[clap(short = '2')]
+        v2: bool,
+
+        /// Id of the transaction to accelerate
+        txid: Txid,
+
+        /// Fee rate of the parent and the child transactions together, in satoshis per virtual
+        /// byte, or a confirmation target given as `fast`, `medium`, `slow` or `<N>blocks`
+        #[clap(long)]
+        fee_rate: FeeTarget,
 
         /// Name of a PSBT file to save. If not given, prints PSBT to STDOUT
         psbt: Option<PathBuf>,
     },
+
+    /// Sign PSBT with the signing account attached to the wallet. Fails for watch-only wallets
+    #[cfg(feature = "hot")]
+    #[display("sign")]
+    Sign {
+        /// Do not ask for a password and default to an empty-line password. For testing purposes
+        /// only.
+        #[clap(short = 'N', long)]
+        no_password: bool,
+
+        /// File containing PSBT; the signed PSBT is saved back to it
+        psbt: PathBuf,
+    },
+
+    /// Sign a message with the key of a wallet address, producing BIP-322 simple signature
+    #[cfg(feature = "hot")]
+    #[display("sign-message")]
+    SignMessage {
+        /// Do not ask for a password and default to an empty-line password. For testing purposes
+        /// only.
+        #[clap(short = 'N', long)]
+        no_password: bool,
+
+        /// Wallet address to sign with, or the derivation index of the address on the default
+        /// keychain. Only P2WPKH and P2TR addresses are supported
+        address: AddrRef,
+
+        /// Message to sign
+        message: String,
+    },
+
+    /// Verify BIP-322 simple signature of a message. Doesn't require a wallet
+    #[display("verify-message")]
+    VerifyMessage {
+        /// Address which has signed the message
+        address: Address,
+
+        /// Signed message
+        message: String,
+
+        /// Base64-encoded signature
+        signature: String,
+    },
+
+    /// Attach signing account file created with `bp-hot` to the wallet. If no file is given,
+    /// detaches the signing account, making the wallet watch-only
+    #[display("set-signer")]
+    SetSigner {
+        /// Do not ask for the password of the account file, which is needed to check the account
+        /// against the wallet descriptor, and default to an empty-line password. For testing
+        /// purposes only.
+        #[clap(short = 'N', long)]
+        no_password: bool,
+
+        /// Encrypted account file holding extended private key for the wallet descriptor
+        account: Option<PathBuf>,
+    },
+
+    /// Lock wallet output from being spent by the transactions composed with `construct`
+    #[display("lock")]
+    Lock {
+        /// Reason of the lock, shown apart from the output label
+        #[clap(long)]
+        label: Option<String>,
+
+        /// Wallet output to lock
+        outpoint: Outpoint,
+    },
+
+    /// Unlock previously locked wallet output
+    #[display("unlock")]
+    Unlock {
+        /// Wallet output to unlock
+        outpoint: Outpoint,
+    },
+
+    /// Export wallet labels in BIP-329 JSON Lines format
+    #[display("export-labels")]
+    ExportLabels {
+        /// Name of a file to save labels to. If not given, prints labels to STDOUT
+        file: Option<PathBuf>,
+    },
+
+    /// Import labels in BIP-329 JSON Lines format, merging them with the existing wallet labels
+    #[display("import-labels")]
+    ImportLabels {
+        /// Replace existing wallet labels with the imported ones if they differ
+        #[clap(long, alias = "replace")]
+        overwrite: bool,
+
+        /// Name of a file to read labels from
+        file: PathBuf,
+    },
+
+    /// Export wallet history as CSV for accounting
+    #[display("export-history")]
+    ExportHistory {
+        /// Export only transactions mined at or after the block height
+        #[clap(long, value_name = "HEIGHT")]
+        since: Option<u32>,
+
+        /// Export only transactions mined at or before the block height; excludes unconfirmed
+        /// transactions
+        #[clap(long, value_name = "HEIGHT")]
+        until: Option<u32>,
+
+        /// Export only transactions mined at or after the date (YYYY-MM-DD, UTC)
+        #[clap(long, value_name = "DATE", value_parser = export::parse_date)]
+        from: Option<u64>,
+
+        /// Export only transactions mined before the end of the date (YYYY-MM-DD, UTC); excludes
+        /// unconfirmed transactions
+        #[clap(long, value_name = "DATE", value_parser = export::parse_date)]
+        to: Option<u64>,
+
+        /// Name of a CSV file to save history to
+        file: PathBuf,
+    },
+
+    /// Export wallet descriptors as a request to Bitcoin Core `importdescriptors` RPC
+    #[display("export-core")]
+    ExportCore {
+        /// Time to rescan the blockchain from: `now`, UNIX timestamp or date (YYYY-MM-DD). If
+        /// not given, defaults to the time of the first wallet transaction
+        #[clap(long, value_name = "TIME")]
+        rescan_from: Option<CoreTimestamp>,
+
+        /// Name of a file to save the request to. If not given, prints it to STDOUT
+        file: Option<PathBuf>,
+    },
+
+    /// Create a watch-only wallet from the output of Bitcoin Core `listdescriptors` RPC
+    ///
+    /// The active external descriptor and the matching internal one become the receiving and the
+    /// change keychains of the wallet. The first sync scans the wallet up to the end of the range
+    /// watched by Bitcoin Core, and skips the history preceding the descriptor timestamp.
+    #[display("import-core")]
+    ImportCore {
+        /// Script type of the descriptor to import, like `wpkh` or `tr`, if Bitcoin Core lists
+        /// descriptors of multiple types
+        #[clap(short, long)]
+        script: Option<String>,
+
+        /// Make the new wallet default
+        #[clap(long)]
+        default: bool,
+
+        /// Overwrite an existing wallet with the same name
+        #[clap(short, long)]
+        force: bool,
+
+        /// The name for the new wallet
+        name: Ident,
+
+        /// JSON file with the output of `listdescriptors` RPC
+        file: PathBuf,
+    },
+
+    /// Create a watch-only wallet from an Electrum wallet file
+    ///
+    /// The wallet keystore is converted into the equivalent descriptor; SLIP-132 extended keys
+    /// (`zpub`, `Zpub` etc.) are converted into `xpub` form. Encrypted wallet files are not
+    /// supported.
+    #[display("import-electrum")]
+    ImportElectrum {
+        /// Make the new wallet default
+        #[clap(long)]
+        default: bool,
+
+        /// Overwrite an existing wallet with the same name
+        #[clap(short, long)]
+        force: bool,
+
+        /// The name for the new wallet
+        name: Ident,
+
+        /// Electrum wallet file
+        file: PathBuf,
+    },
+
+    /// Print general wallet information, including the chain tip the wallet was last
+    /// synchronized with
+    #[display("info")]
+    Info,
+
+    /// Print master key fingerprint, derivation origin and account-level xpub for each key of
+    /// the wallet descriptor, for setting up multisig or watch-only wallets elsewhere
+    #[display("xpub")]
+    Xpub,
+}
+
+/// Options of the coin selection shared by [`BpCommand::Construct`] and [`BpCommand::Estimate`].
+#[derive(Args, Clone, PartialEq, Eq, Debug)]
+pub struct SelectOpts {
+    /// Fee rate in satoshis per virtual byte, used to compute the fee from the estimated size
+    /// of the signed transaction
+    ///
+    /// Instead of a number, a confirmation target may be given as `fast`, `medium`, `slow` or
+    /// `<N>blocks`, in which case the fee rate is estimated by the indexer.
+    #[clap(long, conflicts_with = "fee")]
+    pub fee_rate: Option<FeeTarget>,
+
+    /// Coin selection strategy
+    #[clap(long, value_enum, default_value_t = Strategy::LargestFirst)]
+    pub coin_select: Strategy,
+
+    /// Maximal amount, in satoshis, which the `branch-and-bound` coin selection may leave to
+    /// miners in order to avoid a change output
+    ///
+    /// Defaults to the cost of creating and spending the change output.
+    #[clap(long, value_name = "SATS")]
+    pub changeless_window: Option<Sats>,
+
+    /// What to do with the change below the dust limit of the change output: leave it to
+    /// miners as a part of the fee, or add more coins to make the change output worth it
+    #[clap(long, value_enum, default_value_t = DustPolicy::Burn)]
+    pub dust_policy: DustPolicy,
+
+    /// Spend at least this number of coins, consolidating the coins above the required
+    /// amount into the change
+    #[clap(long, value_name = "COUNT")]
+    pub min_inputs: Option<usize>,
+
+    /// Fail if the payment can't be funded with at most this number of coins
+    #[clap(long, value_name = "COUNT")]
+    pub max_inputs: Option<usize>,
+
+    /// Spend only outputs of the confirmed transactions
+    #[clap(long)]
+    pub confirmed_only: bool,
+}
+
+impl SelectOpts {
+    /// Composes spending parameters with the given `fee` out of the coin selection options.
+    pub fn spend_params(&self, fee: FeePolicy) -> SpendParams {
+        SpendParams {
+            confirmed_only: self.confirmed_only,
+            changeless_window: self.changeless_window,
+            dust_policy: self.dust_policy,
+            min_inputs: self.min_inputs,
+            max_inputs: self.max_inputs,
+            ..SpendParams::with(fee)
+        }
+    }
+}
+
+/// Transaction given to [`BpCommand::Tx`] either by its id or in the raw hex-encoded form.
+#[derive(Clone, Eq, PartialEq, Debug, Display)]
+#[display(inner)]
+pub enum TxRef {
+    Txid(Txid),
+    Raw(Tx),
+}
+
+impl FromStr for TxRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Raw transactions are always longer than 32 bytes
+        if s.len() == 64 {
+            Txid::from_str(s).map(TxRef::Txid).map_err(|e| e.to_string())
+        } else {
+            Tx::from_str(s).map(TxRef::Raw).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Address given to [`BpCommand::SignMessage`] either directly or by its derivation index on the
+/// default keychain.
+#[derive(Clone, Eq, PartialEq, Debug, Display)]
+#[display(inner)]
+pub enum AddrRef {
+    Address(Address),
+    Index(NormalIndex),
+}
+
+impl FromStr for AddrRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match NormalIndex::from_str(s) {
+            Ok(index) => Ok(AddrRef::Index(index)),
+            Err(_) => Address::from_str(s).map(AddrRef::Address).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Parses payment beneficiary given either as `<address>:<sats>` or as `<sats>@<address>`.
+fn parse_beneficiary(s: &str) -> Result<Beneficiary, String> {
+    let res = match s.rsplit_once(':') {
+        Some((address, amount)) if !s.contains('@') => {
+            Beneficiary::from_str(&format!("{amount}@{address}"))
+        }
+        _ => Beneficiary::from_str(s),
+    };
+    res.map_err(|err| err.to_string())
+}
+
+/// Outputs spent by the inputs of the `tx`, looked up in the previous transactions provided by
+/// `prev_tx`, which is called once per transaction. Outputs spent by coinbase inputs or belonging
+/// to transactions which are not found are `None`.
+fn spent_outputs<E>(
+    tx: &Tx,
+    mut prev_tx: impl FnMut(Txid) -> Result<Option<Tx>, E>,
+) -> Result<Vec<Option<TxOut>>, E> {
+    let mut prev_txs = HashMap::<Txid, Option<Tx>>::new();
+    let mut spent = Vec::with_capacity(tx.inputs.len());
+    for input in &tx.inputs {
+        let prevout = input.prev_output;
+        if prevout.txid.is_coinbase() {
+            spent.push(None);
+            continue;
+        }
+        if let Entry::Vacant(entry) = prev_txs.entry(prevout.txid) {
+            entry.insert(prev_tx(prevout.txid)?);
+        }
+        let prev_out = prev_txs[&prevout.txid]
+            .as_ref()
+            .and_then(|tx| tx.outputs.get(prevout.vout.into_usize()));
+        spent.push(prev_out.cloned());
+    }
+    Ok(spent)
+}
+
+/// Fee paid by the `tx` spending the `spent` outputs. The fee is unknown for coinbase
+/// transactions, which pay no fee, and if some of the spent outputs are unknown.
+fn tx_fee(tx: &Tx, spent: &[Option<TxOut>]) -> Option<Sats> {
+    if tx.inputs.iter().any(|input| input.prev_output.txid.is_coinbase()) {
+        return None;
+    }
+    let input_total = spent.iter().try_fold(Sats::ZERO, |total, out| {
+        out.as_ref().and_then(|out| total.checked_add(out.value))
+    })?;
+    let output_total = tx.outputs.iter().map(|out| out.value).sum::<Sats>();
+    input_total.checked_sub(output_total)
+}
+
+/// Details of a wallet descriptor key printed by [`BpCommand::Xpub`].
+#[derive(Clone, Eq, PartialEq, Debug, serde::Serialize)]
+#[serde(crate = "serde_crate", rename_all = "camelCase")]
+struct KeyInfo {
+    /// Fingerprint of the master key.
+    fingerprint: String,
+    /// Derivation of the account key from the master key.
+    origin: String,
+    /// Account-level extended public key.
+    xpub: String,
+    /// Account key together with its origin, as it is given in the descriptor.
+    descriptor_key: String,
+}
+
+impl KeyInfo {
+    fn with(xpub: &XpubAccount) -> Self {
+        let origin = xpub.origin();
+        KeyInfo {
+            fingerprint: origin.master_fp().to_string(),
+            origin: origin.to_string(),
+            xpub: xpub.xpub().to_string(),
+            descriptor_key: xpub.to_string(),
+        }
+    }
+}
+
+/// Order of the UTXOs listed by [`BpCommand::Utxos`].
+#[derive(ValueEnum, Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Display)]
+pub enum UtxoSort {
+    /// By the height of the transaction, listing unconfirmed outputs first
+    #[default]
+    #[display("height")]
+    Height,
+
+    /// By the amount, listing the largest outputs first
+    #[display("amount")]
+    Amount,
 }
 
 #[derive(Debug, Display, Error, From)]
@@ -188,99 +900,400 @@ pub enum ExecError {
     #[from]
     Store(PersistenceError),
 
+    #[from]
+    WalletDir(WalletDirError),
+
     #[from]
     ConstructPsbt(ConstructionError),
 
+    #[from]
+    BuildTx(BuildTxError),
+
+    #[from]
+    BumpFee(BumpFeeError),
+
+    #[from]
+    Cpfp(CpfpError),
+
+    #[from]
+    Broadcast(BroadcastError),
+
+    #[from]
+    NonWallet(NonWalletItem),
+
     #[from]
     DecodePsbt(psbt::DecodeError),
 
     #[from]
     Unfinalized(UnfinalizedInputs),
 
+    #[from]
+    Combine(CombineError),
+
+    #[from]
+    Checksum(ChecksumError),
+
+    #[from]
+    InvalidConfig(ConfigIssues),
+
+    #[from]
+    Invoice(InvoiceError),
+
+    #[from]
+    Batch(BatchError),
+
+    #[from]
+    CoreImport(CoreImportError),
+
+    #[from]
+    ElectrumImport(ElectrumImportError),
+
+    #[from]
+    DescriptorArg(DescriptorArgError),
+
+    #[from]
+    Bip322(Bip322Error),
+
+    #[from]
+    Crypt(CryptError),
+
+    #[cfg(feature = "hot")]
+    #[from]
+    #[from(psbt::SignError)]
+    Signer(crate::hot::DataError),
+
+    #[cfg(feature = "hot")]
+    #[from]
+    SoftSign(crate::hot::SoftSignError),
+
     /// indexer failed with {0}
     #[from]
+    #[cfg_attr(feature = "bitcoind", from(crate::indexers::bitcoind::BitcoindError))]
     #[cfg_attr(feature = "electrum", from(electrum::Error))]
     #[cfg_attr(feature = "electrum", from(crate::indexers::electrum::ElectrumError))]
     #[cfg_attr(feature = "esplora", from(esplora::Error))]
     #[display(doc_comments)]
     Indexer(AnyIndexerError),
+
+    /// indexer server {0} is a Tor onion service; please specify a SOCKS5 proxy with --proxy
+    /// argument to connect to it.
+    #[display(doc_comments)]
+    OnionWithoutProxy(String),
+
+    /// SOCKS5 proxy {0} is not reachable. Details: {1}
+    #[display(doc_comments)]
+    ProxyUnreachable(String, IoError),
+
+    /// the operation requires access to a blockchain indexer, which is prohibited by --offline
+    /// argument.
+    #[display(doc_comments)]
+    OfflineRequired,
+
+    /// wallet descriptor is not specified; please use one of the descriptor arguments.
+    #[display(doc_comments)]
+    NoDescriptor,
+
+    /// wallet named '{0}' already exists; use --force to overwrite it.
+    #[display(doc_comments)]
+    WalletExists(String),
+
+    /// wallet descriptor contains extended keys which can't be used on {0}.
+    #[display(doc_comments)]
+    NetworkMismatch(Network),
+
+    /// custom signet has no public indexers; please specify the indexer server URL explicitly.
+    #[display(doc_comments)]
+    CustomSignetIndexer,
+
+    /// Bitcoin Core node runs signet with network magic {found}, while signet {expected} is
+    /// selected; use `--signet-magic` argument to select the signet of the node.
+    #[display(doc_comments)]
+    SignetMismatch { expected: String, found: String },
+
+    /// transaction fee is not specified and the wallet settings do not provide a default fee.
+    #[display(doc_comments)]
+    NoFee,
+
+    /// keychain {0} is not present in the wallet descriptor.
+    #[display(doc_comments)]
+    UnknownKeychain(Keychain),
+
+    /// transaction {0} is not known to the indexer.
+    #[display(doc_comments)]
+    UnknownTx(Txid),
+
+    /// address {0} doesn't belong to the wallet.
+    #[display(doc_comments)]
+    UnknownAddress(Address),
+
+    /// wallet output script {0:x} has no address representation.
+    #[display(doc_comments)]
+    NonStandardScript(ScriptPubkey),
+
+    /// file {0} contains neither a transaction nor a PSBT.
+    #[display(doc_comments)]
+    InvalidTxFile(String),
+
+    /// invalid BIP-329 label record at line {0}: {1}
+    #[display(doc_comments)]
+    InvalidLabel(usize, String),
+
+    /// the wallet is watch-only and has no signing keys; attach a signing account with
+    /// `set-signer` command first.
+    #[display(doc_comments)]
+    WatchOnly,
+
+    /// signing account {0} doesn't hold the private key for any of the wallet descriptor keys.
+    #[display(doc_comments)]
+    ForeignAccount(String),
+
+    /// invalid wallet passphrase.
+    #[display(doc_comments)]
+    WrongPassphrase,
+
+    /// the repeated passphrase doesn't match.
+    #[display(doc_comments)]
+    PassphraseMismatch,
+
+    /// the command requires a wallet from the data directory, while the wallet descriptor is
+    /// given in the command line.
+    #[display(doc_comments)]
+    NotStored,
+}
+
+impl From<LoadError> for ExecError {
+    fn from(err: LoadError) -> Self {
+        match err {
+            LoadError::Crypt(CryptError::WrongPassphrase) => ExecError::WrongPassphrase,
+            LoadError::Crypt(err) => err.into(),
+            LoadError::Persistence(err) => err.into(),
+        }
+    }
 }
 
 impl<O: DescriptorOpts> Exec for Args<Command, O> {
     type Error = ExecError;
+    type Output = ExecOutput;
     const CONF_FILE_NAME: &'static str = "bp.toml";
 
-    fn exec(self, mut config: Config, conf_filename: &'static str) -> Result<(), Self::Error> {
+    fn exec(
+        self,
+        mut config: Config,
+        conf_filename: &'static str,
+    ) -> Result<ExecOutput, Self::Error> {
+        let mut out = ExecOutput::default();
         match &self.command {
             Command::List => {
                 let dir = self.general.base_dir();
-                let Ok(dir) = fs::read_dir(dir).inspect_err(|err| {
+                let Ok(list) = list_wallets::<XpubDerivable, O::Descr>(&dir).inspect_err(|err| {
                     error!("Error reading wallet directory: {err:?}");
                     eprintln!("System directory is not initialized");
-                    println!("no wallets found");
                 }) else {
-                    return Ok(());
+                    out.data = serde_json::json!({ "wallets": [], "corrupt": [] });
+                    out.line("no wallets found");
+                    return Ok(out);
                 };
-                println!("Known wallets:");
-                let mut count = 0usize;
-                for wallet in dir {
-                    let Ok(entry) = wallet else {
-                        continue;
-                    };
-                    let Ok(meta) = entry.metadata() else {
-                        continue;
-                    };
-                    if !meta.is_dir() {
-                        continue;
+                let wallets = list.wallets.iter().map(|info| {
+                    serde_json::json!({
+                        "name": info.name,
+                        "default": config.default_wallet == info.name,
+                        "type": info.descriptor_type(),
+                        "network": info.network,
+                        "lastSync": info.last_sync,
+                        "descriptor": info.descriptor,
+                    })
+                });
+                let skipped = list.skipped.iter().map(
+                    |skipped| serde_json::json!({ "name": skipped.name, "reason": skipped.reason }),
+                );
+                out.data = serde_json::json!({
+                    "wallets": wallets.collect::<Vec<_>>(),
+                    "corrupt": skipped.collect::<Vec<_>>(),
+                });
+                out.line("Known wallets:");
+                for info in &list.wallets {
+                    let last_sync = info
+                        .last_sync
+                        .map(|height| format!("synced to {height}"))
+                        .unwrap_or_else(|| s!("never synced"));
+                    out.line(format_args!(
+                        "{}{}{}\t{}\t{last_sync}\t{}",
+                        info.name,
+                        if config.default_wallet == info.name { "\t[default]\t" } else { "\t\t" },
+                        info.descriptor_type(),
+                        info.network,
+                        info.descriptor
+                    ));
+                }
+                if list.wallets.is_empty() {
+                    out.line("no wallets found");
+                }
+                if !list.skipped.is_empty() {
+                    out.line("\nCorrupt/unreadable:");
+                }
+                for skipped in &list.skipped {
+                    error!("Error loading wallet {}: {}", skipped.path.display(), skipped.reason);
+                    out.line(format_args!("{}\t\t{}", skipped.name, skipped.reason));
+                }
+            }
+            Command::Migrate { dry_run, from } => {
+                let mut legacy = self.general.clone();
+                legacy.data_dir = from.clone();
+                legacy.process();
+                let migrated =
+                    migrate_wallets(&legacy.base_dir(), &self.general.base_dir(), *dry_run)?;
+                let action = if *dry_run { "would be moved" } else { "moved" };
+                for wallet in &migrated {
+                    out.line(format_args!(
+                        "{}\t{action} from {} to {}",
+                        wallet.name,
+                        wallet.from.display(),
+                        wallet.to.display()
+                    ));
+                    if wallet.encrypted {
+                        eprintln!(
+                            "Warning: wallet {} is encrypted, so its signing account path was not \
+                             updated; re-attach the signing account with `set-signer` command if \
+                             it was stored in the migrated directory",
+                            wallet.name
+                        );
                     }
-                    count += 1;
-                    let name = entry.file_name().into_string().expect("invalid directory name");
-                    print!(
-                        "{name}{}",
-                        if config.default_wallet == name { "\t[default]\t" } else { "\t\t" }
-                    );
-                    let provider = FsTextStore::new(entry.path().clone())?;
-                    let wallet =
-                        match Wallet::<XpubDerivable, O::Descr, WalletCache<Layer2Empty>>::load(
-                            provider, true,
-                        ) {
-                            Err(err) => {
-                                error!("Error loading wallet descriptor: {err}");
-                                println!("# broken wallet descriptor");
-                                continue;
-                            }
-                            Ok(wallet) => wallet,
-                        };
-                    println!("\t{}", wallet.descriptor());
                 }
-                if count == 0 {
-                    println!("no wallets found");
+                if migrated.is_empty() {
+                    out.line("no wallets to migrate");
                 }
+                let wallets = migrated.iter().map(|wallet| {
+                    serde_json::json!({
+                        "name": wallet.name,
+                        "from": wallet.from,
+                        "to": wallet.to,
+                        "encrypted": wallet.encrypted,
+                    })
+                });
+                out.data = serde_json::json!({
+                    "dryRun": dry_run,
+                    "wallets": wallets.collect::<Vec<_>>(),
+                });
             }
-            Command::Default { default } => {
+            Command::Default { default, resolver } => {
                 if let Some(default) = default {
                     config.default_wallet = default.to_string();
+                }
+                if *resolver {
+                    if !self.resolver.is_some() {
+                        eprintln!(
+                            "Error: no blockchain indexer specified; use either --esplora \
+                             --mempool --electrum or --bitcoind argument"
+                        );
+                        exit(1);
+                    }
+                    config.resolver = self.resolver.clone();
+                }
+                if default.is_some() || *resolver {
                     config.store(&self.conf_path(conf_filename));
-                } else {
-                    println!("Default wallet is '{}'", config.default_wallet);
+                }
+                out.data = serde_json::json!({
+                    "defaultWallet": config.default_wallet,
+                    "resolver": config.resolver,
+                });
+                if default.is_none() && !*resolver {
+                    out.line(format_args!("Default wallet is '{}'", config.default_wallet));
+                    match &config.resolver {
+                        ResolverOpt {
+                            electrum: Some(url),
+                            ..
+                        } => out.line(format_args!("Default indexer is electrum server {url}")),
+                        ResolverOpt {
+                            esplora: Some(url),
+                            ..
+                        } => out.line(format_args!("Default indexer is esplora server {url}")),
+                        ResolverOpt {
+                            mempool: Some(url),
+                            ..
+                        } => out.line(format_args!("Default indexer is mempool server {url}")),
+                        ResolverOpt {
+                            bitcoind: Some(url),
+                            ..
+                        } => out.line(format_args!("Default indexer is Bitcoin Core node {url}")),
+                        _ => out.line("No default indexer is set"),
+                    }
                 }
             }
-            Command::Create { name } => {
-                if !self.wallet.descriptor_opts.is_some() {
-                    eprintln!("Error: you must provide an argument specifying wallet descriptor");
-                    exit(1);
+            Command::Config => {
+                let effective = self.effective_config(&config);
+                let issues = effective.validate(&self.general.base_dir());
+                if !issues.is_empty() {
+                    return Err(ConfigIssues(issues).into());
                 }
-                print!("Saving the wallet as '{name}' ... ");
-                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
-                let name = name.to_string();
-                let provider = FsTextStore::new(self.general.wallet_dir(&name))?;
-                wallet.make_persistent(provider, true)?;
-                wallet.set_name(name);
-                if let Err(err) = wallet.store() {
-                    println!("error: {err}");
-                } else {
-                    println!("success");
+                let resolver = &effective.resolver;
+                let indexer = [
+                    ("esplora", &resolver.esplora),
+                    ("electrum", &resolver.electrum),
+                    ("mempool", &resolver.mempool),
+                    ("bitcoind", &resolver.bitcoind),
+                ]
+                .into_iter()
+                .find_map(|(name, url)| url.as_ref().map(|url| format!("{name} {url}")))
+                .unwrap_or_else(|| s!("none"));
+                out.line(format_args!("Config file: {}", self.conf_path(conf_filename).display()));
+                out.line(format_args!(
+                    "Data directory: {} ({})",
+                    effective.data_dir.display(),
+                    effective.data_dir_source
+                ));
+                out.line(format_args!("Network: {}", effective.network));
+                out.line(format_args!(
+                    "Default wallet: {} ({})",
+                    effective.default_wallet, effective.default_wallet_source
+                ));
+                match &effective.wallet_dir {
+                    Some(dir) => out.line(format_args!("Wallet directory: {}", dir.display())),
+                    None => out.line("Wallet: descriptor from the command line"),
+                }
+                out.line(format_args!("Indexer: {indexer} ({})", effective.resolver_source));
+                out.line(format_args!("Proxy: {}", resolver.proxy.as_deref().unwrap_or("none")));
+                match effective.gap_limit {
+                    Some(limit) => out.line(format_args!("Gap limit: {limit}")),
+                    None => out.line("Gap limit: wallet default"),
+                }
+                match effective.fee {
+                    Some(fee) => out.line(format_args!("Fee: {fee}")),
+                    None => out.line("Fee: none"),
                 }
+                out.data = serde_json::json!(effective);
+            }
+            Command::Create {
+                name,
+                default,
+                force,
+                encrypt,
+            } => {
+                let wallet =
+                    self.create_wallet::<O::Descr>(name, &mut config, *default, *force, *encrypt)?;
+                eprintln!("Wallet is saved as '{name}'");
+                if *default {
+                    config.store(&self.conf_path(conf_filename));
+                }
+                let addrs = wallet.addresses(wallet.default_keychain()).take(3);
+                let addrs = addrs.collect::<Vec<_>>();
+                out.line("\nTerm.\tAddress");
+                for derived_addr in &addrs {
+                    out.line(format_args!("{}\t{}", derived_addr.terminal, derived_addr.addr));
+                }
+                out.data = serde_json::json!({ "name": name.to_string(), "addresses": addrs });
+            }
+            Command::Encrypt {
+                new_passphrase: passphrase,
+            } => {
+                let mut store = self.wallet_store(&config)?;
+                let key = WalletKey::new(&new_passphrase(passphrase.as_deref())?);
+                store.rekey(Some(key))?;
+                out.line("Wallet files are encrypted with the new passphrase");
+            }
+            Command::Decrypt => {
+                let mut store = self.wallet_store(&config)?;
+                store.rekey(None)?;
+                out.line("Wallet files are stored in plaintext");
             }
             Command::Address {
                 change,
@@ -302,14 +1315,81 @@ impl<O: DescriptorOpts> Exec for Args<Command, O> {
                     );
                     exit(1);
                 }
-                let index =
-                    index.unwrap_or_else(|| wallet.next_derivation_index(keychain, !*no_shift));
-                println!("\nTerm.\tAddress");
+                let index = index.unwrap_or_else(|| {
+                    let index = wallet.next_derivation_index(keychain, !*no_shift);
+                    if !*no_shift {
+                        for _ in 1..*no {
+                            wallet.next_derivation_index(keychain, true);
+                        }
+                    }
+                    index
+                });
+                let mut addrs = vec![];
+                out.line("\nTerm.\tAddress\tDerivation");
                 for derived_addr in
                     wallet.addresses(keychain).skip(index.index() as usize).take(*no as usize)
                 {
-                    println!("{}\t{}", derived_addr.terminal, derived_addr.addr);
+                    let terminal = derived_addr.terminal;
+                    let paths = wallet.derivation_paths(terminal);
+                    out.line(format_args!(
+                        "{terminal}\t{}\t{}",
+                        derived_addr.addr,
+                        paths.join(" ")
+                    ));
+                    addrs.push(serde_json::json!({
+                        "terminal": terminal,
+                        "address": derived_addr.addr,
+                        "derivation": paths,
+                    }));
                 }
+                out.data = serde_json::json!(addrs);
+            }
+            Command::Verify {
+                search_depth,
+                address,
+            } => {
+                let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                match wallet.find_address(address, *search_depth) {
+                    Some(derived) => {
+                        let terminal = derived.terminal;
+                        let paths = wallet.derivation_paths(terminal);
+                        out.line(format_args!(
+                            "Address {address} belongs to the wallet: keychain {}, index {}",
+                            terminal.keychain, terminal.index
+                        ));
+                        out.line(format_args!("Derivation: {}", paths.join(" ")));
+                        out.data = serde_json::json!({
+                            "address": address,
+                            "mine": true,
+                            "keychain": terminal.keychain,
+                            "index": terminal.index,
+                            "derivation": paths,
+                        });
+                    }
+                    None => {
+                        out.line(format_args!("Address {address} is not mine"));
+                        out.data = serde_json::json!({ "address": address, "mine": false });
+                    }
+                }
+            }
+            Command::Combine { output, psbts } => {
+                let psbts =
+                    psbts.iter().map(|path| psbt_read(path)).collect::<Result<Vec<_>, _>>()?;
+                let psbt = finalize::combine(psbts)?;
+                let signatures = psbt.inputs().map(finalize::signature_count).sum::<usize>();
+                // Multisig inputs lack signatures until the wallet threshold is reached
+                let required = if self.has_wallet(&config) {
+                    O::required_sigs(self.bp_wallet::<O::Descr>(&config)?.descriptor())
+                } else {
+                    finalize::SINGLE_SIG
+                };
+                eprintln!(
+                    "Combined PSBT has {signatures} signatures, {} of {} inputs remain unsigned",
+                    unsigned_inputs(&psbt, required),
+                    psbt.inputs().count()
+                );
+                psbt_write_or_print(&psbt, output.as_deref(), &mut out)?;
+                out.data["signatures"] = serde_json::json!(signatures);
             }
             Command::Finalize {
                 publish,
@@ -321,11 +1401,11 @@ impl<O: DescriptorOpts> Exec for Args<Command, O> {
                     eprintln!("The PSBT is already finalized");
                 } else {
                     let wallet = self.bp_wallet::<O::Descr>(&config)?;
-                    psbt_finalize(&mut psbt, wallet.descriptor())?;
+                    psbt_finalize::<O>(&mut psbt, wallet.descriptor())?;
                 }
 
                 psbt_write(&psbt, psbt_path)?;
-                if let Ok(tx) = psbt_extract(&psbt, *publish, tx.as_deref()) {
+                if let Ok(tx) = psbt_extract(&psbt, *publish, tx.as_deref(), &mut out) {
                     if *publish {
                         let indexer = self.indexer()?;
                         eprint!("Publishing transaction via {} ... ", indexer.name());
@@ -342,10 +1422,10 @@ impl<O: DescriptorOpts> Exec for Args<Command, O> {
                 let mut psbt = psbt_read(psbt_path)?;
                 if !psbt.is_finalized() {
                     let wallet = self.bp_wallet::<O::Descr>(&config)?;
-                    psbt_finalize(&mut psbt, wallet.descriptor())?;
+                    psbt_finalize::<O>(&mut psbt, wallet.descriptor())?;
                 }
 
-                if let Ok(tx) = psbt_extract(&psbt, *publish, tx.as_deref()) {
+                if let Ok(tx) = psbt_extract(&psbt, *publish, tx.as_deref(), &mut out) {
                     if *publish {
                         let indexer = self.indexer()?;
                         eprint!("Publishing transaction via {} ... ", indexer.name());
@@ -354,125 +1434,306 @@ impl<O: DescriptorOpts> Exec for Args<Command, O> {
                     }
                 }
             }
+            Command::Broadcast { dry_run, tx } => {
+                let tx = match tx_read(tx)? {
+                    TxFile::Tx(tx) => tx,
+                    TxFile::Psbt(mut psbt) => {
+                        if !psbt.is_finalized() {
+                            let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                            psbt_finalize::<O>(&mut psbt, wallet.descriptor())?;
+                        }
+                        psbt.extract()?
+                    }
+                };
+                let txid = tx.txid();
+                if *dry_run {
+                    out.line(format_args!("Transaction {txid} is valid; not submitted (dry run)"));
+                } else {
+                    let indexer = self.indexer()?;
+                    // The wallet, if there is one, registers the transaction as unconfirmed; a
+                    // wallet which fails to load is reported rather than silently left stale
+                    let mut wallet = self
+                        .has_wallet(&config)
+                        .then(|| self.bp_wallet::<O::Descr>(&config))
+                        .transpose()?;
+                    eprint!("Publishing transaction via {} ... ", indexer.name());
+                    let res = match &mut wallet {
+                        Some(wallet) => wallet.broadcast(&indexer, &tx).map(|_| ()),
+                        None => match indexer.broadcast(&tx) {
+                            Err(err) => BroadcastError::classify(err).map_or(Ok(()), Err),
+                            Ok(()) => Ok(()),
+                        },
+                    };
+                    if res.is_err() {
+                        eprintln!("failed");
+                    }
+                    res?;
+                    eprintln!("success");
+                    out.line(txid);
+                }
+                out.data = serde_json::json!({ "txid": txid, "submitted": !*dry_run });
+            }
         }
 
-        Ok(())
+        Ok(out)
     }
 }
 
 impl<O: DescriptorOpts> Exec for Args<BpCommand, O> {
     type Error = ExecError;
+    type Output = ExecOutput;
     const CONF_FILE_NAME: &'static str = "bp.toml";
 
-    fn exec(mut self, config: Config, conf_filename: &'static str) -> Result<(), Self::Error> {
+    fn exec(
+        self,
+        mut config: Config,
+        conf_filename: &'static str,
+    ) -> Result<ExecOutput, Self::Error> {
+        let mut out = ExecOutput::default();
         match &self.command {
-            BpCommand::General(cmd) => self.translate(cmd).exec(config, conf_filename)?,
-            BpCommand::Balance {
-                addr: false,
-                utxo: false,
-            } => {
-                let runtime = self.bp_wallet::<O::Descr>(&config)?;
-                println!("\nWallet total balance: {} ṩ", runtime.balance());
-            }
+            BpCommand::General(cmd) => out = self.translate(cmd).exec(config, conf_filename)?,
             BpCommand::Balance {
-                addr: true,
-                utxo: false,
+                addr,
+                utxo,
+                min_confirmations,
             } => {
                 let wallet = self.bp_wallet::<O::Descr>(&config)?;
-                println!("\nTerm.\t{:62}\t# used\tVol., ṩ\tBalance, ṩ", "Address");
-                for info in wallet.address_balance() {
-                    let WalletAddr {
-                        addr,
-                        terminal,
-                        used,
-                        volume,
-                        balance,
-                    } = info;
-                    println!("{terminal}\t{:62}\t{used}\t{volume}\t{balance}", addr.to_string());
-                }
-                self.command = BpCommand::Balance {
-                    addr: false,
-                    utxo: false,
-                };
-                self.sync = false;
-                self.exec(config, conf_filename)?;
+                let fmt = self.general.amount_format();
+                let unit = fmt.symbol();
+                let breakdown = wallet.balance_breakdown(*min_confirmations);
+                out.data = serde_json::json!({
+                    "balance": wallet.balance(),
+                    "keychains": breakdown,
+                });
+                match (addr, utxo) {
+                    (false, false) => {}
+                    (true, false) => {
+                        let addrs = wallet.address_balance().collect::<Vec<_>>();
+                        out.line(format_args!(
+                            "\nTerm.\t{:62}\t# used\tVol., {unit}\tBalance, {unit}",
+                            "Address"
+                        ));
+                        for info in &addrs {
+                            let WalletAddr {
+                                addr,
+                                terminal,
+                                used,
+                                volume,
+                                balance,
+                            } = info;
+                            out.line(format_args!(
+                                "{terminal}\t{:62}\t{used}\t{}\t{}",
+                                addr.to_string(),
+                                fmt.sats(*volume),
+                                fmt.sats(*balance)
+                            ));
+                        }
+                        out.data["addresses"] = serde_json::json!(addrs);
+                    }
+                    (false, true) => {
+                        let coins = wallet.coins().collect::<Vec<_>>();
+                        out.line(format_args!("Balance of {}", wallet.descriptor()));
+                        out.line(format_args!(
+                            "\nHeight\t{:>12}\t{:68}\tAddress",
+                            format!("Amount, {unit}"),
+                            "Outpoint"
+                        ));
+                        for row in &coins {
+                            out.line(format_args!(
+                                "{}\t{: >12}\t{:68}\t{}",
+                                row.height,
+                                fmt.sats(row.amount),
+                                row.outpoint,
+                                row.address
+                            ));
+                        }
+                        out.data["utxos"] = serde_json::json!(coins);
+                    }
+                    (true, true) => {
+                        let mut addrs = vec![];
+                        out.line(format_args!("Balance of {}", wallet.descriptor()));
+                        out.line(format_args!(
+                            "\nHeight\t{:>12}\t{:68}",
+                            format!("Amount, {unit}"),
+                            "Outpoint"
+                        ));
+                        for (derived_addr, utxos) in wallet.address_coins() {
+                            out.line(format_args!(
+                                "{}\t{}",
+                                derived_addr.addr, derived_addr.terminal
+                            ));
+                            for row in &utxos {
+                                out.line(format_args!(
+                                    "{}\t{: >12}\t{:68}",
+                                    row.height,
+                                    fmt.sats(row.amount),
+                                    row.outpoint
+                                ));
+                            }
+                            out.line("");
+                            addrs.push(serde_json::json!({
+                                "address": derived_addr.addr,
+                                "terminal": derived_addr.terminal,
+                                "utxos": utxos,
+                            }));
+                        }
+                        out.data["addresses"] = serde_json::json!(addrs);
+                    }
+                }
+                out.line(format_args!(
+                    "\nKeychain\t{:>14}\t{:>16}\t{:>14}\t{:>12}",
+                    format!("Confirmed, {unit}"),
+                    format!("Unconfirmed, {unit}"),
+                    format!("Immature, {unit}"),
+                    format!("Locked, {unit}")
+                ));
+                for (keychain, balance) in breakdown {
+                    out.line(format_args!(
+                        "{keychain}\t\t{:>14}\t{:>16}\t{:>14}\t{:>12}",
+                        fmt.sats(balance.confirmed),
+                        fmt.sats(balance.unconfirmed),
+                        fmt.sats(balance.immature),
+                        fmt.sats(balance.locked)
+                    ));
+                }
+                out.line(format_args!(
+                    "\nWallet total balance: {} {unit}",
+                    fmt.sats(wallet.balance())
+                ));
             }
-            BpCommand::Balance {
-                addr: false,
-                utxo: true,
+            BpCommand::Utxos {
+                min_amount,
+                keychain,
+                confirmed_only,
+                sort,
             } => {
                 let wallet = self.bp_wallet::<O::Descr>(&config)?;
-                println!("Balance of {}", wallet.descriptor());
-                println!("\nHeight\t{:>12}\t{:68}\tAddress", "Amount, ṩ", "Outpoint");
-                for row in wallet.coins() {
-                    println!(
-                        "{}\t{: >12}\t{:68}\t{}",
-                        row.height, row.amount, row.outpoint, row.address
-                    );
+                let mut coins = wallet
+                    .coins()
+                    .filter(|row| min_amount.is_none_or(|min| row.amount >= min))
+                    .filter(|row| {
+                        keychain.is_none_or(|keychain| row.address.terminal.keychain == keychain)
+                    })
+                    .filter(|row| !*confirmed_only || row.height.is_mined())
+                    .collect::<Vec<_>>();
+                match sort {
+                    UtxoSort::Height => coins.sort_by_key(|row| row.height),
+                    UtxoSort::Amount => coins.sort_by_key(|row| cmp::Reverse(row.amount)),
                 }
-                self.command = BpCommand::Balance {
-                    addr: false,
-                    utxo: false,
-                };
-                self.sync = false;
-                self.exec(config, conf_filename)?;
+
+                let fmt = self.general.amount_format();
+                let mut utxos = vec![];
+                out.line(format_args!(
+                    "\nHeight\t{:>12}\t{:68}\t{:62}\tLocked\tDerivation\tLabel",
+                    format!("Amount, {}", fmt.symbol()),
+                    "Outpoint",
+                    "Address"
+                ));
+                for row in coins {
+                    let terminal = row.address.terminal;
+                    let paths = wallet.derivation_paths(terminal);
+                    let label = row.label.as_deref();
+                    let locked = wallet.is_locked(row.outpoint);
+                    let lock_label = wallet.lock_label(row.outpoint);
+                    out.line(format_args!(
+                        "{}\t{: >12}\t{:68}\t{:62}\t{}\t{}\t{}",
+                        row.height,
+                        fmt.sats(row.amount),
+                        row.outpoint,
+                        row.address.addr.to_string(),
+                        match lock_label {
+                            Some(reason) => format!("yes ({reason})"),
+                            None if locked => s!("yes"),
+                            None => s!("no"),
+                        },
+                        paths.join(" "),
+                        label.unwrap_or_default()
+                    ));
+                    utxos.push(serde_json::json!({
+                        "outpoint": row.outpoint,
+                        "amount": row.amount,
+                        "address": row.address.addr,
+                        "keychain": terminal.keychain,
+                        "index": terminal.index,
+                        "derivation": paths,
+                        "height": row.height,
+                        "locked": locked,
+                        "lockLabel": lock_label,
+                        "label": label,
+                    }));
+                }
+                out.data = serde_json::json!(utxos);
             }
-            BpCommand::Balance {
-                addr: true,
-                utxo: true,
+            BpCommand::History {
+                txid,
+                details,
+                since,
+                until,
             } => {
                 let wallet = self.bp_wallet::<O::Descr>(&config)?;
-                println!("Balance of {}", wallet.descriptor());
-                println!("\nHeight\t{:>12}\t{:68}", "Amount, ṩ", "Outpoint");
-                for (derived_addr, utxos) in wallet.address_coins() {
-                    println!("{}\t{}", derived_addr.addr, derived_addr.terminal);
-                    for row in utxos {
-                        println!("{}\t{: >12}\t{:68}", row.height, row.amount, row.outpoint);
-                    }
-                    println!()
-                }
-                self.command = BpCommand::Balance {
-                    addr: false,
-                    utxo: false,
+                let filter = HistoryFilter {
+                    since_height: *since,
+                    until_height: *until,
+                    ..HistoryFilter::default()
                 };
-                self.sync = false;
-                self.exec(config, conf_filename)?;
-            }
-            BpCommand::History { txid, details } => {
-                let wallet = self.bp_wallet::<O::Descr>(&config)?;
-                println!("History of {}", wallet.descriptor());
-                println!(
-                    "\nHeight\t{:<1$}\t    Amount, ṩ\tFee rate, ṩ/vbyte",
+                let rows =
+                    wallet.history().filter(|row| filter.matches_row(row)).collect::<Vec<_>>();
+                let fmt = self.general.amount_format();
+                let unit = fmt.symbol();
+                out.line(format_args!("History of {}", wallet.descriptor()));
+                out.line(format_args!(
+                    "\nHeight\t{:<1$}\t{2:>13}\tFee rate, ṩ/vbyte\tLabel",
                     "Txid",
-                    if *txid { 64 } else { 18 }
-                );
-                let mut rows = wallet.history().collect::<Vec<_>>();
-                rows.sort_by_key(|row| row.height);
-                for row in rows {
-                    println!(
-                        "{}\t{}\t{}{: >12}\t{: >8.2}",
+                    if *txid { 64 } else { 18 },
+                    format!("Amount, {unit}")
+                ));
+                let mut history = vec![];
+                for row in &rows {
+                    let label = row.label.as_deref();
+                    out.line(format_args!(
+                        "{}\t{}\t{}{: >12}\t{: >8.2}\t{}",
                         row.height,
                         if *txid { row.txid.to_string() } else { format!("{:#}", row.txid) },
                         row.operation,
-                        row.amount,
-                        row.fee.sats() as f64 * 4.0 / row.weight as f64
-                    );
+                        fmt.sats(row.amount),
+                        row.fee.sats() as f64 * 4.0 / row.weight as f64,
+                        label.unwrap_or_default()
+                    ));
+                    let mut entry = serde_json::json!({
+                        "txid": row.txid,
+                        "height": match row.height {
+                            TxStatus::Mined(height) => Some(height),
+                            _ => None,
+                        },
+                        "time": row.time,
+                        "operation": row.operation,
+                        "amount": row.net_amount(),
+                        "fee": row.our_fee(),
+                        "label": label,
+                    });
+                    if *details {
+                        entry["own"] = serde_json::json!(row.own);
+                        entry["counterparties"] = serde_json::json!(row.counterparties);
+                    }
+                    history.push(entry);
                     if *details {
                         for (cp, value) in &row.own {
-                            println!(
-                                "\t* {value: >-12}ṩ\t{}\t{cp}",
+                            out.line(format_args!(
+                                "\t* {: >-12}{unit}\t{}\t{cp}",
+                                fmt.signed(*value),
                                 if *value < 0 {
                                     "taken from"
-                                } else if row.operation == OpType::Credit {
+                                } else if row.operation != OpType::Debit {
                                     "moved to  "
                                 } else {
                                     "change    "
                                 }
-                            );
+                            ));
                         }
                         for (cp, value) in &row.counterparties {
-                            println!(
-                                "\t* {value: >-12}ṩ\t{}\t{cp}",
+                            out.line(format_args!(
+                                "\t* {: >-12}{unit}\t{}\t{cp}",
+                                fmt.signed(*value),
                                 if *value > 0 {
                                     "received  "
                                 } else if row.operation == OpType::Credit {
@@ -480,65 +1741,832 @@ impl<O: DescriptorOpts> Exec for Args<BpCommand, O> {
                                 } else {
                                     "paid to   "
                                 }
-                            );
+                            ));
                         }
-                        println!("\t* {: >-12}ṩ\tminer fee", -row.fee.sats_i64());
-                        println!();
+                        out.line(format_args!(
+                            "\t* {: >-12}{unit}\tminer fee",
+                            fmt.signed(-row.fee.sats_i64())
+                        ));
+                        out.line("");
                     }
                 }
+                out.data = serde_json::json!(history);
             }
-            BpCommand::Tx { tx } => {
-                println!(
-                    "{}",
-                    serde_yaml::to_string(&tx).expect("unable to generate YAML representation")
-                );
+            BpCommand::Fees => {
+                let indexer = self.indexer()?;
+                out.line("\nTarget\tFee rate, ṩ/vbyte");
+                if let AnyIndexer::Mempool(client) = &indexer {
+                    let fees = client.recommended_fees()?;
+                    out.line(format_args!("fastest\t{: >8.2}", fees.fastest_fee));
+                    out.line(format_args!("30 min\t{: >8.2}", fees.half_hour_fee));
+                    out.line(format_args!("1 hour\t{: >8.2}", fees.hour_fee));
+                    out.line(format_args!("economy\t{: >8.2}", fees.economy_fee));
+                    out.line(format_args!("minimum\t{: >8.2}", fees.minimum_fee));
+                    out.data = serde_json::json!({
+                        "fastest": fees.fastest_fee,
+                        "halfHour": fees.half_hour_fee,
+                        "hour": fees.hour_fee,
+                        "economy": fees.economy_fee,
+                        "minimum": fees.minimum_fee,
+                    });
+                } else {
+                    // Other indexers provide estimations for confirmation targets
+                    let estimator = self.fee_estimator(&indexer);
+                    let mut data = serde_json::Map::new();
+                    for target in [
+                        fees::FAST_TARGET_BLOCKS,
+                        fees::MEDIUM_TARGET_BLOCKS,
+                        fees::SLOW_TARGET_BLOCKS,
+                    ] {
+                        let rate = estimator.estimate_feerate(target)?;
+                        let name = FeeTarget::Blocks(target).to_string();
+                        out.line(format_args!("{name}\t{rate: >8.2}"));
+                        data.insert(name, serde_json::json!(rate));
+                    }
+                    out.data = serde_json::Value::Object(data);
+                }
+            }
+            BpCommand::Tx { tx: TxRef::Raw(tx) } => out = ExecOutput::document(tx),
+            BpCommand::Tx {
+                tx: TxRef::Txid(txid),
+            } => {
+                let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let indexer = self.indexer()?;
+                let network = AddressNetwork::from(self.general.network);
+                let tx = indexer.transaction(*txid)?.ok_or(ExecError::UnknownTx(*txid))?;
+                let ours = wallet
+                    .address_balance()
+                    .map(|addr| (addr.addr.script_pubkey(), addr.terminal))
+                    .collect::<HashMap<_, _>>();
+                let describe = |script: &ScriptPubkey| {
+                    let addr = Address::with(script, network)
+                        .map(|addr| addr.to_string())
+                        .unwrap_or_else(|_| s!("<non-standard>"));
+                    let paths = ours.get(script).map(|terminal| wallet.derivation_paths(*terminal));
+                    (addr, paths)
+                };
+
+                out.line(format_args!("Transaction {txid}"));
+                let label = wallet.data().tx_annotations.get(txid);
+                if let Some(label) = label {
+                    out.line(format_args!("Label: {label}"));
+                }
+                let status = indexer.status(*txid)?;
+                let confirmations = match status {
+                    TxStatus::Mined(info) => {
+                        let tip = indexer.tip()?;
+                        let depth = tip.height.get().saturating_sub(info.height.get()) + 1;
+                        out.line(format_args!(
+                            "Status: mined at height {} ({depth} confirmations)",
+                            info.height
+                        ));
+                        depth
+                    }
+                    _ => {
+                        out.line(format_args!("Status: {}", status.map(|info| info.height)));
+                        0
+                    }
+                };
+                let size = tx.consensus_serialize().len();
+                let weight = tx.weight_units().to_u32();
+                let vsize = weight.div_ceil(4);
+                out.line(format_args!("Size: {size} bytes, {vsize} vbytes, {weight} WU"));
+
+                let fmt = self.general.amount_format();
+                let unit = fmt.symbol();
+                let spent = spent_outputs(&tx, |txid| indexer.transaction(txid))?;
+                let mut inputs = vec![];
+                out.line(format_args!("\nInputs:\nAmount, {unit}\tOutpoint\tAddress\tDerivation"));
+                for (input, prev_out) in tx.inputs.iter().zip(&spent) {
+                    let prevout = input.prev_output;
+                    // Coinbase inputs don't spend any previous transaction
+                    if prevout.txid.is_coinbase() {
+                        out.line(format_args!("{}\t{prevout}\t<coinbase>\t", fmt.sats(Sats::ZERO)));
+                        inputs.push(serde_json::json!({
+                            "outpoint": prevout,
+                            "amount": Sats::ZERO,
+                            "address": "<coinbase>",
+                            "mine": false,
+                            "derivation": null,
+                        }));
+                        continue;
+                    }
+                    let amount = prev_out.as_ref().map(|out| out.value);
+                    let (addr, paths) = prev_out
+                        .as_ref()
+                        .map(|out| describe(&out.script_pubkey))
+                        .unwrap_or_else(|| (s!("<unknown>"), None));
+                    out.line(format_args!(
+                        "{}\t{prevout}\t{addr}\t{}",
+                        amount.map(|a| fmt.sats(a)).unwrap_or_else(|| s!("?")),
+                        paths.as_ref().map(|p| p.join(" ")).unwrap_or_default()
+                    ));
+                    inputs.push(serde_json::json!({
+                        "outpoint": prevout,
+                        "amount": amount,
+                        "address": addr,
+                        "mine": paths.is_some(),
+                        "derivation": paths,
+                    }));
+                }
+
+                let mut outputs = vec![];
+                out.line(format_args!("\nOutputs:\nAmount, {unit}\tVout\tAddress\tDerivation"));
+                for (vout, output) in tx.outputs.iter().enumerate() {
+                    let (addr, paths) = describe(&output.script_pubkey);
+                    out.line(format_args!(
+                        "{}\t{vout}\t{addr}\t{}",
+                        fmt.sats(output.value),
+                        paths.as_ref().map(|p| p.join(" ")).unwrap_or_default()
+                    ));
+                    outputs.push(serde_json::json!({
+                        "vout": vout,
+                        "amount": output.value,
+                        "address": addr,
+                        "mine": paths.is_some(),
+                        "derivation": paths,
+                    }));
+                }
+                let coinbase = tx.inputs.iter().any(|input| input.prev_output.txid.is_coinbase());
+                let fee = tx_fee(&tx, &spent);
+                match fee {
+                    Some(fee) => out.line(format_args!(
+                        "\nFee: {} {unit} ({:.2} ṩ/vbyte)",
+                        fmt.sats(fee),
+                        fee.sats() as f64 / vsize as f64
+                    )),
+                    None if coinbase => out.line("\nFee: none, the transaction is coinbase"),
+                    None => {
+                        out.line("\nFee: unknown, some of the spent transactions are not found")
+                    }
+                }
+                out.data = serde_json::json!({
+                    "txid": txid,
+                    "label": label,
+                    "status": status,
+                    "confirmations": confirmations,
+                    "size": size,
+                    "vsize": vsize,
+                    "weight": weight,
+                    "fee": fee,
+                    "inputs": inputs,
+                    "outputs": outputs,
+                });
             }
             BpCommand::Inspect { psbt } => {
                 let psbt = psbt_read(psbt)?;
-                println!(
-                    "{}",
-                    serde_yaml::to_string(&psbt).expect("unable to generate YAML representation")
-                );
+                out = ExecOutput::document(&psbt);
             }
             BpCommand::Construct {
                 v2,
-                to: beneficiaries,
+                to,
+                uri: invoices,
+                batch,
+                duplicates,
+                allow_duplicate_outputs,
+                bip69,
+                select,
+                change_index,
+                allow_absurd_fee,
+                no_rbf,
+                lock_time,
+                op_return,
                 fee,
                 psbt: psbt_file,
             } => {
-                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
-
-                // Do coin selection
-                let total_amount =
-                    beneficiaries.iter().try_fold(Sats::ZERO, |sats, b| match b.amount {
-                        Payment::Max => Err(()),
-                        Payment::Fixed(s) => sats.checked_add(s).ok_or(()),
-                    });
-                let coins: Vec<_> = match total_amount {
-                    Ok(sats) if sats > Sats::ZERO => {
-                        wallet.coinselect(sats + *fee, coinselect::all).collect()
+                let fee = match select.fee_rate {
+                    Some(target) => FeePolicy::Rate(self.fee_rate(target)?),
+                    None => FeePolicy::Absolute(fee.or(self.settings.fee).ok_or(ExecError::NoFee)?),
+                };
+                let mut beneficiaries = to.clone();
+                for invoice in invoices {
+                    invoice.check_network(self.general.network)?;
+                    beneficiaries.push(invoice.to_beneficiary()?);
+                }
+                if !*allow_duplicate_outputs {
+                    check_duplicate_outputs(&beneficiaries)?;
+                }
+                let batch = match batch {
+                    Some(path) => {
+                        let data = fs::read_to_string(path)?;
+                        let batch = match path.extension().and_then(|ext| ext.to_str()) {
+                            Some("json") => Batch::from_json(&data, *duplicates)?,
+                            _ => Batch::from_csv(&data, *duplicates)?,
+                        };
+                        batch.check_network(self.general.network)?;
+                        Some(batch)
                     }
-                    _ => {
+                    None => None,
+                };
+                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
+                if op_return.is_none()
+                    && batch.is_none()
+                    && !beneficiaries
+                        .iter()
+                        .any(|b| matches!(b.amount, Payment::Fixed(sats) if sats > Sats::ZERO))
+                {
+                    eprintln!(
+                        "Warning: you are not paying to anybody but just aggregating all your \
+                         balances to a single UTXO",
+                    );
+                }
+                let params = SpendParams {
+                    rbf: !*no_rbf,
+                    lock_time: lock_time.map(LockTime::from_consensus_u32),
+                    version: if *v2 { PsbtVer::V2 } else { PsbtVer::V0 },
+                    max_fee_rate: fees::MAX_FEE_RATE,
+                    allow_absurd_fee: *allow_absurd_fee,
+                    op_return: *op_return,
+                    order: if *bip69 { TxOrder::Bip69 } else { TxOrder::AsGiven },
+                    change: change_index.map(ChangePolicy::Fixed).unwrap_or_default(),
+                    ..select.spend_params(fee)
+                };
+                let fmt = self.general.amount_format();
+                let unit = fmt.symbol();
+                let built = match &batch {
+                    Some(batch) => {
                         eprintln!(
-                            "Warning: you are not paying to anybody but just aggregating all your \
-                             balances to a single UTXO",
+                            "Paying {} {unit} to {} recipients in a batch",
+                            fmt.sats(batch.total()?),
+                            batch.len()
                         );
-                        wallet.utxos().map(WalletUtxo::into_outpoint).collect()
+                        wallet.pay_batch(batch, params, &select.coin_select)?
                     }
+                    None => wallet.build_tx(&beneficiaries, params, &select.coin_select)?,
                 };
-
-                // TODO: Support lock time and RBFs
-                let params = TxParams::with(*fee);
-                let (mut psbt, _) =
-                    wallet.construct_psbt(coins, beneficiaries.iter().copied(), params)?;
+                let labels = invoices.iter().filter_map(|invoice| invoice.label.as_deref());
+                let label = labels.collect::<Vec<_>>().join(", ");
+                if !label.is_empty() {
+                    let txid = built.psbt.to_unsigned_tx().txid();
+                    wallet.with_data(|data| {
+                        data.tx_annotations.insert(txid, label);
+                        Ok::<_, ExecError>(())
+                    })?;
+                }
+                let fee = built.meta.fee;
+                eprintln!(
+                    "Transaction fee: {} {unit} ({:.2} ṩ/vbyte for {} vbytes)",
+                    fmt.sats(fee),
+                    built.fee_rate,
+                    built.vsize
+                );
+                match built.strategy {
+                    Some(strategy) => eprintln!("Coins selected by {strategy} strategy"),
+                    None => eprintln!("Spending all available coins"),
+                }
+                let change_vout = built.meta.change.map(|change| change.vout.into_usize());
+                let recipients = built
+                    .psbt
+                    .outputs()
+                    .enumerate()
+                    .filter(|(vout, _)| Some(*vout) != change_vout)
+                    .filter_map(|(_, output)| {
+                        let address = Address::with(&output.script, built.meta.network).ok()?;
+                        Some((address, output.value()))
+                    })
+                    .collect::<Vec<_>>();
+                eprintln!("Recipients:");
+                for (address, amount) in &recipients {
+                    eprintln!("  {address}\t{} {unit}", fmt.sats(*amount));
+                }
+                let change_terminal = built.meta.change.map(|change| change.terminal);
+                if let Some(terminal) = change_terminal {
+                    eprintln!(
+                        "Change: {} {unit} to the address {terminal}",
+                        fmt.sats(built.change)
+                    );
+                } else if built.dropped_change > Sats::ZERO {
+                    eprintln!(
+                        "No change output: {} {unit} of change below the dust limit is added to \
+                         the fee",
+                        fmt.sats(built.dropped_change)
+                    );
+                } else {
+                    eprintln!("No change output");
+                }
+                psbt_write_or_print(&built.psbt, psbt_file.as_deref(), &mut out)?;
+                out.data["fee"] = serde_json::json!(fee);
+                out.data["strategy"] = serde_json::json!(built.strategy.map(|s| s.to_string()));
+                out.data["change"] = serde_json::json!(built.change);
+                out.data["droppedChange"] = serde_json::json!(built.dropped_change);
+                out.data["changeTerminal"] =
+                    serde_json::json!(change_terminal.map(|terminal| terminal.to_string()));
+                out.data["vsize"] = serde_json::json!(built.vsize);
+                out.data["feeRate"] = serde_json::json!(built.fee_rate);
+                let recipients = recipients.iter().map(|(address, amount)| {
+                    serde_json::json!({ "address": address.to_string(), "amount": amount })
+                });
+                out.data["recipients"] = serde_json::json!(recipients.collect::<Vec<_>>());
+            }
+            BpCommand::Estimate {
+                to: beneficiaries,
+                allow_duplicate_outputs,
+                select,
+                fee,
+            } => {
+                let fee = match select.fee_rate {
+                    Some(target) => FeePolicy::Rate(self.fee_rate(target)?),
+                    None => FeePolicy::Absolute(fee.or(self.settings.fee).ok_or(ExecError::NoFee)?),
+                };
+                if !*allow_duplicate_outputs {
+                    check_duplicate_outputs(beneficiaries)?;
+                }
+                let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let params = select.spend_params(fee);
+                let estimate = wallet.estimate_tx(beneficiaries, params, &select.coin_select)?;
+                let fmt = self.general.amount_format();
+                let unit = fmt.symbol();
+                out.line(format!(
+                    "Inputs: {} coins with {} {unit} in total",
+                    estimate.inputs.len(),
+                    fmt.sats(estimate.input_value)
+                ));
+                for outpoint in &estimate.inputs {
+                    out.line(format!("  {outpoint}"));
+                }
+                out.line(format!(
+                    "Fee: {} {unit} ({:.2} ṩ/vbyte for {} vbytes)",
+                    fmt.sats(estimate.fee),
+                    estimate.fee_rate,
+                    estimate.vsize
+                ));
+                if estimate.change > Sats::ZERO {
+                    out.line(format!("Change: {} {unit}", fmt.sats(estimate.change)));
+                } else if estimate.dropped_change > Sats::ZERO {
+                    out.line(format!(
+                        "No change output: {} {unit} of change below the dust limit is added to \
+                         the fee",
+                        fmt.sats(estimate.dropped_change)
+                    ));
+                } else {
+                    out.line("No change output");
+                }
+                out.data = serde_json::json!({
+                    "inputs": estimate.inputs.iter().map(Outpoint::to_string).collect::<Vec<_>>(),
+                    "inputValue": estimate.input_value,
+                    "fee": estimate.fee,
+                    "strategy": estimate.strategy.map(|s| s.to_string()),
+                    "change": estimate.change,
+                    "droppedChange": estimate.dropped_change,
+                    "vsize": estimate.vsize,
+                    "feeRate": estimate.fee_rate,
+                });
+            }
+            BpCommand::Sweep {
+                v2,
+                address,
+                fee_rate,
+                keychain,
+                below,
+                confirmed_only,
+                no_rbf,
+                psbt: psbt_file,
+            } => {
+                let fee = FeePolicy::Rate(self.fee_rate(*fee_rate)?);
+                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let params = SpendParams {
+                    rbf: !*no_rbf,
+                    version: if *v2 { PsbtVer::V2 } else { PsbtVer::V0 },
+                    confirmed_only: *confirmed_only,
+                    ..SpendParams::with(fee)
+                };
+                let filter = SweepFilter {
+                    keychain: *keychain,
+                    below: *below,
+                };
+                let built = wallet.sweep(*address, params, filter)?;
+                let amount = built.psbt.outputs().map(|output| output.value()).sum::<Sats>();
+                let fmt = self.general.amount_format();
+                let unit = fmt.symbol();
+                eprintln!(
+                    "Sweeping {} coins: {} {unit} to {address}, fee {} {unit} ({:.2} ṩ/vbyte for \
+                     {} vbytes)",
+                    built.psbt.inputs().count(),
+                    fmt.sats(amount),
+                    fmt.sats(built.meta.fee),
+                    built.fee_rate,
+                    built.vsize
+                );
+                psbt_write_or_print(&built.psbt, psbt_file.as_deref(), &mut out)?;
+                out.data["amount"] = serde_json::json!(amount);
+                out.data["fee"] = serde_json::json!(built.meta.fee);
+                out.data["vsize"] = serde_json::json!(built.vsize);
+                out.data["feeRate"] = serde_json::json!(built.fee_rate);
+            }
+            BpCommand::Consolidate {
+                v2,
+                fee_rate,
+                below,
+                min_inputs,
+                confirmed_only,
+                psbt: psbt_file,
+            } => {
+                let fee = FeePolicy::Rate(self.fee_rate(*fee_rate)?);
+                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let params = SpendParams {
+                    version: if *v2 { PsbtVer::V2 } else { PsbtVer::V0 },
+                    confirmed_only: *confirmed_only,
+                    ..SpendParams::with(fee)
+                };
+                let filter = SweepFilter {
+                    keychain: None,
+                    below: *below,
+                };
+                let built = wallet.consolidate(params, filter, *min_inputs)?;
+                let inputs = built.psbt.inputs().count();
+                let output = built.psbt.outputs().next().expect("consolidation output");
+                let address = Address::with(&output.script, built.meta.network)
+                    .map_err(|_| ExecError::NonStandardScript(output.script.clone()))?;
+                let fmt = self.general.amount_format();
+                let unit = fmt.symbol();
+                eprintln!(
+                    "Consolidating {inputs} coins into {} {unit} on {address}, fee {} {unit} \
+                     ({:.2} ṩ/vbyte for {} vbytes)",
+                    fmt.sats(output.value()),
+                    fmt.sats(built.meta.fee),
+                    built.fee_rate,
+                    built.vsize
+                );
+                psbt_write_or_print(&built.psbt, psbt_file.as_deref(), &mut out)?;
+                out.data["inputs"] = serde_json::json!(inputs);
+                out.data["amount"] = serde_json::json!(output.value());
+                out.data["address"] = serde_json::json!(address.to_string());
+                out.data["fee"] = serde_json::json!(built.meta.fee);
+                out.data["vsize"] = serde_json::json!(built.vsize);
+                out.data["feeRate"] = serde_json::json!(built.fee_rate);
+            }
+            BpCommand::BumpFee {
+                v2,
+                txid,
+                fee_rate,
+                psbt: psbt_file,
+            } => {
+                let fee_rate = self.fee_rate(*fee_rate)?;
+                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let (mut psbt, meta) = wallet.bump_fee(*txid, fee_rate)?;
                 psbt.version = if *v2 { PsbtVer::V2 } else { PsbtVer::V0 };
-                psbt_write_or_print(&psbt, psbt_file.as_deref())?;
+                let fmt = self.general.amount_format();
+                eprintln!("Replacement transaction fee: {} {}", fmt.sats(meta.fee), fmt.symbol());
+                psbt_write_or_print(&psbt, psbt_file.as_deref(), &mut out)?;
+                out.data["fee"] = serde_json::json!(meta.fee);
+            }
+            BpCommand::Cpfp {
+                v2,
+                txid,
+                fee_rate,
+                psbt: psbt_file,
+            } => {
+                let fee_rate = self.fee_rate(*fee_rate)?;
+                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let mut child = wallet.cpfp(*txid, fee_rate)?;
+                child.psbt.version = if *v2 { PsbtVer::V2 } else { PsbtVer::V0 };
+                let fmt = self.general.amount_format();
+                eprintln!(
+                    "Child transaction fee: {} {}; package fee rate: {:.2} ṩ/vbyte for {} vbytes",
+                    fmt.sats(child.meta.fee),
+                    fmt.symbol(),
+                    child.package_fee_rate,
+                    child.package_vsize
+                );
+                psbt_write_or_print(&child.psbt, psbt_file.as_deref(), &mut out)?;
+                out.data["fee"] = serde_json::json!(child.meta.fee);
+                out.data["packageVsize"] = serde_json::json!(child.package_vsize);
+                out.data["packageFeeRate"] = serde_json::json!(child.package_fee_rate);
+            }
+            #[cfg(feature = "hot")]
+            BpCommand::Sign {
+                no_password,
+                psbt: psbt_file,
+            } => {
+                use bpstd::XprivAccount;
+
+                use crate::hot::{sign_psbt, SecureIo};
+
+                let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let account_file =
+                    wallet.data().signing_account.clone().ok_or(ExecError::WatchOnly)?;
+                let password =
+                    if *no_password { s!("") } else { rpassword::prompt_password("Password: ")? };
+                let account = XprivAccount::read(&account_file, &password)?;
+                let mut psbt = psbt_read(psbt_file)?;
+                let sig_count = sign_psbt(&account, &mut psbt)?.signatures;
+                psbt_write(&psbt, psbt_file)?;
+                let remaining = unsigned_inputs(&psbt, O::required_sigs(wallet.descriptor()));
+                out.line(format_args!(
+                    "Done {sig_count} signatures, {remaining} of {} inputs remain unsigned",
+                    psbt.inputs().count()
+                ));
+                out.data = serde_json::json!({
+                    "signatures": sig_count,
+                    "unsignedInputs": remaining,
+                });
+            }
+            #[cfg(feature = "hot")]
+            BpCommand::SignMessage {
+                no_password,
+                address,
+                message,
+            } => {
+                use bpstd::{KeyOrigin, XprivAccount};
+
+                use crate::hot::SecureIo;
+
+                let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let derived = match address {
+                    AddrRef::Address(addr) => {
+                        wallet.find_address(addr, 0).ok_or(ExecError::UnknownAddress(*addr))?
+                    }
+                    AddrRef::Index(index) => wallet
+                        .addresses_from(wallet.default_keychain(), *index)
+                        .next()
+                        .expect("address iterator is infinite"),
+                };
+                let account_file =
+                    wallet.data().signing_account.clone().ok_or(ExecError::WatchOnly)?;
+                let password =
+                    if *no_password { s!("") } else { rpassword::prompt_password("Password: ")? };
+                let account = XprivAccount::read(&account_file, &password)?;
+                let origin = wallet
+                    .derivation_paths(derived.terminal)
+                    .iter()
+                    .filter_map(|path| KeyOrigin::from_str(path).ok())
+                    .find(|origin| account.origin().is_subset_of(origin))
+                    .ok_or(Bip322Error::KeyMismatch)?;
+                let signature =
+                    bip322::sign_message(&account, &origin, &derived.addr, message.as_bytes())?;
+                out.line(&signature);
+                out.data = serde_json::json!({
+                    "address": derived.addr,
+                    "terminal": derived.terminal,
+                    "signature": signature,
+                });
+            }
+            BpCommand::VerifyMessage {
+                address,
+                message,
+                signature,
+            } => {
+                bip322::verify_message(address, message.as_bytes(), signature)?;
+                out.line(format_args!("Signature of {address} is valid"));
+                out.data = serde_json::json!({ "address": address, "valid": true });
+            }
+            BpCommand::SetSigner {
+                no_password,
+                account,
+            } => {
+                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
+                if let Some(path) = account {
+                    check_signing_account(path, *no_password, wallet.descriptor())?;
+                }
+                wallet.set_signing_account(account.clone());
+                match account {
+                    Some(path) => {
+                        out.line(format_args!("Signing account {} attached", path.display()))
+                    }
+                    None => out.line("Wallet is watch-only now"),
+                }
+                out.data = serde_json::json!({ "watchOnly": account.is_none() });
+            }
+            BpCommand::Lock { label, outpoint } => {
+                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
+                wallet.lock_utxo(*outpoint, label.clone())?;
+                out.line(format_args!("Output {outpoint} is locked"));
+                out.data = serde_json::json!({ "outpoint": outpoint, "locked": true });
+            }
+            BpCommand::Unlock { outpoint } => {
+                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
+                if wallet.unlock_utxo(*outpoint) {
+                    out.line(format_args!("Output {outpoint} is unlocked"));
+                } else {
+                    out.line(format_args!("Output {outpoint} was not locked"));
+                }
+                out.data = serde_json::json!({ "outpoint": outpoint, "locked": false });
+            }
+            BpCommand::ExportLabels { file } => {
+                let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let labels = wallet.data().labels();
+                let mut jsonl = String::new();
+                for label in &labels {
+                    jsonl.push_str(&serde_json::to_string(label).expect("label serialization"));
+                    jsonl.push('\n');
+                }
+                match file {
+                    Some(path) => {
+                        fs::write(path, jsonl)?;
+                        out.line(format_args!(
+                            "{} labels exported to {}",
+                            labels.len(),
+                            path.display()
+                        ));
+                    }
+                    None => out.line(jsonl.trim_end()),
+                }
+                out.data = serde_json::json!(labels);
+            }
+            BpCommand::ImportLabels { overwrite, file } => {
+                let mut labels = vec![];
+                for (no, line) in fs::read_to_string(file)?.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let label = serde_json::from_str::<Label>(line)
+                        .map_err(|err| ExecError::InvalidLabel(no + 1, err.to_string()))?;
+                    labels.push(label);
+                }
+                let mut wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let report = wallet.import_labels(labels, *overwrite);
+                out.line(format_args!(
+                    "{} labels imported, {} already known, {} skipped, {} conflicting",
+                    report.added,
+                    report.unchanged,
+                    report.skipped.len(),
+                    report.conflicts.len()
+                ));
+                for label in &report.skipped {
+                    out.line(format_args!(
+                        "Skipped {} label for {}: unsupported type or invalid reference",
+                        label.ty, label.reference
+                    ));
+                }
+                for conflict in &report.conflicts {
+                    out.line(format_args!(
+                        "Conflict for {} {}: keeping '{}', ignoring '{}'",
+                        conflict.label.ty,
+                        conflict.label.reference,
+                        conflict.existing,
+                        conflict.label.label
+                    ));
+                }
+                if !report.conflicts.is_empty() {
+                    out.line(
+                        "Use --overwrite to replace the existing labels with the imported ones",
+                    );
+                }
+                out.data = serde_json::json!({
+                    "added": report.added,
+                    "unchanged": report.unchanged,
+                    "skipped": report.skipped,
+                    "conflicts": report.conflicts.iter().map(|conflict| serde_json::json!({
+                        "label": conflict.label,
+                        "existing": conflict.existing,
+                    })).collect::<Vec<_>>(),
+                });
+            }
+            BpCommand::ExportHistory {
+                since,
+                until,
+                from,
+                to,
+                file,
+            } => {
+                let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let filter = HistoryFilter {
+                    since_height: *since,
+                    until_height: *until,
+                    since_time: *from,
+                    until_time: to.map(|to| to + export::SECS_IN_DAY - 1),
+                };
+                wallet.export_history(file, &filter)?;
+                let count = wallet.history().filter(|row| filter.matches_row(row)).count();
+                out.line(format_args!("{count} transactions exported to {}", file.display()));
+                out.data = serde_json::json!({ "file": file, "transactions": count });
+            }
+            BpCommand::ExportCore { rescan_from, file } => {
+                let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let timestamp =
+                    rescan_from.unwrap_or_else(|| CoreTimestamp::Time(wallet.birth_time()));
+                let descriptors = wallet.core_descriptors(timestamp)?;
+                let request =
+                    serde_json::to_string_pretty(&descriptors).expect("descriptor serialization");
+                match file {
+                    Some(path) => {
+                        fs::write(path, request)?;
+                        out.line(format_args!(
+                            "{} descriptors exported to {}",
+                            descriptors.len(),
+                            path.display()
+                        ));
+                    }
+                    None => out.line(request),
+                }
+                out.data = serde_json::json!(descriptors);
+            }
+            BpCommand::ImportCore {
+                script,
+                default,
+                force,
+                name,
+                file,
+            } => {
+                let json = fs::read_to_string(file)?;
+                let import = CoreImport::from_json(&json, script.as_deref())?;
+                let descriptor =
+                    O::parse_descriptor(&checksum::with_checksum(&import.descriptor)?)?;
+                eprint!("Saving the wallet as '{name}' ... ");
+                let mut wallet = self.create_wallet_from::<O::Descr>(
+                    name,
+                    descriptor,
+                    &mut config,
+                    *default,
+                    *force,
+                    false,
+                )?;
+                for (keychain, range_end) in &import.range_end {
+                    wallet.set_scan_depth(*keychain, range_end.saturating_add(1));
+                }
+                wallet.set_birth_time(import.timestamp);
+                wallet.store()?;
+                eprintln!("success");
+                if *default {
+                    config.store(&self.conf_path(conf_filename));
+                }
+                out.line(format_args!("Descriptor: {}", import.descriptor));
+                let mut ranges = vec![];
+                for (keychain, range_end) in &import.range_end {
+                    out.line(format_args!("Keychain {keychain}: scanning up to index {range_end}"));
+                    ranges.push(serde_json::json!({ "keychain": keychain, "end": range_end }));
+                }
+                if let Some(timestamp) = import.timestamp {
+                    out.line(format_args!("Birth time: {}", export::format_timestamp(timestamp)));
+                }
+                out.data = serde_json::json!({
+                    "name": name.to_string(),
+                    "descriptor": import.descriptor,
+                    "ranges": ranges,
+                    "birthTime": import.timestamp,
+                });
+            }
+            BpCommand::ImportElectrum {
+                default,
+                force,
+                name,
+                file,
+            } => {
+                let descriptor = export::electrum_descriptor(&fs::read_to_string(file)?)?;
+                out.line(format_args!("Descriptor: {descriptor}"));
+                let descriptor = O::parse_descriptor(&checksum::with_checksum(&descriptor)?)?;
+                eprint!("Saving the wallet as '{name}' ... ");
+                let wallet = self.create_wallet_from::<O::Descr>(
+                    name,
+                    descriptor,
+                    &mut config,
+                    *default,
+                    *force,
+                    false,
+                )?;
+                eprintln!("success");
+                if *default {
+                    config.store(&self.conf_path(conf_filename));
+                }
+                let addrs = wallet.addresses(wallet.default_keychain()).take(3);
+                let addrs = addrs.collect::<Vec<_>>();
+                out.line("\nTerm.\tAddress");
+                for derived_addr in &addrs {
+                    out.line(format_args!("{}\t{}", derived_addr.terminal, derived_addr.addr));
+                }
+                out.data = serde_json::json!({ "name": name.to_string(), "addresses": addrs });
+            }
+            BpCommand::Info => {
+                let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                out.line(format_args!("Name:        {}", wallet.data().name));
+                out.line(format_args!("Network:     {}", wallet.network()));
+                out.line(format_args!("Descriptor:  {}", wallet.descriptor()));
+                match wallet.last_block() {
+                    Some(tip) => {
+                        out.line(format_args!("Chain tip:   {} {}", tip.height, tip.block_hash));
+                        out.line(format_args!(
+                            "Block time:  {}",
+                            export::format_timestamp(tip.time)
+                        ));
+                    }
+                    None => out.line("Chain tip:   wallet was never synchronized"),
+                }
+                out.data = serde_json::json!({
+                    "name": wallet.data().name,
+                    "network": wallet.network().to_string(),
+                    "descriptor": wallet.descriptor().to_string(),
+                    "tip": wallet.last_block(),
+                });
+            }
+            BpCommand::Xpub => {
+                let wallet = self.bp_wallet::<O::Descr>(&config)?;
+                let mut keys = vec![];
+                for (no, xpub) in wallet.descriptor().xpubs().enumerate() {
+                    let key = KeyInfo::with(xpub);
+                    out.line(format_args!("Key #{}", no + 1));
+                    out.line(format_args!("  - fingerprint: {}", key.fingerprint));
+                    out.line(format_args!("  - origin:      {}", key.origin));
+                    out.line(format_args!("  - xpub:        {}", key.xpub));
+                    out.line(format_args!("  - descriptor:  {}", key.descriptor_key));
+                    keys.push(key);
+                }
+                out.data = serde_json::json!({ "keys": keys });
             }
         };
 
-        println!();
+        out.line("");
 
-        Ok(())
+        Ok(out)
     }
 }
 
@@ -550,6 +2578,33 @@ fn psbt_read(psbt_path: &Path) -> Result<Psbt, ExecError> {
     Ok(psbt)
 }
 
+#[allow(clippy::large_enum_variant)]
+enum TxFile {
+    Tx(Tx),
+    Psbt(Psbt),
+}
+
+fn tx_read(tx_path: &Path) -> Result<TxFile, ExecError> {
+    eprint!("Reading transaction from file {} ... ", tx_path.display());
+    let data = fs::read(tx_path)?;
+    let text = String::from_utf8_lossy(&data);
+    let text = text.trim();
+    let file = if let Ok(psbt) = Psbt::deserialize(&data) {
+        TxFile::Psbt(psbt)
+    } else if let Ok(tx) = Tx::consensus_deserialize(&data) {
+        TxFile::Tx(tx)
+    } else if let Ok(psbt) = text.parse::<Psbt>() {
+        TxFile::Psbt(psbt)
+    } else if let Ok(tx) = text.parse::<Tx>() {
+        TxFile::Tx(tx)
+    } else {
+        eprintln!("failed");
+        return Err(ExecError::InvalidTxFile(tx_path.display().to_string()));
+    };
+    eprintln!("success");
+    Ok(file)
+}
+
 fn psbt_write(psbt: &Psbt, psbt_path: &Path) -> Result<(), ExecError> {
     eprint!("Saving PSBT to file {} ... ", psbt_path.display());
     let mut psbt_file = File::create(psbt_path)?;
@@ -558,46 +2613,104 @@ fn psbt_write(psbt: &Psbt, psbt_path: &Path) -> Result<(), ExecError> {
     Ok(())
 }
 
-fn psbt_write_or_print(psbt: &Psbt, psbt_path: Option<&Path>) -> Result<(), ExecError> {
+/// Counts PSBT inputs which are not finalized and have less than `required` signatures.
+fn unsigned_inputs(psbt: &Psbt, required: usize) -> usize {
+    psbt.inputs()
+        .filter(|input| !input.is_finalized() && finalize::signature_count(input) < required)
+        .count()
+}
+
+/// Checks that the signing `account` file holds the private key for one of the keys of the
+/// wallet `descriptor`, so a wrong account can't be attached to the wallet.
+#[cfg(feature = "hot")]
+fn check_signing_account(
+    account: &Path,
+    no_password: bool,
+    descriptor: &impl Descriptor<XpubDerivable>,
+) -> Result<(), ExecError> {
+    use bpstd::XprivAccount;
+
+    use crate::hot::SecureIo;
+
+    let password =
+        if no_password { s!("") } else { rpassword::prompt_password("Account password: ")? };
+    let xpub = XprivAccount::read(account, &password)?.to_xpub_account();
+    if !descriptor.xpubs().any(|key| key.xpub() == xpub.xpub()) {
+        return Err(ExecError::ForeignAccount(xpub.to_string()));
+    }
+    Ok(())
+}
+
+/// Checks that the signing `account` file exists. Without `hot` feature the account can't be
+/// decrypted, and it gets checked against the wallet descriptor only when used for signing.
+#[cfg(not(feature = "hot"))]
+fn check_signing_account(
+    account: &Path,
+    _no_password: bool,
+    _descriptor: &impl Descriptor<XpubDerivable>,
+) -> Result<(), ExecError> {
+    fs::metadata(account)?;
+    Ok(())
+}
+
+fn psbt_write_or_print(
+    psbt: &Psbt,
+    psbt_path: Option<&Path>,
+    out: &mut ExecOutput,
+) -> Result<(), ExecError> {
+    let encoded = match psbt.version {
+        PsbtVer::V0 => psbt.to_string(),
+        PsbtVer::V2 => format!("{psbt:#}"),
+    };
     match psbt_path {
         Some(file_name) => {
             psbt_write(psbt, file_name)?;
         }
-        None => match psbt.version {
-            PsbtVer::V0 => println!("{psbt}"),
-            PsbtVer::V2 => println!("{psbt:#}"),
-        },
+        None => out.line(&encoded),
     }
+    out.data = serde_json::json!({ "psbt": encoded });
     Ok(())
 }
 
-fn psbt_finalize<D: Descriptor<K, V>, K, V>(
+fn psbt_finalize<O: DescriptorOpts>(
     psbt: &mut Psbt,
-    descriptor: &D,
+    descriptor: &O::Descr,
 ) -> Result<(), ExecError> {
     eprint!("Finalizing PSBT ... ");
-    let inputs = psbt.finalize(descriptor);
+    let total = psbt.inputs().count();
+    let incomplete = finalize::finalize(psbt, descriptor, O::required_sigs(descriptor));
     eprint!(
-        "{} of {} inputs were finalized",
-        inputs.to_string().bright_green(),
-        psbt.inputs().count()
+        "{} of {total} inputs were finalized",
+        (total - incomplete.len()).to_string().bright_green()
     );
-    if psbt.is_finalized() {
+    if incomplete.is_empty() {
         eprintln!(", transaction is ready for the extraction");
     } else {
-        eprintln!(" and some non-finalized inputs remains");
+        eprintln!(" and some non-finalized inputs remains:");
+        for input in incomplete {
+            eprintln!("- {input}");
+        }
     }
     Ok(())
 }
 
-fn psbt_extract(psbt: &Psbt, publish: bool, tx: Option<&Path>) -> Result<Tx, ExecError> {
+fn psbt_extract(
+    psbt: &Psbt,
+    publish: bool,
+    tx: Option<&Path>,
+    out: &mut ExecOutput,
+) -> Result<Tx, ExecError> {
     eprint!("Extracting signed transaction ... ");
     match psbt.extract() {
         Ok(extracted) => {
             eprintln!("success");
             if !publish && tx.is_none() {
-                println!("{extracted}");
+                out.line(&extracted);
             }
+            out.data = serde_json::json!({
+                "txid": extracted.txid(),
+                "tx": extracted.to_string(),
+            });
             if let Some(file) = tx {
                 eprint!("Saving transaction to file {} ...", file.display());
                 let mut file = File::create(file)?;
@@ -619,3 +2732,154 @@ fn psbt_extract(psbt: &Psbt, publish: bool, tx: Option<&Path>) -> Result<Tx, Exe
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unsigned_inputs() {
+        // PSBTs of the same transaction with a partial signature by different keys each
+        let first = Psbt::from_str(
+            "cHNidP8BAFICAAAAARERERERERERERERERERERERERERERERERERERERERERAAAAAAD/////AegDAAAAAAAAFgAUIiIiIiIiIiIiIiIiIiIiIiIiIiIAAAAAACICAnm+Zn753LusVaBilc6HCwcCm/zbLc4o2VnygVsW+BeYCTAGAgEBAgEBAQAA",
+        )
+        .unwrap();
+        let second = Psbt::from_str(
+            "cHNidP8BAFICAAAAARERERERERERERERERERERERERERERERERERERERERERAAAAAAD/////AegDAAAAAAAAFgAUIiIiIiIiIiIiIiIiIiIiIiIiIiIAAAAAACICAsYEf5RB7X1tMEVAbpXAfNhcd45LjO88p6usCblccJ7lCTAGAgEBAgEBAQAA",
+        )
+        .unwrap();
+        assert_eq!(unsigned_inputs(&first, finalize::SINGLE_SIG), 0);
+        // A single signature doesn't complete the input of 2-of-N multisig
+        assert_eq!(unsigned_inputs(&first, 2), 1);
+        let combined = finalize::combine([first, second]).unwrap();
+        assert_eq!(unsigned_inputs(&combined, 2), 0);
+        assert_eq!(unsigned_inputs(&combined, 3), 1);
+    }
+
+    #[test]
+    fn test_key_info() {
+        let xpub = XpubAccount::from_str(
+            "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ",
+        )
+        .unwrap();
+        let key = KeyInfo::with(&xpub);
+        assert_eq!(key.fingerprint, "73c5da0a");
+        assert_eq!(key.origin, "73c5da0a/86h/0h/0h");
+        assert_eq!(
+            key.xpub,
+            "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ"
+        );
+        assert_eq!(key.descriptor_key, format!("[{}]{}", key.origin, key.xpub));
+        let json = serde_json::to_value(&key).unwrap();
+        assert_eq!(json["descriptorKey"], key.descriptor_key);
+        assert_eq!(json["fingerprint"], "73c5da0a");
+    }
+
+    #[test]
+    fn test_tx_details() {
+        use bpstd::{Outpoint, SeqNo, SigScript, TxIn, TxVer, VarIntArray};
+
+        let tx = |prevouts: &[Outpoint], values: &[u64]| Tx {
+            version: TxVer::V2,
+            inputs: VarIntArray::from_checked(
+                prevouts
+                    .iter()
+                    .map(|prevout| TxIn {
+                        prev_output: *prevout,
+                        sig_script: SigScript::new(),
+                        sequence: SeqNo::from_consensus_u32(0xFFFFFFFF),
+                        witness: none!(),
+                    })
+                    .collect(),
+            ),
+            outputs: VarIntArray::from_checked(
+                values.iter().map(|value| TxOut::new(ScriptPubkey::new(), Sats(*value))).collect(),
+            ),
+            lock_time: LockTime::ZERO,
+        };
+        let coinbase = tx(&[Outpoint::coinbase()], &[50_000, 30_000]);
+        let prev = coinbase.txid();
+        let unknown = Txid::from_str(&"11".repeat(32)).unwrap();
+        let requested = std::cell::RefCell::new(vec![]);
+        let lookup = |txid| {
+            requested.borrow_mut().push(txid);
+            Ok::<_, ()>((txid == prev).then(|| coinbase.clone()))
+        };
+
+        // Coinbase transactions spend nothing and pay no fee
+        let spent = spent_outputs(&coinbase, lookup).unwrap();
+        assert_eq!(spent, vec![None]);
+        assert_eq!(tx_fee(&coinbase, &spent), None);
+        assert!(requested.borrow().is_empty());
+
+        // Each previous transaction is requested once
+        let spending = tx(&[Outpoint::new(prev, 1), Outpoint::new(prev, 0)], &[75_000]);
+        let spent = spent_outputs(&spending, lookup).unwrap();
+        assert_eq!(spent, vec![Some(coinbase.outputs[1].clone()), Some(coinbase.outputs[0].clone())]);
+        assert_eq!(tx_fee(&spending, &spent), Some(Sats(5_000)));
+        assert_eq!(*requested.borrow(), vec![prev]);
+
+        // The fee is unknown if some spent outputs are missing
+        requested.borrow_mut().clear();
+        let partial = tx(
+            &[Outpoint::new(prev, 0), Outpoint::new(unknown, 0), Outpoint::new(prev, 5)],
+            &[40_000],
+        );
+        let spent = spent_outputs(&partial, lookup).unwrap();
+        assert_eq!(spent, vec![Some(coinbase.outputs[0].clone()), None, None]);
+        assert_eq!(tx_fee(&partial, &spent), None);
+        assert_eq!(*requested.borrow(), vec![prev, unknown]);
+
+        // Lookup errors are propagated
+        assert_eq!(spent_outputs(&spending, |_| Err::<Option<Tx>, _>("offline")), Err("offline"));
+    }
+
+    #[test]
+    fn test_parse_beneficiary() {
+        let address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+        let fixed = parse_beneficiary(&format!("{address}:1500")).unwrap();
+        assert_eq!(fixed.address.to_string(), address);
+        assert_eq!(fixed.amount, Payment::Fixed(Sats(1500)));
+        assert_eq!(parse_beneficiary(&format!("1500@{address}")).unwrap(), fixed);
+
+        let max = parse_beneficiary(&format!("{address}:MAX")).unwrap();
+        assert_eq!(max.address.to_string(), address);
+        assert_eq!(max.amount, Payment::Max);
+        assert_eq!(parse_beneficiary(&format!("MAX@{address}")).unwrap(), max);
+
+        assert!(parse_beneficiary(&format!("{address}:lots")).is_err());
+        assert!(parse_beneficiary(address).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "hot")]
+    fn test_check_signing_account() {
+        use bpstd::{HardenedIndex, XprivAccount};
+        use descriptors::{StdDescr, Wpkh};
+
+        use crate::hot::SecureIo;
+
+        let account = |seed: u8| {
+            XprivAccount::with_seed(false, &[seed; 32]).derive([
+                HardenedIndex::hardened(84),
+                HardenedIndex::hardened(0),
+                HardenedIndex::hardened(0),
+            ])
+        };
+        let descriptor = |account: &XprivAccount| {
+            let xpub =
+                XpubDerivable::with(account.to_xpub_account(), &[Keychain::OUTER, Keychain::INNER]);
+            StdDescr::from(Wpkh::from(xpub))
+        };
+        let path =
+            std::env::temp_dir().join(format!("bp-wallet-account-{}.key", std::process::id()));
+        account(0x42).write(&path, "").unwrap();
+
+        assert!(check_signing_account(&path, true, &descriptor(&account(0x42))).is_ok());
+        let err = check_signing_account(&path, true, &descriptor(&account(0x43))).unwrap_err();
+        let xpub = account(0x42).to_xpub_account().to_string();
+        assert!(matches!(err, ExecError::ForeignAccount(account) if account == xpub));
+
+        fs::remove_file(path).unwrap();
+    }
+}
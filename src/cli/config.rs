@@ -20,44 +20,450 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fs;
-use std::path::Path;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use amplify::IoError;
+use bpstd::{Network, Sats};
+
+use crate::cli::ResolverOpt;
+use crate::fs::{delete_wallet, rename_wallet, write_atomic, WalletDirError};
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 #[derive(serde::Serialize, serde::Deserialize)]
-#[serde(crate = "serde_crate", rename_all = "camelCase")]
+#[serde(crate = "serde_crate", rename_all = "camelCase", default)]
 pub struct Config {
     pub default_wallet: String,
+
+    /// Indexer used when no indexer is given with command-line arguments
+    #[serde(default)]
+    pub resolver: ResolverOpt,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             default_wallet: s!("default"),
+            resolver: none!(),
+        }
+    }
+}
+
+/// Errors reading or writing configuration file.
+#[derive(Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ConfigError {
+    /// configuration file '{0}' is not found.
+    NotFound(String),
+
+    /// access to configuration file '{0}' is denied.
+    PermissionDenied(String),
+
+    /// configuration file '{0}' is invalid: {2} (line {1}).
+    Parse(String, usize, String),
+
+    /// unable to access configuration file '{0}'. Details: {1}
+    Io(String, IoError),
+}
+
+impl ConfigError {
+    fn with_io(conf_path: &Path, err: io::Error) -> Self {
+        let path = conf_path.display().to_string();
+        match err.kind() {
+            io::ErrorKind::NotFound => ConfigError::NotFound(path),
+            io::ErrorKind::PermissionDenied => ConfigError::PermissionDenied(path),
+            _ => ConfigError::Io(path, err.into()),
         }
     }
 }
 
 impl Config {
+    /// Loads configuration from a file at `conf_path`, creating the file with the default
+    /// configuration if it doesn't exist yet. If the file can't be read or parsed, reports the
+    /// error and uses the default configuration, leaving the file intact.
     pub fn load(conf_path: &Path) -> Self {
-        fs::read_to_string(conf_path)
-            .map_err(|err| {
-                error!("Unable to read config file: {err:?}");
-            })
-            .and_then(|s| {
-                toml::from_str(&s).map_err(|err| {
-                    error!("Unable to parse config file: {err}");
-                })
-            })
-            .unwrap_or_else(|_| {
-                eprintln!("Unable to find or parse config file; using config defaults");
+        match Config::read(conf_path) {
+            Ok(conf) => conf,
+            Err(ConfigError::NotFound(_)) => {
+                eprintln!("Unable to find config file; using config defaults");
                 let conf = Config::default();
                 conf.store(conf_path);
                 conf
-            })
+            }
+            Err(err) => {
+                error!("{err}");
+                eprintln!("Unable to read config file; using config defaults");
+                Config::default()
+            }
+        }
     }
 
+    /// Reads configuration from a file at `conf_path`.
+    ///
+    /// Fields missing from the file take default values, and unknown fields are ignored, such
+    /// that the files written by other versions of the software remain readable.
+    pub fn read(conf_path: &Path) -> Result<Self, ConfigError> { read_toml(conf_path) }
+
+    /// Stores configuration to a file at `conf_path`, reporting errors to the log.
     pub fn store(&self, conf_path: &Path) {
-        fs::write(conf_path, toml::to_string(self).expect("config must convert to TOML")).ok();
+        if let Err(err) = self.write(conf_path) {
+            error!("Unable to store config file: {err}");
+        }
+    }
+
+    /// Writes configuration to a file at `conf_path`.
+    ///
+    /// The configuration is first written to a temporary file, which then replaces the original
+    /// one, so the config file never remains truncated.
+    pub fn write(&self, conf_path: &Path) -> Result<(), ConfigError> { write_toml(self, conf_path) }
+
+    /// Renames wallet inside `base_dir`, updating the default wallet if it was pointing to the
+    /// renamed wallet. The updated config has to be stored by the caller.
+    pub fn rename_wallet(
+        &mut self,
+        base_dir: &Path,
+        from: &str,
+        to: &str,
+    ) -> Result<(), WalletDirError> {
+        rename_wallet(base_dir, from, to)?;
+        if self.default_wallet == from {
+            self.default_wallet = to.to_owned();
+        }
+        Ok(())
+    }
+
+    /// Deletes wallet from `base_dir`, refusing to delete the default wallet unless `force` is
+    /// set. If `backup` is set, moves the wallet to the trash directory and returns its new
+    /// location.
+    pub fn delete_wallet(
+        &self,
+        base_dir: &Path,
+        name: &str,
+        force: bool,
+        backup: bool,
+    ) -> Result<Option<PathBuf>, WalletDirError> {
+        if self.default_wallet == name && !force {
+            return Err(WalletDirError::IsDefault(name.to_owned()));
+        }
+        delete_wallet(base_dir, name, backup)
+    }
+}
+
+fn read_toml<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, ConfigError> {
+    let s = fs::read_to_string(path).map_err(|err| ConfigError::with_io(path, err))?;
+    toml::from_str(&s).map_err(|err| {
+        let line =
+            err.span().map(|span| s[..span.start].matches('\n').count() + 1).unwrap_or_default();
+        ConfigError::Parse(path.display().to_string(), line, err.message().to_owned())
+    })
+}
+
+fn write_toml<T: serde::Serialize>(value: &T, path: &Path) -> Result<(), ConfigError> {
+    let s = toml::to_string(value).expect("config must convert to TOML");
+    let io_err = |err| ConfigError::with_io(path, err);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(io_err)?;
+    }
+    write_atomic(path, s).map_err(io_err)
+}
+
+/// Origin of a setting value in effect.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Display)]
+#[derive(serde::Serialize)]
+#[serde(crate = "serde_crate", rename_all = "camelCase")]
+pub enum SettingSource {
+    /// Command-line argument or its environment variable.
+    #[display("command line")]
+    CommandLine,
+
+    /// Settings file of the used wallet.
+    #[display("wallet settings")]
+    Wallet,
+
+    /// Global configuration file.
+    #[display("config file")]
+    ConfigFile,
+
+    /// Built-in default.
+    #[default]
+    #[display("default")]
+    Default,
+}
+
+/// Invalid setting value found by [`EffectiveConfig::validate`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[derive(serde::Serialize)]
+#[serde(crate = "serde_crate", rename_all = "camelCase")]
+#[display("{field} (from {origin}): {problem}")]
+pub struct ConfigIssue {
+    pub field: &'static str,
+    pub origin: SettingSource,
+    pub problem: String,
+}
+
+/// Issues found by [`EffectiveConfig::validate`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Error)]
+pub struct ConfigIssues(pub Vec<ConfigIssue>);
+
+impl Display for ConfigIssues {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid configuration")?;
+        for issue in &self.0 {
+            write!(f, "\n- {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Configuration in effect for a command: the configuration file merged with the settings of the
+/// used wallet and the command-line arguments.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(serde::Serialize)]
+#[serde(crate = "serde_crate", rename_all = "camelCase")]
+pub struct EffectiveConfig {
+    pub data_dir: PathBuf,
+    pub data_dir_source: SettingSource,
+    pub network: Network,
+    pub default_wallet: String,
+    pub default_wallet_source: SettingSource,
+    /// Directory of the used wallet, unless the wallet descriptor is given in the command line.
+    pub wallet_dir: Option<PathBuf>,
+    pub resolver: ResolverOpt,
+    pub resolver_source: SettingSource,
+    pub gap_limit: Option<u32>,
+    pub fee: Option<Sats>,
+}
+
+impl EffectiveConfig {
+    /// Checks that the data directory exists or can be created, that the default wallet exists
+    /// and that the indexer and proxy URLs are well-formed, returning all the found issues.
+    pub fn validate(&self, base_dir: &Path) -> Vec<ConfigIssue> {
+        let mut issues = vec![];
+        let mut issue = |field, origin, problem: String| {
+            issues.push(ConfigIssue {
+                field,
+                origin,
+                problem,
+            })
+        };
+
+        match self.data_dir.ancestors().find(|dir| dir.exists()) {
+            Some(dir) if !dir.is_dir() => issue(
+                "data_dir",
+                self.data_dir_source,
+                format!("'{}' is not a directory", dir.display()),
+            ),
+            Some(dir) if dir.metadata().is_ok_and(|meta| meta.permissions().readonly()) => {
+                issue("data_dir", self.data_dir_source, format!("'{}' is read-only", dir.display()))
+            }
+            None => issue(
+                "data_dir",
+                self.data_dir_source,
+                format!("'{}' can't be created", self.data_dir.display()),
+            ),
+            Some(_) => {}
+        }
+
+        if !base_dir.join(&self.default_wallet).is_dir() {
+            issue(
+                "default_wallet",
+                self.default_wallet_source,
+                format!("wallet '{}' doesn't exist", self.default_wallet),
+            );
+        }
+
+        let resolver = &self.resolver;
+        for (field, url) in [
+            ("esplora", &resolver.esplora),
+            ("mempool", &resolver.mempool),
+            ("bitcoind", &resolver.bitcoind),
+        ] {
+            if let Some(url) = url {
+                if let Err(problem) = check_url(url, &["http", "https"]) {
+                    issue(field, self.resolver_source, problem);
+                }
+            }
+        }
+        if let Some(url) = &resolver.electrum {
+            let url = if url.contains("://") { url.clone() } else { format!("tcp://{url}") };
+            if let Err(problem) = check_url(&url, &["tcp", "ssl"]) {
+                issue("electrum", self.resolver_source, problem);
+            }
+        }
+        if let Some(url) = &resolver.proxy {
+            if let Err(problem) = check_url(url, &["socks5", "socks5h"]) {
+                issue("proxy", self.resolver_source, problem);
+            }
+        }
+        if let Some(cookie) = &resolver.rpc_cookie {
+            if !cookie.is_file() {
+                issue(
+                    "rpc_cookie",
+                    self.resolver_source,
+                    format!("cookie file '{}' doesn't exist", cookie.display()),
+                );
+            }
+        }
+        issues
+    }
+}
+
+/// Checks that the URL has one of the `schemes` and a host name, and that its port, if given, is
+/// a number. Placeholders like `{network}` are allowed in the URL path.
+fn check_url(url: &str, schemes: &[&str]) -> Result<(), String> {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return Err(format!("URL '{url}' has no scheme"));
+    };
+    if !schemes.contains(&scheme) {
+        return Err(format!(
+            "URL '{url}' has unsupported scheme '{scheme}'; expected {}",
+            schemes.join(" or ")
+        ));
+    }
+    let authority = rest.split('/').next().unwrap_or_default();
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if port.contains('{') => host,
+        Some((host, port)) => {
+            port.parse::<u16>().map_err(|_| format!("URL '{url}' has invalid port '{port}'"))?;
+            host
+        }
+        None => authority,
+    };
+    if host.is_empty() {
+        return Err(format!("URL '{url}' has no host"));
+    }
+    Ok(())
+}
+
+/// Name of the file with wallet-specific settings inside the wallet directory.
+pub const WALLET_SETTINGS_FILE: &str = "wallet.toml";
+
+/// Wallet-specific settings, which take precedence over the global [`Config`], but are overridden
+/// by command-line arguments.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(crate = "serde_crate", rename_all = "camelCase", default)]
+pub struct WalletSettings {
+    /// Indexer used for the wallet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolver: Option<ResolverOpt>,
+
+    /// Number of consecutive unused addresses after which the address discovery stops
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gap_limit: Option<u32>,
+
+    /// Fee paid by the transactions constructed by the wallet, if not given explicitly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee: Option<Sats>,
+}
+
+impl WalletSettings {
+    /// Reads settings from the wallet directory; if the directory has no settings file, returns
+    /// empty settings.
+    pub fn load(wallet_dir: &Path) -> Result<Self, ConfigError> {
+        match read_toml(&wallet_dir.join(WALLET_SETTINGS_FILE)) {
+            Err(ConfigError::NotFound(_)) => Ok(none!()),
+            res => res,
+        }
+    }
+
+    /// Writes settings into the wallet directory.
+    pub fn store(&self, wallet_dir: &Path) -> Result<(), ConfigError> {
+        write_toml(self, &wallet_dir.join(WALLET_SETTINGS_FILE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> Config {
+        Config {
+            default_wallet: s!("savings"),
+            resolver: ResolverOpt {
+                bitcoind: None,
+                rpc_cookie: Some(PathBuf::from("/home/user/.bitcoin/.cookie")),
+                rpc_user: Some(s!("user")),
+                rpc_pass: None,
+                electrum: Some(s!("ssl://electrum.example.com:50002")),
+                esplora: Some(s!("https://blockstream.info/{network}/api")),
+                mempool: None,
+                esplora_timeout: Some(30),
+                sync_retries: Some(3),
+                sync_retry_delay: Some(500),
+                sync_parallelism: Some(8),
+                proxy: Some(s!("socks5://127.0.0.1:9050")),
+            },
+        }
+    }
+
+    #[test]
+    fn test_check_url() {
+        assert_eq!(check_url("https://blockstream.info/{network}/api", &["http", "https"]), Ok(()));
+        assert_eq!(check_url("http://127.0.0.1:{port}", &["http", "https"]), Ok(()));
+        assert_eq!(check_url("tcp://example.com:50001", &["tcp", "ssl"]), Ok(()));
+        assert!(check_url("blockstream.info/api", &["http", "https"]).is_err());
+        assert!(check_url("ftp://example.com", &["http", "https"]).is_err());
+        assert!(check_url("https://:8080/api", &["http", "https"]).is_err());
+        assert!(check_url("ssl://example.com:port", &["tcp", "ssl"]).is_err());
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let conf = test_config();
+        let s = toml::to_string(&conf).unwrap();
+        assert_eq!(toml::from_str::<Config>(&s).unwrap(), conf);
+    }
+
+    #[test]
+    fn test_config_defaults_and_unknown_keys() {
+        let conf =
+            toml::from_str::<Config>("unknownKey = 1\n[resolver]\nesplora = \"url\"\n").unwrap();
+        assert_eq!(conf.default_wallet, Config::default().default_wallet);
+        assert_eq!(conf.resolver.esplora.as_deref(), Some("url"));
+    }
+
+    #[test]
+    fn test_resolver_merge() {
+        let conf = test_config().resolver;
+        let mut resolver = ResolverOpt {
+            mempool: Some(s!("https://mempool.space/api")),
+            rpc_user: Some(s!("cli")),
+            sync_retries: Some(1),
+            ..none!()
+        };
+        resolver.merge(&conf);
+        assert_eq!(resolver.mempool.as_deref(), Some("https://mempool.space/api"));
+        assert_eq!(resolver.electrum, None);
+        assert_eq!(resolver.esplora, None);
+        assert_eq!(resolver.rpc_user.as_deref(), Some("cli"));
+        assert_eq!(resolver.rpc_cookie, None);
+        assert_eq!(resolver.esplora_timeout, Some(30));
+        assert_eq!(resolver.retry_policy().retries, 1);
+        assert_eq!(resolver.retry_policy().base_delay.as_millis(), 500);
+        assert_eq!(resolver.sync_parallelism(), 8);
+        assert_eq!(resolver.proxy, conf.proxy);
+
+        let mut resolver = ResolverOpt::default();
+        resolver.merge(&conf);
+        assert_eq!(resolver, conf);
+    }
+
+    #[test]
+    fn test_config_file_errors() {
+        let dir = std::env::temp_dir().join(format!("bp-wallet-config-{}", std::process::id()));
+        let path = dir.join("bp.toml");
+
+        assert!(matches!(Config::read(&path), Err(ConfigError::NotFound(_))));
+
+        let conf = test_config();
+        conf.write(&path).unwrap();
+        assert_eq!(Config::read(&path).unwrap(), conf);
+
+        fs::write(&path, "defaultWallet = \"savings\"\nresolver = 5\n").unwrap();
+        assert!(matches!(Config::read(&path), Err(ConfigError::Parse(_, 2, _))));
+
+        fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -28,32 +28,32 @@ use log::LevelFilter;
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Display)]
 pub enum LogLevel {
     #[display("none")]
-    None = 0,
+    None,
 
     /// Report only errors to `stderr` and normal program output to stdin
-    /// (if it is not directed to a file). Corresponds to zero verbosity
-    /// flags.
+    /// (if it is not directed to a file).
     #[display("error")]
     Error,
 
     /// Report warning messages and errors, plus standard program output.
-    /// Corresponds to a single `-v` verbosity flag.
+    /// Corresponds to zero verbosity flags.
     #[display("warn")]
     Warn,
 
-    /// Report genetic information messages, warnings and errors.
-    /// Corresponds to a double `-vv` verbosity flag.
+    /// Report genetic information messages, like the wallet sync summary,
+    /// warnings and errors. Corresponds to a single `-v` verbosity flag.
     #[display("info")]
     Info,
 
-    /// Report debugging information and all non-trace messages, including
-    /// general information, warnings and errors.
-    /// Corresponds to triple `-vvv` verbosity flag.
+    /// Report debugging information, like the per-address sync details,
+    /// and all non-trace messages, including general information, warnings
+    /// and errors. Corresponds to a double `-vv` verbosity flag.
     #[display("debug")]
     Debug,
 
-    /// Print all possible messages including tracing information.
-    /// Corresponds to quadruple `-vvvv` verbosity flag.
+    /// Print all possible messages including tracing information, like the
+    /// raw indexer requests and responses. Corresponds to triple `-vvv`
+    /// verbosity flag.
     #[display("trace")]
     Trace,
 }
@@ -68,16 +68,21 @@ impl From<LogLevel> for u8 {
 
 impl LogLevel {
     /// Indicates number of required verbosity flags
-    pub fn verbosity_flag_count(&self) -> u8 { *self as u8 }
+    pub fn verbosity_flag_count(&self) -> u8 {
+        match self {
+            LogLevel::None | LogLevel::Error | LogLevel::Warn => 0,
+            LogLevel::Info => 1,
+            LogLevel::Debug => 2,
+            LogLevel::Trace => 3,
+        }
+    }
 
     /// Constructs enum value from a given number of verbosity flags
     pub fn from_verbosity_flag_count(level: u8) -> Self {
         match level {
-            0 => LogLevel::None,
-            1 => LogLevel::Error,
-            2 => LogLevel::Warn,
-            3 => LogLevel::Info,
-            4 => LogLevel::Debug,
+            0 => LogLevel::Warn,
+            1 => LogLevel::Info,
+            2 => LogLevel::Debug,
             _ => LogLevel::Trace,
         }
     }
@@ -26,11 +26,16 @@ mod args;
 mod config;
 mod command;
 
-pub use args::{Args, Exec};
-pub use command::{BpCommand, Command, ExecError};
-pub use config::Config;
+pub use args::{new_passphrase, Args, Exec, ExecOutput, StderrProgress};
+pub use command::{AddrRef, BpCommand, Command, ExecError, TxRef, UtxoSort};
+pub use config::{
+    Config, ConfigError, ConfigIssue, ConfigIssues, EffectiveConfig, SettingSource, WalletSettings,
+    WALLET_SETTINGS_FILE,
+};
 pub use loglevel::LogLevel;
 pub use opts::{
-    DescrStdOpts, DescriptorOpts, GeneralOpts, ResolverOpt, WalletOpts, DATA_DIR, DATA_DIR_ENV,
-    DEFAULT_ELECTRUM, DEFAULT_ESPLORA,
+    bitcoind_rpc_port, parse_args, with_legacy_env, AmountFormat, AmountUnit, DescrStdOpts,
+    DescriptorArg, DescriptorArgError, DescriptorOpts, GeneralOpts, OutputFormat, ResolverOpt,
+    WalletOpts, DATA_DIR, DATA_DIR_ENV, DEFAULT_BITCOIND, DEFAULT_ELECTRUM, DEFAULT_ESPLORA,
+    DEFAULT_MEMPOOL, DEFAULT_SIGNET_MAGIC, LEGACY_ENV_VARS, WALLET_ENV,
 };
@@ -20,15 +20,79 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
-use bpstd::{Network, XpubDerivable};
+use amplify::confinement::Confined;
+use amplify::hex::{FromHex, ToHex};
+use amplify::num::u4;
+use bpstd::{Network, Sats, XpubDerivable};
 use clap::ValueHint;
-use descriptors::{Descriptor, StdDescr, TrKey, Wpkh};
+use descriptors::{Descriptor, Pkh, ShMulti, ShSortedMulti, StdDescr, TrKey, Wpkh};
 use strict_encoding::Ident;
 
-pub const DATA_DIR_ENV: &str = "LNPBP_DATA_DIR";
+use crate::checksum::{normalize, normalize_hardened, ChecksumError};
+use crate::finalize;
+use crate::indexers::esplora::DEFAULT_PARALLELISM;
+use crate::indexers::{bitcoind, RETRY_BASE_DELAY_MS};
+use crate::RetryPolicy;
+
+pub const DATA_DIR_ENV: &str = "BP_DATA_DIR";
+/// Environment variable with the name of the wallet used if no wallet is given in the command
+/// line; takes precedence over the default wallet from the configuration file.
+pub const WALLET_ENV: &str = "BP_WALLET";
+
+/// Environment variables used by the previous versions, together with their current names.
+pub const LEGACY_ENV_VARS: [(&str, &str); 10] = [
+    ("LNPBP_DATA_DIR", DATA_DIR_ENV),
+    ("LNPBP_NETWORK", "BP_NETWORK"),
+    ("BITCOIND_RPC", "BP_BITCOIND"),
+    ("BITCOIND_RPC_COOKIE", "BP_RPC_COOKIE"),
+    ("BITCOIND_RPC_USER", "BP_RPC_USER"),
+    ("BITCOIND_RPC_PASS", "BP_RPC_PASS"),
+    ("ELECRTUM_SERVER", "BP_ELECTRUM"),
+    ("ESPLORA_SERVER", "BP_ESPLORA"),
+    ("MEMPOOL_SERVER", "BP_MEMPOOL"),
+    ("SOCKS5_PROXY", "BP_PROXY"),
+];
+
+/// Makes arguments of the `command` read from the environment default to the values of their
+/// [legacy environment variables](LEGACY_ENV_VARS), unless the current variables are set.
+///
+/// Environment variables are read with the `var` function.
+pub fn with_legacy_env(
+    mut command: clap::Command,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> clap::Command {
+    for (legacy, current) in LEGACY_ENV_VARS {
+        let Some(value) = var(legacy).filter(|_| var(current).is_none()) else {
+            continue;
+        };
+        let ids = command
+            .get_arguments()
+            .filter(|arg| arg.get_env() == Some(OsStr::new(current)))
+            .map(|arg| arg.get_id().clone())
+            .collect::<Vec<_>>();
+        if !ids.is_empty() {
+            eprintln!("Warning: {legacy} environment variable is deprecated; use {current}");
+        }
+        for id in ids {
+            command = command.mut_arg(id, |arg| arg.default_value(value.clone()));
+        }
+    }
+    command
+}
+
+/// Parses the command-line arguments, falling back to the legacy environment variables (see
+/// [`with_legacy_env`]).
+pub fn parse_args<P: clap::Parser>() -> P {
+    let matches = with_legacy_env(P::command(), |name| env::var_os(name)).get_matches();
+    P::from_arg_matches(&matches).unwrap_or_else(|err| err.exit())
+}
 #[cfg(target_os = "linux")]
 pub const DATA_DIR: &str = "~/.lnp-bp";
 #[cfg(any(target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
@@ -42,82 +106,518 @@ pub const DATA_DIR: &str = "~/Documents";
 #[cfg(target_os = "android")]
 pub const DATA_DIR: &str = ".";
 
+pub const DEFAULT_BITCOIND: &str = "http://127.0.0.1:{port}";
 pub const DEFAULT_ELECTRUM: &str = "example.com:50001";
 pub const DEFAULT_ESPLORA: &str = "https://blockstream.info/{network}/api";
 pub const DEFAULT_MEMPOOL: &str = "https://mempool.space/{network}/api";
 
-#[derive(Args, Clone, PartialEq, Eq, Debug)]
-#[group(args = ["electrum", "esplora", "mempool"])]
+/// Network magic of the default public signet.
+pub const DEFAULT_SIGNET_MAGIC: [u8; 4] = [0x0a, 0x03, 0xcf, 0x40];
+
+/// Parses hex-encoded 4-byte network magic of a custom signet.
+pub fn parse_signet_magic(s: &str) -> Result<[u8; 4], String> {
+    let bytes = Vec::<u8>::from_hex(s).map_err(|err| format!("invalid signet magic: {err}"))?;
+    <[u8; 4]>::try_from(bytes).map_err(|_| s!("signet magic must be exactly 4 bytes long"))
+}
+
+#[derive(Args, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(crate = "serde_crate", rename_all = "camelCase")]
+#[group(args = ["bitcoind", "electrum", "esplora", "mempool"])]
 pub struct ResolverOpt {
+    /// Bitcoin Core node JSON-RPC server to use
+    ///
+    /// The node must not be in the initial block download state. If the URL is not given, the
+    /// default RPC port for the selected network on the localhost is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[arg(
+        long,
+        global = true,
+        default_missing_value = DEFAULT_BITCOIND,
+        num_args = 0..=1,
+        require_equals = true,
+        env = "BP_BITCOIND",
+        value_hint = ValueHint::Url,
+        value_name = "URL"
+    )]
+    pub bitcoind: Option<String>,
+
+    /// Bitcoin Core RPC cookie file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "rpc_user",
+        env = "BP_RPC_COOKIE",
+        value_hint = ValueHint::FilePath,
+        value_name = "FILE"
+    )]
+    pub rpc_cookie: Option<PathBuf>,
+
+    /// Bitcoin Core RPC user name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[arg(long, global = true, env = "BP_RPC_USER")]
+    pub rpc_user: Option<String>,
+
+    /// Bitcoin Core RPC password
+    #[serde(skip)]
+    #[arg(long, global = true, env = "BP_RPC_PASS")]
+    pub rpc_pass: Option<String>,
+
     /// Electrum server to use
+    ///
+    /// The server address may be prefixed with `ssl://` for TLS connections or with `tcp://`
+    /// for plaintext ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     #[arg(
         long,
         global = true,
         default_missing_value = DEFAULT_ELECTRUM,
         num_args = 0..=1,
         require_equals = true,
-        env = "ELECRTUM_SERVER",
+        env = "BP_ELECTRUM",
         value_hint = ValueHint::Url,
         value_name = "URL"
     )]
     pub electrum: Option<String>,
 
     /// Esplora server to use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     #[arg(
         long,
         global = true,
         default_missing_value = DEFAULT_ESPLORA,
         num_args = 0..=1,
         require_equals = true,
-        env = "ESPLORA_SERVER",
+        env = "BP_ESPLORA",
         value_hint = ValueHint::Url,
         value_name = "URL"
     )]
     pub esplora: Option<String>,
 
     /// Mempool server to use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     #[arg(
         long,
         global = true,
         default_missing_value = DEFAULT_MEMPOOL,
         num_args = 0..=1,
         require_equals = true,
-        env = "MEMPOOL_SERVER",
+        env = "BP_MEMPOOL",
         value_hint = ValueHint::Url,
         value_name = "URL"
     )]
     pub mempool: Option<String>,
+
+    /// Timeout for HTTP requests to esplora and mempool servers, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[arg(long, global = true, value_name = "SECS")]
+    pub esplora_timeout: Option<u64>,
+
+    /// Number of retries for indexer requests failed with transient errors during wallet sync
+    /// [default: 0]
+    #[serde(default, alias = "esploraRetries", skip_serializing_if = "Option::is_none")]
+    #[arg(long, global = true, alias = "esplora-retries", value_name = "COUNT")]
+    pub sync_retries: Option<u8>,
+
+    /// Delay before the first retry of a failed indexer request, in milliseconds [default: 250]
+    ///
+    /// Each subsequent retry doubles the delay and adds a random jitter to it. Not supported by
+    /// electrum indexer, which retries requests immediately.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[arg(long, global = true, value_name = "MS")]
+    pub sync_retry_delay: Option<u64>,
+
+    /// Number of addresses queried in parallel during wallet sync with esplora or mempool
+    /// [default: 4]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[arg(long, global = true, value_name = "COUNT")]
+    pub sync_parallelism: Option<usize>,
+
+    /// SOCKS5 proxy to route esplora, mempool and electrum connections through, like
+    /// `socks5://127.0.0.1:9050` for a local Tor daemon
+    ///
+    /// Host names are resolved by the proxy, which is required to access `.onion` servers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[arg(
+        long,
+        global = true,
+        env = "BP_PROXY",
+        value_hint = ValueHint::Url,
+        value_name = "URL"
+    )]
+    pub proxy: Option<String>,
+}
+
+impl ResolverOpt {
+    pub fn is_some(&self) -> bool {
+        self.bitcoind.is_some()
+            || self.electrum.is_some()
+            || self.esplora.is_some()
+            || self.mempool.is_some()
+    }
+
+    /// Returns proxy URL with the `socks5h` scheme, such that DNS resolution happens on the
+    /// proxy side.
+    pub fn socks5h_proxy(&self) -> Option<String> {
+        let proxy = self.proxy.as_ref()?;
+        let addr = proxy
+            .strip_prefix("socks5h://")
+            .or_else(|| proxy.strip_prefix("socks5://"))
+            .unwrap_or(proxy);
+        Some(format!("socks5h://{addr}"))
+    }
+
+    /// Fills the options which are not set from `other`, which has a lower precedence.
+    ///
+    /// The indexer server and the Bitcoin Core RPC credentials are taken from `other` only as a
+    /// whole, such that the values coming from different sources are never mixed up.
+    pub fn merge(&mut self, other: &ResolverOpt) {
+        if !self.is_some() {
+            self.bitcoind = other.bitcoind.clone();
+            self.electrum = other.electrum.clone();
+            self.esplora = other.esplora.clone();
+            self.mempool = other.mempool.clone();
+        }
+        if self.rpc_cookie.is_none() && self.rpc_user.is_none() && self.rpc_pass.is_none() {
+            self.rpc_cookie = other.rpc_cookie.clone();
+            self.rpc_user = other.rpc_user.clone();
+            self.rpc_pass = other.rpc_pass.clone();
+        }
+        self.esplora_timeout = self.esplora_timeout.or(other.esplora_timeout);
+        self.sync_retries = self.sync_retries.or(other.sync_retries);
+        self.sync_retry_delay = self.sync_retry_delay.or(other.sync_retry_delay);
+        self.sync_parallelism = self.sync_parallelism.or(other.sync_parallelism);
+        if self.proxy.is_none() {
+            self.proxy = other.proxy.clone();
+        }
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::new(
+            self.sync_retries.unwrap_or_default(),
+            Duration::from_millis(self.sync_retry_delay.unwrap_or(RETRY_BASE_DELAY_MS)),
+        )
+    }
+
+    pub fn sync_parallelism(&self) -> usize {
+        self.sync_parallelism.unwrap_or(DEFAULT_PARALLELISM)
+    }
+
+    pub fn rpc_auth(&self) -> bitcoind::Auth {
+        match (&self.rpc_cookie, &self.rpc_user) {
+            (Some(cookie), _) => bitcoind::Auth::Cookie(cookie.clone()),
+            (None, Some(user)) => {
+                bitcoind::Auth::UserPass(user.clone(), self.rpc_pass.clone().unwrap_or_default())
+            }
+            (None, None) => bitcoind::Auth::None,
+        }
+    }
+}
+
+/// Default JSON-RPC port used by Bitcoin Core for a given network.
+pub fn bitcoind_rpc_port(network: Network) -> u16 {
+    match network {
+        Network::Mainnet => 8332,
+        Network::Testnet3 => 18332,
+        Network::Testnet4 => 48332,
+        Network::Signet => 38332,
+        Network::Regtest => 18443,
+    }
 }
 
 pub trait DescriptorOpts: clap::Args + Clone + Eq + Debug {
     type Descr: Descriptor + serde::Serialize + for<'de> serde::Deserialize<'de>;
     fn is_some(&self) -> bool;
     fn descriptor(&self) -> Option<Self::Descr>;
+
+    /// Parses wallet descriptor imported from other wallet software. The descriptor may be
+    /// followed by its checksum.
+    ///
+    /// The default implementation doesn't support any descriptors.
+    fn parse_descriptor(s: &str) -> Result<Self::Descr, DescriptorArgError> {
+        let ty = s.split_once('(').map(|(ty, _)| ty).unwrap_or(s);
+        Err(DescriptorArgError::UnsupportedType(ty.to_owned()))
+    }
+
+    /// Number of signatures required to finalize inputs of the wallet `descriptor`.
+    ///
+    /// The default implementation assumes single-signature descriptors.
+    fn required_sigs(_descriptor: &Self::Descr) -> usize { finalize::SINGLE_SIG }
 }
 
 #[derive(Args, Clone, PartialEq, Eq, Debug)]
 #[group(multiple = false)]
 pub struct DescrStdOpts {
     /// Use wpkh(WPKH) descriptor as wallet
-    #[arg(long, global = true)]
-    pub wpkh: Option<XpubDerivable>,
+    ///
+    /// The key may be followed by the `#checksum` of the descriptor, which is verified.
+    #[arg(long, global = true, value_parser = parse_wpkh)]
+    pub wpkh: Option<DescriptorArg<XpubDerivable>>,
 
     /// Use tr(TR_KEY_ONLY) descriptor as wallet
-    #[arg(long, global = true)]
-    pub tr_key_only: Option<XpubDerivable>,
+    ///
+    /// The key may be followed by the `#checksum` of the descriptor, which is verified.
+    #[arg(long, global = true, value_parser = parse_tr_key_only)]
+    pub tr_key_only: Option<DescriptorArg<XpubDerivable>>,
+
+    /// Use the given descriptor as wallet
+    ///
+    /// Descriptors pkh(KEY), wpkh(KEY), tr(KEY), sh(multi(...)) and sh(sortedmulti(...)) are
+    /// supported. The descriptor may be followed by its `#checksum`, which is verified.
+    #[arg(long, global = true, value_parser = parse_std_descriptor)]
+    pub descriptor: Option<DescriptorArg<StdDescr>>,
+}
+
+/// Descriptor or its key given in a command-line argument.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DescriptorArg<T> {
+    pub value: T,
+    /// Notice on the checksum of the given descriptor, which is reported to the user once the
+    /// descriptor is used, since the arguments are parsed before the logging is set up.
+    pub notice: Option<String>,
+}
+
+impl<T: Clone> DescriptorArg<T> {
+    /// Reports the notice on the descriptor checksum, if any, returning the parsed value.
+    pub fn report(&self) -> T {
+        if let Some(notice) = &self.notice {
+            warn!("{notice}");
+        }
+        self.value.clone()
+    }
+}
+
+/// Errors parsing wallet descriptor given in the command-line arguments.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum DescriptorArgError {
+    /// {0}
+    DescriptorChecksum(ChecksumError),
+
+    /// invalid extended public key '{0}': {1}
+    Key(String, String),
+
+    /// taproot script tree '{0}' has more than one leaf, while the standard descriptors can
+    /// compute merkle root of single-leaf trees only.
+    TapTreeLeaves(String),
+
+    /// taproot leaf '{0}' can't be reproduced by the standard descriptors, which lack OP_CHECKSIG
+    /// among the tapscript opcodes; the wallet would derive addresses not matching the
+    /// descriptor.
+    TapLeaf(String),
+
+    /// invalid multisig '{0}': the threshold must not exceed 15 or the number of keys, and up to
+    /// 16 keys can be used.
+    Multisig(String),
+
+    /// {0} descriptors can't be used since the standard descriptors derive addresses for them
+    /// not matching other wallets: P2WSH scripts are hashed with double SHA256 and nested P2WPKH
+    /// uses P2PKH redeem script.
+    NonStandardAddresses(String),
+
+    /// {0}(...) descriptors, including miniscript ones, are not supported by the standard
+    /// descriptors; only pkh(KEY), wpkh(KEY), tr(KEY), sh(multi(...)) and sh(sortedmulti(...))
+    /// can be used.
+    UnsupportedType(String),
+}
+
+/// Verifies the checksum of the descriptor `descr`, if it is present, returning a notice for the
+/// user if the checksum is absent, since a typo in the key would silently produce a different
+/// wallet.
+///
+/// Hardened derivation markers `'` are normalized to `h`; the checksum, if given, must match the
+/// descriptor as it was typed. The notice gives the canonical descriptor with its checksum if it
+/// differs from the given one.
+fn verify_descriptor(descr: &str) -> Result<Option<String>, DescriptorArgError> {
+    let canonical = normalize(descr).map_err(DescriptorArgError::DescriptorChecksum)?;
+    Ok(if !descr.contains('#') {
+        Some(format!(
+            "descriptor is given without a checksum; please verify that it matches {canonical}"
+        ))
+    } else if canonical != descr {
+        Some(format!("descriptor is normalized to {canonical}"))
+    } else {
+        None
+    })
+}
+
+fn parse_key(key: &str) -> Result<XpubDerivable, DescriptorArgError> {
+    let key = normalize_hardened(key.trim());
+    XpubDerivable::from_str(&key).map_err(|err| DescriptorArgError::Key(key, err.to_string()))
+}
+
+/// Parses the key of a single-key descriptor of type `ty`, which may be given either as a key or
+/// as a full descriptor, optionally followed by the descriptor checksum (see
+/// [`verify_descriptor`]).
+fn parse_descriptor_key(
+    s: &str,
+    ty: &str,
+) -> Result<DescriptorArg<XpubDerivable>, DescriptorArgError> {
+    let s = s.trim();
+    let (body, checksum) = s.split_once('#').unwrap_or((s, ""));
+    let key = body
+        .strip_prefix(ty)
+        .and_then(|rest| rest.strip_prefix('('))
+        .and_then(|rest| rest.strip_suffix(')'))
+        .unwrap_or(body);
+    if let Some((other, _)) = key.split_once('(') {
+        return Err(DescriptorArgError::UnsupportedType(other.to_owned()));
+    }
+    let mut descr = format!("{ty}({key})");
+    if !checksum.is_empty() {
+        descr = format!("{descr}#{checksum}");
+    }
+    let notice = verify_descriptor(&descr)?;
+    Ok(DescriptorArg {
+        value: parse_key(key)?,
+        notice,
+    })
+}
+
+/// Checks taproot descriptor with a script tree, `tr(KEY,TREE)`, for its checksum and internal key
+/// and reports the tree part which can't be represented by [`StdDescr::TrTree`]. Descriptors
+/// without a script tree pass the check.
+fn check_tap_tree(s: &str) -> Result<(), DescriptorArgError> {
+    let s = s.trim();
+    let (body, _) = s.split_once('#').unwrap_or((s, ""));
+    let Some((internal_key, tree)) = body
+        .strip_prefix("tr(")
+        .and_then(|rest| rest.strip_suffix(')'))
+        .and_then(|rest| rest.split_once(','))
+    else {
+        return Ok(());
+    };
+    verify_descriptor(s)?;
+    parse_tap_tree(internal_key, tree).map(|_| ())
+}
+
+fn parse_tap_tree(internal_key: &str, tree: &str) -> Result<StdDescr, DescriptorArgError> {
+    parse_key(internal_key)?;
+    let tree = tree.trim();
+    if tree.starts_with('{') {
+        return Err(DescriptorArgError::TapTreeLeaves(tree.to_owned()));
+    }
+    Err(DescriptorArgError::TapLeaf(tree.to_owned()))
+}
+
+/// Splits descriptor expression `ty(args)` into its type and arguments.
+fn split_expr(s: &str) -> Result<(&str, &str), DescriptorArgError> {
+    s.trim()
+        .split_once('(')
+        .and_then(|(ty, rest)| Some((ty, rest.strip_suffix(')')?)))
+        .ok_or_else(|| DescriptorArgError::UnsupportedType(s.trim().to_owned()))
+}
+
+/// Parses arguments of `multi(k,KEY,...)` and `sortedmulti(k,KEY,...)` expressions.
+fn parse_multi(
+    args: &str,
+) -> Result<(u4, Confined<Vec<XpubDerivable>, 1, 16>), DescriptorArgError> {
+    let err = || DescriptorArgError::Multisig(args.to_owned());
+    let mut args = args.split(',');
+    let threshold = args.next().and_then(|k| k.trim().parse::<u8>().ok()).ok_or_else(err)?;
+    let keys = args.map(parse_key).collect::<Result<Vec<_>, _>>()?;
+    if threshold == 0 || threshold as usize > keys.len() {
+        return Err(err());
+    }
+    let threshold = u4::try_from(threshold).map_err(|_| err())?;
+    let keys = Confined::try_from(keys).map_err(|_| err())?;
+    Ok((threshold, keys))
+}
+
+/// Parses descriptor `body`, given without the checksum, into one of the standard descriptor
+/// templates.
+///
+/// Descriptors with P2WSH scripts, `wsh(...)` and `sh(wsh(...))`, and `sh(wpkh(...))` are checked
+/// for their keys and then rejected, since the standard descriptors derive addresses for them
+/// unknown to other wallets and unspendable by any of them: P2WSH scripts are hashed with double
+/// SHA256, and nested P2WPKH uses P2PKH redeem script.
+fn parse_std_descr(body: &str) -> Result<StdDescr, DescriptorArgError> {
+    let (ty, args) = split_expr(body)?;
+    Ok(match ty {
+        "pkh" => Pkh::from(parse_key(args)?).into(),
+        "wpkh" => Wpkh::from(parse_key(args)?).into(),
+        "tr" => match args.split_once(',') {
+            Some((internal_key, tree)) => return parse_tap_tree(internal_key, tree),
+            None => TrKey::from(parse_key(args)?).into(),
+        },
+        "sh" => match split_expr(args)? {
+            ("wpkh", key) => {
+                parse_key(key)?;
+                return Err(DescriptorArgError::NonStandardAddresses(s!("sh(wpkh(...))")));
+            }
+            ("multi", args) => {
+                let (threshold, keys) = parse_multi(args)?;
+                ShMulti { threshold, keys }.into()
+            }
+            ("sortedmulti", args) => {
+                let (threshold, keys) = parse_multi(args)?;
+                ShSortedMulti { threshold, keys }.into()
+            }
+            ("wsh", script) => {
+                check_wsh_multi(script)?;
+                return Err(DescriptorArgError::NonStandardAddresses(s!("sh(wsh(...))")));
+            }
+            (ty, _) => return Err(DescriptorArgError::UnsupportedType(ty.to_owned())),
+        },
+        "wsh" => {
+            check_wsh_multi(args)?;
+            return Err(DescriptorArgError::NonStandardAddresses(s!("wsh(...)")));
+        }
+        ty => return Err(DescriptorArgError::UnsupportedType(ty.to_owned())),
+    })
+}
+
+/// Checks `multi(...)` or `sortedmulti(...)` script of a P2WSH descriptor.
+fn check_wsh_multi(script: &str) -> Result<(), DescriptorArgError> {
+    match split_expr(script)? {
+        ("multi" | "sortedmulti", args) => parse_multi(args).map(|_| ()),
+        (ty, _) => Err(DescriptorArgError::UnsupportedType(ty.to_owned())),
+    }
+}
+
+/// Parses descriptor given with an optional checksum into one of the standard descriptor
+/// templates (see [`parse_std_descr`]).
+fn parse_std_descriptor(s: &str) -> Result<DescriptorArg<StdDescr>, DescriptorArgError> {
+    let s = s.trim();
+    let notice = verify_descriptor(s)?;
+    let (body, _) = s.split_once('#').unwrap_or((s, ""));
+    Ok(DescriptorArg {
+        value: parse_std_descr(body)?,
+        notice,
+    })
+}
+
+fn parse_wpkh(s: &str) -> Result<DescriptorArg<XpubDerivable>, DescriptorArgError> {
+    parse_descriptor_key(s, "wpkh")
+}
+
+fn parse_tr_key_only(s: &str) -> Result<DescriptorArg<XpubDerivable>, DescriptorArgError> {
+    check_tap_tree(s)?;
+    parse_descriptor_key(s, "tr")
 }
 
 impl DescriptorOpts for DescrStdOpts {
     type Descr = StdDescr;
 
-    fn is_some(&self) -> bool { self.tr_key_only.is_some() | self.wpkh.is_some() }
+    fn is_some(&self) -> bool {
+        self.tr_key_only.is_some() | self.wpkh.is_some() | self.descriptor.is_some()
+    }
     fn descriptor(&self) -> Option<Self::Descr> {
-        if let Some(ref x) = self.tr_key_only {
-            Some(TrKey::from(x.clone()).into())
+        if let Some(ref x) = self.descriptor {
+            Some(x.report())
+        } else if let Some(ref x) = self.tr_key_only {
+            Some(TrKey::from(x.report()).into())
         } else {
-            self.wpkh.as_ref().map(|x| Wpkh::from(x.clone()).into())
+            self.wpkh.as_ref().map(|x| Wpkh::from(x.report()).into())
         }
     }
+
+    fn parse_descriptor(s: &str) -> Result<Self::Descr, DescriptorArgError> {
+        parse_std_descriptor(s).map(|arg| arg.report())
+    }
+
+    fn required_sigs(descriptor: &Self::Descr) -> usize { finalize::required_sigs(descriptor) }
 }
 
 #[derive(Args, Clone, PartialEq, Eq, Debug)]
@@ -156,24 +656,230 @@ pub struct GeneralOpts {
     pub data_dir: PathBuf,
 
     /// Network to use
-    #[arg(short, long, global = true, default_value = "testnet3", env = "LNPBP_NETWORK")]
+    #[arg(short, long, global = true, default_value = "testnet3", env = "BP_NETWORK")]
     pub network: Network,
 
+    /// Network magic of a custom signet, in hex
+    ///
+    /// Wallets for a custom signet are kept apart from the default signet ones. Custom signets
+    /// have no public indexers, so the indexer server URL must be given explicitly; a Bitcoin
+    /// Core node is checked to run the selected signet. Addresses are the same on all signets.
+    #[arg(long, global = true, value_name = "HEX", value_parser = parse_signet_magic)]
+    pub signet_magic: Option<[u8; 4]>,
+
     /// Do not add network prefix to the `--data-dir`
     #[arg(long = "no-network-prefix", global = true)]
     pub no_prefix: bool,
+
+    /// Never access the network
+    ///
+    /// Disables wallet sync even if `--sync` or a descriptor is given; commands which require
+    /// a blockchain indexer fail.
+    #[arg(long, global = true, env = "BP_OFFLINE")]
+    pub offline: bool,
+
+    /// Seconds to wait for the wallet to be released by another `bp` process
+    ///
+    /// By default, commands fail immediately if the wallet is in use by another process.
+    #[arg(long, global = true, default_value = "0", value_name = "SECS")]
+    pub wait_lock: u64,
+
+    /// Format of the command output
+    ///
+    /// Progress information is printed to STDERR in `human` format only, so with `json` or
+    /// `yaml` formats STDOUT contains only the serialized command result.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Human, env = "BP_FORMAT")]
+    pub format: OutputFormat,
+
+    /// Print command results as JSON; same as `--format json`
+    #[arg(long, global = true, conflicts_with = "format")]
+    pub json: bool,
+
+    /// Unit in which amounts are printed
+    ///
+    /// Affects only the human-readable output; JSON and other serialized formats always contain
+    /// amounts in satoshis.
+    #[arg(long, global = true, value_enum, default_value_t = AmountUnit::Sat, env = "BP_UNIT")]
+    pub unit: AmountUnit,
+
+    /// Separate thousands in amounts printed in satoshis with commas
+    #[arg(long, global = true)]
+    pub group_digits: bool,
+}
+
+/// Unit in which amounts are printed in the human-readable command output.
+#[derive(ValueEnum, Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Display)]
+pub enum AmountUnit {
+    /// Satoshis
+    #[default]
+    #[display("sat")]
+    Sat,
+
+    /// Bitcoins, always with 8 decimal digits
+    #[display("btc")]
+    Btc,
+}
+
+impl AmountUnit {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            AmountUnit::Sat => "ṩ",
+            AmountUnit::Btc => "BTC",
+        }
+    }
+}
+
+/// Formatting of amounts in the human-readable command output, selected with `--unit` and
+/// `--group-digits` arguments.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct AmountFormat {
+    pub unit: AmountUnit,
+    pub group_digits: bool,
+}
+
+impl AmountFormat {
+    #[inline]
+    pub fn symbol(self) -> &'static str { self.unit.symbol() }
+
+    /// Formats the amount without the unit symbol.
+    pub fn sats(self, amount: Sats) -> String { self.format(false, amount.sats()) }
+
+    /// Formats the amount, which may be negative, without the unit symbol.
+    pub fn signed(self, amount: i64) -> String { self.format(amount < 0, amount.unsigned_abs()) }
+
+    fn format(self, negative: bool, sats: u64) -> String {
+        let sign = if negative { "-" } else { "" };
+        match self.unit {
+            AmountUnit::Btc => format!("{sign}{}.{:08}", sats / 100_000_000, sats % 100_000_000),
+            AmountUnit::Sat if self.group_digits => {
+                let digits = sats.to_string();
+                let mut grouped = String::with_capacity(digits.len() * 4 / 3 + 1);
+                grouped.push_str(sign);
+                for (pos, digit) in digits.chars().enumerate() {
+                    if pos > 0 && (digits.len() - pos) % 3 == 0 {
+                        grouped.push(',');
+                    }
+                    grouped.push(digit);
+                }
+                grouped
+            }
+            AmountUnit::Sat => format!("{sign}{sats}"),
+        }
+    }
+}
+
+/// Format in which commands print their results.
+///
+/// Available to all [`crate::cli::Exec`] implementations via [`GeneralOpts::format`], such that
+/// commands can either print human-readable text or pass their result to
+/// [`OutputFormat::print`].
+#[derive(ValueEnum, Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Display)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    #[display("human")]
+    Human,
+
+    /// JSON document
+    #[display("json")]
+    Json,
+
+    /// YAML document
+    #[display("yaml")]
+    Yaml,
+
+    /// CSV table with a row for each element of a list; nested values are written as JSON
+    #[display("csv")]
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn is_human(self) -> bool { self == OutputFormat::Human }
+
+    /// Prints serialized `value` to STDOUT.
+    ///
+    /// In `human` format the value is printed as YAML, which is the closest to a human-readable
+    /// form any serializable value can have; commands are expected to produce their own text for
+    /// that format.
+    pub fn print(self, value: &impl serde::Serialize) {
+        match self {
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(value)
+                    .expect("unable to generate JSON representation")
+            ),
+            OutputFormat::Human | OutputFormat::Yaml => println!(
+                "{}",
+                serde_yaml::to_string(value).expect("unable to generate YAML representation")
+            ),
+            OutputFormat::Csv => print!(
+                "{}",
+                csv_table(
+                    &serde_json::to_value(value).expect("unable to generate CSV representation")
+                )
+            ),
+        }
+    }
+}
+
+/// Formats a list of JSON objects as a CSV table, using the keys of the first object as the
+/// columns. A single object is formatted as a table with one row.
+fn csv_table(value: &serde_json::Value) -> String {
+    use serde_json::Value;
+
+    use crate::export::csv_cell;
+
+    fn cell(value: Option<&Value>) -> String {
+        match value {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(s)) => csv_cell(s),
+            Some(value @ (Value::Array(_) | Value::Object(_))) => csv_cell(&value.to_string()),
+            Some(value) => value.to_string(),
+        }
+    }
+
+    let rows = match value {
+        Value::Array(rows) => rows.as_slice(),
+        value => std::slice::from_ref(value),
+    };
+    let columns = match rows.first() {
+        Some(Value::Object(first)) => first.keys().cloned().collect::<Vec<_>>(),
+        _ => return rows.iter().map(|row| cell(Some(row)) + "\n").collect(),
+    };
+    let mut table = columns.join(",") + "\n";
+    for row in rows {
+        let line = columns.iter().map(|column| cell(row.get(column))).collect::<Vec<_>>().join(",");
+        table.push_str(&line);
+        table.push('\n');
+    }
+    table
 }
 
 impl GeneralOpts {
     pub fn process(&mut self) {
+        if self.json {
+            self.format = OutputFormat::Json;
+        }
         self.data_dir =
             PathBuf::from(shellexpand::tilde(&self.data_dir.display().to_string()).to_string());
+        if self.signet_magic.is_some() && self.network != Network::Signet {
+            eprintln!("Warning: --signet-magic is ignored for {} network", self.network);
+        }
+    }
+
+    /// Returns network magic of a custom signet, if one is selected.
+    pub fn custom_signet(&self) -> Option<[u8; 4]> {
+        self.signet_magic
+            .filter(|magic| self.network == Network::Signet && *magic != DEFAULT_SIGNET_MAGIC)
     }
 
     pub fn base_dir(&self) -> PathBuf {
         let mut dir = self.data_dir.clone();
         if !self.no_prefix {
-            dir.push(self.network.to_string());
+            match self.custom_signet() {
+                Some(magic) => dir.push(format!("signet-{}", magic.to_hex())),
+                None => dir.push(self.network.to_string()),
+            }
         }
         dir
     }
@@ -183,4 +889,226 @@ impl GeneralOpts {
         dir.push(wallet_name);
         dir
     }
+
+    pub fn amount_format(&self) -> AmountFormat {
+        AmountFormat {
+            unit: self.unit,
+            group_digits: self.group_digits,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bpstd::{AddressNetwork, DeriveScripts};
+    use descriptors::{ShWpkh, WshSortedMulti};
+
+    use super::*;
+    use crate::checksum::{descriptor_checksum, with_checksum};
+
+    #[test]
+    fn test_amount_format() {
+        let mut fmt = AmountFormat::default();
+        assert_eq!(fmt.sats(Sats(1234567)), "1234567");
+        fmt.group_digits = true;
+        assert_eq!(fmt.sats(Sats(1234567)), "1,234,567");
+        assert_eq!(fmt.signed(-123456), "-123,456");
+        assert_eq!(fmt.sats(Sats::ZERO), "0");
+
+        fmt.unit = AmountUnit::Btc;
+        assert_eq!(fmt.sats(Sats(1234567)), "0.01234567");
+        assert_eq!(fmt.sats(Sats::from(2_100_000_000_000_000u64)), "21000000.00000000");
+        assert_eq!(fmt.signed(-1), "-0.00000001");
+    }
+
+    const XPUB: &str = "xpub6DJ2dNUysrn5Vt36jH2KLBT2i1auw1tTSSomg8PhqNiUtx8QX2SvC9nrHu81fT41fvDUnhMjEzQgXnQjKEu3oaqMSzhSrHMxyyoEAmUHQbY";
+    const XPUB2: &str = "xpub6D3jMtEzUFnbvcyE6k9rx1ycmRinaJ8ihE5vLMQYxDTR6pnCBHfcMRFftHPEbdy6iio6bQw1R4uV2vLpcvGFa5qS9mDzZrRQRtZ4ETfKPjm";
+
+    fn address(descr: &StdDescr, keychain: u8, index: u16) -> String {
+        descr.derive_address(AddressNetwork::Mainnet, keychain, index).next().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_parse_descriptor() {
+        let a = format!("[d34db33f/48h/0h/0h]{XPUB}/<0;1>/*");
+        let b = format!("[3442193e/48h/0h/1h]{XPUB2}/<0;1>/*");
+        // Addresses are computed independently from BIP-32 public derivation of both keys; at
+        // index 0/0 the key of `b` sorts after the key of `a`, at index 1/2 before it.
+        let vectors = [
+            (
+                format!("sh(sortedmulti(2,{b},{a}))"),
+                "3Pd6uysjKGFKSoDa16p76fnbffJn2L6GBv",
+                "3QML3dsmXNGAHkhh6WGdY3N8zJmqxXDuHh",
+                2,
+            ),
+            (
+                format!("sh(multi(2,{b},{a}))"),
+                "3M726YnPGqczrXCVw2wea5nTRUTxswNxCG",
+                "3QML3dsmXNGAHkhh6WGdY3N8zJmqxXDuHh",
+                2,
+            ),
+            (
+                format!("pkh({a})"),
+                "17SxZefMhyWx6cFVnkeoPTNergRHYbeDbY",
+                "1LYPRmNCT9Xqw24ds6DnUpYBbdJPkLXTLk",
+                1,
+            ),
+            (
+                format!("wpkh({a})"),
+                "bc1qg6ucjz7kgdedam7v5yarecy54uqw82yym06z3q",
+                "bc1q6edqzl9tz66zdj3e2ejswwkjv4wved6wvgpfg3",
+                1,
+            ),
+        ];
+        for (descr, receive, change, required) in vectors {
+            let descr = with_checksum(&descr).unwrap();
+            let parsed = DescrStdOpts::parse_descriptor(&descr).unwrap();
+            assert_eq!(address(&parsed, 0, 0), receive, "{descr}");
+            assert_eq!(address(&parsed, 1, 2), change, "{descr}");
+            assert_eq!(DescrStdOpts::required_sigs(&parsed), required, "{descr}");
+        }
+
+        for threshold in [0, 3, 16] {
+            let args = format!("{threshold},{a},{b}");
+            assert_eq!(
+                DescrStdOpts::parse_descriptor(&format!("sh(multi({args}))")),
+                Err(DescriptorArgError::Multisig(args))
+            );
+        }
+        assert_eq!(
+            DescrStdOpts::parse_descriptor(&format!("wsh(and_v(v:pk({a}),older(144)))")),
+            Err(DescriptorArgError::UnsupportedType(s!("and_v")))
+        );
+    }
+
+    #[test]
+    fn test_non_standard_addresses() {
+        let a = format!("[d34db33f/48h/0h/0h]{XPUB}/<0;1>/*");
+        let b = format!("[3442193e/48h/0h/1h]{XPUB2}/<0;1>/*");
+        assert_eq!(
+            DescrStdOpts::parse_descriptor(&format!("wsh(sortedmulti(2,{b},{a}))")),
+            Err(DescriptorArgError::NonStandardAddresses(s!("wsh(...)")))
+        );
+        assert_eq!(
+            DescrStdOpts::parse_descriptor(&format!("sh(wsh(multi(2,{b},{a})))")),
+            Err(DescriptorArgError::NonStandardAddresses(s!("sh(wsh(...))")))
+        );
+        assert_eq!(
+            DescrStdOpts::parse_descriptor(&format!("sh(wpkh({a}))")),
+            Err(DescriptorArgError::NonStandardAddresses(s!("sh(wpkh(...))")))
+        );
+        assert_eq!(
+            DescrStdOpts::parse_descriptor(&format!("wsh(sortedmulti(3,{b},{a}))")),
+            Err(DescriptorArgError::Multisig(format!("3,{b},{a}")))
+        );
+
+        // Once the standard descriptors derive the addresses computed independently for
+        // wsh(sortedmulti(2,b,a)) and sh(wpkh(a)), these descriptors can be accepted.
+        let (threshold, keys) = parse_multi(&format!("2,{b},{a}")).unwrap();
+        let descr = StdDescr::from(WshSortedMulti { threshold, keys });
+        assert_ne!(
+            address(&descr, 0, 0),
+            "bc1qt2ktqhqpuc8hx33ny8a7uu9tnvwgdzd0d08fska2zj8adkqvzaks2339sl"
+        );
+        let descr = StdDescr::from(ShWpkh::from(parse_key(&a).unwrap()));
+        assert_ne!(address(&descr, 0, 0), "3CtePBnG48gZrPZRtciz9N7yfdKxJSBxov");
+    }
+
+    #[test]
+    fn test_checksum_notice() {
+        let key = format!("[d34db33f/84h/0h/0h]{XPUB}/<0;1>/*");
+        let descr = with_checksum(&format!("wpkh({key})")).unwrap();
+
+        let parsed = parse_wpkh(&key).unwrap();
+        assert_eq!(parsed.value.to_string(), key);
+        assert!(parsed.notice.as_ref().unwrap().ends_with(&descr));
+        assert_eq!(parse_wpkh(&descr).unwrap().notice, None);
+        let (_, checksum) = descr.split_once('#').unwrap();
+        assert_eq!(parse_wpkh(&format!("{key}#{checksum}")).unwrap().notice, None);
+        let typo = descr.replace("84h", "85h");
+        assert!(matches!(parse_wpkh(&typo), Err(DescriptorArgError::DescriptorChecksum(_))));
+
+        let opts = DescrStdOpts {
+            wpkh: Some(parsed),
+            tr_key_only: None,
+            descriptor: None,
+        };
+        let wpkh = opts.descriptor().unwrap();
+        assert_eq!(wpkh.to_string(), descr.split_once('#').unwrap().0);
+        let opts = DescrStdOpts {
+            wpkh: None,
+            tr_key_only: None,
+            descriptor: Some(parse_std_descriptor(&descr).unwrap()),
+        };
+        assert_eq!(opts.descriptor(), Some(wpkh));
+    }
+
+    #[test]
+    fn test_descriptor_round_trip() {
+        let a = format!("[d34db33f/48'/0'/0']{XPUB}/<0;1>/*");
+        let b = format!("[3442193e/48'/0'/1']{XPUB2}/<0;1>/*");
+        for typed in [
+            format!("wpkh({a})"),
+            format!("tr({a})"),
+            format!("pkh({a})"),
+            format!("sh(multi(2,{b},{a}))"),
+            format!("sh(sortedmulti(2,{b},{a}))"),
+        ] {
+            // Wallet stores the descriptor in the canonical form, which is reported to the user
+            let canonical = normalize(&typed).unwrap();
+            let parsed = parse_std_descriptor(&typed).unwrap();
+            assert!(parsed.notice.as_ref().unwrap().ends_with(&canonical), "{typed}");
+            assert_eq!(with_checksum(&parsed.value.to_string()).unwrap(), canonical);
+
+            let reparsed = parse_std_descriptor(&canonical).unwrap();
+            assert_eq!(reparsed.notice, None);
+            assert_eq!(reparsed.value, parsed.value);
+            let checksummed = with_checksum(&typed).unwrap();
+            let reparsed = parse_std_descriptor(&checksummed).unwrap();
+            assert_eq!(reparsed.notice, Some(format!("descriptor is normalized to {canonical}")));
+            assert_eq!(reparsed.value, parsed.value);
+
+            // Checksum of another descriptor is reported with the expected one
+            let (_, found) = canonical.split_once('#').unwrap();
+            assert_eq!(
+                parse_std_descriptor(&format!("{typed}#{found}")),
+                Err(DescriptorArgError::DescriptorChecksum(ChecksumError::Mismatch {
+                    expected: descriptor_checksum(&typed).unwrap(),
+                    found: found.to_owned(),
+                }))
+            );
+        }
+
+        // Nested P2WPKH is normalized and checked, but rejected for its non-standard addresses
+        let typed = format!("sh(wpkh({a}))");
+        let canonical = normalize(&typed).unwrap();
+        assert_eq!(canonical, with_checksum(&typed.replace('\'', "h")).unwrap());
+        assert_eq!(
+            parse_std_descriptor(&canonical),
+            Err(DescriptorArgError::NonStandardAddresses(s!("sh(wpkh(...))")))
+        );
+    }
+
+    #[test]
+    fn test_tap_tree() {
+        let key = format!("[d34db33f/86h/0h/0h]{XPUB}/<0;1>/*");
+
+        let descr = parse_tr_key_only(&format!("tr({key})")).unwrap();
+        assert_eq!(descr.value.to_string(), key);
+
+        let leaf = format!("pk({key})");
+        assert_eq!(
+            parse_tr_key_only(&format!("tr({key},{leaf})")),
+            Err(DescriptorArgError::TapLeaf(leaf.clone()))
+        );
+        let tree = format!("{{{leaf},{leaf}}}");
+        assert_eq!(
+            parse_tr_key_only(&format!("tr({key},{tree})")),
+            Err(DescriptorArgError::TapTreeLeaves(tree))
+        );
+        assert!(matches!(
+            parse_tr_key_only(&format!("tr({XPUB}x,{leaf})")),
+            Err(DescriptorArgError::Key(..))
+        ));
+    }
 }
@@ -20,8 +20,507 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::WalletUtxo;
+//! Coin selection: choosing wallet UTXOs to fund a transaction.
+
+use std::cmp;
 
-// TODO: Use traits and structs with internal state
+use bpstd::{Outpoint, Sats};
+
+use crate::WalletUtxo;
 
 pub fn all(_: &WalletUtxo) -> bool { true }
+
+/// Filter accepting only UTXOs created by mined transactions.
+pub fn confirmed(utxo: &WalletUtxo) -> bool { utxo.status.is_mined() }
+
+/// Default minimal amount of a change output, matching the dust threshold of P2PKH outputs.
+pub const MIN_CHANGE: Sats = Sats(546);
+
+/// Maximal number of combinations tried by [`Strategy::BranchAndBound`] before falling back to
+/// [`Strategy::LargestFirst`].
+pub const BNB_MAX_TRIES: u32 = 100_000;
+
+/// Parameters of a transaction which has to be funded by the coin selection.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SelectionParams {
+    /// Amount paid to the beneficiaries.
+    pub target: Sats,
+    /// Fee rate, in satoshis per virtual byte.
+    pub fee_rate: f64,
+    /// Size of the transaction without the inputs and the change output, in virtual bytes.
+    pub base_vsize: u32,
+    /// Size of each of the wallet inputs, in virtual bytes.
+    pub input_vsize: u32,
+    /// Size of the change output, in virtual bytes.
+    pub change_vsize: u32,
+    /// Amount which the change output must exceed; smaller change is left to miners as a part of
+    /// the fee.
+    pub min_change: Sats,
+    /// Maximal amount by which the changeless selection of [`Strategy::BranchAndBound`] may
+    /// exceed the target amount and the fee, leaving the excess to miners. Defaults to the cost
+    /// of the change output plus the minimal change.
+    pub changeless_window: Option<Sats>,
+    /// Handling of the change below [`SelectionParams::min_change`] by the strategies adding
+    /// coins one by one.
+    pub dust_policy: DustPolicy,
+    /// Minimal number of inputs; the strategies adding coins one by one keep adding them after
+    /// the target amount is covered, consolidating the coins into the change.
+    pub min_inputs: Option<usize>,
+    /// Maximal number of inputs; the selection fails if the target amount can't be covered by
+    /// this number of coins. Strategies which can't fit into the limit in their own coin order
+    /// fall back to the largest-first selection.
+    pub max_inputs: Option<usize>,
+}
+
+/// Handling of the change which is too small to create a change output above the dust limit.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Display)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+pub enum DustPolicy {
+    /// Leave the change to miners as a part of the fee
+    #[default]
+    #[display("burn")]
+    Burn,
+
+    /// Add more coins, such that the change exceeds the dust limit; if there are no more coins,
+    /// the change is left to miners
+    #[display("add-input")]
+    AddInput,
+}
+
+impl SelectionParams {
+    /// Fee for a transaction with a given number of `inputs` and an optional `change` output.
+    pub fn fee(&self, inputs: usize, change: bool) -> Sats {
+        let vsize = self.base_vsize
+            + self.input_vsize * inputs as u32
+            + if change { self.change_vsize } else { 0 };
+        Sats((vsize as f64 * self.fee_rate).ceil() as u64)
+    }
+
+    fn input_fee(&self) -> u64 { (self.input_vsize as f64 * self.fee_rate).ceil() as u64 }
+
+    /// Checks whether the number of inputs is within [`SelectionParams::min_inputs`] and
+    /// [`SelectionParams::max_inputs`] limits.
+    pub fn allows_inputs(&self, count: usize) -> bool {
+        self.min_inputs.is_none_or(|min| count >= min)
+            && self.max_inputs.is_none_or(|max| count <= max)
+    }
+
+    /// Computes fee and change for the given `inputs` with a total amount of `total` satoshis.
+    ///
+    /// Returns `None` if the inputs do not cover the target amount and the fee.
+    fn finish(&self, inputs: Vec<Outpoint>, total: u64) -> Option<Selection> {
+        let target = self.target.sats();
+        let fee = self.fee(inputs.len(), false).sats();
+        if total < target + fee {
+            return None;
+        }
+        let fee_with_change = self.fee(inputs.len(), true).sats();
+        match total.checked_sub(target + fee_with_change) {
+            Some(change) if change > self.min_change.sats() => Some(Selection {
+                inputs,
+                fee: Sats(fee_with_change),
+                change: Sats(change),
+                dropped_change: Sats::ZERO,
+                strategy: None,
+            }),
+            _ => Some(Selection {
+                inputs,
+                fee: Sats(total - target),
+                change: Sats::ZERO,
+                dropped_change: Sats(total - target - fee),
+                strategy: None,
+            }),
+        }
+    }
+}
+
+/// Coins chosen to fund a transaction.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Selection {
+    pub inputs: Vec<Outpoint>,
+    /// Transaction fee, which includes change too small to be worth an output.
+    pub fee: Sats,
+    /// Amount of the change output; zero if the transaction has no change.
+    pub change: Sats,
+    /// Amount in excess of the fee required by the fee rate, which is left to miners as a part
+    /// of the fee since it is not enough for a change output.
+    pub dropped_change: Sats,
+    /// Strategy which has chosen the coins, if it is one of the library strategies.
+    pub strategy: Option<Strategy>,
+}
+
+impl Selection {
+    /// Checks whether the transaction needs a change output.
+    pub fn has_change(&self) -> bool { self.change > Sats::ZERO }
+}
+
+/// Coin selection algorithm.
+pub trait CoinSelect {
+    /// Chooses coins from the `candidates` funding a transaction with the given `params`.
+    ///
+    /// Returns `None` if the candidates are insufficient to pay the target amount and the fee.
+    fn select(&self, candidates: &[WalletUtxo], params: &SelectionParams) -> Option<Selection>;
+}
+
+/// Coin selection strategies provided by the library.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Display)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+pub enum Strategy {
+    /// Spend the largest coins first, minimizing the number of inputs
+    #[default]
+    #[display("largest-first")]
+    LargestFirst,
+
+    /// Spend the smallest coins first, consolidating dust
+    #[display("smallest-first")]
+    SmallestFirst,
+
+    /// Search for a combination of coins not requiring change output, falling back to the
+    /// largest-first selection if there is none
+    #[display("branch-and-bound")]
+    BranchAndBound,
+
+    /// Spend the coins with the most confirmations first; unconfirmed coins are spent last
+    #[display("oldest-first")]
+    OldestFirst,
+}
+
+impl CoinSelect for Strategy {
+    fn select(&self, candidates: &[WalletUtxo], params: &SelectionParams) -> Option<Selection> {
+        let mut candidates = candidates.to_vec();
+        match self {
+            Strategy::LargestFirst => {
+                candidates.sort_by_key(|utxo| cmp::Reverse(utxo.value));
+            }
+            Strategy::SmallestFirst => {
+                candidates.sort_by_key(|utxo| utxo.value);
+            }
+            Strategy::OldestFirst => {
+                candidates.sort_by_key(|utxo| (!utxo.status.is_mined(), utxo.status.height()));
+            }
+            Strategy::BranchAndBound => {
+                return branch_and_bound(&candidates, params)
+                    .filter(|selection| params.allows_inputs(selection.inputs.len()))
+                    .map(|selection| Selection {
+                        strategy: Some(Strategy::BranchAndBound),
+                        ..selection
+                    })
+                    .or_else(|| Strategy::LargestFirst.select(&candidates, params));
+            }
+        }
+        accumulate(&candidates, params)
+            .map(|selection| Selection {
+                strategy: Some(*self),
+                ..selection
+            })
+            .or_else(|| match (self, params.max_inputs) {
+                (Strategy::LargestFirst, _) | (_, None) => None,
+                // The coin order of the strategy may need more inputs than the limit allows
+                (_, Some(_)) => Strategy::LargestFirst.select(&candidates, params),
+            })
+    }
+}
+
+/// Adds `candidates` in their order until they cover the target amount and the fee, using no
+/// less than [`SelectionParams::min_inputs`] and no more than [`SelectionParams::max_inputs`]
+/// candidates.
+///
+/// With [`DustPolicy::AddInput`], keeps adding candidates while the change is too small for a
+/// change output, falling back to the first selection covering the target if no selection has
+/// enough change.
+fn accumulate(candidates: &[WalletUtxo], params: &SelectionParams) -> Option<Selection> {
+    let mut inputs = vec![];
+    let mut total = 0u64;
+    let mut dusty = None;
+    let max_inputs = params.max_inputs.unwrap_or(usize::MAX);
+    for utxo in candidates.iter().take(max_inputs) {
+        inputs.push(utxo.outpoint);
+        total += utxo.value.sats();
+        if inputs.len() < params.min_inputs.unwrap_or_default() {
+            continue;
+        }
+        if total >= params.target.sats() + params.fee(inputs.len(), false).sats() {
+            let selection = params.finish(inputs.clone(), total)?;
+            if params.dust_policy == DustPolicy::Burn || selection.dropped_change == Sats::ZERO {
+                return Some(selection);
+            }
+            dusty.get_or_insert(selection);
+        }
+    }
+    dusty
+}
+
+/// Searches for a set of coins covering the target amount and the fee without a change output,
+/// wasting no more than the cost of a change output.
+fn branch_and_bound(candidates: &[WalletUtxo], params: &SelectionParams) -> Option<Selection> {
+    let input_fee = params.input_fee();
+    // Values of the coins after paying for their own inputs
+    let mut effective = candidates
+        .iter()
+        .filter(|utxo| utxo.value.sats() > input_fee)
+        .map(|utxo| (utxo.value.sats() - input_fee, utxo))
+        .collect::<Vec<_>>();
+    effective.sort_by_key(|(value, _)| cmp::Reverse(*value));
+
+    let target = params.target.sats() + params.fee(0, false).sats();
+    let tolerance = params.changeless_window.as_ref().map(Sats::sats).unwrap_or_else(|| {
+        params.fee(0, true).sats() - params.fee(0, false).sats() + params.min_change.sats()
+    });
+    let values = effective.iter().map(|(value, _)| *value).collect::<Vec<_>>();
+    let remaining = values.iter().sum::<u64>();
+
+    let mut selection = vec![];
+    let mut tries = BNB_MAX_TRIES;
+    if !bnb_search(&values, 0, 0, remaining, target, tolerance, &mut selection, &mut tries) {
+        return None;
+    }
+    let inputs = selection.iter().map(|idx| effective[*idx].1.outpoint).collect();
+    let total = selection.iter().map(|idx| effective[*idx].1.value.sats()).sum();
+    params.finish(inputs, total)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn bnb_search(
+    values: &[u64],
+    idx: usize,
+    selected: u64,
+    remaining: u64,
+    target: u64,
+    tolerance: u64,
+    selection: &mut Vec<usize>,
+    tries: &mut u32,
+) -> bool {
+    if *tries == 0 {
+        return false;
+    }
+    *tries -= 1;
+    if selected > target + tolerance {
+        return false;
+    }
+    if selected >= target {
+        return true;
+    }
+    if idx == values.len() || selected + remaining < target {
+        return false;
+    }
+    let value = values[idx];
+    selection.push(idx);
+    if bnb_search(
+        values,
+        idx + 1,
+        selected + value,
+        remaining - value,
+        target,
+        tolerance,
+        selection,
+        tries,
+    ) {
+        return true;
+    }
+    selection.pop();
+    bnb_search(values, idx + 1, selected, remaining - value, target, tolerance, selection, tries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bpstd::{Terminal, Txid, Vout};
+
+    use super::*;
+    use crate::{MiningInfo, TxStatus};
+
+    fn utxo(no: u32, value: u64) -> WalletUtxo {
+        let txid =
+            Txid::from_str("cca7507897abc89628f450e8b1e0c6fca4ec3f7b34cccf55f3f531c659ff4d79")
+                .unwrap();
+        WalletUtxo {
+            outpoint: Outpoint::new(txid, Vout::from_u32(no)),
+            value: Sats(value),
+            terminal: Terminal::from_str("&0/0").unwrap(),
+            status: TxStatus::Mempool,
+        }
+    }
+
+    fn params(target: u64) -> SelectionParams {
+        SelectionParams {
+            target: Sats(target),
+            fee_rate: 1.0,
+            base_vsize: 10,
+            input_vsize: 68,
+            change_vsize: 31,
+            min_change: Sats(546),
+            changeless_window: None,
+            dust_policy: DustPolicy::Burn,
+            min_inputs: None,
+            max_inputs: None,
+        }
+    }
+
+    #[test]
+    fn test_largest_and_smallest_first() {
+        let coins = [utxo(0, 1_000), utxo(1, 50_000), utxo(2, 10_000)];
+
+        let selection = Strategy::LargestFirst.select(&coins, &params(20_000)).unwrap();
+        assert_eq!(selection.inputs, vec![coins[1].outpoint]);
+        assert_eq!(selection.fee, Sats(10 + 68 + 31));
+        assert_eq!(selection.strategy, Some(Strategy::LargestFirst));
+        assert_eq!(selection.change, Sats(50_000 - 20_000 - 109));
+
+        let selection = Strategy::SmallestFirst.select(&coins, &params(5_000)).unwrap();
+        assert_eq!(selection.inputs, vec![coins[0].outpoint, coins[2].outpoint]);
+
+        assert_eq!(Strategy::LargestFirst.select(&coins, &params(61_000)), None);
+    }
+
+    #[test]
+    fn test_branch_and_bound_changeless() {
+        let coins = [utxo(0, 50_000), utxo(1, 7_000), utxo(2, 3_100), utxo(3, 30_000)];
+
+        // 7_000 + 3_100 cover the target and the fee, leaving less than the change output costs
+        let selection = Strategy::BranchAndBound.select(&coins, &params(9_900)).unwrap();
+        assert_eq!(selection.inputs, vec![coins[1].outpoint, coins[2].outpoint]);
+        assert_eq!(selection.change, Sats::ZERO);
+        assert_eq!(selection.fee, Sats(200));
+        assert_eq!(selection.strategy, Some(Strategy::BranchAndBound));
+
+        // No changeless solution: falls back to the largest-first
+        let selection = Strategy::BranchAndBound.select(&coins, &params(1_000)).unwrap();
+        assert_eq!(selection.inputs, vec![coins[0].outpoint]);
+        assert!(selection.has_change());
+        assert_eq!(selection.strategy, Some(Strategy::LargestFirst));
+
+        // Narrow window excludes the changeless solution wasting 100 sats
+        let params = SelectionParams {
+            changeless_window: Some(Sats(50)),
+            ..params(9_900)
+        };
+        let selection = Strategy::BranchAndBound.select(&coins, &params).unwrap();
+        assert_eq!(selection.strategy, Some(Strategy::LargestFirst));
+    }
+
+    #[test]
+    fn test_dust_policy() {
+        let coins = [utxo(0, 10_000), utxo(1, 5_000)];
+
+        // Change output would get 391 sats, which is below the dust limit, so it is dropped and
+        // the 422 sats remaining without it are left to miners
+        let selection = Strategy::LargestFirst.select(&coins, &params(9_500)).unwrap();
+        assert_eq!(selection.inputs, vec![coins[0].outpoint]);
+        assert_eq!(selection.change, Sats::ZERO);
+        assert_eq!(selection.fee, Sats(500));
+        assert_eq!(selection.dropped_change, Sats(500 - 78));
+        // Change equal to the dust limit is dropped as well
+        let selection = Strategy::LargestFirst.select(&coins, &params(10_000 - 109 - 546)).unwrap();
+        assert_eq!(selection.change, Sats::ZERO);
+        let selection = Strategy::LargestFirst.select(&coins, &params(10_000 - 109 - 547)).unwrap();
+        assert_eq!(selection.change, Sats(547));
+
+        let add_input = SelectionParams {
+            dust_policy: DustPolicy::AddInput,
+            ..params(9_500)
+        };
+        let selection = Strategy::LargestFirst.select(&coins, &add_input).unwrap();
+        assert_eq!(selection.inputs, vec![coins[0].outpoint, coins[1].outpoint]);
+        assert_eq!(selection.fee, Sats(10 + 2 * 68 + 31));
+        assert_eq!(selection.change, Sats(15_000 - 9_500 - 177));
+        assert_eq!(selection.dropped_change, Sats::ZERO);
+
+        // No more coins to add: the change is left to miners
+        let selection = Strategy::LargestFirst.select(&coins[..1], &add_input).unwrap();
+        assert_eq!(selection.inputs, vec![coins[0].outpoint]);
+        assert_eq!(selection.dropped_change, Sats(422));
+    }
+
+    #[test]
+    fn test_input_limits() {
+        let coins = [utxo(0, 10_000), utxo(1, 5_000), utxo(2, 3_000), utxo(3, 2_000)];
+
+        let min_inputs = SelectionParams {
+            min_inputs: Some(3),
+            ..params(1_000)
+        };
+        for strategy in [Strategy::LargestFirst, Strategy::BranchAndBound] {
+            let selection = strategy.select(&coins, &min_inputs).unwrap();
+            assert_eq!(selection.inputs.len(), 3);
+            assert_eq!(selection.change, Sats(18_000 - 1_000 - (10 + 3 * 68 + 31)));
+        }
+        assert_eq!(Strategy::LargestFirst.select(&coins[..2], &min_inputs), None);
+
+        let max_inputs = SelectionParams {
+            max_inputs: Some(2),
+            ..params(14_000)
+        };
+        let selection = Strategy::LargestFirst.select(&coins, &max_inputs).unwrap();
+        assert_eq!(selection.inputs, vec![coins[0].outpoint, coins[1].outpoint]);
+        // Smallest coins can't fit into the limit, so the largest ones are selected instead
+        let selection = Strategy::SmallestFirst.select(&coins, &max_inputs).unwrap();
+        assert_eq!(selection.inputs, vec![coins[0].outpoint, coins[1].outpoint]);
+        assert_eq!(selection.strategy, Some(Strategy::LargestFirst));
+        let unreachable = SelectionParams {
+            max_inputs: Some(2),
+            ..params(16_000)
+        };
+        assert_eq!(Strategy::LargestFirst.select(&coins, &unreachable), None);
+        assert!(max_inputs.allows_inputs(2));
+        assert!(!max_inputs.allows_inputs(3));
+    }
+
+    #[test]
+    fn test_selection_properties() {
+        const STRATEGIES: [Strategy; 4] = [
+            Strategy::LargestFirst,
+            Strategy::SmallestFirst,
+            Strategy::BranchAndBound,
+            Strategy::OldestFirst,
+        ];
+
+        // Deterministic pseudo-random generator, so failures are reproducible
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move |max: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % max
+        };
+
+        for _ in 0..500 {
+            let mut coins =
+                (0..next(12) as u32 + 1).map(|no| utxo(no, next(100_000) + 1)).collect::<Vec<_>>();
+            for coin in &mut coins {
+                if next(2) == 0 {
+                    coin.status = TxStatus::Mined(MiningInfo::genesis());
+                }
+            }
+            let candidates = coins.iter().copied().filter(confirmed).collect::<Vec<_>>();
+            let params = SelectionParams {
+                fee_rate: next(50) as f64 + 1.0,
+                dust_policy: if next(2) == 0 { DustPolicy::Burn } else { DustPolicy::AddInput },
+                ..params(next(150_000))
+            };
+            for strategy in STRATEGIES {
+                let Some(selection) = strategy.select(&candidates, &params) else {
+                    continue;
+                };
+                assert!(selection.inputs.iter().all(|outpoint| candidates
+                    .iter()
+                    .any(|utxo| utxo.outpoint == *outpoint && utxo.status.is_mined())));
+                let total = candidates
+                    .iter()
+                    .filter(|utxo| selection.inputs.contains(&utxo.outpoint))
+                    .map(|utxo| utxo.value.sats())
+                    .sum::<u64>();
+                let fee = params.fee(selection.inputs.len(), selection.has_change());
+                assert!(selection.fee >= fee, "{strategy}: fee {} < {fee}", selection.fee);
+                assert_eq!(selection.fee.sats() - selection.dropped_change.sats(), fee.sats());
+                assert_eq!(
+                    total,
+                    params.target.sats() + selection.fee.sats() + selection.change.sats(),
+                    "{strategy}: inputs don't balance the outputs"
+                );
+                assert!(!selection.has_change() || selection.change > params.min_change);
+                assert!(selection.strategy.is_some());
+            }
+        }
+    }
+}
@@ -23,6 +23,7 @@
 use std::cmp::Ordering;
 use std::fmt::{self, Display, Formatter, LowerHex};
 use std::num::{NonZeroU32, ParseIntError};
+use std::ops::AddAssign;
 use std::str::FromStr;
 
 use amplify::hex;
@@ -119,6 +120,24 @@ impl<T> TxStatus<T> {
     pub fn is_mined(&self) -> bool { matches!(self, Self::Mined(_)) }
 }
 
+impl TxStatus {
+    pub fn height(&self) -> Option<BlockHeight> {
+        match self {
+            TxStatus::Mined(info) => Some(info.height),
+            _ => None,
+        }
+    }
+
+    /// Number of confirmations of a transaction given the height of the blockchain `tip`; zero
+    /// for transactions which are not mined.
+    pub fn confirmations(&self, tip: BlockHeight) -> u32 {
+        match self.height() {
+            Some(height) => tip.get().saturating_sub(height.get()) + 1,
+            None => 0,
+        }
+    }
+}
+
 impl<T> Display for TxStatus<T>
 where T: Display
 {
@@ -158,6 +177,35 @@ impl From<Outpoint> for Inpoint {
     }
 }
 
+/// Serializes maps keyed by [`Inpoint`]s using their string representation as keys, so they can
+/// be stored in the formats supporting only string keys.
+#[cfg(feature = "serde")]
+pub(crate) mod inpoint_keys {
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Inpoint;
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &BTreeMap<Inpoint, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(map.iter().map(|(inpoint, value)| (inpoint.to_string(), value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<Inpoint, V>, D::Error> {
+        BTreeMap::<String, V>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, value)| Ok((Inpoint::from_str(&key).map_err(D::Error::custom)?, value)))
+            .collect()
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Display, From, Error)]
 #[display(doc_comments)]
 pub enum InpointParseError {
@@ -219,6 +267,8 @@ impl WalletTx {
 
     pub fn credited_debited(&self) -> (Sats, Sats) { (self.credit_sum(), self.debit_sum()) }
 
+    pub fn is_coinbase(&self) -> bool { self.inputs.iter().any(|input| input.coinbase) }
+
     pub fn balance_change(&self) -> i64 {
         let credit = self.credit_sum().sats_i64();
         let debit = self.debit_sum().sats_i64();
@@ -373,6 +423,59 @@ impl WalletUtxo {
     }
 }
 
+/// Number of confirmations required before coinbase outputs can be spent.
+pub const COINBASE_MATURITY: u32 = 100;
+
+/// Balance split by the confirmation status of the coins.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Balance {
+    /// Coins having at least the required number of confirmations.
+    pub confirmed: Sats,
+    /// Coins in mempool or having less than the required number of confirmations.
+    pub unconfirmed: Sats,
+    /// Coinbase outputs which have not reached [`COINBASE_MATURITY`] yet.
+    pub immature: Sats,
+    /// Coins locked from being spent by the wallet, independently of their confirmation status.
+    pub locked: Sats,
+}
+
+impl Balance {
+    pub fn total(&self) -> Sats {
+        self.confirmed + self.unconfirmed + self.immature + self.locked
+    }
+
+    /// Adds a coin to the balance bucket matching its number of `confirmations`.
+    pub fn add_coin(
+        &mut self,
+        value: Sats,
+        confirmations: u32,
+        coinbase: bool,
+        min_confirmations: u32,
+    ) {
+        if coinbase && confirmations < COINBASE_MATURITY {
+            self.immature.saturating_add_assign(value);
+        } else if confirmations > 0 && confirmations >= min_confirmations {
+            self.confirmed.saturating_add_assign(value);
+        } else {
+            self.unconfirmed.saturating_add_assign(value);
+        }
+    }
+}
+
+impl AddAssign for Balance {
+    fn add_assign(&mut self, rhs: Self) {
+        self.confirmed.saturating_add_assign(rhs.confirmed);
+        self.unconfirmed.saturating_add_assign(rhs.unconfirmed);
+        self.immature.saturating_add_assign(rhs.immature);
+        self.locked.saturating_add_assign(rhs.locked);
+    }
+}
+
 #[cfg_attr(
     feature = "serde",
     derive(serde::Serialize, serde::Deserialize),
@@ -387,6 +490,65 @@ pub struct WalletAddr<T = Sats> {
     pub balance: T,
 }
 
+/// Address derived from the wallet descriptor, together with its usage status.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct AddrInfo {
+    pub terminal: Terminal,
+    pub addr: Address,
+    pub script_pubkey: ScriptPubkey,
+    /// Whether the address was used by any of the wallet transactions.
+    pub used: bool,
+}
+
+/// Wallet address annotated with its usage and balance.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct AddrUsage {
+    pub terminal: Terminal,
+    pub addr: Address,
+    pub script_pubkey: ScriptPubkey,
+    /// Derivation paths of the address keys, one for each key of the wallet descriptor.
+    pub derivation: Vec<String>,
+    /// Number of wallet transactions spending from or paying to the address.
+    pub tx_count: u32,
+    /// Balance of the unspent outputs on the address.
+    pub balance: Balance,
+    /// Whether the address was handed out and reserved by the wallet.
+    pub reserved: bool,
+    /// Label attached to the address.
+    pub label: Option<String>,
+}
+
+/// Unspent output controlled by the wallet.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct UtxoInfo {
+    pub outpoint: Outpoint,
+    pub value: Sats,
+    pub script_pubkey: ScriptPubkey,
+    /// Keychain and index of the key derivation for the output script.
+    pub terminal: Terminal,
+    /// Height of the block mining the transaction; `None` for transactions in mempool.
+    pub height: Option<BlockHeight>,
+    /// Label attached to the output or, if there is none, to the address of the output.
+    pub label: Option<String>,
+    /// Whether the output is locked from being spent by the wallet.
+    pub locked: bool,
+}
+
 impl<T> Ord for WalletAddr<T>
 where T: Eq
 {
@@ -443,6 +605,35 @@ mod tests {
         assert_eq!(Inpoint::from_str(s).unwrap().to_string(), s);
     }
 
+    #[test]
+    fn test_balance_buckets() {
+        let tip = BlockHeight::new(1000).unwrap();
+        let mined = |height| {
+            TxStatus::Mined(MiningInfo {
+                height: BlockHeight::new(height).unwrap(),
+                ..MiningInfo::genesis()
+            })
+        };
+        assert_eq!(TxStatus::Mempool.confirmations(tip), 0);
+        assert_eq!(mined(1000).confirmations(tip), 1);
+        assert_eq!(mined(995).confirmations(tip), 6);
+
+        let mut balance = Balance::default();
+        balance.add_coin(Sats(1), TxStatus::Mempool.confirmations(tip), false, 0);
+        balance.add_coin(Sats(10), mined(1000).confirmations(tip), false, 1);
+        balance.add_coin(Sats(100), mined(999).confirmations(tip), false, 6);
+        balance.add_coin(Sats(1000), mined(995).confirmations(tip), false, 6);
+        balance.add_coin(Sats(10000), mined(950).confirmations(tip), true, 6);
+        balance.add_coin(Sats(100000), mined(900).confirmations(tip), true, 6);
+        assert_eq!(balance, Balance {
+            confirmed: Sats(101010),
+            unconfirmed: Sats(101),
+            immature: Sats(10000),
+            locked: Sats::ZERO,
+        });
+        assert_eq!(balance.total(), Sats(111111));
+    }
+
     #[test]
     fn test_party_str_round_trip() {
         fn assert_from_str_to_str(party: Party) {
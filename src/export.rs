@@ -0,0 +1,727 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of the wallet history for accounting and of the wallet descriptors to other wallets;
+//! import of watch-only wallets from Bitcoin Core and Electrum.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+#[cfg(feature = "fs")]
+use std::io;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::str::FromStr;
+
+use bpstd::{Descriptor, IdxBase, Keychain};
+
+use crate::checksum::{self, ChecksumError};
+#[cfg(feature = "serde_json")]
+use crate::slip132::Slip132Error;
+use crate::{Layer2, Layer2Tx, OpType, TxRow, TxStatus, Wallet, WalletCacheProvider};
+
+/// Number of satoshis in one bitcoin.
+const SATS_IN_BTC: u64 = 100_000_000;
+/// Number of seconds in a day.
+pub const SECS_IN_DAY: u64 = 86_400;
+
+/// Columns of the history exported by [`Wallet::history_csv`].
+pub const HISTORY_CSV_COLUMNS: [&str; 11] = [
+    "timestamp",
+    "txid",
+    "direction",
+    "amount_sats",
+    "amount_btc",
+    "fee_sats",
+    "fee_btc",
+    "balance_sats",
+    "balance_btc",
+    "height",
+    "label",
+];
+
+/// Range of the wallet history to export. Bounds are inclusive; unconfirmed transactions are
+/// exported only if there is no upper bound.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct HistoryFilter {
+    pub since_height: Option<u32>,
+    pub until_height: Option<u32>,
+    /// Lower bound for the block time, as a UNIX timestamp.
+    pub since_time: Option<u64>,
+    /// Upper bound for the block time, as a UNIX timestamp.
+    pub until_time: Option<u64>,
+}
+
+impl HistoryFilter {
+    /// Checks whether a transaction mined at the `height` (`None` for unconfirmed transactions)
+    /// in a block with the timestamp `time` fits the range.
+    pub fn matches(&self, height: Option<u32>, time: Option<u64>) -> bool {
+        let Some(height) = height else {
+            return self.until_height.is_none() && self.until_time.is_none();
+        };
+        self.since_height.is_none_or(|since| height >= since)
+            && self.until_height.is_none_or(|until| height <= until)
+            && self.since_time.is_none_or(|since| time.is_some_and(|time| time >= since))
+            && self.until_time.is_none_or(|until| time.is_some_and(|time| time <= until))
+    }
+
+    /// Checks whether a wallet history row fits the range.
+    pub fn matches_row<L2: Layer2Tx>(&self, row: &TxRow<L2>) -> bool {
+        let height = match row.height {
+            TxStatus::Mined(height) => Some(height.get()),
+            _ => None,
+        };
+        self.matches(height, row.time)
+    }
+}
+
+/// Formats amount of satoshis as bitcoins with the full precision.
+pub fn btc_amount(sats: i64) -> String {
+    let sign = if sats < 0 { "-" } else { "" };
+    let sats = sats.unsigned_abs();
+    format!("{sign}{}.{:08}", sats / SATS_IN_BTC, sats % SATS_IN_BTC)
+}
+
+/// Formats UNIX timestamp as UTC date and time in ISO 8601 format.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (days, secs) = (timestamp / SECS_IN_DAY, timestamp % SECS_IN_DAY);
+    // Conversion of days since the epoch into a civil date, following
+    // <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Parses UTC date in `YYYY-MM-DD` format into the UNIX timestamp of the beginning of the day.
+pub fn parse_date(s: &str) -> Result<u64, String> {
+    let err = || format!("invalid date '{s}'; expected YYYY-MM-DD");
+    let mut parts = s.trim().splitn(3, '-').map(str::parse::<u64>);
+    let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(err());
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=month_days).contains(&day) {
+        return Err(err());
+    }
+    // Conversion of a civil date into days since the epoch, following
+    // <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year % 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = (era * 146_097 + doe).checked_sub(719_468).ok_or_else(err)?;
+    Ok(days * SECS_IN_DAY)
+}
+
+/// Escapes a CSV cell value, quoting it if it contains separators, quotes or line breaks.
+pub(crate) fn csv_cell(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+impl<K, D: Descriptor<K>, C: WalletCacheProvider<L2::Cache>, L2: Layer2> Wallet<K, D, C, L2> {
+    /// Formats the wallet history as a CSV table with [`HISTORY_CSV_COLUMNS`], listing the
+    /// transactions in the range given by the `filter` in chronological order.
+    ///
+    /// Amounts are the net changes of the wallet balance; the running balance accounts for all
+    /// wallet transactions, including the ones outside the range.
+    pub fn history_csv(&self, filter: &HistoryFilter) -> String {
+        let mut csv = HISTORY_CSV_COLUMNS.join(",");
+        csv.push('\n');
+        let mut balance = 0i64;
+        for row in self.history() {
+            let amount = row.net_amount();
+            balance += amount;
+            if !filter.matches_row(&row) {
+                continue;
+            }
+            let direction = match row.operation {
+                OpType::Transfer => "self",
+                _ if amount < 0 => "out",
+                _ => "in",
+            };
+            let fee = row.our_fee().map(|fee| fee.sats_i64());
+            let label = self.data().tx_annotations.get(&row.txid);
+            let cells = [
+                row.time.map(format_timestamp).unwrap_or_default(),
+                row.txid.to_string(),
+                direction.to_owned(),
+                amount.to_string(),
+                btc_amount(amount),
+                fee.map(|fee| fee.to_string()).unwrap_or_default(),
+                fee.map(btc_amount).unwrap_or_default(),
+                balance.to_string(),
+                btc_amount(balance),
+                match row.height {
+                    TxStatus::Mined(height) => height.to_string(),
+                    _ => String::new(),
+                },
+                label.map(|label| csv_cell(label)).unwrap_or_default(),
+            ];
+            csv.push_str(&cells.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Writes the wallet history in the range given by the `filter` to a CSV file; see
+    /// [`Wallet::history_csv`] for the details. The file is replaced atomically.
+    #[cfg(feature = "fs")]
+    pub fn export_history(&self, path: impl AsRef<Path>, filter: &HistoryFilter) -> io::Result<()> {
+        crate::fs::write_atomic(path, self.history_csv(filter))
+    }
+}
+
+/// Time from which Bitcoin Core rescans the blockchain for the transactions of imported
+/// descriptors.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+pub enum CoreTimestamp {
+    /// Skip the rescan, as the descriptors have no transactions in the past.
+    #[display("now")]
+    Now,
+
+    /// Rescan blocks starting with the given UNIX timestamp.
+    #[display(inner)]
+    Time(u64),
+}
+
+impl FromStr for CoreTimestamp {
+    type Err = String;
+
+    /// Parses `now`, a UNIX timestamp or a date in `YYYY-MM-DD` format.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "now" => Ok(CoreTimestamp::Now),
+            s => match u64::from_str(s) {
+                Ok(time) => Ok(CoreTimestamp::Time(time)),
+                Err(_) => parse_date(s).map(CoreTimestamp::Time),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CoreTimestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            CoreTimestamp::Now => serializer.serialize_str("now"),
+            CoreTimestamp::Time(time) => serializer.serialize_u64(*time),
+        }
+    }
+}
+
+/// Entry of the request to Bitcoin Core `importdescriptors` RPC.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(crate = "serde_crate"))]
+pub struct CoreDescriptor {
+    /// Descriptor of a single keychain with its checksum.
+    pub desc: String,
+    pub active: bool,
+    /// Range of the derivation indexes for Core to watch, inclusive.
+    pub range: [u32; 2],
+    pub timestamp: CoreTimestamp,
+    /// Whether the descriptor is used for change addresses.
+    pub internal: bool,
+}
+
+/// Selects the `pos`-th alternative from each multipath (`<0;1>`) derivation step of a
+/// descriptor, as Bitcoin Core requires a separate descriptor for each keychain.
+fn single_path(descr: &str, pos: usize) -> String {
+    let mut result = String::with_capacity(descr.len());
+    let mut rest = descr;
+    while let Some(start) = rest.find('<') {
+        let Some(len) = rest[start..].find('>') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let alternatives = rest[start + 1..start + len].split(';').collect::<Vec<_>>();
+        result.push_str(alternatives.get(pos).or(alternatives.last()).copied().unwrap_or_default());
+        rest = &rest[start + len + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Errors importing wallet from the output of Bitcoin Core `listdescriptors` RPC.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum CoreImportError {
+    /// invalid `listdescriptors` output: {0}.
+    Json(String),
+
+    /// descriptor entry {0} has no `desc` field.
+    NoDescriptor(usize),
+
+    /// descriptor entry {0} contains private keys, while this is a watch-only wallet; please
+    /// export the descriptors from Bitcoin Core with `listdescriptors false`.
+    PrivateKey(usize),
+
+    /// no active ranged external descriptor found.
+    NoExternal,
+
+    /// multiple active external descriptors of types {0} found; please select one of them by its
+    /// script type.
+    Ambiguous(String),
+
+    /// {0}
+    #[from]
+    Checksum(ChecksumError),
+}
+
+/// Watch-only wallet imported from the output of Bitcoin Core `listdescriptors` RPC.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct CoreImport {
+    /// Wallet descriptor without checksum, having a multipath `<0;1>` derivation step if Bitcoin
+    /// Core has provided both the external and the internal descriptor.
+    pub descriptor: String,
+    /// Last derivation index watched by Bitcoin Core for each of the wallet keychains.
+    pub range_end: BTreeMap<Keychain, u32>,
+    /// The earliest creation time of the imported descriptors, if known.
+    pub timestamp: Option<u64>,
+}
+
+/// Detects whether a descriptor contains private keys, either extended (`xprv`, `tprv`) or
+/// single ones in the WIF encoding.
+#[cfg(feature = "serde_json")]
+fn has_private_key(desc: &str) -> bool {
+    desc.split(|c: char| !c.is_ascii_alphanumeric()).any(|token| {
+        if token.starts_with("xprv") || token.starts_with("tprv") {
+            return true;
+        }
+        // WIF is a version byte for mainnet or testnets followed by the 32-byte secret key and,
+        // for compressed public keys, by 0x01.
+        matches!(
+            bpstd::base58::decode_check(token).as_deref(),
+            Ok([0x80 | 0xef, key @ ..]) if key.len() == 32 || (key.len() == 33 && key[32] == 0x01)
+        )
+    })
+}
+
+impl CoreImport {
+    /// Parses the output of `listdescriptors` RPC, or just the array of its descriptor entries.
+    ///
+    /// Only active ranged descriptors are imported; entries without the `active` field are
+    /// treated as active. If there are multiple external descriptors (Bitcoin Core creates one
+    /// for each script type), the one to import must be selected by the `script` type, like
+    /// `wpkh` or `tr`. The internal descriptor matching the external one becomes the change
+    /// keychain of the wallet.
+    ///
+    /// Descriptor checksums are verified, and descriptors with private keys are rejected.
+    #[cfg(feature = "serde_json")]
+    pub fn from_json(s: &str, script: Option<&str>) -> Result<Self, CoreImportError> {
+        use serde_json::Value;
+
+        let value =
+            serde_json::from_str::<Value>(s).map_err(|e| CoreImportError::Json(e.to_string()))?;
+        let entries = value
+            .get("descriptors")
+            .unwrap_or(&value)
+            .as_array()
+            .ok_or_else(|| CoreImportError::Json(s!("expected array of descriptors")))?;
+
+        let mut external = vec![];
+        let mut internal = vec![];
+        for (no, entry) in entries.iter().enumerate() {
+            let no = no + 1;
+            let desc = entry["desc"].as_str().ok_or(CoreImportError::NoDescriptor(no))?;
+            let (desc, _) = checksum::split_checksum(desc)?;
+            if has_private_key(desc) {
+                return Err(CoreImportError::PrivateKey(no));
+            }
+            let range_end = match &entry["range"] {
+                Value::Array(range) => range.last().and_then(Value::as_u64),
+                range => range.as_u64(),
+            };
+            let Some(range_end) = range_end else {
+                continue;
+            };
+            if !entry["active"].as_bool().unwrap_or(true) {
+                continue;
+            }
+            if script.is_some_and(|script| !desc.starts_with(&format!("{script}("))) {
+                continue;
+            }
+            let item = (
+                checksum::normalize_hardened(desc),
+                u32::try_from(range_end).unwrap_or(u32::MAX),
+                entry["timestamp"].as_u64(),
+            );
+            match entry["internal"].as_bool().unwrap_or_default() {
+                false => external.push(item),
+                true => internal.push(item),
+            }
+        }
+
+        let (desc, range_end, timestamp) = match external.len() {
+            0 => return Err(CoreImportError::NoExternal),
+            1 => external.remove(0),
+            _ => {
+                let types = external
+                    .iter()
+                    .map(|(desc, ..)| desc.split_once('(').map(|(ty, _)| ty).unwrap_or(desc))
+                    .collect::<Vec<_>>();
+                return Err(CoreImportError::Ambiguous(types.join(", ")));
+            }
+        };
+        let mut import = CoreImport {
+            descriptor: desc.clone(),
+            range_end: BTreeMap::from([(Keychain::from(0u8), range_end)]),
+            timestamp,
+        };
+        let change =
+            internal.into_iter().find(|(internal, ..)| internal.replace("/1/*", "/0/*") == desc);
+        if let Some((_, range_end, timestamp)) = change {
+            import.descriptor = desc.replace("/0/*", "/<0;1>/*");
+            import.range_end.insert(Keychain::from(1u8), range_end);
+            import.timestamp = import.timestamp.into_iter().chain(timestamp).min();
+        }
+        Ok(import)
+    }
+}
+
+/// Errors importing wallet from an Electrum wallet file.
+#[cfg(feature = "serde_json")]
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum ElectrumImportError {
+    /// the Electrum wallet file is encrypted; encrypted wallets are not supported, please
+    /// disable the wallet file encryption in Electrum or export the master public key instead.
+    Encrypted,
+
+    /// invalid Electrum wallet file: {0}.
+    Json(String),
+
+    /// Electrum wallets of type '{0}' are not supported; only standard and multisig wallets
+    /// can be imported.
+    UnsupportedWallet(String),
+
+    /// Electrum keystore '{0}' of type '{1}' is not supported; only BIP-32 and hardware wallet
+    /// keystores can be imported.
+    UnsupportedKeystore(String, String),
+
+    /// Electrum keystore '{0}' has no extended public key.
+    NoXpub(String),
+
+    /// script types of the Electrum wallet keys do not match the wallet type.
+    KeyMismatch,
+
+    /// {0}
+    #[from]
+    Slip132(Slip132Error),
+}
+
+/// Converts an Electrum wallet file into the equivalent wallet descriptor, without checksum.
+///
+/// Standard wallets are converted into single-key descriptors and multisig wallets into
+/// `sortedmulti` descriptors, with the script type defined by the SLIP-132 version of the
+/// keystore extended keys (`zpub`, `Zpub` etc.); the keys are converted into `xpub` or `tpub`
+/// form. Private keys stored in the wallet file are ignored.
+#[cfg(feature = "serde_json")]
+pub fn electrum_descriptor(s: &str) -> Result<String, ElectrumImportError> {
+    use serde_json::Value;
+
+    use crate::slip132::{KeyApplication, Slip132Key};
+
+    let s = s.trim();
+    // Encrypted wallet files are base64-encoded ECIES messages starting with `BIE1` magic
+    if s.starts_with("QklFMQ") {
+        return Err(ElectrumImportError::Encrypted);
+    }
+    let wallet =
+        serde_json::from_str::<Value>(s).map_err(|e| ElectrumImportError::Json(e.to_string()))?;
+    let wallet_type = wallet["wallet_type"]
+        .as_str()
+        .ok_or_else(|| ElectrumImportError::Json(s!("no wallet type")))?;
+
+    let keystore = |name: &str| -> Result<(String, KeyApplication), ElectrumImportError> {
+        let keystore = &wallet[name];
+        let ty = keystore["type"].as_str().unwrap_or_default();
+        if ty != "bip32" && ty != "hardware" {
+            return Err(ElectrumImportError::UnsupportedKeystore(name.to_owned(), ty.to_owned()));
+        }
+        let xpub = keystore["xpub"]
+            .as_str()
+            .ok_or_else(|| ElectrumImportError::NoXpub(name.to_owned()))?;
+        let key = Slip132Key::from_base58(xpub)?;
+        let fingerprint = keystore["root_fingerprint"].as_str();
+        let origin = match (fingerprint, keystore["derivation"].as_str()) {
+            (Some(fp), Some(path)) => {
+                let path = path.trim_start_matches('m').trim_start_matches('/');
+                let path = checksum::normalize_hardened(path);
+                if path.is_empty() {
+                    format!("[{fp}]")
+                } else {
+                    format!("[{fp}/{path}]")
+                }
+            }
+            _ => none!(),
+        };
+        Ok((format!("{origin}{}/<0;1>/*", key.xpub), key.application))
+    };
+
+    if wallet_type == "standard" {
+        let (key, application) = keystore("keystore")?;
+        return Ok(match application {
+            KeyApplication::Legacy => format!("pkh({key})"),
+            KeyApplication::NestedWpkh => format!("sh(wpkh({key}))"),
+            KeyApplication::Wpkh => format!("wpkh({key})"),
+            KeyApplication::NestedWsh | KeyApplication::Wsh => {
+                return Err(ElectrumImportError::KeyMismatch);
+            }
+        });
+    }
+
+    // Multisig wallets have `<m>of<n>` type with keystores named `x1/` to `x<n>/`
+    let unsupported = || ElectrumImportError::UnsupportedWallet(wallet_type.to_owned());
+    let (m, n) = wallet_type.split_once("of").ok_or_else(unsupported)?;
+    let m = u8::from_str(m).map_err(|_| unsupported())?;
+    let n = u8::from_str(n).map_err(|_| unsupported())?;
+    if m == 0 || m > n {
+        return Err(unsupported());
+    }
+    let mut keys = Vec::with_capacity(n as usize);
+    let mut applications = Vec::with_capacity(n as usize);
+    for no in 1..=n {
+        let (key, application) = keystore(&format!("x{no}/"))?;
+        keys.push(key);
+        applications.push(application);
+    }
+    if applications.iter().any(|application| *application != applications[0]) {
+        return Err(ElectrumImportError::KeyMismatch);
+    }
+    let multi = format!("sortedmulti({m},{})", keys.join(","));
+    Ok(match applications[0] {
+        KeyApplication::Legacy => format!("sh({multi})"),
+        KeyApplication::NestedWpkh | KeyApplication::NestedWsh => format!("sh(wsh({multi}))"),
+        KeyApplication::Wpkh | KeyApplication::Wsh => format!("wsh({multi})"),
+    })
+}
+
+impl<K, D: Descriptor<K> + Display, C: WalletCacheProvider<L2::Cache>, L2: Layer2>
+    Wallet<K, D, C, L2>
+{
+    /// Renders the wallet descriptor as the entries of Bitcoin Core `importdescriptors` request,
+    /// one for the external and one for the internal (change) keychain.
+    ///
+    /// The range covers all used addresses plus the gap limit of the keychain. Other keychains
+    /// are not supported by Bitcoin Core and are skipped.
+    pub fn core_descriptors(
+        &self,
+        timestamp: CoreTimestamp,
+    ) -> Result<Vec<CoreDescriptor>, ChecksumError> {
+        let generator: &D = self;
+        let descr = generator.to_string();
+        let mut entries = vec![];
+        for (pos, keychain) in generator.keychains().into_iter().enumerate() {
+            let internal = match keychain {
+                k if k == Keychain::from(0u8) => false,
+                k if k == Keychain::from(1u8) => true,
+                _ => continue,
+            };
+            let end = self.last_derivation_index(keychain).index() + self.gap_limit(keychain);
+            entries.push(CoreDescriptor {
+                desc: checksum::with_checksum(&single_path(&descr, pos))?,
+                active: true,
+                range: [0, end.saturating_sub(1)],
+                timestamp,
+                internal,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Returns the timestamp of the first block mining a wallet transaction, to be used as
+    /// the rescan start when the wallet is imported into Bitcoin Core.
+    ///
+    /// If the wallet creation time is known from the wallet import, the earliest of it and the
+    /// first transaction time is used. Otherwise, if the wallet has no confirmed transactions,
+    /// returns zero, causing the rescan of the whole blockchain, since the wallet may be not
+    /// synced yet.
+    pub fn birth_time(&self) -> u64 {
+        let first_tx = self.history().find_map(|row| row.time);
+        first_tx.into_iter().chain(self.data().birth_time).min().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_btc_amount() {
+        assert_eq!(btc_amount(0), "0.00000000");
+        assert_eq!(btc_amount(1), "0.00000001");
+        assert_eq!(btc_amount(-150_000_000), "-1.50000000");
+        assert_eq!(btc_amount(2_100_000_000_000_000), "21000000.00000000");
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(1_231_006_505), "2009-01-03T18:15:05Z");
+        assert_eq!(format_timestamp(1_709_251_199), "2024-02-29T23:59:59Z");
+        assert_eq!(parse_date("2009-01-03"), Ok(1_230_940_800));
+        assert_eq!(parse_date("2024-02-29"), Ok(1_709_164_800));
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("2024-02-30").is_err());
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("2000-02-29").is_ok());
+        assert!(parse_date("1900-02-29").is_err());
+        assert!(parse_date("2024-04-31").is_err());
+        assert!(parse_date("2024-12-31").is_ok());
+        assert!(parse_date("yesterday").is_err());
+        assert_eq!(CoreTimestamp::from_str("now"), Ok(CoreTimestamp::Now));
+        assert_eq!(CoreTimestamp::from_str("1231006505"), Ok(CoreTimestamp::Time(1_231_006_505)));
+        assert_eq!(CoreTimestamp::from_str("2009-01-03"), Ok(CoreTimestamp::Time(1_230_940_800)));
+    }
+
+    #[test]
+    fn test_single_path() {
+        let descr = "wpkh([d34db33f/84h/0h/0h]xpub6DJ2dNUysrn5/<0;1>/*)";
+        assert_eq!(single_path(descr, 0), "wpkh([d34db33f/84h/0h/0h]xpub6DJ2dNUysrn5/0/*)");
+        assert_eq!(single_path(descr, 1), "wpkh([d34db33f/84h/0h/0h]xpub6DJ2dNUysrn5/1/*)");
+        assert_eq!(single_path("wpkh(xpub/0/*)", 1), "wpkh(xpub/0/*)");
+    }
+
+    #[test]
+    #[cfg(feature = "serde_json")]
+    fn test_core_import() {
+        const XPUB: &str = "xpub6DJ2dNUysrn5Vt36jH2KLBT2i1auw1tTSSomg8PhqNiUtx8QX2SvC9nrHu81fT41fvDUnhMjEzQgXnQjKEu3oaqMSzhSrHMxyyoEAmUHQbY";
+        let entry = |desc: String, internal: bool, time: u64| {
+            let desc = checksum::with_checksum(&desc).unwrap();
+            format!(
+                r#"{{ "desc": "{desc}", "timestamp": {time}, "active": true, "internal": {internal}, "range": [0, 999], "next": 0 }}"#
+            )
+        };
+        let entries = [
+            entry(format!("wpkh([d34db33f/84'/0'/0']{XPUB}/0/*)"), false, 1_700_000_000),
+            entry(format!("wpkh([d34db33f/84'/0'/0']{XPUB}/1/*)"), true, 1_600_000_000),
+            entry(format!("tr([d34db33f/86'/0'/0']{XPUB}/0/*)"), false, 1_700_000_000),
+        ];
+        let json =
+            format!(r#"{{ "wallet_name": "core", "descriptors": [{}] }}"#, entries.join(","));
+
+        assert_eq!(
+            CoreImport::from_json(&json, None),
+            Err(CoreImportError::Ambiguous(s!("wpkh, tr")))
+        );
+        let import = CoreImport::from_json(&json, Some("wpkh")).unwrap();
+        assert_eq!(import.descriptor, format!("wpkh([d34db33f/84h/0h/0h]{XPUB}/<0;1>/*)"));
+        assert_eq!(import.range_end[&Keychain::from(0u8)], 999);
+        assert_eq!(import.range_end[&Keychain::from(1u8)], 999);
+        assert_eq!(import.timestamp, Some(1_600_000_000));
+
+        let import = CoreImport::from_json(&format!("[{}]", entries[2]), None).unwrap();
+        assert_eq!(import.descriptor, format!("tr([d34db33f/86h/0h/0h]{XPUB}/0/*)"));
+        assert_eq!(import.range_end.len(), 1);
+
+        assert_eq!(CoreImport::from_json("[]", None), Err(CoreImportError::NoExternal));
+        let private = entry(s!("wpkh(tprv8ZgxMBicQKsPd1EzCPZcQSPhsotX5HvRDCivA7ASNQFmjWuTsW3WWEwUNKFAZrnD9qpz55rtyLdphqkwRZUqNWYXwSEzd6P4pYvXGByRim3/0/*)"), false, 0);
+        assert_eq!(
+            CoreImport::from_json(&format!("[{private}]"), None),
+            Err(CoreImportError::PrivateKey(1))
+        );
+        for wif in [
+            "5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ",
+            "KwdMAjGmerYanjeui5SHS7JkmpZvVipYvB2LJGU1ZxJwYvP98617",
+        ] {
+            let private = entry(format!("tr({XPUB}/0/*,pk({wif}))"), false, 0);
+            assert_eq!(
+                CoreImport::from_json(&format!("[{private}]"), None),
+                Err(CoreImportError::PrivateKey(1))
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde_json")]
+    fn test_electrum_descriptor() {
+        const XPUB: &str = "xpub6DJ2dNUysrn5Vt36jH2KLBT2i1auw1tTSSomg8PhqNiUtx8QX2SvC9nrHu81fT41fvDUnhMjEzQgXnQjKEu3oaqMSzhSrHMxyyoEAmUHQbY";
+        const ZPUB: &str = "zpub6rxZEhppBDs3CURLPzbZkMe33wsopFsTGfrDEvBUbPUF19ks2Ln3SH78LK3BfGMrVCT6HeYrAK7nJMdrkdj5Q4CZBg6J26zwXRvWwtzrWqR";
+        const ZPUB_MULTI: &str = "Zpub73reMwZFkBRQd3aiLf4YaRyqmjv52cZ3awVtABT1yAJedLKmnkAKXPy491zfDhakifX5AEYy3XWHRXFd3rt2YYKE29ChRWTwRACPXnLSWEE";
+
+        let standard = format!(
+            r#"{{ "wallet_type": "standard", "seed_type": "segwit", "keystore": {{ "type": "bip32", "xpub": "{ZPUB}", "derivation": "m/84'/0'/0'", "root_fingerprint": "d34db33f" }} }}"#
+        );
+        assert_eq!(
+            electrum_descriptor(&standard).unwrap(),
+            format!("wpkh([d34db33f/84h/0h/0h]{XPUB}/<0;1>/*)")
+        );
+
+        let multisig = format!(
+            r#"{{ "wallet_type": "2of2", "x1/": {{ "type": "hardware", "xpub": "{ZPUB_MULTI}", "derivation": "m/48'/0'/0'/2'", "root_fingerprint": "d34db33f" }}, "x2/": {{ "type": "bip32", "xpub": "{ZPUB_MULTI}" }} }}"#
+        );
+        assert_eq!(
+            electrum_descriptor(&multisig).unwrap(),
+            format!("wsh(sortedmulti(2,[d34db33f/48h/0h/0h/2h]{XPUB}/<0;1>/*,{XPUB}/<0;1>/*))")
+        );
+
+        assert_eq!(
+            electrum_descriptor("QklFMQKQ7bo0yBqzN0eyeHVhtv4Fl7fLvw=="),
+            Err(ElectrumImportError::Encrypted)
+        );
+        assert_eq!(
+            electrum_descriptor(
+                r#"{ "wallet_type": "imported", "keystore": { "type": "imported" } }"#
+            ),
+            Err(ElectrumImportError::UnsupportedWallet(s!("imported")))
+        );
+    }
+
+    #[test]
+    fn test_history_filter() {
+        let filter = HistoryFilter {
+            since_height: Some(100),
+            until_time: Some(2000),
+            ..HistoryFilter::default()
+        };
+        assert!(filter.matches(Some(100), Some(2000)));
+        assert!(!filter.matches(Some(99), Some(1000)));
+        assert!(!filter.matches(Some(200), Some(2001)));
+        assert!(!filter.matches(Some(200), None));
+        assert!(!filter.matches(None, None));
+        assert!(HistoryFilter::default().matches(None, None));
+    }
+}
@@ -0,0 +1,569 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Estimation of transaction sizes and fees before the transaction is signed.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bpstd::opcodes::{
+    OP_CHECKMULTISIG, OP_CHECKSIG, OP_CHECKSIGADD, OP_NUMEQUAL, OP_PUSHDATA1, OP_PUSHDATA2,
+    OP_PUSHDATA4, OP_PUSHNUM_1, OP_PUSHNUM_16,
+};
+use bpstd::{DerivedScript, Sats, ScriptPubkey, SeqNo, VarInt};
+use descriptors::SpkClass;
+
+use crate::Indexer;
+
+/// Size of the transaction version, lock time and input and output counts, in virtual bytes.
+pub const TX_OVERHEAD_VBYTES: u32 = 10;
+/// Size of an input without the signature script and witness, in virtual bytes.
+const INPUT_BASE_VBYTES: u32 = 41;
+/// Size of an output without the script, in virtual bytes.
+const OUTPUT_BASE_VBYTES: u32 = 9;
+/// Size of a pushed ECDSA signature, including the sighash type and the push opcode, in bytes.
+const ECDSA_SIG_BYTES: u32 = 73;
+/// Size of a pushed BIP-340 signature with the default sighash type, including the push opcode,
+/// in bytes.
+const BIP340_SIG_BYTES: u32 = 65;
+/// Size of the P2SH signature script pushing a P2WSH witness program, in bytes.
+const NESTED_WSH_SIG_SCRIPT_BYTES: u32 = 35;
+
+/// Output spent by a transaction input, which defines the input size.
+pub trait SpentOutput {
+    /// Whether spending the output requires a witness.
+    fn has_witness(&self) -> bool;
+
+    /// Estimates the size of the data satisfying the spending conditions of the output
+    /// (signature script and witness), in virtual bytes.
+    fn satisfaction_vsize(&self) -> u32;
+}
+
+/// Output known only by its script, whose satisfaction is estimated by the script type; see
+/// [`satisfaction_vsize`].
+impl SpentOutput for ScriptPubkey {
+    fn has_witness(&self) -> bool { has_witness(self) }
+
+    fn satisfaction_vsize(&self) -> u32 { satisfaction_vsize(self) }
+}
+
+/// Output derived from a wallet descriptor, whose satisfaction is estimated from the derived
+/// redeem, witness or tapscript. Multisig scripts are satisfied by the threshold number of
+/// signatures; taproot outputs with a script tree are assumed to be spent through their largest
+/// script path.
+impl SpentOutput for DerivedScript {
+    fn has_witness(&self) -> bool {
+        match self {
+            DerivedScript::Bare(script_pubkey) => has_witness(script_pubkey),
+            DerivedScript::Bip13(_) => false,
+            _ => true,
+        }
+    }
+
+    fn satisfaction_vsize(&self) -> u32 {
+        match self {
+            DerivedScript::Bare(script_pubkey) => satisfaction_vsize(script_pubkey),
+            DerivedScript::NestedKey(_) => 50,
+            DerivedScript::TaprootKeyOnly(_) => 17,
+            DerivedScript::Bip13(redeem_script) => {
+                let script = redeem_script.as_slice();
+                // Redeem script is pushed after the signatures and the dummy element consumed by
+                // OP_CHECKMULTISIG
+                let push_len = match script.len() {
+                    len if len < OP_PUSHDATA1 as usize => 1,
+                    len if len <= u8::MAX as usize => 2,
+                    _ => 3,
+                };
+                let len = 1
+                    + multisig_threshold(script) * ECDSA_SIG_BYTES
+                    + push_len
+                    + script.len() as u32;
+                // Signature script length above one byte enlarges the input
+                len + VarInt::with(len as usize).len() as u32 - 1
+            }
+            DerivedScript::Segwit(witness_script) => {
+                witness_script_weight(witness_script.as_slice()).div_ceil(4)
+            }
+            DerivedScript::NestedScript(witness_script) => {
+                NESTED_WSH_SIG_SCRIPT_BYTES
+                    + witness_script_weight(witness_script.as_slice()).div_ceil(4)
+            }
+            DerivedScript::TaprootScript(_, tap_tree) => tap_tree
+                .iter()
+                .map(|leaf| {
+                    let script = leaf.script.script.as_slice();
+                    let (keys, threshold) = tapscript_keys(script);
+                    let control_block = 33 + 32 * leaf.depth.to_u8() as u32;
+                    let weight = 1
+                        + threshold * BIP340_SIG_BYTES
+                        + (keys - threshold)
+                        + var_len(script.len())
+                        + var_len(control_block as usize);
+                    weight.div_ceil(4)
+                })
+                .max()
+                .unwrap_or(17),
+            derived => satisfaction_vsize(&derived.to_script_pubkey()),
+        }
+    }
+}
+
+/// Size of the data of `len` bytes together with its length prefix, in bytes.
+fn var_len(len: usize) -> u32 { (VarInt::with(len).len() + len) as u32 }
+
+/// Number of signatures satisfying the `script`: the threshold of a multisig script, or one for
+/// the other scripts.
+fn multisig_threshold(script: &[u8]) -> u32 {
+    match (script.first(), script.last()) {
+        (Some(&op), Some(&OP_CHECKMULTISIG)) if (OP_PUSHNUM_1..=OP_PUSHNUM_16).contains(&op) => {
+            (op - OP_PUSHNUM_1 + 1) as u32
+        }
+        _ => 1,
+    }
+}
+
+/// Weight of the witness satisfying the P2WSH `script`, in weight units.
+fn witness_script_weight(script: &[u8]) -> u32 {
+    let threshold = multisig_threshold(script);
+    // OP_CHECKMULTISIG consumes an extra empty witness element
+    let dummy = (script.last() == Some(&OP_CHECKMULTISIG)) as u32;
+    1 + dummy + threshold * ECDSA_SIG_BYTES + var_len(script.len())
+}
+
+/// Number of keys checked by the tapscript `script`, and the number of signatures satisfying it.
+/// All the keys are assumed to sign unless the script is a `multi_a` one with a small threshold.
+fn tapscript_keys(script: &[u8]) -> (u32, u32) {
+    let mut keys = 0u32;
+    let mut pos = 0;
+    while let Some(&op) = script.get(pos) {
+        pos += 1 + match op {
+            OP_CHECKSIG | OP_CHECKSIGADD => {
+                keys += 1;
+                0
+            }
+            len if len < OP_PUSHDATA1 => len as usize,
+            OP_PUSHDATA1 | OP_PUSHDATA2 | OP_PUSHDATA4 => {
+                let size = match op {
+                    OP_PUSHDATA1 => 1,
+                    OP_PUSHDATA2 => 2,
+                    _ => 4,
+                };
+                let len = script.get(pos + 1..pos + 1 + size).map(|bytes| {
+                    bytes.iter().rev().fold(0usize, |len, byte| len << 8 | *byte as usize)
+                });
+                size + len.unwrap_or_default()
+            }
+            _ => 0,
+        };
+    }
+    let keys = keys.max(1);
+    let threshold = match script {
+        [.., op, OP_NUMEQUAL] if (OP_PUSHNUM_1..=OP_PUSHNUM_16).contains(op) => {
+            (op - OP_PUSHNUM_1 + 1) as u32
+        }
+        _ => keys,
+    };
+    (keys, threshold.min(keys))
+}
+
+fn has_witness(script_pubkey: &ScriptPubkey) -> bool {
+    script_pubkey.is_p2wpkh()
+        || script_pubkey.is_p2wsh()
+        || script_pubkey.is_p2tr()
+        || script_pubkey.is_p2sh()
+}
+
+/// Estimates the size of the data satisfying the spending conditions of an output with the given
+/// `script_pubkey` (signature script and witness), in virtual bytes.
+///
+/// Single-key spending is assumed for all script types except P2WSH, for which a 2-of-3 multisig
+/// is assumed; P2SH outputs are assumed to wrap P2WPKH. Outputs derived from a wallet descriptor
+/// are sized by their scripts instead; see [`SpentOutput`] implementation for [`DerivedScript`].
+pub fn satisfaction_vsize(script_pubkey: &ScriptPubkey) -> u32 {
+    if script_pubkey.is_p2wpkh() {
+        27
+    } else if script_pubkey.is_p2tr() {
+        17
+    } else if script_pubkey.is_p2sh() {
+        50
+    } else if script_pubkey.is_p2wsh() {
+        64
+    } else {
+        107
+    }
+}
+
+/// Estimates the size of a signed transaction input spending the `spent` output, in virtual
+/// bytes.
+pub fn input_vsize(spent: &impl SpentOutput) -> u32 {
+    INPUT_BASE_VBYTES + spent.satisfaction_vsize()
+}
+
+/// Size of a transaction output with the given `script_pubkey`, in virtual bytes.
+pub fn output_vsize(script_pubkey: &ScriptPubkey) -> u32 {
+    OUTPUT_BASE_VBYTES + script_pubkey.len() as u32
+}
+
+/// Estimates the size of a signed transaction spending the `inputs` outputs and creating outputs
+/// with the `outputs` scripts, in virtual bytes.
+pub fn estimate_vsize<'a, S: SpentOutput + 'a>(
+    inputs: impl IntoIterator<Item = &'a S>,
+    outputs: impl IntoIterator<Item = &'a ScriptPubkey>,
+) -> u32 {
+    let mut witness = false;
+    let mut vsize = TX_OVERHEAD_VBYTES;
+    for spent in inputs {
+        witness |= spent.has_witness();
+        vsize += input_vsize(spent);
+    }
+    for script_pubkey in outputs {
+        vsize += output_vsize(script_pubkey);
+    }
+    if witness {
+        // Segwit marker and flag
+        vsize += 1;
+    }
+    vsize
+}
+
+/// Computes fee for a transaction of `vsize` virtual bytes paying `sats_per_vbyte` fee rate,
+/// rounding up.
+pub fn fee_for_rate(vsize: u32, sats_per_vbyte: f64) -> Sats {
+    Sats((vsize as f64 * sats_per_vbyte).ceil() as u64)
+}
+
+/// Computes the dust limit of an output with the given `script_pubkey`: outputs of a smaller
+/// value cost more to spend than they are worth, and are not relayed by the network nodes.
+///
+/// Uses the Bitcoin Core dust limits of the standard script classes (see [`SpkClass::dust_limit`]).
+/// Bare scripts, including unspendable `OP_RETURN` outputs, have no dust limit.
+pub fn dust_limit(script_pubkey: &ScriptPubkey) -> Sats {
+    let class = if script_pubkey.is_p2pkh() {
+        SpkClass::P2pkh
+    } else if script_pubkey.is_p2sh() {
+        SpkClass::P2sh
+    } else if script_pubkey.is_p2wpkh() {
+        SpkClass::P2wpkh
+    } else if script_pubkey.is_p2wsh() {
+        SpkClass::P2wsh
+    } else if script_pubkey.is_p2tr() {
+        SpkClass::P2tr
+    } else {
+        SpkClass::Bare
+    };
+    class.dust_limit()
+}
+
+/// Fee paid by a constructed transaction.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FeePolicy {
+    /// Fee rate, in satoshis per virtual byte.
+    Rate(f64),
+    /// Absolute fee amount, independent of the transaction size.
+    Absolute(Sats),
+}
+
+/// Input sequence number signalling replaceability of the transaction according to BIP-125.
+pub const SEQ_NO_RBF: u32 = 0xFFFF_FFFD;
+/// Largest input sequence number which doesn't signal replaceability but still enables
+/// transaction lock time.
+pub const SEQ_NO_NON_RBF: u32 = 0xFFFF_FFFE;
+/// Minimal fee rate by which a replacement transaction must increase the fee of the replaced
+/// one, in satoshis per virtual byte; matches the Bitcoin Core default.
+pub const INCREMENTAL_RELAY_FEE_RATE: f64 = 1.0;
+/// Minimal fee rate of transactions relayed by the network nodes, in satoshis per virtual byte;
+/// matches the Bitcoin Core default.
+pub const MIN_RELAY_FEE_RATE: f64 = 1.0;
+/// Fee rate above which the fee is considered absurdly high, in satoshis per virtual byte.
+pub const MAX_FEE_RATE: f64 = 1000.0;
+
+/// Detects whether an input with the sequence number signals replaceability of its transaction
+/// according to BIP-125.
+pub fn signals_rbf(seq_no: SeqNo) -> bool { seq_no.to_consensus_u32() < SEQ_NO_NON_RBF }
+
+/// Returns input sequence number for transactions which do or don't signal replaceability.
+pub fn seq_no(rbf: bool) -> SeqNo {
+    SeqNo::from_consensus_u32(if rbf { SEQ_NO_RBF } else { SEQ_NO_NON_RBF })
+}
+
+/// Confirmation target, in blocks, used for the `fast` fee rate.
+pub const FAST_TARGET_BLOCKS: u16 = 1;
+/// Confirmation target, in blocks, used for the `medium` fee rate.
+pub const MEDIUM_TARGET_BLOCKS: u16 = 6;
+/// Confirmation target, in blocks, used for the `slow` fee rate.
+pub const SLOW_TARGET_BLOCKS: u16 = 144;
+
+/// Fee rate in satoshis per virtual byte, which is always a finite non-negative number.
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Display)]
+#[display(inner)]
+pub struct FeeRate(f64);
+
+// Fee rates are never NaN since they are rejected during construction
+impl Eq for FeeRate {}
+
+impl Default for FeeRate {
+    fn default() -> Self { FeeRate(MIN_RELAY_FEE_RATE) }
+}
+
+impl FeeRate {
+    /// Constructs fee rate, returning `None` for negative, infinite or NaN values.
+    pub fn from_sats_per_vbyte(rate: f64) -> Option<Self> {
+        (rate.is_finite() && rate >= 0.0).then_some(FeeRate(rate))
+    }
+
+    pub fn sats_per_vbyte(self) -> f64 { self.0 }
+}
+
+impl FromStr for FeeRate {
+    type Err = FeeTargetParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let rate = f64::from_str(s).map_err(|_| FeeTargetParseError::Invalid(s.to_owned()))?;
+        FeeRate::from_sats_per_vbyte(rate)
+            .ok_or_else(|| FeeTargetParseError::InvalidRate(s.to_owned()))
+    }
+}
+
+/// Fee rate given either explicitly or as a confirmation target, which has to be resolved into a
+/// fee rate using fee estimations provided by an indexer.
+///
+/// Parsed from a number of satoshis per virtual byte (`5`, `2.5`), one of `fast`, `medium` and
+/// `slow` or from a number of blocks with `blocks` suffix (`3blocks`).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FeeTarget {
+    /// Explicit fee rate, in satoshis per virtual byte.
+    Rate(f64),
+    /// Number of blocks within which the transaction should get mined.
+    Blocks(u16),
+}
+
+// Fee rates are never NaN since they are rejected during parsing
+impl Eq for FeeTarget {}
+
+impl Display for FeeTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            FeeTarget::Rate(rate) => Display::fmt(&rate, f),
+            FeeTarget::Blocks(FAST_TARGET_BLOCKS) => f.write_str("fast"),
+            FeeTarget::Blocks(MEDIUM_TARGET_BLOCKS) => f.write_str("medium"),
+            FeeTarget::Blocks(SLOW_TARGET_BLOCKS) => f.write_str("slow"),
+            FeeTarget::Blocks(blocks) => write!(f, "{blocks}blocks"),
+        }
+    }
+}
+
+/// Errors parsing [`FeeTarget`].
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum FeeTargetParseError {
+    /// invalid fee rate '{0}'; expected number of satoshis per virtual byte, `fast`, `medium`,
+    /// `slow` or a confirmation target like `3blocks`.
+    Invalid(String),
+
+    /// fee rate '{0}' must be a finite non-negative number.
+    InvalidRate(String),
+
+    /// confirmation target must be at least one block.
+    ZeroBlocks,
+}
+
+impl FromStr for FeeTarget {
+    type Err = FeeTargetParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_lowercase().as_str() {
+            "fast" => return Ok(FeeTarget::Blocks(FAST_TARGET_BLOCKS)),
+            "medium" => return Ok(FeeTarget::Blocks(MEDIUM_TARGET_BLOCKS)),
+            "slow" => return Ok(FeeTarget::Blocks(SLOW_TARGET_BLOCKS)),
+            _ => {}
+        }
+        if let Some(blocks) = s.strip_suffix("blocks").or_else(|| s.strip_suffix("block")) {
+            let blocks = u16::from_str(blocks.trim())
+                .map_err(|_| FeeTargetParseError::Invalid(s.to_owned()))?;
+            if blocks == 0 {
+                return Err(FeeTargetParseError::ZeroBlocks);
+            }
+            return Ok(FeeTarget::Blocks(blocks));
+        }
+        FeeRate::from_str(s).map(|rate| FeeTarget::Rate(rate.sats_per_vbyte()))
+    }
+}
+
+/// Resolves [`FeeTarget`]s into fee rates, requesting fee estimations from an indexer at most
+/// once per confirmation target.
+pub struct FeeEstimator<'i, I: Indexer> {
+    indexer: &'i I,
+    cache: RefCell<BTreeMap<u16, f64>>,
+    fallback: Option<f64>,
+    min_rate: f64,
+}
+
+impl<'i, I: Indexer> FeeEstimator<'i, I> {
+    pub fn new(indexer: &'i I) -> Self {
+        Self {
+            indexer,
+            cache: none!(),
+            fallback: None,
+            min_rate: MIN_RELAY_FEE_RATE,
+        }
+    }
+
+    /// Sets fee rate which is used instead of failing if the indexer doesn't provide fee
+    /// estimation.
+    pub fn with_fallback(mut self, fallback: Option<f64>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Sets minimal fee rate; lower estimations are replaced with it. Defaults to
+    /// [`MIN_RELAY_FEE_RATE`].
+    pub fn with_min_rate(mut self, min_rate: f64) -> Self {
+        self.min_rate = min_rate;
+        self
+    }
+
+    /// Returns fee rate for a transaction to get mined within `target_blocks`, in satoshis per
+    /// virtual byte.
+    pub fn estimate_feerate(&self, target_blocks: u16) -> Result<f64, I::Error> {
+        if let Some(rate) = self.cache.borrow().get(&target_blocks) {
+            return Ok(*rate);
+        }
+        let rate = match (self.indexer.fee_rate(target_blocks), self.fallback) {
+            (Ok(rate), _) => rate.max(self.min_rate),
+            (Err(_), Some(fallback)) => fallback,
+            (Err(err), None) => return Err(err),
+        };
+        self.cache.borrow_mut().insert(target_blocks, rate);
+        Ok(rate)
+    }
+
+    /// Returns fee rate for the target, in satoshis per virtual byte.
+    pub fn fee_rate(&self, target: FeeTarget) -> Result<f64, I::Error> {
+        match target {
+            FeeTarget::Rate(rate) => Ok(rate),
+            FeeTarget::Blocks(blocks) => self.estimate_feerate(blocks),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::hex::FromHex;
+
+    use super::*;
+
+    #[test]
+    fn test_p2wpkh_vsize() {
+        let p2wpkh =
+            ScriptPubkey::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        assert_eq!(estimate_vsize([&p2wpkh], [&p2wpkh, &p2wpkh]), 141);
+        assert_eq!(estimate_vsize([&p2wpkh, &p2wpkh], [&p2wpkh]), 178);
+        assert_eq!(fee_for_rate(141, 1.5), Sats(212));
+        assert_eq!(fee_for_rate(141, 0.0), Sats::ZERO);
+    }
+
+    #[test]
+    fn test_derived_satisfaction_vsize() {
+        use bpstd::{InternalPk, RedeemScript, TapScript, TapTree, WitnessScript};
+
+        let key = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let multi =
+            Vec::<u8>::from_hex(&format!("52{}53ae", format!("21{key}").repeat(3))).unwrap();
+        assert_eq!(multi.len(), 105);
+
+        // 2-of-3 P2SH multisig: OP_0, two signatures and OP_PUSHDATA1 of the redeem script,
+        // with the 3-byte length prefix of the signature script
+        let bip13 = DerivedScript::Bip13(RedeemScript::from_checked(multi.clone()));
+        assert!(!bip13.has_witness());
+        assert_eq!(bip13.satisfaction_vsize(), 256);
+        assert!(bip13.satisfaction_vsize() > satisfaction_vsize(&bip13.to_script_pubkey()));
+
+        let segwit = DerivedScript::Segwit(WitnessScript::from_checked(multi.clone()));
+        assert_eq!(segwit.satisfaction_vsize(), 64);
+        let nested = DerivedScript::NestedScript(WitnessScript::from_checked(multi));
+        assert_eq!(nested.satisfaction_vsize(), 99);
+        assert!(nested.has_witness());
+
+        // 2-of-3 multi_a leaf spent with one empty signature
+        let xonly = &key[2..];
+        let multi_a =
+            Vec::<u8>::from_hex(&format!("20{xonly}ac{}529c", format!("20{xonly}ba").repeat(2)))
+                .unwrap();
+        let internal_pk = InternalPk::from_str(xonly).unwrap();
+        let tree = TapTree::with_single_leaf(TapScript::from_checked(multi_a));
+        let tr = DerivedScript::TaprootScript(internal_pk, tree);
+        assert_eq!(tr.satisfaction_vsize(), 68);
+        assert_eq!(DerivedScript::TaprootKeyOnly(internal_pk).satisfaction_vsize(), 17);
+    }
+
+    #[test]
+    fn test_dust_limit() {
+        let dust = |hex: &str| dust_limit(&ScriptPubkey::from_hex(hex).unwrap());
+        // P2PKH
+        assert_eq!(dust("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac"), Sats(546));
+        // P2SH
+        assert_eq!(dust("a914751e76e8199196d454941c45d1b3a323f1433bd687"), Sats(540));
+        // P2WPKH
+        assert_eq!(dust("0014751e76e8199196d454941c45d1b3a323f1433bd6"), Sats(294));
+        // P2WSH
+        assert_eq!(
+            dust("0020a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"),
+            Sats(330)
+        );
+        // P2TR
+        assert_eq!(
+            dust("5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"),
+            Sats(330)
+        );
+        assert_eq!(dust("6a04deadbeef"), Sats::ZERO);
+    }
+
+    #[test]
+    fn test_rbf_signalling() {
+        assert!(signals_rbf(seq_no(true)));
+        assert!(!signals_rbf(seq_no(false)));
+        assert!(signals_rbf(SeqNo::from_consensus_u32(0)));
+        assert!(!signals_rbf(SeqNo::from_consensus_u32(0xFFFF_FFFF)));
+    }
+
+    #[test]
+    fn test_fee_target_parse() {
+        assert_eq!(FeeTarget::from_str("2.5"), Ok(FeeTarget::Rate(2.5)));
+        assert_eq!(FeeTarget::from_str("fast"), Ok(FeeTarget::Blocks(FAST_TARGET_BLOCKS)));
+        assert_eq!(FeeTarget::from_str("Slow"), Ok(FeeTarget::Blocks(SLOW_TARGET_BLOCKS)));
+        assert_eq!(FeeTarget::from_str("3blocks"), Ok(FeeTarget::Blocks(3)));
+        assert_eq!(FeeTarget::from_str("1block"), Ok(FeeTarget::Blocks(1)));
+        assert_eq!(FeeTarget::from_str("0blocks"), Err(FeeTargetParseError::ZeroBlocks));
+        assert!(FeeTarget::from_str("-1").is_err());
+        assert!(FeeTarget::from_str("soon").is_err());
+        assert_eq!(FeeRate::from_str("2.5").unwrap().sats_per_vbyte(), 2.5);
+        assert!(FeeRate::from_str("fast").is_err());
+        assert!(FeeRate::from_str("NaN").is_err());
+        for s in ["2.5", "fast", "medium", "slow", "3blocks"] {
+            assert_eq!(FeeTarget::from_str(s).unwrap().to_string(), s);
+        }
+    }
+}
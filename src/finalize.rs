@@ -0,0 +1,334 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Combination of PSBTs signed by different signers, finalization of signed PSBTs and extraction
+//! of the signed transactions, reporting inputs which lack signatures.
+
+use std::fmt::{self, Display, Formatter};
+
+use bpstd::{DeriveSet, Tx, Txid};
+use descriptors::{Descriptor, StdDescr};
+use psbt::{Input, Psbt};
+
+/// Number of signatures required to finalize an input of a single-signature descriptor.
+pub const SINGLE_SIG: usize = 1;
+
+/// Number of signatures required to finalize inputs of the standard `descriptor`: the threshold of
+/// multisig descriptors, or [`SINGLE_SIG`] for the other descriptors.
+pub fn required_sigs<S: DeriveSet>(descriptor: &StdDescr<S>) -> usize {
+    match descriptor {
+        StdDescr::ShMulti(d) => d.threshold() as usize,
+        StdDescr::ShSortedMulti(d) => d.threshold() as usize,
+        StdDescr::WshMulti(d) => d.threshold() as usize,
+        StdDescr::WshSortedMulti(d) => d.threshold() as usize,
+        StdDescr::TrMulti(d) => d.threshold as usize,
+        StdDescr::TrSortedMulti(d) => d.threshold as usize,
+        _ => SINGLE_SIG,
+    }
+}
+
+/// Data which PSBT input lacks to be finalized.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+pub enum Missing {
+    /// Not enough signatures.
+    #[display("{present} of {required} signatures")]
+    Signatures { present: usize, required: usize },
+
+    /// Input has enough signatures, but still can't be finalized with the descriptor, for
+    /// instance because the signatures are made by keys not present in the descriptor.
+    #[display("signatures don't satisfy the wallet descriptor")]
+    Satisfaction,
+}
+
+/// PSBT input which can't be finalized.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display("input {index}: {missing}")]
+pub struct IncompleteInput {
+    pub index: usize,
+    pub missing: Missing,
+}
+
+/// Error finalizing PSBT, listing inputs which can't be finalized.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Error)]
+pub struct Incomplete(pub Vec<IncompleteInput>);
+
+impl Display for Incomplete {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "PSBT can't be finalized: ")?;
+        for (no, input) in self.0.iter().enumerate() {
+            if no > 0 {
+                f.write_str("; ")?;
+            }
+            Display::fmt(input, f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error combining PSBTs.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum CombineError {
+    /// no PSBTs to combine.
+    Empty,
+
+    /// PSBT {index} is for transaction {found}, while the other PSBTs are for transaction
+    /// {expected}.
+    TxMismatch {
+        index: usize,
+        expected: Txid,
+        found: Txid,
+    },
+}
+
+/// Combines PSBTs of the same unsigned transaction, signed by different signers, into a single
+/// PSBT holding signatures from all of them.
+///
+/// Following BIP-174 combiner rules, key-value maps of the global section, inputs and outputs
+/// are merged, and optional fields absent in a PSBT are filled from the others. If several PSBTs
+/// have different values for the same key, the one from the PSBT coming first is kept. Inputs
+/// finalized in any of the PSBTs are finalized in the result.
+pub fn combine(psbts: impl IntoIterator<Item = Psbt>) -> Result<Psbt, CombineError> {
+    let mut psbts = psbts.into_iter();
+    let mut combined = psbts.next().ok_or(CombineError::Empty)?;
+    let expected = combined.txid();
+    for (index, psbt) in psbts.enumerate() {
+        let found = psbt.txid();
+        if found != expected {
+            return Err(CombineError::TxMismatch {
+                index: index + 1,
+                expected,
+                found,
+            });
+        }
+        union(&mut combined.xpubs, &psbt.xpubs);
+        union(&mut combined.proprietary, &psbt.proprietary);
+        for (ty, other) in &psbt.unknown {
+            union(combined.unknown.entry(*ty).or_default(), other);
+        }
+        for (input, other) in combined.inputs_mut().zip(psbt.inputs()) {
+            combine_input(input, other);
+        }
+        for (output, other) in combined.outputs_mut().zip(psbt.outputs()) {
+            fill(&mut output.redeem_script, &other.redeem_script);
+            fill(&mut output.witness_script, &other.witness_script);
+            union(&mut output.bip32_derivation, &other.bip32_derivation);
+            fill(&mut output.tap_internal_key, &other.tap_internal_key);
+            fill(&mut output.tap_tree, &other.tap_tree);
+            union(&mut output.tap_bip32_derivation, &other.tap_bip32_derivation);
+            union(&mut output.proprietary, &other.proprietary);
+            for (ty, other) in &other.unknown {
+                union(output.unknown.entry(*ty).or_default(), other);
+            }
+        }
+    }
+    Ok(combined)
+}
+
+/// Merges data of the `other` PSBT input into the `input` of the same transaction.
+fn combine_input(input: &mut Input, other: &Input) {
+    fill(&mut input.non_witness_tx, &other.non_witness_tx);
+    fill(&mut input.witness_utxo, &other.witness_utxo);
+    union(&mut input.partial_sigs, &other.partial_sigs);
+    fill(&mut input.sighash_type, &other.sighash_type);
+    fill(&mut input.redeem_script, &other.redeem_script);
+    fill(&mut input.witness_script, &other.witness_script);
+    union(&mut input.bip32_derivation, &other.bip32_derivation);
+    fill(&mut input.proof_of_reserves, &other.proof_of_reserves);
+    union(&mut input.ripemd160, &other.ripemd160);
+    union(&mut input.sha256, &other.sha256);
+    union(&mut input.hash160, &other.hash160);
+    union(&mut input.hash256, &other.hash256);
+    fill(&mut input.tap_key_sig, &other.tap_key_sig);
+    union(&mut input.tap_script_sig, &other.tap_script_sig);
+    union(&mut input.tap_leaf_script, &other.tap_leaf_script);
+    union(&mut input.tap_bip32_derivation, &other.tap_bip32_derivation);
+    fill(&mut input.tap_internal_key, &other.tap_internal_key);
+    fill(&mut input.tap_merkle_root, &other.tap_merkle_root);
+    union(&mut input.proprietary, &other.proprietary);
+    for (ty, other) in &other.unknown {
+        union(input.unknown.entry(*ty).or_default(), other);
+    }
+    if !input.is_finalized() && other.is_finalized() {
+        input.final_script_sig = other.final_script_sig.clone();
+        input.final_witness = other.final_witness.clone();
+    }
+}
+
+/// Adds entries of the `other` map with the keys absent from the `map`, keeping values already
+/// present in the `map`.
+fn union<M: Clone + IntoIterator + Extend<M::Item>>(map: &mut M, other: &M) {
+    let own = map.clone();
+    map.extend(other.clone());
+    map.extend(own);
+}
+
+/// Sets the `field` to the `other` value, if the field is absent.
+fn fill<T: Clone>(field: &mut Option<T>, other: &Option<T>) {
+    if field.is_none() {
+        field.clone_from(other);
+    }
+}
+
+/// Counts signatures present in PSBT input.
+pub fn signature_count(input: &Input) -> usize {
+    input.partial_sigs.len() + input.tap_key_sig.iter().count() + input.tap_script_sig.len()
+}
+
+/// Finalizes all PSBT inputs which have at least `required` signatures satisfying the
+/// descriptor, returning inputs which remain incomplete. Already finalized inputs are kept as
+/// they are.
+pub fn finalize<K, V, D: Descriptor<K, V>>(
+    psbt: &mut Psbt,
+    descriptor: &D,
+    required: usize,
+) -> Vec<IncompleteInput> {
+    psbt.finalize(descriptor);
+    psbt.inputs()
+        .enumerate()
+        .filter(|(_, input)| !input.is_finalized())
+        .map(|(index, input)| {
+            let present = signature_count(input);
+            let missing = if present < required {
+                Missing::Signatures { present, required }
+            } else {
+                Missing::Satisfaction
+            };
+            IncompleteInput { index, missing }
+        })
+        .collect()
+}
+
+/// Finalizes PSBT with [`finalize`] and extracts the signed transaction from it, which can be
+/// serialized with [`bpstd::ConsensusEncode`] for the broadcast.
+pub fn finalize_extract<K, V, D: Descriptor<K, V>>(
+    psbt: &mut Psbt,
+    descriptor: &D,
+    required: usize,
+) -> Result<Tx, Incomplete> {
+    let incomplete = finalize(psbt, descriptor, required);
+    if !incomplete.is_empty() {
+        return Err(Incomplete(incomplete));
+    }
+    Ok(psbt.extract().expect("all inputs are finalized"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bpstd::{Sats, ScriptPubkey, SighashType, TxOut, WitnessScript};
+    use psbt::{KeyData, ValueData};
+
+    use super::*;
+
+    #[test]
+    fn test_combine() {
+        // PSBTs of the same transaction with a partial signature by different keys each
+        let first = Psbt::from_str(
+            "cHNidP8BAFICAAAAARERERERERERERERERERERERERERERERERERERERERERAAAAAAD/////AegDAAAAAAAAFgAUIiIiIiIiIiIiIiIiIiIiIiIiIiIAAAAAACICAnm+Zn753LusVaBilc6HCwcCm/zbLc4o2VnygVsW+BeYCTAGAgEBAgEBAQAA",
+        )
+        .unwrap();
+        let second = Psbt::from_str(
+            "cHNidP8BAFICAAAAARERERERERERERERERERERERERERERERERERERERERERAAAAAAD/////AegDAAAAAAAAFgAUIiIiIiIiIiIiIiIiIiIiIiIiIiIAAAAAACICAsYEf5RB7X1tMEVAbpXAfNhcd45LjO88p6usCblccJ7lCTAGAgEBAgEBAQAA",
+        )
+        .unwrap();
+        let other_tx = Psbt::from_str(
+            "cHNidP8BAFICAAAAATMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzAAAAAAD/////AegDAAAAAAAAFgAUIiIiIiIiIiIiIiIiIiIiIiIiIiIAAAAAACICAsYEf5RB7X1tMEVAbpXAfNhcd45LjO88p6usCblccJ7lCTAGAgEBAgEBAQAA",
+        )
+        .unwrap();
+
+        let combined = combine([first.clone(), second, first.clone()]).unwrap();
+        assert_eq!(combined.txid(), first.txid());
+        assert_eq!(signature_count(combined.inputs().next().unwrap()), 2);
+
+        assert_eq!(
+            combine([first.clone(), other_tx.clone()]).unwrap_err(),
+            CombineError::TxMismatch {
+                index: 1,
+                expected: first.txid(),
+                found: other_tx.txid(),
+            }
+        );
+        assert_eq!(combine(Vec::<Psbt>::new()).unwrap_err(), CombineError::Empty);
+    }
+
+    #[test]
+    fn test_combine_fields() {
+        let psbt = Psbt::from_str(
+            "cHNidP8BAFICAAAAARERERERERERERERERERERERERERERERERERERERERERAAAAAAD/////AegDAAAAAAAAFgAUIiIiIiIiIiIiIiIiIiIiIiIiIiIAAAAAACICAnm+Zn753LusVaBilc6HCwcCm/zbLc4o2VnygVsW+BeYCTAGAgEBAgEBAQAA",
+        )
+        .unwrap();
+        let unknown = |key: u8, value: u8| (KeyData::from(vec![key]), ValueData::from(vec![value]));
+        let utxo = TxOut::new(ScriptPubkey::from_checked(vec![0x51]), Sats(2_000));
+
+        // The first signer knows the spent output, the second one the sighash type and the
+        // output script; both have unknown fields, one of which conflicts
+        let mut first = psbt.clone();
+        let input = first.inputs_mut().next().unwrap();
+        input.witness_utxo = Some(utxo.clone());
+        input.unknown.entry(0xF0).or_default().extend([unknown(1, 1)]);
+        first.unknown.entry(0xF0).or_default().extend([unknown(1, 1)]);
+        let mut second = psbt;
+        let input = second.inputs_mut().next().unwrap();
+        input.sighash_type = Some(SighashType::all());
+        input.unknown.entry(0xF0).or_default().extend([unknown(1, 9), unknown(2, 2)]);
+        second.outputs_mut().next().unwrap().witness_script =
+            Some(WitnessScript::from_checked(vec![0x51]));
+        second.unknown.entry(0xF1).or_default().extend([unknown(3, 3)]);
+
+        let combined = combine([first, second]).unwrap();
+        let input = combined.inputs().next().unwrap();
+        assert_eq!(input.witness_utxo, Some(utxo));
+        assert_eq!(input.sighash_type, Some(SighashType::all()));
+        assert_eq!(input.partial_sigs.len(), 1);
+        assert_eq!(
+            input.unknown[&0xF0].clone().into_iter().collect::<Vec<_>>(),
+            vec![unknown(1, 1), unknown(2, 2)]
+        );
+        let output = combined.outputs().next().unwrap();
+        assert_eq!(output.witness_script, Some(WitnessScript::from_checked(vec![0x51])));
+        assert_eq!(combined.unknown.len(), 2);
+    }
+
+    #[test]
+    fn test_incomplete_display() {
+        let incomplete = Incomplete(vec![
+            IncompleteInput {
+                index: 0,
+                missing: Missing::Satisfaction,
+            },
+            IncompleteInput {
+                index: 2,
+                missing: Missing::Signatures {
+                    present: 1,
+                    required: 2,
+                },
+            },
+        ]);
+        assert_eq!(
+            incomplete.to_string(),
+            "PSBT can't be finalized: input 0: signatures don't satisfy the wallet descriptor; \
+             input 2: 1 of 2 signatures"
+        );
+    }
+}
@@ -20,23 +20,143 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::path::PathBuf;
-use std::{fs, io};
+use std::fmt::Display;
+#[cfg(feature = "encryption")]
+use std::fmt::{self, Debug, Formatter};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{fs, io, thread};
 
+#[cfg(feature = "encryption")]
+use aes_gcm::aead::{Aead, Nonce, OsRng, Payload};
+#[cfg(feature = "encryption")]
+use aes_gcm::{AeadCore, Aes256Gcm, Key, KeyInit};
+use amplify::IoError;
+#[cfg(feature = "encryption")]
+use argon2::Argon2;
 use descriptors::Descriptor;
 use nonasync::persistence::{PersistenceError, PersistenceProvider};
+use psbt::PsbtConstructor;
+#[cfg(feature = "encryption")]
+use rand::RngCore;
+#[cfg(feature = "encryption")]
+use zeroize::Zeroizing;
 
 use super::*;
 use crate::{
-    Layer2Cache, Layer2Data, Layer2Descriptor, NoLayer2, WalletCache, WalletData, WalletDescr,
+    BlockHeight, Layer2, Layer2Cache, Layer2Data, Layer2Descriptor, Layer2Empty, Network, NoLayer2,
+    Wallet, WalletCache, WalletData, WalletDescr,
 };
 
+/// Name of the lock file created in a wallet directory while the wallet is in use.
+pub const LOCK_FILE: &str = ".lock";
+
+/// Interval between attempts to acquire a wallet lock held by another process.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Advisory lock of a wallet directory, preventing concurrent use of the wallet by several
+/// processes.
+///
+/// The lock is an OS file lock on the [`LOCK_FILE`] file, which keeps the id of the owning
+/// process for diagnostics. The lock is held while the lock file stays open, so it is released by
+/// the OS if the owning process crashes; the file itself is left in place.
+#[derive(Debug)]
+pub struct WalletLock {
+    path: PathBuf,
+    _file: fs::File,
+}
+
+impl WalletLock {
+    /// Locks the wallet directory `dir`, waiting up to `wait` for the lock to be released if it is
+    /// held by another process.
+    pub fn acquire(dir: &Path, wait: Duration) -> Result<Self, WalletDirError> {
+        let path = dir.join(LOCK_FILE);
+        let start = Instant::now();
+        let mut file = loop {
+            if let Some(file) = try_lock_file(&path)? {
+                break file;
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= wait {
+                return Err(WalletDirError::Locked(path.display().to_string()));
+            }
+            thread::sleep(LOCK_RETRY_INTERVAL.min(wait - elapsed));
+        };
+        file.set_len(0)?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Self { path, _file: file })
+    }
+
+    /// Path to the lock file.
+    pub fn path(&self) -> &Path { &self.path }
+}
+
+/// Opens the lock file at `path` and locks it exclusively, returning `None` if the lock is held by
+/// another open file handle.
+#[cfg(unix)]
+fn try_lock_file(path: &Path) -> io::Result<Option<fs::File>> {
+    use std::os::unix::io::AsRawFd;
+
+    let file =
+        fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+    // SAFETY: the file descriptor is valid while the file is open
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(Some(file));
+    }
+    match io::Error::last_os_error() {
+        err if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+        err => Err(err),
+    }
+}
+
+/// Opens the lock file at `path` denying its sharing, which locks it until the file is closed;
+/// returns `None` if the file is opened by another handle.
+#[cfg(windows)]
+fn try_lock_file(path: &Path) -> io::Result<Option<fs::File>> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    let res = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .share_mode(0)
+        .open(path);
+    match res {
+        Ok(file) => Ok(Some(file)),
+        Err(err) if err.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Opens the lock file at `path`; platforms without file locks don't protect wallets from the
+/// concurrent use.
+#[cfg(not(any(unix, windows)))]
+fn try_lock_file(path: &Path) -> io::Result<Option<fs::File>> {
+    fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path).map(Some)
+}
+
+impl PartialEq for WalletLock {
+    fn eq(&self, other: &Self) -> bool { self.path == other.path }
+}
+
+impl Eq for WalletLock {}
+
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct FsTextStore {
     pub descr: PathBuf,
     pub data: PathBuf,
     pub cache: PathBuf,
     pub l2: PathBuf,
+    /// Lock of the wallet directory, released once the store and all its clones are dropped.
+    pub lock: Option<Arc<WalletLock>>,
+    /// Key encrypting the wallet files at rest; the files are stored in plaintext if absent.
+    #[cfg(feature = "encryption")]
+    pub key: Option<WalletKey>,
 }
 
 impl FsTextStore {
@@ -57,10 +177,299 @@ impl FsTextStore {
             data,
             cache,
             l2,
+            lock: None,
+            #[cfg(feature = "encryption")]
+            key: None,
+        })
+    }
+
+    /// Creates store for the wallet directory at `path`, locking it for exclusive use by the
+    /// current process (see [`WalletLock`]).
+    pub fn locked(path: PathBuf, wait: Duration) -> Result<Self, WalletDirError> {
+        let mut store = Self::new(path.clone())?;
+        store.lock = Some(Arc::new(WalletLock::acquire(&path, wait)?));
+        Ok(store)
+    }
+
+    /// Checks whether the wallet files are encrypted at rest, judging by the header of the
+    /// descriptor file. Returns `false` if there is no descriptor file.
+    #[cfg(feature = "encryption")]
+    pub fn is_encrypted(&self) -> io::Result<bool> {
+        match fs::read(&self.descr) {
+            Ok(data) => Ok(is_encrypted(&data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Sets the key for the encrypted wallet files from the `passphrase`, checking that it
+    /// decrypts the descriptor file. Does nothing if the wallet files are not encrypted.
+    #[cfg(feature = "encryption")]
+    pub fn unlock(&mut self, passphrase: &str) -> Result<(), CryptError> {
+        let data = fs::read(&self.descr)?;
+        if !is_encrypted(&data) {
+            return Ok(());
+        }
+        let key = WalletKey::with_salt(passphrase, EncryptedFile::parse(&data)?.salt);
+        key.decrypt(&data)?;
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// Re-writes all wallet files, including their backups, encrypting them with the `key`, or
+    /// storing them in plaintext if the `key` is `None`.
+    ///
+    /// Encrypted files must be decryptable with the current key of the store (see
+    /// [`FsTextStore::unlock`]). All files are decrypted and written re-encrypted to temporary
+    /// files before any of them replaces the original, so a wrong key or a failed write leaves the
+    /// wallet unchanged.
+    #[cfg(feature = "encryption")]
+    pub fn rekey(&mut self, key: Option<WalletKey>) -> Result<(), CryptError> {
+        let mut files = vec![];
+        for path in [&self.descr, &self.data, &self.cache, &self.l2] {
+            for path in [path.clone(), backup_path(path)] {
+                let data = match fs::read(&path) {
+                    Ok(data) => data,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(err.into()),
+                };
+                files.push((path, self.decrypt(data)?));
+            }
+        }
+        let mut staged = Vec::with_capacity(files.len());
+        for (path, data) in files {
+            let tmp = match &key {
+                Some(key) => write_tmp(&path, key.encrypt(&data)),
+                None => write_tmp(&path, &*data),
+            };
+            match tmp {
+                Ok(tmp) => staged.push((tmp, path)),
+                Err(err) => {
+                    for (tmp, _) in staged {
+                        let _ = fs::remove_file(tmp);
+                    }
+                    return Err(err.into());
+                }
+            }
+        }
+        for (tmp, path) in staged {
+            fs::rename(tmp, path)?;
+        }
+        self.key = key;
+        Ok(())
+    }
+
+    /// Decrypts the file `data` if they are encrypted, returning plaintext data as they are.
+    #[cfg(feature = "encryption")]
+    fn decrypt(&self, data: Vec<u8>) -> Result<Zeroizing<Vec<u8>>, CryptError> {
+        if !is_encrypted(&data) {
+            return Ok(Zeroizing::new(data));
+        }
+        self.key.as_ref().ok_or(CryptError::PassphraseRequired)?.decrypt(&data)
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<String, PersistenceError> {
+        #[cfg(feature = "encryption")]
+        let data = self.decrypt(data).map_err(PersistenceError::with)?.to_vec();
+        String::from_utf8(data).map_err(PersistenceError::with)
+    }
+
+    fn parse_toml<T>(&self, data: Vec<u8>) -> Result<T, PersistenceError>
+    where
+        for<'de> T: serde::Deserialize<'de>,
+    {
+        toml::from_str(&self.decode(data)?).map_err(PersistenceError::with)
+    }
+
+    fn parse_yaml<T>(&self, data: Vec<u8>) -> Result<T, PersistenceError>
+    where
+        for<'de> T: serde::Deserialize<'de>,
+    {
+        serde_yaml::from_str(&self.decode(data)?).map_err(PersistenceError::with)
+    }
+
+    fn encode(&self, s: String) -> Vec<u8> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.key {
+            return key.encrypt(s.as_bytes());
+        }
+        s.into_bytes()
+    }
+}
+
+/// Magic bytes starting wallet files encrypted at rest, making them self-identifying.
+#[cfg(feature = "encryption")]
+pub const ENCRYPTION_MAGIC: [u8; 6] = *b"BPWENC";
+
+/// Version of the format of the wallet files encrypted at rest.
+#[cfg(feature = "encryption")]
+pub const ENCRYPTION_VERSION: u8 = 1;
+
+#[cfg(feature = "encryption")]
+const SALT_LEN: usize = 16;
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 12;
+#[cfg(feature = "encryption")]
+const HEADER_LEN: usize = ENCRYPTION_MAGIC.len() + 1 + SALT_LEN;
+
+/// Checks whether the wallet file `data` are encrypted at rest.
+#[cfg(feature = "encryption")]
+pub fn is_encrypted(data: &[u8]) -> bool { data.starts_with(&ENCRYPTION_MAGIC) }
+
+/// Errors encrypting and decrypting wallet files.
+#[cfg(feature = "encryption")]
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum CryptError {
+    /// wallet is encrypted; a passphrase is required to open it.
+    PassphraseRequired,
+
+    /// invalid wallet passphrase.
+    WrongPassphrase,
+
+    /// wallet file is encrypted with a key different from the one of the wallet descriptor file.
+    ForeignKey,
+
+    /// encrypted wallet file has unsupported format version {0}.
+    UnsupportedVersion(u8),
+
+    /// encrypted wallet file is truncated or corrupted.
+    InvalidFormat,
+
+    /// I/O error. Details: {0}
+    #[from]
+    #[from(io::Error)]
+    Io(IoError),
+}
+
+/// Errors loading a wallet stored with [`FsTextStore`], which distinguish failures to decrypt
+/// the wallet files from the other ones.
+#[derive(Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum LoadError {
+    /// {0}
+    #[cfg(feature = "encryption")]
+    Crypt(CryptError),
+
+    /// wallet can't be loaded. Details: {0}
+    Persistence(PersistenceError),
+}
+
+impl From<PersistenceError> for LoadError {
+    fn from(err: PersistenceError) -> Self {
+        #[cfg(feature = "encryption")]
+        let err = match err.0.downcast::<CryptError>() {
+            Ok(err) => return LoadError::Crypt(*err),
+            Err(err) => PersistenceError(err),
+        };
+        LoadError::Persistence(err)
+    }
+}
+
+/// Parts of an encrypted wallet file, which consists of [`ENCRYPTION_MAGIC`], format version,
+/// Argon2id salt, AES-256-GCM nonce and the ciphertext. The header preceding the nonce is
+/// authenticated together with the ciphertext.
+#[cfg(feature = "encryption")]
+struct EncryptedFile<'data> {
+    header: &'data [u8],
+    salt: [u8; SALT_LEN],
+    nonce: &'data [u8],
+    ciphertext: &'data [u8],
+}
+
+#[cfg(feature = "encryption")]
+impl<'data> EncryptedFile<'data> {
+    fn parse(data: &'data [u8]) -> Result<Self, CryptError> {
+        if !is_encrypted(data) || data.len() < HEADER_LEN + NONCE_LEN {
+            return Err(CryptError::InvalidFormat);
+        }
+        let version = data[ENCRYPTION_MAGIC.len()];
+        if version != ENCRYPTION_VERSION {
+            return Err(CryptError::UnsupportedVersion(version));
+        }
+        let (header, rest) = data.split_at(HEADER_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&header[HEADER_LEN - SALT_LEN..]);
+        Ok(EncryptedFile {
+            header,
+            salt,
+            nonce,
+            ciphertext,
         })
     }
 }
 
+/// Key encrypting wallet files at rest with AES-256-GCM, derived from a passphrase with
+/// Argon2id.
+///
+/// All files of a wallet are encrypted with the same key, so they share a single salt, which is
+/// stored in the header of each file. The passphrase itself is not kept.
+#[cfg(feature = "encryption")]
+#[derive(Clone, Eq, PartialEq)]
+pub struct WalletKey {
+    salt: [u8; SALT_LEN],
+    key: Zeroizing<[u8; 32]>,
+}
+
+#[cfg(feature = "encryption")]
+impl Debug for WalletKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str("WalletKey(..)") }
+}
+
+#[cfg(feature = "encryption")]
+impl WalletKey {
+    /// Derives key from the `passphrase` using a new random salt.
+    pub fn new(passphrase: &str) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        rand::rng().fill_bytes(&mut salt);
+        Self::with_salt(passphrase, salt)
+    }
+
+    fn with_salt(passphrase: &str, salt: [u8; SALT_LEN]) -> Self {
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key[..])
+            .expect("valid Argon2 parameters");
+        WalletKey { salt, key }
+    }
+
+    /// Encrypts the file `data`, prepending them with the header of the encrypted file.
+    pub fn encrypt(&self, data: &[u8]) -> Vec<u8> {
+        let mut file = Vec::with_capacity(HEADER_LEN + NONCE_LEN + data.len() + 16);
+        file.extend_from_slice(&ENCRYPTION_MAGIC);
+        file.push(ENCRYPTION_VERSION);
+        file.extend_from_slice(&self.salt);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key[..]));
+        let payload = Payload {
+            msg: data,
+            aad: &file,
+        };
+        let ciphertext = cipher.encrypt(&nonce, payload).expect("failed to encrypt");
+        file.extend_from_slice(&nonce);
+        file.extend(ciphertext);
+        file
+    }
+
+    /// Decrypts the encrypted file `data`.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Zeroizing<Vec<u8>>, CryptError> {
+        let file = EncryptedFile::parse(data)?;
+        if file.salt != self.salt {
+            return Err(CryptError::ForeignKey);
+        }
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key[..]));
+        let payload = Payload {
+            msg: file.ciphertext,
+            aad: file.header,
+        };
+        cipher
+            .decrypt(Nonce::<Aes256Gcm>::from_slice(file.nonce), payload)
+            .map(Zeroizing::new)
+            .map_err(|_| CryptError::WrongPassphrase)
+    }
+}
+
 impl<K, D: Descriptor<K>, L2: Layer2Descriptor> PersistenceProvider<WalletDescr<K, D, L2>>
     for FsTextStore
 where
@@ -69,14 +478,14 @@ where
     for<'de> L2: serde::Serialize + serde::Deserialize<'de>,
 {
     fn load(&self) -> Result<WalletDescr<K, D, L2>, PersistenceError> {
-        let descr = fs::read_to_string(&self.descr).map_err(PersistenceError::with)?;
-        toml::from_str(&descr).map_err(PersistenceError::with)
+        load_with_backup(&self.descr, |data| self.parse_toml(data))
     }
 
     fn store(&self, object: &WalletDescr<K, D, L2>) -> Result<(), PersistenceError> {
         let s = toml::to_string_pretty(object).map_err(PersistenceError::with)?;
-        fs::write(&self.descr, s).map_err(PersistenceError::with)?;
-        Ok(())
+        store_with_backup(&self.descr, self.encode(s), |data| {
+            self.parse_toml::<WalletDescr<K, D, L2>>(data)
+        })
     }
 }
 
@@ -86,14 +495,14 @@ where
     for<'de> L2: serde::Serialize + serde::Deserialize<'de>,
 {
     fn load(&self) -> Result<WalletCache<L2>, PersistenceError> {
-        let file = fs::File::open(&self.cache).map_err(PersistenceError::with)?;
-        serde_yaml::from_reader(file).map_err(PersistenceError::with)
+        load_with_backup(&self.cache, |data| self.parse_yaml(data))
     }
 
     fn store(&self, object: &WalletCache<L2>) -> Result<(), PersistenceError> {
-        let file = fs::File::create(&self.cache).map_err(PersistenceError::with)?;
-        serde_yaml::to_writer(file, object).map_err(PersistenceError::with)?;
-        Ok(())
+        let s = serde_yaml::to_string(object).map_err(PersistenceError::with)?;
+        store_with_backup(&self.cache, self.encode(s), |data| {
+            self.parse_yaml::<WalletCache<L2>>(data)
+        })
     }
 }
 
@@ -103,14 +512,14 @@ where
     for<'de> L2: serde::Serialize + serde::Deserialize<'de>,
 {
     fn load(&self) -> Result<WalletData<L2>, PersistenceError> {
-        let data = fs::read_to_string(&self.data).map_err(PersistenceError::with)?;
-        toml::from_str(&data).map_err(PersistenceError::with)
+        load_with_backup(&self.data, |data| self.parse_toml(data))
     }
 
     fn store(&self, object: &WalletData<L2>) -> Result<(), PersistenceError> {
         let s = toml::to_string_pretty(object).map_err(PersistenceError::with)?;
-        fs::write(&self.data, s).map_err(PersistenceError::with)?;
-        Ok(())
+        store_with_backup(&self.data, self.encode(s), |data| {
+            self.parse_toml::<WalletData<L2>>(data)
+        })
     }
 }
 
@@ -125,3 +534,721 @@ impl PersistenceProvider<NoLayer2> for FsTextStore {
         Ok(())
     }
 }
+
+/// Writes `contents` to the file at `path` atomically: the data are written to a temporary file in
+/// the same directory, which then replaces the target file.
+pub fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp = write_tmp(path, contents)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// Writes `contents` to a temporary file next to `path` and flushes it to disk, returning the path
+/// of the temporary file.
+fn write_tmp(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<PathBuf> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(contents.as_ref()).and_then(|_| file.sync_all()).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })?;
+    Ok(tmp)
+}
+
+/// Returns path of the single-generation backup kept for a wallet file at `path`.
+pub fn backup_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut bak = path.as_ref().as_os_str().to_owned();
+    bak.push(".bak");
+    PathBuf::from(bak)
+}
+
+/// Atomically replaces the file at `path` with `contents`, keeping the previous version of the
+/// file as a backup (see [`backup_path`]) if it can be `parse`d. A corrupt file never replaces
+/// the last good backup.
+///
+/// The backup is itself updated atomically, so at any moment at least one of the primary and the
+/// backup files is complete.
+fn store_with_backup<T>(
+    path: &Path,
+    contents: impl AsRef<[u8]>,
+    parse: impl Fn(Vec<u8>) -> Result<T, PersistenceError>,
+) -> Result<(), PersistenceError> {
+    if let Ok(data) = fs::read(path) {
+        if parse(data).is_ok() {
+            let bak = backup_path(path);
+            let mut tmp = bak.as_os_str().to_owned();
+            tmp.push(".tmp");
+            fs::copy(path, &tmp).map_err(PersistenceError::with)?;
+            fs::rename(&tmp, &bak).map_err(PersistenceError::with)?;
+        }
+    }
+    write_atomic(path, contents).map_err(PersistenceError::with)
+}
+
+/// Reads and parses the file at `path`, falling back to its backup (see [`backup_path`]) if the
+/// primary file is missing or corrupt. If both fail, the error for the primary file is returned.
+fn load_with_backup<T>(
+    path: &Path,
+    parse: impl Fn(Vec<u8>) -> Result<T, PersistenceError>,
+) -> Result<T, PersistenceError> {
+    let primary = fs::read(path).map_err(PersistenceError::with).and_then(&parse);
+    let err = match primary {
+        Ok(object) => return Ok(object),
+        Err(err) => err,
+    };
+    let bak = backup_path(path);
+    let Ok(data) = fs::read(&bak) else {
+        return Err(err);
+    };
+    match parse(data) {
+        Ok(object) => {
+            #[cfg(feature = "log")]
+            log::warn!(
+                "wallet file '{}' is corrupt ({err}), recovered from backup '{}'",
+                path.display(),
+                bak.display()
+            );
+            Ok(object)
+        }
+        Err(_) => Err(err),
+    }
+}
+
+/// Information about a wallet found in the wallet data directory.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WalletInfo {
+    /// Wallet name, matching the name of the wallet directory.
+    pub name: String,
+    pub path: PathBuf,
+    pub descriptor: String,
+    pub network: Network,
+    /// Height of the most recent block known from the last wallet sync.
+    pub last_sync: Option<BlockHeight>,
+}
+
+impl WalletInfo {
+    /// Returns descriptor type, like `wpkh` or `tr`.
+    pub fn descriptor_type(&self) -> &str {
+        self.descriptor.split_once('(').map(|(ty, _)| ty).unwrap_or(&self.descriptor)
+    }
+}
+
+/// Wallet data directory entry which is not a loadable wallet.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SkippedWallet {
+    pub name: String,
+    pub path: PathBuf,
+    /// Reason why the directory can't be loaded as a wallet.
+    pub reason: String,
+}
+
+/// Result of scanning the wallet data directory.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct WalletList {
+    pub wallets: Vec<WalletInfo>,
+    pub skipped: Vec<SkippedWallet>,
+}
+
+/// Loads the wallet from the `store`, reporting the failures to decrypt the wallet files (like a
+/// wrong passphrase) with [`LoadError::Crypt`].
+#[allow(clippy::type_complexity)]
+pub fn load_wallet<K, D: Descriptor<K>, L2: Layer2>(
+    store: FsTextStore,
+    autosave: bool,
+) -> Result<Wallet<K, D, WalletCache<L2::Cache>, L2>, LoadError>
+where
+    FsTextStore: PersistenceProvider<WalletDescr<K, D, L2::Descr>>
+        + PersistenceProvider<WalletData<L2::Data>>
+        + PersistenceProvider<WalletCache<L2::Cache>>
+        + PersistenceProvider<L2>,
+{
+    Wallet::load(store, autosave).map_err(LoadError::from)
+}
+
+/// Scans `base_dir` for the wallets, loading each of its subdirectories.
+///
+/// Directories which can't be loaded as a wallet (corrupted or not wallet-related) are reported
+/// in [`WalletList::skipped`] and do not abort the scan; the same applies to the entries which
+/// can't be read. Both lists are sorted by the wallet name.
+pub fn list_wallets<K, D: Descriptor<K> + Display>(base_dir: &Path) -> io::Result<WalletList>
+where FsTextStore: PersistenceProvider<WalletDescr<K, D, Layer2Empty>> {
+    let mut list = WalletList::default();
+    for entry in fs::read_dir(base_dir)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                // The name of an entry which can't be read is unknown
+                list.skipped.push(SkippedWallet {
+                    name: s!(""),
+                    path: base_dir.to_owned(),
+                    reason: err.to_string(),
+                });
+                continue;
+            }
+        };
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Hidden directories, like the wallet trash, are not wallets
+        if name.starts_with('.') {
+            continue;
+        }
+        let provider = match entry.file_type() {
+            Ok(file_type) if !file_type.is_dir() => continue,
+            Ok(_) => FsTextStore::new(path.clone()).map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        let wallet = provider.and_then(|provider| {
+            load_wallet::<K, D, NoLayer2>(provider, false).map_err(|err| err.to_string())
+        });
+        match wallet {
+            Ok(wallet) => list.wallets.push(WalletInfo {
+                name,
+                path,
+                descriptor: wallet.descriptor().to_string(),
+                network: wallet.network(),
+                last_sync: wallet.last_sync_height(),
+            }),
+            Err(reason) => list.skipped.push(SkippedWallet { name, path, reason }),
+        }
+    }
+    list.wallets.sort_by(|a, b| a.name.cmp(&b.name));
+    list.skipped.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(list)
+}
+
+/// Name of the directory inside the wallet data directory where deleted wallets are moved to.
+pub const TRASH_DIR: &str = ".trash";
+
+/// Errors managing wallet directories.
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum WalletDirError {
+    /// wallet '{0}' is not found.
+    NotFound(String),
+
+    /// wallet named '{0}' already exists.
+    AlreadyExists(String),
+
+    /// '{0}' is not a valid wallet name.
+    InvalidName(String),
+
+    /// wallet '{0}' is the default wallet and can't be deleted without force flag.
+    IsDefault(String),
+
+    /// wallet is in use by another process holding the lock on '{0}'.
+    Locked(String),
+
+    /// wallet data can't be updated. Details: {0}
+    #[from]
+    Store(PersistenceError),
+
+    /// I/O error. Details: {0}
+    #[from]
+    #[from(io::Error)]
+    Io(IoError),
+}
+
+/// Ensures the wallet name refers to a directory located directly inside the wallet data
+/// directory.
+fn check_wallet_name(name: &str) -> Result<(), WalletDirError> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(WalletDirError::InvalidName(name.to_owned()));
+    }
+    Ok(())
+}
+
+/// Renames wallet directory `from` into `to` inside `base_dir`.
+///
+/// The directory is moved with a single rename operation, so the wallet is never seen partially
+/// moved.
+pub fn rename_wallet(base_dir: &Path, from: &str, to: &str) -> Result<(), WalletDirError> {
+    check_wallet_name(from)?;
+    check_wallet_name(to)?;
+    let src = base_dir.join(from);
+    if !src.is_dir() {
+        return Err(WalletDirError::NotFound(from.to_owned()));
+    }
+    let dst = base_dir.join(to);
+    if dst.exists() {
+        return Err(WalletDirError::AlreadyExists(to.to_owned()));
+    }
+    fs::rename(src, dst)?;
+    Ok(())
+}
+
+/// Deletes wallet directory `name` inside `base_dir`.
+///
+/// If `backup` is true, the wallet directory is moved into [`TRASH_DIR`] instead of being
+/// removed; the function returns the new location of the wallet in this case.
+pub fn delete_wallet(
+    base_dir: &Path,
+    name: &str,
+    backup: bool,
+) -> Result<Option<PathBuf>, WalletDirError> {
+    check_wallet_name(name)?;
+    let dir = base_dir.join(name);
+    if !dir.is_dir() {
+        return Err(WalletDirError::NotFound(name.to_owned()));
+    }
+    if !backup {
+        fs::remove_dir_all(dir)?;
+        return Ok(None);
+    }
+    let trash = base_dir.join(TRASH_DIR);
+    fs::create_dir_all(&trash)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let backup = trash.join(format!("{name}-{timestamp}"));
+    fs::rename(dir, &backup)?;
+    Ok(Some(backup))
+}
+
+/// Wallet directory moved by [`migrate_wallets`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct MigratedWallet {
+    pub name: String,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Whether the wallet files are encrypted, in which case the paths stored in the wallet data
+    /// are not rewritten and must be updated by the user after unlocking the wallet.
+    pub encrypted: bool,
+}
+
+/// Moves all wallet directories from the legacy data directory `from` into `to`, returning the
+/// list of moved wallets. If `dry_run` is true, the wallets are listed but not moved.
+///
+/// Absolute paths stored in the wallet data which point inside `from` (like the signing account
+/// file) are rewritten to point to the same location inside `to`. This is not possible for
+/// encrypted wallets, which are reported with [`MigratedWallet::encrypted`] set instead.
+///
+/// Nothing is moved if some of the wallets already exists in `to` or is in use by another
+/// process. Since the moved wallets are no longer present in `from`, repeated migration does
+/// nothing.
+pub fn migrate_wallets(
+    from: &Path,
+    to: &Path,
+    dry_run: bool,
+) -> Result<Vec<MigratedWallet>, WalletDirError> {
+    let mut plan = vec![];
+    if !from.is_dir() || from == to {
+        return Ok(plan);
+    }
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !entry.file_type()?.is_dir() || check_wallet_name(&name).is_err() {
+            continue;
+        }
+        let src = entry.path();
+        let dst = to.join(&name);
+        if dst.exists() {
+            return Err(WalletDirError::AlreadyExists(name));
+        }
+        // Lock file is left in place after use, so we check whether it is actually held
+        if src.join(LOCK_FILE).exists() {
+            WalletLock::acquire(&src, Duration::ZERO)?;
+        }
+        #[cfg(feature = "encryption")]
+        let encrypted = FsTextStore::new(src.clone())?.is_encrypted()?;
+        #[cfg(not(feature = "encryption"))]
+        let encrypted = false;
+        plan.push(MigratedWallet {
+            name,
+            from: src,
+            to: dst,
+            encrypted,
+        });
+    }
+    plan.sort_by(|a, b| a.name.cmp(&b.name));
+    if dry_run {
+        return Ok(plan);
+    }
+
+    fs::create_dir_all(to)?;
+    // Moved wallets with their original data files, which are restored if some of the wallets
+    // fails to migrate, so the wallets are never left split between the two directories
+    let mut moved = Vec::<(&MigratedWallet, Option<(PathBuf, Vec<u8>)>)>::new();
+    let res = plan.iter().try_for_each(|wallet| -> Result<(), WalletDirError> {
+        move_dir(&wallet.from, &wallet.to)?;
+        moved.push((wallet, None));
+        let store = FsTextStore::new(wallet.to.clone())?;
+        // Paths in the encrypted wallet data can't be rewritten without the passphrase
+        if wallet.encrypted || !store.data.exists() {
+            return Ok(());
+        }
+        let mut data: WalletData<Layer2Empty> = store.load()?;
+        let Some(rel) =
+            data.signing_account.as_deref().and_then(|path| path.strip_prefix(from).ok())
+        else {
+            return Ok(());
+        };
+        data.signing_account = Some(to.join(rel));
+        let original = fs::read(&store.data)?;
+        store.store(&data)?;
+        moved.last_mut().expect("wallet is moved").1 = Some((store.data, original));
+        Ok(())
+    });
+    if let Err(err) = res {
+        // Rollback errors are ignored, since the error which caused it is the one to report
+        for (wallet, original) in moved.into_iter().rev() {
+            if let Some((path, original)) = original {
+                let _ = write_atomic(path, original);
+            }
+            let _ = move_dir(&wallet.to, &wallet.from);
+        }
+        return Err(err);
+    }
+    Ok(plan)
+}
+
+/// Moves directory `from` to `to`, falling back to copying it and removing the original if the
+/// directories are on different file systems.
+fn move_dir(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if crosses_devices(&err) => {
+            if let Err(err) = copy_dir(from, to) {
+                let _ = fs::remove_dir_all(to);
+                return Err(err);
+            }
+            fs::remove_dir_all(from)
+        }
+        res => res,
+    }
+}
+
+/// Checks whether the `err` reports renaming a file across file systems.
+fn crosses_devices(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    const ERROR_CROSSES_DEVICES: Option<i32> = Some(libc::EXDEV);
+    // ERROR_NOT_SAME_DEVICE
+    #[cfg(windows)]
+    const ERROR_CROSSES_DEVICES: Option<i32> = Some(17);
+    #[cfg(not(any(unix, windows)))]
+    const ERROR_CROSSES_DEVICES: Option<i32> = None;
+
+    ERROR_CROSSES_DEVICES.is_some() && err.raw_os_error() == ERROR_CROSSES_DEVICES
+}
+
+/// Recursively copies directory `from` into a new directory `to`.
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let dst = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dst)?;
+        } else {
+            fs::copy(entry.path(), dst)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bpstd::Txid;
+
+    use super::*;
+    use crate::{Inpoint, Layer2Empty};
+
+    #[test]
+    fn test_recover_from_backup() {
+        let dir = std::env::temp_dir().join(format!("bp-wallet-fs-{}", std::process::id()));
+        let store = FsTextStore::new(dir.clone()).unwrap();
+
+        let mut data = WalletData::<Layer2Empty>::new_layer1();
+        data.name = s!("first");
+        store.store(&data).unwrap();
+        data.name = s!("second");
+        let inpoint = Inpoint::new(Txid::from([0x11; 32]), 2);
+        data.txin_annotations.insert(inpoint, s!("rent"));
+        store.store(&data).unwrap();
+
+        let loaded: WalletData<Layer2Empty> = store.load().unwrap();
+        assert_eq!(loaded.name, "second");
+        assert_eq!(loaded.txin_annotations, data.txin_annotations);
+
+        // Simulate a write interrupted mid-way
+        let full = fs::read(&store.data).unwrap();
+        fs::write(&store.data, &full[..full.len() / 2]).unwrap();
+        let loaded: WalletData<Layer2Empty> = store.load().unwrap();
+        assert_eq!(loaded.name, "first");
+
+        // The corrupt file doesn't replace the last good backup
+        data.name = s!("third");
+        store.store(&data).unwrap();
+        fs::write(&store.data, "corrupt").unwrap();
+        let loaded: WalletData<Layer2Empty> = store.load().unwrap();
+        assert_eq!(loaded.name, "first");
+
+        fs::remove_file(backup_path(&store.data)).unwrap();
+        assert!(PersistenceProvider::<WalletData<Layer2Empty>>::load(&store).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_wallet_round_trip() {
+        use std::str::FromStr;
+
+        use bpstd::{Keychain, Outpoint, XpubDerivable};
+        use descriptors::{StdDescr, TrKey};
+
+        let dir = std::env::temp_dir().join(format!("bp-wallet-load-{}", std::process::id()));
+        let xpub = XpubDerivable::from_str(
+            "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1>/*",
+        )
+        .unwrap();
+        let mut wallet = Wallet::<XpubDerivable, StdDescr, WalletCache<Layer2Empty>>::new_layer1(
+            TrKey::from(xpub).into(),
+            Network::Mainnet,
+        );
+        wallet.make_persistent(FsTextStore::new(dir.clone()).unwrap(), false).unwrap();
+        wallet.next_address(Keychain::OUTER);
+        let reserved = wallet.next_address(Keychain::OUTER);
+        wallet.set_birth_time(Some(1_700_000_000));
+        wallet.set_gap_limit(Keychain::INNER, 50);
+        let outpoint = Outpoint::new(Txid::from([0x11; 32]), 1);
+        let inpoint = Inpoint::new(Txid::from([0x11; 32]), 0);
+        wallet
+            .with_data(|data| {
+                data.locked_utxos.insert(outpoint, Some(s!("cold storage")));
+                data.txin_annotations.insert(inpoint, s!("rent"));
+                Ok::<_, PersistenceError>(())
+            })
+            .unwrap();
+        wallet.store().unwrap();
+        drop(wallet);
+
+        let store = FsTextStore::new(dir.clone()).unwrap();
+        let mut loaded = load_wallet::<XpubDerivable, StdDescr, NoLayer2>(store, false).unwrap();
+        // Reservations survive the reload
+        assert_eq!(loaded.data().last_used.get(&Keychain::OUTER), Some(&NormalIndex::from(2u16)));
+        assert_ne!(loaded.peek_address(Keychain::OUTER), reserved);
+        assert_eq!(loaded.next_address(Keychain::OUTER).terminal.index, NormalIndex::from(2u16));
+        assert_eq!(loaded.data().birth_time, Some(1_700_000_000));
+        assert_eq!(loaded.gap_limit(Keychain::INNER), 50);
+        assert_eq!(loaded.lock_label(outpoint), Some("cold storage"));
+        assert_eq!(loaded.data().txin_annotations.get(&inpoint).map(String::as_str), Some("rent"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_list_skips_unreadable() {
+        use bpstd::XpubDerivable;
+        use descriptors::StdDescr;
+
+        let dir = std::env::temp_dir().join(format!("bp-wallet-list-{}", std::process::id()));
+        // Wallet whose descriptor file can't be read
+        fs::create_dir_all(dir.join("broken").join("descriptor.toml")).unwrap();
+        fs::create_dir_all(dir.join(TRASH_DIR)).unwrap();
+        fs::write(dir.join("notes.txt"), "not a wallet").unwrap();
+
+        let list = list_wallets::<XpubDerivable, StdDescr>(&dir).unwrap();
+        assert!(list.wallets.is_empty());
+        assert_eq!(list.skipped.len(), 1);
+        assert_eq!(list.skipped[0].name, "broken");
+        assert_eq!(list.skipped[0].path, dir.join("broken"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn test_encrypted_store() {
+        use bpstd::XpubDerivable;
+        use descriptors::StdDescr;
+
+        let dir = std::env::temp_dir().join(format!("bp-wallet-crypt-{}", std::process::id()));
+        let mut store = FsTextStore::new(dir.clone()).unwrap();
+        store.key = Some(WalletKey::new("secret"));
+
+        let mut data = WalletData::<Layer2Empty>::new_layer1();
+        data.name = s!("first");
+        store.store(&data).unwrap();
+        data.name = s!("second");
+        store.store(&data).unwrap();
+        assert!(is_encrypted(&fs::read(&store.data).unwrap()));
+        assert!(is_encrypted(&fs::read(backup_path(&store.data)).unwrap()));
+        let loaded: WalletData<Layer2Empty> = store.load().unwrap();
+        assert_eq!(loaded.name, "second");
+
+        // Files are self-identifying, and are not readable without the passphrase, which is
+        // checked against the descriptor file
+        fs::copy(&store.data, &store.descr).unwrap();
+        let mut reopened = FsTextStore::new(dir.clone()).unwrap();
+        assert!(reopened.is_encrypted().unwrap());
+        let err = PersistenceProvider::<WalletData<Layer2Empty>>::load(&reopened).unwrap_err();
+        assert!(matches!(LoadError::from(err), LoadError::Crypt(CryptError::PassphraseRequired)));
+        assert!(matches!(reopened.unlock("wrong"), Err(CryptError::WrongPassphrase)));
+        let mut wrong = FsTextStore::new(dir.clone()).unwrap();
+        let salt = store.key.as_ref().unwrap().salt;
+        wrong.key = Some(WalletKey::with_salt("wrong", salt));
+        let err = load_wallet::<XpubDerivable, StdDescr, NoLayer2>(wrong, false).unwrap_err();
+        assert!(matches!(err, LoadError::Crypt(CryptError::WrongPassphrase)));
+        reopened.unlock("secret").unwrap();
+        let loaded: WalletData<Layer2Empty> = reopened.load().unwrap();
+        assert_eq!(loaded.name, "second");
+
+        // Re-encryption with a new passphrase covers the backups too
+        reopened.rekey(Some(WalletKey::new("new secret"))).unwrap();
+        let mut reopened = FsTextStore::new(dir.clone()).unwrap();
+        assert!(matches!(reopened.unlock("secret"), Err(CryptError::WrongPassphrase)));
+        reopened.unlock("new secret").unwrap();
+        let backup = fs::read(backup_path(&store.data)).unwrap();
+        assert!(reopened.decrypt(backup.clone()).is_ok());
+        // The key derived with the former salt is not tried against the re-keyed files
+        assert!(matches!(store.decrypt(backup), Err(CryptError::ForeignKey)));
+
+        // A write failing midway leaves all files under the former key, without temporary files
+        let mut blocker = backup_path(&store.data).into_os_string();
+        blocker.push(".tmp");
+        fs::create_dir(&blocker).unwrap();
+        assert!(reopened.rekey(Some(WalletKey::new("third secret"))).is_err());
+        fs::remove_dir(&blocker).unwrap();
+        let mut descr_tmp = store.descr.clone().into_os_string();
+        descr_tmp.push(".tmp");
+        assert!(!Path::new(&descr_tmp).exists());
+        let mut reopened = FsTextStore::new(dir.clone()).unwrap();
+        reopened.unlock("new secret").unwrap();
+        assert!(reopened.decrypt(fs::read(backup_path(&store.data)).unwrap()).is_ok());
+
+        reopened.rekey(None).unwrap();
+        assert!(!reopened.is_encrypted().unwrap());
+        let plaintext = FsTextStore::new(dir.clone()).unwrap();
+        let loaded: WalletData<Layer2Empty> = plaintext.load().unwrap();
+        assert_eq!(loaded.name, "second");
+        assert!(!is_encrypted(&fs::read(backup_path(&store.data)).unwrap()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_wallets() {
+        let root = std::env::temp_dir().join(format!("bp-wallet-migrate-{}", std::process::id()));
+        let legacy = root.join("legacy");
+        let current = root.join("current");
+
+        let mut data = WalletData::<Layer2Empty>::new_layer1();
+        data.signing_account = Some(legacy.join("alice").join("account.key"));
+        FsTextStore::new(legacy.join("alice")).unwrap().store(&data).unwrap();
+        fs::create_dir_all(legacy.join("bob")).unwrap();
+        fs::create_dir_all(legacy.join(TRASH_DIR)).unwrap();
+
+        let plan = migrate_wallets(&legacy, &current, true).unwrap();
+        let names = plan.iter().map(|wallet| wallet.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["alice", "bob"]);
+        assert!(!current.exists());
+
+        let moved = migrate_wallets(&legacy, &current, false).unwrap();
+        assert_eq!(moved, plan);
+        assert!(current.join("bob").is_dir());
+        let store = FsTextStore::new(current.join("alice")).unwrap();
+        let data: WalletData<Layer2Empty> = store.load().unwrap();
+        assert_eq!(data.signing_account, Some(current.join("alice").join("account.key")));
+
+        assert_eq!(migrate_wallets(&legacy, &current, false).unwrap(), vec![]);
+
+        fs::create_dir_all(legacy.join("bob")).unwrap();
+        let err = migrate_wallets(&legacy, &current, true).unwrap_err();
+        assert!(matches!(err, WalletDirError::AlreadyExists(name) if name == "bob"));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_rollback() {
+        let root = std::env::temp_dir().join(format!("bp-wallet-rollback-{}", std::process::id()));
+        let legacy = root.join("legacy");
+        let current = root.join("current");
+
+        let mut data = WalletData::<Layer2Empty>::new_layer1();
+        let account = legacy.join("alice").join("account.key");
+        data.signing_account = Some(account.clone());
+        let alice = FsTextStore::new(legacy.join("alice")).unwrap();
+        alice.store(&data).unwrap();
+        let bob = FsTextStore::new(legacy.join("bob")).unwrap();
+        fs::write(&bob.data, "corrupt").unwrap();
+
+        // Alice is migrated first, and gets back together with her data once Bob fails
+        let err = migrate_wallets(&legacy, &current, false).unwrap_err();
+        assert!(matches!(err, WalletDirError::Store(_)));
+        assert!(!current.join("alice").exists() && !current.join("bob").exists());
+        let data: WalletData<Layer2Empty> = alice.load().unwrap();
+        assert_eq!(data.signing_account, Some(account));
+        assert_eq!(fs::read_to_string(&bob.data).unwrap(), "corrupt");
+
+        // Fallback for the moves across file systems
+        let copy = root.join("copy");
+        copy_dir(&legacy, &copy).unwrap();
+        let copied = FsTextStore::new(copy.join("alice")).unwrap();
+        assert_eq!(fs::read(copied.data).unwrap(), fs::read(&alice.data).unwrap());
+        let copied = FsTextStore::new(copy.join("bob")).unwrap();
+        assert_eq!(fs::read_to_string(copied.data).unwrap(), "corrupt");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn test_migrate_encrypted() {
+        let root = std::env::temp_dir().join(format!("bp-wallet-migcrypt-{}", std::process::id()));
+        let legacy = root.join("legacy");
+        let current = root.join("current");
+
+        let mut store = FsTextStore::new(legacy.join("alice")).unwrap();
+        store.key = Some(WalletKey::new("secret"));
+        let mut data = WalletData::<Layer2Empty>::new_layer1();
+        data.signing_account = Some(legacy.join("alice").join("account.key"));
+        store.store(&data).unwrap();
+        fs::copy(&store.data, &store.descr).unwrap();
+
+        let plan = migrate_wallets(&legacy, &current, true).unwrap();
+        assert!(plan[0].encrypted);
+        let moved = migrate_wallets(&legacy, &current, false).unwrap();
+        assert_eq!(moved, plan);
+
+        // The stale path is reported rather than rewritten
+        let mut store = FsTextStore::new(current.join("alice")).unwrap();
+        store.unlock("secret").unwrap();
+        let data: WalletData<Layer2Empty> = store.load().unwrap();
+        assert_eq!(data.signing_account, Some(legacy.join("alice").join("account.key")));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_wallet_lock() {
+        let dir = std::env::temp_dir().join(format!("bp-wallet-lock-{}", std::process::id()));
+        let first = FsTextStore::locked(dir.clone(), Duration::ZERO).unwrap();
+        let lock_file = dir.join(LOCK_FILE);
+        assert!(lock_file.exists());
+
+        let err = FsTextStore::locked(dir.clone(), Duration::ZERO).unwrap_err();
+        assert!(matches!(err, WalletDirError::Locked(_)));
+
+        // Clones of the store share the lock
+        let clone = first.clone();
+        drop(first);
+        assert!(lock_file.exists());
+
+        // Waiting process acquires the lock once it is released
+        let holder = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            drop(clone);
+        });
+        let second = FsTextStore::locked(dir.clone(), Duration::from_secs(10)).unwrap();
+        holder.join().unwrap();
+        assert_eq!(fs::read_to_string(&lock_file).unwrap(), format!("{}\n", std::process::id()));
+        drop(second);
+
+        // Lock file left by a crashed process doesn't prevent locking
+        fs::write(&lock_file, "123456789\n").unwrap();
+        FsTextStore::locked(dir.clone(), Duration::ZERO).unwrap();
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
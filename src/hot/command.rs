@@ -27,13 +27,12 @@ use std::{env, fs};
 use amplify::hex::ToHex;
 use amplify::{Display, IoError};
 use bip39::Mnemonic;
-use bpstd::signers::TestnetRefSigner;
 use bpstd::{HardenedIndex, SighashCache, Tx, XprivAccount};
 use clap::Subcommand;
 use colored::Colorize;
 use psbt::Psbt;
 
-use crate::hot::{calculate_entropy, DataError, SecureIo, Seed, SeedType};
+use crate::hot::{calculate_entropy, sign_psbt, DataError, SecureIo, Seed, SeedType};
 use crate::Bip43;
 
 const SEED_PASSWORD_ENVVAR: &str = "SEED_PASSWORD";
@@ -316,7 +315,6 @@ fn sign(psbt_file: &Path, account_file: &Path, no_password: bool) -> Result<(),
     let account = XprivAccount::read(account_file, &password)?;
 
     eprintln!("Signing key: {}", account.to_xpub_account());
-    eprintln!("Signing using testnet signer");
 
     let data = fs::read(psbt_file)?;
     let mut psbt = Psbt::deserialize(&data)?;
@@ -324,8 +322,7 @@ fn sign(psbt_file: &Path, account_file: &Path, no_password: bool) -> Result<(),
     eprintln!("PSBT version: {:#}", psbt.version);
     eprintln!("Transaction id: {}", psbt.txid());
 
-    let signer = TestnetRefSigner::new(&account);
-    let sig_count = psbt.sign(&signer)?;
+    let sig_count = sign_psbt(&account, &mut psbt)?.signatures;
 
     fs::write(psbt_file, psbt.serialize(psbt.version))?;
     eprintln!(
@@ -0,0 +1,236 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! BIP-39 mnemonic phrases and the keys derived from them.
+//!
+//! Unlike [`super::Seed`], which uses the mnemonic entropy as the BIP-32 seed, the keys here are
+//! derived from the BIP-39 seed (mnemonic with an optional passphrase), as done by the most
+//! hardware and software wallets. The secrets are kept in memory only and are zeroized on drop.
+
+use std::ops::Deref;
+use std::str::FromStr;
+
+use bip39::Mnemonic;
+use bpstd::{PubkeyHash, XkeyOrigin, Xpriv, XprivAccount, XpubDerivable, XpubFp};
+use descriptors::Descriptor;
+use rand::RngCore;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::hot::SeedType;
+
+/// Errors handling BIP-39 mnemonics.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum MnemonicError {
+    /// invalid mnemonic phrase: {0}.
+    #[from]
+    Invalid(bip39::Error),
+
+    /// wallet descriptor has no extended keys.
+    NoKeys,
+
+    /// the mnemonic (with the given passphrase) has master key fingerprint {found}, which is
+    /// not used by the wallet descriptor (expected {expected}).
+    FingerprintMismatch { expected: XpubFp, found: XpubFp },
+
+    /// the mnemonic (with the given passphrase) does not produce extended key {0} of the wallet
+    /// descriptor.
+    KeyMismatch(XkeyOrigin),
+}
+
+/// Key material, which is wiped from memory once dropped.
+///
+/// BIP-32 key types don't provide zeroization, so the memory holding the key is overwritten as a
+/// whole.
+pub struct Secret<T>(Option<Wiped<T>>);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self { Secret(Some(Wiped(value))) }
+}
+
+impl<T> Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T { &self.0.as_ref().expect("secret is wiped only on drop").0 }
+}
+
+impl<T> Drop for Secret<T> {
+    fn drop(&mut self) { self.0.zeroize(); }
+}
+
+/// Value wiped by zeroizing the [`Option`] holding it, which overwrites all of its memory.
+struct Wiped<T>(T);
+
+impl<T> Zeroize for Wiped<T> {
+    fn zeroize(&mut self) {}
+}
+
+/// BIP-39 mnemonic together with the seed derived from it using the passphrase.
+pub struct MnemonicSeed {
+    mnemonic: Mnemonic,
+    seed: [u8; 64],
+}
+
+impl Drop for MnemonicSeed {
+    fn drop(&mut self) { self.seed.zeroize(); }
+}
+
+impl MnemonicSeed {
+    /// Generates a new random mnemonic of the given size.
+    pub fn generate(seed_type: SeedType, passphrase: &str) -> Self {
+        let mut entropy = Zeroizing::new(vec![0u8; seed_type.byte_len()]);
+        rand::rng().fill_bytes(&mut entropy);
+        let mnemonic = Mnemonic::from_entropy(&entropy).expect("mnemonic generator is broken");
+        Self::with(mnemonic, passphrase)
+    }
+
+    /// Parses the mnemonic phrase, validating its words and checksum.
+    pub fn from_phrase(phrase: &str, passphrase: &str) -> Result<Self, MnemonicError> {
+        let mnemonic = Mnemonic::from_str(phrase.trim())?;
+        Ok(Self::with(mnemonic, passphrase))
+    }
+
+    fn with(mnemonic: Mnemonic, passphrase: &str) -> Self {
+        let seed = mnemonic.to_seed(passphrase);
+        MnemonicSeed { mnemonic, seed }
+    }
+
+    #[inline]
+    pub fn word_count(&self) -> usize { self.mnemonic.word_count() }
+
+    /// Mnemonic phrase, which is zeroized once dropped.
+    pub fn phrase(&self) -> Zeroizing<String> { Zeroizing::new(self.mnemonic.to_string()) }
+
+    #[inline]
+    pub fn master_xpriv(&self, testnet: bool) -> Secret<Xpriv> {
+        Secret::new(Xpriv::new_master(testnet, &self.seed))
+    }
+
+    /// Fingerprint of the master key, which is the same for mainnet and testnet keys.
+    ///
+    /// The fingerprint is computed from the HASH160 of the master public key, as defined by
+    /// BIP-32, so it matches the key origins produced by other wallets.
+    pub fn master_fp(&self) -> XpubFp {
+        let pk = self.master_xpriv(false).to_xpub().to_compr_pk();
+        let hash = <[u8; 20]>::from(PubkeyHash::from(pk));
+        let mut fp = [0u8; 4];
+        fp.copy_from_slice(&hash[..4]);
+        XpubFp::from(fp)
+    }
+
+    /// Derives account extended private key with the given origin.
+    pub fn derive(&self, origin: &XkeyOrigin, testnet: bool) -> Secret<XprivAccount> {
+        let xpriv = Secret::new(self.master_xpriv(testnet).derive_priv(origin.derivation()));
+        let origin = XkeyOrigin::new(self.master_fp(), origin.derivation().into());
+        Secret::new(XprivAccount::new(*xpriv, origin).expect("seed must always derive"))
+    }
+
+    /// Derives private keys for all extended keys of the wallet descriptor, checking that the
+    /// mnemonic and passphrase actually correspond to the descriptor.
+    ///
+    /// Keys of the descriptor which belong to other master keys (like in multisig wallets) are
+    /// skipped; however, at least one of the keys must match the mnemonic.
+    pub fn signing_accounts<D: Descriptor<XpubDerivable>>(
+        &self,
+        descriptor: &D,
+    ) -> Result<Vec<Secret<XprivAccount>>, MnemonicError> {
+        let master_fp = self.master_fp();
+        // Pre-allocated, so the accounts are not left in memory by re-allocations
+        let mut accounts = Vec::with_capacity(descriptor.xpubs().count());
+        let mut expected = None;
+        for xpub in descriptor.xpubs() {
+            let origin = xpub.origin();
+            if origin.master_fp() != master_fp {
+                expected = expected.or(Some(origin.master_fp()));
+                continue;
+            }
+            let account = self.derive(origin, xpub.xpub().is_testnet());
+            // Only the keys are compared, since parent fingerprints may be computed differently
+            if account.xpriv().to_xpub().to_compr_pk() != xpub.xpub().to_compr_pk() {
+                return Err(MnemonicError::KeyMismatch(origin.clone()));
+            }
+            accounts.push(account);
+        }
+        if !accounts.is_empty() {
+            return Ok(accounts);
+        }
+        match expected {
+            Some(expected) => Err(MnemonicError::FingerprintMismatch {
+                expected,
+                found: master_fp,
+            }),
+            None => Err(MnemonicError::NoKeys),
+        }
+    }
+
+    /// Checks that the mnemonic and passphrase correspond to the wallet descriptor.
+    pub fn verify<D: Descriptor<XpubDerivable>>(
+        &self,
+        descriptor: &D,
+    ) -> Result<(), MnemonicError> {
+        self.signing_accounts(descriptor).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use descriptors::{StdDescr, TrKey};
+
+    use super::*;
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                          abandon abandon about";
+
+    fn descriptor() -> StdDescr {
+        let xpub = XpubDerivable::from_str(
+            "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1>/*",
+        )
+        .unwrap();
+        TrKey::from(xpub).into()
+    }
+
+    #[test]
+    fn test_mnemonic_verify() {
+        let seed = MnemonicSeed::from_phrase(PHRASE, "").unwrap();
+        assert_eq!(seed.word_count(), 12);
+        assert_eq!(seed.master_fp().to_string(), "73c5da0a");
+        let accounts = seed.signing_accounts(&descriptor()).unwrap();
+        assert_eq!(accounts.len(), 1);
+
+        let seed = MnemonicSeed::from_phrase(PHRASE, "TREZOR").unwrap();
+        assert!(matches!(
+            seed.verify(&descriptor()),
+            Err(MnemonicError::FingerprintMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_mnemonic_checksum() {
+        let invalid = PHRASE.replace("about", "abandon");
+        assert!(matches!(MnemonicSeed::from_phrase(&invalid, ""), Err(MnemonicError::Invalid(_))));
+
+        let seed = MnemonicSeed::generate(SeedType::Bit256, "");
+        assert_eq!(seed.word_count(), 24);
+        let restored = MnemonicSeed::from_phrase(&seed.phrase(), "").unwrap();
+        assert_eq!(restored.master_fp(), seed.master_fp());
+    }
+}
@@ -23,15 +23,17 @@
 mod seed;
 #[cfg(feature = "cli")]
 mod command;
-#[cfg(feature = "cli")]
 pub mod signer;
 mod password;
+mod mnemonic;
 
 #[cfg(feature = "cli")]
 pub use command::{HotArgs, HotCommand};
 pub use io::{decrypt, encrypt, DataError, SecureIo};
+pub use mnemonic::{MnemonicError, MnemonicSeed, Secret};
 pub use password::calculate_entropy;
 pub use seed::{Seed, SeedType};
+pub use signer::{sign_psbt, SignReport, SoftSignError};
 
 mod io {
     use std::io;
@@ -83,6 +85,9 @@ mod io {
 
         #[from]
         Sign(SignError),
+
+        #[from]
+        SoftSign(super::SoftSignError),
     }
 
     pub trait SecureIo {
@@ -20,16 +20,65 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//! Software signers, using extended private keys kept in memory.
+
 use std::collections::HashSet;
 
 use amplify::Wrapper;
 use bpstd::secp256k1::{ecdsa, schnorr as bip340};
 use bpstd::{
-    Address, InternalKeypair, InternalPk, KeyOrigin, LegacyPk, Sats, Sighash, Sign, TapLeafHash,
-    TapMerklePath, TapNodeHash, TapSighash, XOnlyPk, Xpriv, XprivAccount,
+    Address, InternalKeypair, InternalPk, KeyOrigin, LegacyPk, Sats, ScriptPubkey, Sighash, Sign,
+    TapLeafHash, TapMerklePath, TapNodeHash, TapSighash, WPubkeyHash, XOnlyPk, Xpriv, XprivAccount,
 };
 use descriptors::Descriptor;
-use psbt::{Psbt, Rejected, Signer};
+use psbt::{Input, Psbt, Rejected, SignError, Signer};
+
+/// Errors signing PSBT with [`sign_psbt`].
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SoftSignError {
+    /// input {0} has no witness UTXO; signing it is refused since the spent amount and script
+    /// can't be verified, which may be used to trick the signer into paying excessive fees.
+    NoWitnessUtxo(usize),
+
+    /// script spent by input {0} doesn't match the signing key derived for the input.
+    ScriptMismatch(usize),
+
+    /// input {0} requires signature for a taproot script path, which is not supported.
+    ScriptPath(usize),
+
+    /// {0}
+    #[from]
+    #[display(inner)]
+    Sign(SignError),
+}
+
+/// Information about PSBT signed with [`sign_psbt`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct SignReport {
+    /// Number of inputs spending outputs of the signing account.
+    pub inputs: usize,
+    /// Number of signatures added to the PSBT.
+    pub signatures: usize,
+}
+
+/// Signs PSBT inputs whose BIP-32 derivations belong to the `account`, producing ECDSA
+/// signatures for P2WPKH inputs and BIP-340 signatures for taproot key-path inputs.
+///
+/// All inputs are verified before any signature is made: if some of the inputs with keys of the
+/// account lacks witness UTXO, or spends a script which doesn't correspond to the derived key,
+/// the whole PSBT is refused. Inputs with keys of other signers are ignored.
+pub fn sign_psbt(account: &XprivAccount, psbt: &mut Psbt) -> Result<SignReport, SoftSignError> {
+    let signer = XprivSigner::new(account);
+    let mut report = SignReport::default();
+    for (index, input) in psbt.inputs().enumerate() {
+        if signer.check_input(index, input)? {
+            report.inputs += 1;
+        }
+    }
+    report.signatures = psbt.sign(&signer)?;
+    Ok(report)
+}
 
 pub struct SignTxInfo {
     pub fee: Sats,
@@ -60,7 +109,58 @@ where Self: 'me
     fn approve(&self, _psbt: &Psbt) -> Result<Self::Sign<'_>, Rejected> { Ok(&self.signer) }
 }
 
-impl XprivSigner<'_> {
+impl<'xpriv> Signer for XprivSigner<'xpriv> {
+    type Sign<'s>
+        = &'s XprivSigner<'s>
+    where Self: 's;
+
+    fn approve(&self, _psbt: &Psbt) -> Result<Self::Sign<'_>, Rejected> { Ok(self) }
+}
+
+impl<'xpriv> XprivSigner<'xpriv> {
+    pub fn new(account: &'xpriv XprivAccount) -> Self { XprivSigner { account } }
+
+    /// Checks whether the input has keys of the signing account, and, if it does, that the input
+    /// can be safely signed.
+    fn check_input(&self, index: usize, input: &Input) -> Result<bool, SoftSignError> {
+        let ecdsa_key = input.bip32_derivation.iter().find_map(|(pk, origin)| {
+            let sk = self.derive_subkey(Some(origin))?;
+            (sk.to_compr_pk().to_inner() == pk.pubkey).then_some(sk)
+        });
+        let tap_keys = input
+            .tap_bip32_derivation
+            .iter()
+            .filter_map(|(pk, derivation)| {
+                let sk = self.derive_subkey(Some(&derivation.origin))?;
+                (sk.to_xonly_pk() == *pk).then_some((pk, derivation))
+            })
+            .collect::<Vec<_>>();
+        if ecdsa_key.is_none() && tap_keys.is_empty() {
+            return Ok(false);
+        }
+
+        let script_pubkey =
+            &input.witness_utxo.as_ref().ok_or(SoftSignError::NoWitnessUtxo(index))?.script_pubkey;
+        if let Some(sk) = ecdsa_key {
+            if *script_pubkey != ScriptPubkey::p2wpkh(WPubkeyHash::from(sk.to_compr_pk())) {
+                return Err(SoftSignError::ScriptMismatch(index));
+            }
+        }
+        for (pk, derivation) in tap_keys {
+            if !derivation.leaf_hashes.is_empty() {
+                return Err(SoftSignError::ScriptPath(index));
+            }
+            let internal_pk = input.tap_internal_key.ok_or(SoftSignError::ScriptMismatch(index))?;
+            let output_pk = internal_pk.to_output_pk(input.tap_merkle_root).0;
+            if internal_pk.to_xonly_pk() != *pk
+                || *script_pubkey != ScriptPubkey::p2tr_tweaked(output_pk)
+            {
+                return Err(SoftSignError::ScriptMismatch(index));
+            }
+        }
+        Ok(true)
+    }
+
     fn derive_subkey(&self, origin: Option<&KeyOrigin>) -> Option<Xpriv> {
         let origin = origin?;
         if !self.account.origin().is_subset_of(origin) {
@@ -133,3 +233,111 @@ impl Sign for &'_ XprivSigner<'_> {
 
     fn should_sign_key_path(&self, _index: usize) -> bool { true }
 }
+
+#[cfg(test)]
+mod tests {
+    use amplify::hex::FromHex;
+    use bpstd::{
+        Derive, HardenedIndex, Keychain, NormalIndex, Outpoint, SeqNo, Terminal, Txid,
+        XpubDerivable,
+    };
+    use descriptors::{StdDescr, TrKey, Wpkh};
+    use psbt::{Prevout, PsbtVer};
+
+    use super::*;
+
+    /// Account `m/<purpose>h/0h/0h` of a fixed test seed.
+    fn account(purpose: u16) -> XprivAccount {
+        XprivAccount::with_seed(false, &[0x42; 32]).derive([
+            HardenedIndex::hardened(purpose),
+            HardenedIndex::hardened(0),
+            HardenedIndex::hardened(0),
+        ])
+    }
+
+    fn xpub(account: &XprivAccount) -> XpubDerivable {
+        XpubDerivable::with(account.to_xpub_account(), &[Keychain::OUTER, Keychain::INNER])
+    }
+
+    /// PSBT spending the first receiving address of the `descriptor` to its second one.
+    fn psbt(descriptor: &StdDescr) -> Psbt {
+        let script_pubkey = |index| {
+            let index = NormalIndex::normal(index);
+            descriptor.derive(Keychain::OUTER, index).next().unwrap().to_script_pubkey()
+        };
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        let outpoint = Outpoint::new(Txid::from_hex(&"11".repeat(32)).unwrap(), 0);
+        psbt.append_input_expect(
+            Prevout::new(outpoint, Sats(100_000)),
+            descriptor,
+            Terminal::new(Keychain::OUTER, NormalIndex::normal(0)),
+            script_pubkey(0),
+            SeqNo::from_consensus_u32(0xFFFF_FFFD),
+        );
+        psbt.append_output_expect(script_pubkey(1), Sats(99_000));
+        psbt
+    }
+
+    #[test]
+    fn test_sign_wpkh() {
+        let account = account(84);
+        let descriptor = StdDescr::from(Wpkh::from(xpub(&account)));
+        let mut psbt = psbt(&descriptor);
+        let report = sign_psbt(&account, &mut psbt).unwrap();
+        assert_eq!(report, SignReport {
+            inputs: 1,
+            signatures: 1
+        });
+        assert_eq!(psbt.inputs().next().unwrap().partial_sigs.len(), 1);
+
+        // Keys of the other accounts are ignored
+        let mut psbt = self::psbt(&descriptor);
+        let report = sign_psbt(&self::account(86), &mut psbt).unwrap();
+        assert_eq!(report, SignReport::default());
+        assert!(psbt.inputs().next().unwrap().partial_sigs.is_empty());
+    }
+
+    #[test]
+    fn test_sign_tr() {
+        let account = account(86);
+        let descriptor = StdDescr::from(TrKey::from(xpub(&account)));
+        let mut psbt = psbt(&descriptor);
+        let report = sign_psbt(&account, &mut psbt).unwrap();
+        assert_eq!(report, SignReport {
+            inputs: 1,
+            signatures: 1
+        });
+        assert!(psbt.inputs().next().unwrap().tap_key_sig.is_some());
+    }
+
+    #[test]
+    fn test_sign_refused() {
+        let account = account(84);
+        let descriptor = StdDescr::from(Wpkh::from(xpub(&account)));
+        let other = self::psbt(&descriptor).outputs().next().unwrap().script.clone();
+
+        let mut psbt = self::psbt(&descriptor);
+        psbt.input_mut(0).unwrap().witness_utxo = None;
+        assert_eq!(sign_psbt(&account, &mut psbt), Err(SoftSignError::NoWitnessUtxo(0)));
+
+        let mut psbt = self::psbt(&descriptor);
+        psbt.input_mut(0).unwrap().witness_utxo.as_mut().unwrap().script_pubkey = other;
+        assert_eq!(sign_psbt(&account, &mut psbt), Err(SoftSignError::ScriptMismatch(0)));
+        assert!(psbt.inputs().next().unwrap().partial_sigs.is_empty());
+
+        let account = self::account(86);
+        let descriptor = StdDescr::from(TrKey::from(xpub(&account)));
+        let mut psbt = self::psbt(&descriptor);
+        let other = psbt.outputs().next().unwrap().script.clone();
+        psbt.input_mut(0).unwrap().witness_utxo.as_mut().unwrap().script_pubkey = other;
+        assert_eq!(sign_psbt(&account, &mut psbt), Err(SoftSignError::ScriptMismatch(0)));
+
+        let mut psbt = self::psbt(&descriptor);
+        let input = psbt.input_mut(0).unwrap();
+        for derivation in input.tap_bip32_derivation.values_mut() {
+            derivation.leaf_hashes.push(TapLeafHash::from([1u8; 32]));
+        }
+        assert_eq!(sign_psbt(&account, &mut psbt), Err(SoftSignError::ScriptPath(0)));
+        assert!(psbt.inputs().next().unwrap().tap_key_sig.is_none());
+    }
+}
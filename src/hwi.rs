@@ -0,0 +1,326 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signing with hardware wallets using [HWI](https://github.com/bitcoin-core/HWI) command-line
+//! tool.
+//!
+//! HWI is run as an external process, speaking its JSON protocol, so the library doesn't depend on
+//! the device-specific libraries. HWI must be installed separately.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+
+use amplify::IoError;
+use base64::Engine;
+use bpstd::{Network, XpubDerivable, XpubFp};
+use descriptors::Descriptor;
+use psbt::Psbt;
+use serde_json::Value;
+
+/// Default name of the HWI executable.
+pub const HWI_BIN: &str = "hwi";
+
+/// HWI error code reported when the user has rejected the action on the device.
+pub const HWI_ACTION_CANCELED: i64 = -14;
+/// HWI error code reported when the device is busy with another action.
+pub const HWI_DEVICE_BUSY: i64 = -15;
+/// HWI error code reported when the device is locked or not ready.
+pub const HWI_DEVICE_NOT_READY: i64 = -12;
+
+/// Signer which doesn't have direct access to the keys and signs the whole PSBT at once, like a
+/// hardware wallet.
+pub trait ExternalSigner {
+    type Error: std::error::Error;
+
+    /// Fingerprints of the master keys available to the signer.
+    fn fingerprints(&self) -> BTreeSet<XpubFp>;
+
+    /// Returns a copy of the `psbt` with the signatures added by the signer.
+    fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt, Self::Error>;
+}
+
+/// Errors running HWI.
+#[derive(Clone, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum HwiError {
+    /// unable to run HWI. Details: {0}
+    #[from]
+    #[from(std::io::Error)]
+    Exec(IoError),
+
+    /// HWI has produced invalid output: {0}
+    Output(String),
+
+    /// signing was rejected on the device {0}.
+    Canceled(XpubFp),
+
+    /// device {0} is locked, busy or not ready; unlock it and open the bitcoin application.
+    NotReady(XpubFp),
+
+    /// device {fingerprint} has failed with error {code}: {message}
+    Device {
+        fingerprint: XpubFp,
+        code: i64,
+        message: String,
+    },
+
+    /// HWI has failed with error {code}: {message}
+    Hwi { code: i64, message: String },
+
+    /// no connected hardware wallet holds keys of the wallet descriptor (expected fingerprints
+    /// {0}).
+    NoDevice(String),
+
+    /// device {0} requires PIN to be entered with `hwi promptpin` and `hwi sendpin` commands.
+    NeedsPin(XpubFp),
+
+    /// device {0} requires passphrase to be entered on the device or via `--password` argument
+    /// of HWI.
+    NeedsPassphrase(XpubFp),
+
+    /// device {0} has returned invalid PSBT.
+    InvalidPsbt(XpubFp),
+}
+
+/// Hardware wallet connected to the computer, as reported by `hwi enumerate`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct HwiDevice {
+    /// Device type, like `trezor`, `ledger` or `coldcard`.
+    pub device_type: String,
+    pub model: String,
+    pub path: String,
+    /// Fingerprint of the device master key; absent if the device is locked.
+    pub fingerprint: Option<XpubFp>,
+    pub needs_pin_sent: bool,
+    pub needs_passphrase_sent: bool,
+}
+
+impl HwiDevice {
+    fn from_json(value: &Value) -> Result<Self, HwiError> {
+        let string = |key: &str| value[key].as_str().unwrap_or_default().to_owned();
+        let fingerprint = match value["fingerprint"].as_str() {
+            Some(fp) => Some(
+                XpubFp::from_str(fp)
+                    .map_err(|_| HwiError::Output(format!("invalid fingerprint '{fp}'")))?,
+            ),
+            None => None,
+        };
+        Ok(HwiDevice {
+            device_type: string("type"),
+            model: string("model"),
+            path: string("path"),
+            fingerprint,
+            needs_pin_sent: value["needs_pin_sent"].as_bool().unwrap_or_default(),
+            needs_passphrase_sent: value["needs_passphrase_sent"].as_bool().unwrap_or_default(),
+        })
+    }
+}
+
+/// Runner of the HWI executable.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Hwi {
+    bin: PathBuf,
+    network: Network,
+}
+
+impl Hwi {
+    /// Uses HWI executable found in `PATH`.
+    pub fn new(network: Network) -> Self { Self::with(HWI_BIN, network) }
+
+    pub fn with(bin: impl Into<PathBuf>, network: Network) -> Self {
+        Hwi {
+            bin: bin.into(),
+            network,
+        }
+    }
+
+    fn chain(&self) -> &'static str {
+        match self.network {
+            Network::Mainnet => "main",
+            Network::Testnet3 => "test",
+            Network::Testnet4 => "testnet4",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    fn run(&self, fingerprint: Option<XpubFp>, args: &[&str]) -> Result<Value, HwiError> {
+        let mut cmd = Command::new(&self.bin);
+        cmd.args(["--chain", self.chain()]);
+        if let Some(fp) = fingerprint {
+            cmd.args(["--fingerprint", &fp.to_string()]);
+        }
+        let output = cmd.args(args).output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let value = serde_json::from_str::<Value>(&stdout).map_err(|_| {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            HwiError::Output(if stderr.trim().is_empty() { stdout.into() } else { stderr.into() })
+        })?;
+        check_error(&value, fingerprint)?;
+        Ok(value)
+    }
+
+    /// Lists hardware wallets connected to the computer.
+    pub fn enumerate(&self) -> Result<Vec<HwiDevice>, HwiError> {
+        let value = self.run(None, &["enumerate"])?;
+        let devices =
+            value.as_array().ok_or_else(|| HwiError::Output(s!("device list is expected")))?;
+        devices.iter().map(HwiDevice::from_json).collect()
+    }
+
+    /// Finds connected device holding the master key of one of the extended keys of the wallet
+    /// descriptor.
+    pub fn device_for<D: Descriptor<XpubDerivable>>(
+        &self,
+        descriptor: &D,
+    ) -> Result<HwiDevice, HwiError> {
+        let expected =
+            descriptor.xpubs().map(|xpub| xpub.origin().master_fp()).collect::<BTreeSet<_>>();
+        self.enumerate()?
+            .into_iter()
+            .find(|device| device.fingerprint.is_some_and(|fp| expected.contains(&fp)))
+            .ok_or_else(|| {
+                let expected = expected.iter().map(XpubFp::to_string).collect::<Vec<_>>();
+                HwiError::NoDevice(expected.join(", "))
+            })
+    }
+
+    /// Creates signer using the connected device holding a key of the wallet descriptor.
+    pub fn signer_for<D: Descriptor<XpubDerivable>>(
+        self,
+        descriptor: &D,
+    ) -> Result<HwiSigner, HwiError> {
+        let device = self.device_for(descriptor)?;
+        HwiSigner::with(self, device)
+    }
+}
+
+/// Converts HWI error reply into [`HwiError`].
+fn check_error(value: &Value, fingerprint: Option<XpubFp>) -> Result<(), HwiError> {
+    let Some(message) = value["error"].as_str() else {
+        return Ok(());
+    };
+    let code = value["code"].as_i64().unwrap_or_default();
+    let message = message.to_owned();
+    Err(match (fingerprint, code) {
+        (Some(fp), HWI_ACTION_CANCELED) => HwiError::Canceled(fp),
+        (Some(fp), HWI_DEVICE_BUSY | HWI_DEVICE_NOT_READY) => HwiError::NotReady(fp),
+        (Some(fingerprint), code) => HwiError::Device {
+            fingerprint,
+            code,
+            message,
+        },
+        (None, code) => HwiError::Hwi { code, message },
+    })
+}
+
+/// Hardware wallet signer, signing PSBTs with HWI.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct HwiSigner {
+    hwi: Hwi,
+    device: HwiDevice,
+    fingerprint: XpubFp,
+}
+
+impl HwiSigner {
+    /// Creates signer for the device, failing if the device is not ready for signing.
+    pub fn with(hwi: Hwi, device: HwiDevice) -> Result<Self, HwiError> {
+        let fingerprint = device.fingerprint.ok_or_else(|| HwiError::NoDevice(s!("none")))?;
+        if device.needs_pin_sent {
+            return Err(HwiError::NeedsPin(fingerprint));
+        }
+        if device.needs_passphrase_sent {
+            return Err(HwiError::NeedsPassphrase(fingerprint));
+        }
+        Ok(HwiSigner {
+            hwi,
+            device,
+            fingerprint,
+        })
+    }
+
+    pub fn device(&self) -> &HwiDevice { &self.device }
+}
+
+impl ExternalSigner for HwiSigner {
+    type Error = HwiError;
+
+    fn fingerprints(&self) -> BTreeSet<XpubFp> { BTreeSet::from([self.fingerprint]) }
+
+    fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt, Self::Error> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let encoded = engine.encode(psbt.serialize(psbt.version));
+        let value = self.hwi.run(Some(self.fingerprint), &["signtx", &encoded])?;
+        let signed =
+            value["psbt"].as_str().ok_or_else(|| HwiError::Output(s!("signed PSBT is missing")))?;
+        let data = engine.decode(signed).map_err(|_| HwiError::InvalidPsbt(self.fingerprint))?;
+        let mut signed =
+            Psbt::deserialize(&data).map_err(|_| HwiError::InvalidPsbt(self.fingerprint))?;
+        signed.version = psbt.version;
+        Ok(signed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hwi_replies() {
+        let value = serde_json::json!([
+            {
+                "type": "trezor",
+                "model": "trezor_t",
+                "path": "webusb:001:1",
+                "fingerprint": "73c5da0a",
+                "needs_pin_sent": false,
+                "needs_passphrase_sent": false
+            },
+            { "type": "coldcard", "model": "coldcard", "needs_pin_sent": true },
+        ]);
+        let devices = value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(HwiDevice::from_json)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(devices[0].fingerprint, Some(XpubFp::from_str("73c5da0a").unwrap()));
+        assert_eq!(devices[1].fingerprint, None);
+        assert!(devices[1].needs_pin_sent);
+
+        let fp = devices[0].fingerprint.unwrap();
+        let hwi = Hwi::new(Network::Testnet3);
+        assert!(matches!(
+            HwiSigner::with(hwi.clone(), devices[1].clone()),
+            Err(HwiError::NoDevice(_))
+        ));
+        assert!(HwiSigner::with(hwi, devices[0].clone()).is_ok());
+
+        let canceled = serde_json::json!({ "error": "Action canceled by user", "code": -14 });
+        assert!(matches!(check_error(&canceled, Some(fp)), Err(HwiError::Canceled(_))));
+        assert!(matches!(check_error(&canceled, None), Err(HwiError::Hwi { code: -14, .. })));
+        assert!(check_error(&serde_json::json!({ "psbt": "" }), Some(fp)).is_ok());
+    }
+}
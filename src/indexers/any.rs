@@ -22,12 +22,19 @@
 use bpstd::{Network, Tx, Txid};
 use descriptors::Descriptor;
 
-use crate::{BlockHash, Indexer, Layer2, MayError, TxStatus, WalletCache, WalletDescr};
+use crate::{
+    BlockHash, Indexer, Layer2, MayError, MiningInfo, SyncError, SyncProgress, TxStatus,
+    WalletCache, WalletDescr,
+};
 
 /// Type that contains any of the client types implementing the Indexer trait
 #[derive(From)]
 #[non_exhaustive]
 pub enum AnyIndexer {
+    #[cfg(feature = "bitcoind")]
+    #[from]
+    /// Bitcoin Core RPC indexer
+    Bitcoind(Box<super::bitcoind::Client>),
     #[cfg(feature = "electrum")]
     #[from]
     /// Electrum indexer
@@ -44,6 +51,8 @@ pub enum AnyIndexer {
 impl AnyIndexer {
     pub fn name(&self) -> &'static str {
         match self {
+            #[cfg(feature = "bitcoind")]
+            AnyIndexer::Bitcoind(_) => "bitcoind",
             #[cfg(feature = "electrum")]
             AnyIndexer::Electrum(_) => "electrum",
             #[cfg(feature = "esplora")]
@@ -58,6 +67,10 @@ impl AnyIndexer {
 #[derive(Debug, Display, Error, From)]
 #[display(doc_comments)]
 pub enum AnyIndexerError {
+    #[cfg(feature = "bitcoind")]
+    #[display(inner)]
+    #[from]
+    Bitcoind(super::bitcoind::BitcoindError),
     #[cfg(feature = "electrum")]
     #[display(inner)]
     #[from]
@@ -67,6 +80,9 @@ pub enum AnyIndexerError {
     #[display(inner)]
     #[from]
     Esplora(esplora::Error),
+    #[display(inner)]
+    #[from]
+    Unsupported(super::UnsupportedRequest),
 }
 
 impl Indexer for AnyIndexer {
@@ -74,6 +90,8 @@ impl Indexer for AnyIndexer {
 
     fn network(&self) -> Result<Network, Self::Error> {
         match self {
+            #[cfg(feature = "bitcoind")]
+            AnyIndexer::Bitcoind(inner) => inner.network().map_err(|e| e.into()),
             #[cfg(feature = "electrum")]
             AnyIndexer::Electrum(inner) => inner.network().map_err(|e| e.into()),
             #[cfg(feature = "esplora")]
@@ -86,14 +104,22 @@ impl Indexer for AnyIndexer {
     fn create<K, D: Descriptor<K>, L2: Layer2>(
         &self,
         descr: &WalletDescr<K, D, L2::Descr>,
-    ) -> MayError<WalletCache<L2::Cache>, Vec<Self::Error>> {
+    ) -> MayError<WalletCache<L2::Cache>, Vec<SyncError<Self::Error>>> {
         match self {
+            #[cfg(feature = "bitcoind")]
+            AnyIndexer::Bitcoind(inner) => {
+                let result = inner.create::<K, D, L2>(descr);
+                MayError {
+                    ok: result.ok,
+                    err: result.err.map(|v| v.into_iter().map(|e| e.map_err(Into::into)).collect()),
+                }
+            }
             #[cfg(feature = "electrum")]
             AnyIndexer::Electrum(inner) => {
                 let result = inner.create::<K, D, L2>(descr);
                 MayError {
                     ok: result.ok,
-                    err: result.err.map(|v| v.into_iter().map(|e| e.into()).collect()),
+                    err: result.err.map(|v| v.into_iter().map(|e| e.map_err(Into::into)).collect()),
                 }
             }
             #[cfg(feature = "esplora")]
@@ -101,7 +127,7 @@ impl Indexer for AnyIndexer {
                 let result = inner.create::<K, D, L2>(descr);
                 MayError {
                     ok: result.ok,
-                    err: result.err.map(|v| v.into_iter().map(|e| e.into()).collect()),
+                    err: result.err.map(|v| v.into_iter().map(|e| e.map_err(Into::into)).collect()),
                 }
             }
             #[cfg(feature = "mempool")]
@@ -109,40 +135,49 @@ impl Indexer for AnyIndexer {
                 let result = inner.create::<K, D, L2>(descr);
                 MayError {
                     ok: result.ok,
-                    err: result.err.map(|v| v.into_iter().map(|e| e.into()).collect()),
+                    err: result.err.map(|v| v.into_iter().map(|e| e.map_err(Into::into)).collect()),
                 }
             }
         }
     }
 
-    fn update<K, D: Descriptor<K>, L2: Layer2>(
+    fn update_with_progress<K, D: Descriptor<K>, L2: Layer2>(
         &self,
         descr: &WalletDescr<K, D, L2::Descr>,
         cache: &mut WalletCache<L2::Cache>,
-    ) -> MayError<usize, Vec<Self::Error>> {
+        progress: &mut dyn SyncProgress,
+    ) -> MayError<usize, Vec<SyncError<Self::Error>>> {
         match self {
+            #[cfg(feature = "bitcoind")]
+            AnyIndexer::Bitcoind(inner) => {
+                let result = inner.update_with_progress::<K, D, L2>(descr, cache, progress);
+                MayError {
+                    ok: result.ok,
+                    err: result.err.map(|v| v.into_iter().map(|e| e.map_err(Into::into)).collect()),
+                }
+            }
             #[cfg(feature = "electrum")]
             AnyIndexer::Electrum(inner) => {
-                let result = inner.update::<K, D, L2>(descr, cache);
+                let result = inner.update_with_progress::<K, D, L2>(descr, cache, progress);
                 MayError {
                     ok: result.ok,
-                    err: result.err.map(|v| v.into_iter().map(|e| e.into()).collect()),
+                    err: result.err.map(|v| v.into_iter().map(|e| e.map_err(Into::into)).collect()),
                 }
             }
             #[cfg(feature = "esplora")]
             AnyIndexer::Esplora(inner) => {
-                let result = inner.update::<K, D, L2>(descr, cache);
+                let result = inner.update_with_progress::<K, D, L2>(descr, cache, progress);
                 MayError {
                     ok: result.ok,
-                    err: result.err.map(|v| v.into_iter().map(|e| e.into()).collect()),
+                    err: result.err.map(|v| v.into_iter().map(|e| e.map_err(Into::into)).collect()),
                 }
             }
             #[cfg(feature = "mempool")]
             AnyIndexer::Mempool(inner) => {
-                let result = inner.update::<K, D, L2>(descr, cache);
+                let result = inner.update_with_progress::<K, D, L2>(descr, cache, progress);
                 MayError {
                     ok: result.ok,
-                    err: result.err.map(|v| v.into_iter().map(|e| e.into()).collect()),
+                    err: result.err.map(|v| v.into_iter().map(|e| e.map_err(Into::into)).collect()),
                 }
             }
         }
@@ -150,6 +185,8 @@ impl Indexer for AnyIndexer {
 
     fn broadcast(&self, tx: &Tx) -> Result<(), Self::Error> {
         match self {
+            #[cfg(feature = "bitcoind")]
+            AnyIndexer::Bitcoind(inner) => inner.broadcast(tx).map_err(|e| e.into()),
             #[cfg(feature = "electrum")]
             AnyIndexer::Electrum(inner) => inner.broadcast(tx).map_err(|e| e.into()),
             #[cfg(feature = "esplora")]
@@ -161,6 +198,8 @@ impl Indexer for AnyIndexer {
 
     fn status(&self, txid: Txid) -> Result<TxStatus, Self::Error> {
         match self {
+            #[cfg(feature = "bitcoind")]
+            AnyIndexer::Bitcoind(inner) => inner.status(txid).map_err(|e| e.into()),
             #[cfg(feature = "electrum")]
             AnyIndexer::Electrum(inner) => inner.status(txid).map_err(|e| e.into()),
             #[cfg(feature = "esplora")]
@@ -170,8 +209,23 @@ impl Indexer for AnyIndexer {
         }
     }
 
+    fn transaction(&self, txid: Txid) -> Result<Option<Tx>, Self::Error> {
+        match self {
+            #[cfg(feature = "bitcoind")]
+            AnyIndexer::Bitcoind(inner) => inner.transaction(txid).map_err(|e| e.into()),
+            #[cfg(feature = "electrum")]
+            AnyIndexer::Electrum(inner) => inner.transaction(txid).map_err(|e| e.into()),
+            #[cfg(feature = "esplora")]
+            AnyIndexer::Esplora(inner) => inner.transaction(txid).map_err(|e| e.into()),
+            #[cfg(feature = "mempool")]
+            AnyIndexer::Mempool(inner) => inner.transaction(txid).map_err(|e| e.into()),
+        }
+    }
+
     fn block_hash(&self, block_height: u32) -> Result<BlockHash, Self::Error> {
         match self {
+            #[cfg(feature = "bitcoind")]
+            AnyIndexer::Bitcoind(inner) => inner.block_hash(block_height).map_err(|e| e.into()),
             #[cfg(feature = "electrum")]
             AnyIndexer::Electrum(inner) => inner.block_hash(block_height).map_err(|e| e.into()),
             #[cfg(feature = "esplora")]
@@ -180,4 +234,30 @@ impl Indexer for AnyIndexer {
             AnyIndexer::Mempool(inner) => inner.block_hash(block_height).map_err(|e| e.into()),
         }
     }
+
+    fn tip(&self) -> Result<MiningInfo, Self::Error> {
+        match self {
+            #[cfg(feature = "bitcoind")]
+            AnyIndexer::Bitcoind(inner) => inner.tip().map_err(|e| e.into()),
+            #[cfg(feature = "electrum")]
+            AnyIndexer::Electrum(inner) => inner.tip().map_err(|e| e.into()),
+            #[cfg(feature = "esplora")]
+            AnyIndexer::Esplora(inner) => inner.tip().map_err(|e| e.into()),
+            #[cfg(feature = "mempool")]
+            AnyIndexer::Mempool(inner) => inner.tip().map_err(|e| e.into()),
+        }
+    }
+
+    fn fee_rate(&self, target_blocks: u16) -> Result<f64, Self::Error> {
+        match self {
+            #[cfg(feature = "bitcoind")]
+            AnyIndexer::Bitcoind(inner) => inner.fee_rate(target_blocks).map_err(|e| e.into()),
+            #[cfg(feature = "electrum")]
+            AnyIndexer::Electrum(inner) => inner.fee_rate(target_blocks).map_err(|e| e.into()),
+            #[cfg(feature = "esplora")]
+            AnyIndexer::Esplora(inner) => inner.fee_rate(target_blocks).map_err(|e| e.into()),
+            #[cfg(feature = "mempool")]
+            AnyIndexer::Mempool(inner) => inner.fee_rate(target_blocks).map_err(|e| e.into()),
+        }
+    }
 }
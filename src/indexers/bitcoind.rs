@@ -0,0 +1,889 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Indexer using JSON-RPC interface of a Bitcoin Core node.
+//!
+//! The indexer doesn't require the node to have a wallet or a transaction index: it uses
+//! `scantxoutset` to find wallet UTXOs (checking them against the mempool with `gettxout`) and
+//! `getrawtransaction` with an explicit block hash to retrieve the transactions which have
+//! created them. Since the node keeps no address index,
+//! the history contains only transactions which have created currently unspent wallet outputs.
+//! Retrieval of the previous outputs requires Bitcoin Core v25 or later.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::{fs, io};
+
+use amplify::hex::FromHex;
+use amplify::IoError;
+use base64::Engine;
+use bpstd::{
+    Address, BlockHash, ConsensusEncode, IdxBase, Network, Outpoint, Sats, ScriptPubkey, Tx, Txid,
+    Weight,
+};
+use descriptors::Descriptor;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::{invalidate_reorged, retry, RetryPolicy, SyncError, SyncProgress, UnsupportedRequest};
+use crate::{
+    BlockHeight, Indexer, Layer2, MayError, MiningInfo, Party, TxCredit, TxDebit, TxStatus,
+    WalletAddr, WalletCache, WalletDescr, WalletTx,
+};
+
+/// Minimal number of addresses per keychain which are checked by `scantxoutset`.
+pub const SCAN_RANGE: usize = 1000;
+
+/// Error code used by Bitcoin Core for unknown transactions and blocks.
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+/// Bitcoin Core RPC error code returned while the node is still starting up.
+const RPC_IN_WARMUP: i64 = -28;
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum BitcoindError {
+    /// unable to read Bitcoin Core RPC cookie file. Details: {0}
+    #[from]
+    #[from(io::Error)]
+    Cookie(IoError),
+
+    /// unable to connect Bitcoin Core node. Details: {0}
+    #[from]
+    Transport(minreq::Error),
+
+    /// Bitcoin Core returned invalid JSON-RPC response. Details: {0}
+    #[from]
+    Json(serde_json::Error),
+
+    /// Bitcoin Core node has rejected RPC credentials.
+    Unauthorized,
+
+    /// Bitcoin Core returned RPC error {code}: {message}
+    Rpc { code: i64, message: String },
+
+    /// Bitcoin Core node is in initial block download state ({0} blocks out of {1} known
+    /// headers are processed); wait until it completes to synchronize the wallet.
+    InitialBlockDownload(u64, u64),
+
+    /// Bitcoin Core returned invalid data for the transaction {0}.
+    InvalidTx(Txid),
+
+    /// Bitcoin Core returned a genesis block hash which doesn't match any of the known networks.
+    InvalidGenesis,
+
+    /// Bitcoin Core returned invalid server data.
+    InvalidServerData,
+
+    /// Bitcoin Core has not collected enough data to estimate fee rate for the confirmation
+    /// target of {0} blocks.
+    NoFeeEstimate(u16),
+
+    /// {0}
+    #[from]
+    Unsupported(UnsupportedRequest),
+}
+
+impl BitcoindError {
+    /// Detects errors which may disappear if the same request is repeated later.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, BitcoindError::Transport(_) | BitcoindError::Rpc {
+            code: RPC_IN_WARMUP,
+            ..
+        })
+    }
+}
+
+/// Authentication method for Bitcoin Core JSON-RPC interface.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum Auth {
+    #[default]
+    None,
+    /// Cookie file created by the node in its data directory.
+    Cookie(PathBuf),
+    /// User name and password, as configured with `rpcuser` and `rpcpassword` or `rpcauth`.
+    UserPass(String, String),
+}
+
+impl Auth {
+    fn header(&self) -> Result<Option<String>, BitcoindError> {
+        let credentials = match self {
+            Auth::None => return Ok(None),
+            Auth::Cookie(path) => fs::read_to_string(path)?.trim().to_owned(),
+            Auth::UserPass(user, pass) => format!("{user}:{pass}"),
+        };
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        Ok(Some(format!("Basic {encoded}")))
+    }
+}
+
+/// Represents a client for interacting with the Bitcoin Core JSON-RPC interface.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Client {
+    url: String,
+    auth: Auth,
+    retry: RetryPolicy,
+}
+
+impl Client {
+    /// Creates a new Bitcoin Core RPC client with the specified URL and authentication method.
+    ///
+    /// The client doesn't connect to the node until the first request is made.
+    pub fn new(url: &str, auth: Auth) -> Self {
+        Client {
+            url: url.to_owned(),
+            auth,
+            retry: none!(),
+        }
+    }
+
+    /// Sets the policy for retrying requests which have failed with transient errors, like
+    /// connection failures or node still warming up.
+    pub fn with_retries(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Performs a JSON-RPC call to the node, returning the value of `result` field.
+    ///
+    /// Calls failed with transient errors are repeated according to the client retry policy.
+    pub fn call(&self, method: &str, params: Value) -> Result<Value, BitcoindError> {
+        retry(self.retry, BitcoindError::is_transient, || self.call_once(method, &params))
+    }
+
+    fn call_once(&self, method: &str, params: &Value) -> Result<Value, BitcoindError> {
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": "bp-wallet",
+            "method": method,
+            "params": params,
+        });
+        let mut request = minreq::post(&self.url)
+            .with_header("Content-Type", "application/json")
+            .with_body(body.to_string());
+        if let Some(auth) = self.auth.header()? {
+            request = request.with_header("Authorization", auth);
+        }
+        #[cfg(feature = "log")]
+        log::trace!("RPC request to {}: {body}", super::redact_url(&self.url));
+        let response = request.send()?;
+        if response.status_code == 401 {
+            return Err(BitcoindError::Unauthorized);
+        }
+        #[cfg(feature = "log")]
+        log::trace!(
+            "RPC response {}: {}",
+            response.status_code,
+            response.as_str().unwrap_or_default()
+        );
+        let mut reply: Value = serde_json::from_str(response.as_str()?)?;
+        match reply.get("error") {
+            None | Some(Value::Null) => {}
+            Some(err) => {
+                return Err(BitcoindError::Rpc {
+                    code: err.get("code").and_then(Value::as_i64).unwrap_or_default(),
+                    message: err
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_owned(),
+                });
+            }
+        }
+        Ok(reply["result"].take())
+    }
+
+    /// Checks that the node has completed initial block download and returns information about
+    /// its best block.
+    pub fn tip(&self) -> Result<MiningInfo, BitcoindError> {
+        let info = self.call("getblockchaininfo", json!([]))?;
+        let blocks = info.get("blocks").and_then(Value::as_u64).unwrap_or_default();
+        let headers = info.get("headers").and_then(Value::as_u64).unwrap_or_default();
+        if info.get("initialblockdownload").and_then(Value::as_bool).unwrap_or_default() {
+            return Err(BitcoindError::InitialBlockDownload(blocks, headers));
+        }
+        let block_hash = info
+            .get("bestblockhash")
+            .and_then(Value::as_str)
+            .and_then(|s| BlockHash::from_str(s).ok())
+            .ok_or(BitcoindError::InvalidServerData)?;
+        let time = info
+            .get("time")
+            .or_else(|| info.get("mediantime"))
+            .and_then(Value::as_u64)
+            .ok_or(BitcoindError::InvalidServerData)?;
+        // Freshly started regtest node has only the genesis block
+        let height = u32::try_from(blocks)
+            .map_err(|_| BitcoindError::InvalidServerData)?
+            .try_into()
+            .unwrap_or(BlockHeight::MIN);
+        Ok(MiningInfo {
+            height,
+            time,
+            block_hash,
+        })
+    }
+
+    /// Returns network magic of the signet the node runs, or `None` if the node runs another
+    /// network.
+    pub fn signet_magic(&self) -> Result<Option<[u8; 4]>, BitcoindError> {
+        let info = self.call("getblockchaininfo", json!([]))?;
+        let Some(challenge) = info.get("signet_challenge") else {
+            return Ok(None);
+        };
+        let challenge = challenge
+            .as_str()
+            .and_then(|s| Vec::<u8>::from_hex(s).ok())
+            .ok_or(BitcoindError::InvalidServerData)?;
+        Ok(Some(signet_magic(&ScriptPubkey::from_checked(challenge))))
+    }
+
+    fn wallet_tx(&self, txid: Txid, height: u32) -> Result<WalletTx, BitcoindError> {
+        let block_hash = self.block_hash(height)?;
+        let details =
+            self.call("getrawtransaction", json!([txid.to_string(), 2, block_hash.to_string()]))?;
+
+        let tx = details
+            .get("hex")
+            .and_then(Value::as_str)
+            .and_then(|s| Tx::from_str(s).ok())
+            .ok_or(BitcoindError::InvalidTx(txid))?;
+        let time = details
+            .get("blocktime")
+            .and_then(Value::as_u64)
+            .ok_or(BitcoindError::InvalidTx(txid))?;
+        let height = BlockHeight::try_from(height).map_err(|_| BitcoindError::InvalidTx(txid))?;
+        let vin =
+            details.get("vin").and_then(Value::as_array).ok_or(BitcoindError::InvalidTx(txid))?;
+
+        let size = tx.consensus_serialize().len() as u32;
+        let weight = tx.weight_units().to_u32();
+
+        let mut inputs = Vec::with_capacity(tx.inputs.len());
+        for (input, details) in tx.inputs.into_iter().zip(vin) {
+            let coinbase = details.get("coinbase").is_some();
+            let (payer, value) = match details.get("prevout") {
+                Some(prevout) => {
+                    let script = prevout
+                        .get("scriptPubKey")
+                        .and_then(|spk| spk.get("hex"))
+                        .and_then(Value::as_str)
+                        .and_then(|s| ScriptPubkey::from_hex(s).ok())
+                        .ok_or(BitcoindError::InvalidTx(txid))?;
+                    let value = prevout
+                        .get("value")
+                        .and_then(btc_to_sats)
+                        .ok_or(BitcoindError::InvalidTx(txid))?;
+                    (Party::Unknown(script), value)
+                }
+                None if coinbase => (Party::Subsidy, Sats::ZERO),
+                None => return Err(BitcoindError::InvalidTx(txid)),
+            };
+            inputs.push(TxCredit {
+                outpoint: input.prev_output,
+                payer,
+                sequence: input.sequence,
+                coinbase,
+                script_sig: input.sig_script,
+                witness: input.witness,
+                value,
+            });
+        }
+
+        let outputs = tx
+            .outputs
+            .into_iter()
+            .enumerate()
+            .map(|(no, txout)| TxDebit {
+                outpoint: Outpoint::new(txid, no as u32),
+                beneficiary: Party::Unknown(txout.script_pubkey),
+                value: txout.value,
+                spent: None,
+            })
+            .collect();
+
+        Ok(WalletTx {
+            txid,
+            status: TxStatus::Mined(MiningInfo {
+                height,
+                time,
+                block_hash,
+            }),
+            inputs,
+            outputs,
+            fee: details.get("fee").and_then(btc_to_sats).unwrap_or_default(),
+            size,
+            weight,
+            version: tx.version,
+            locktime: tx.lock_time,
+        })
+    }
+}
+
+/// Computes network magic of the signet with the given block `challenge` script: the first four
+/// bytes of the double SHA256 hash of the challenge serialized with its length prefix.
+pub fn signet_magic(challenge: &ScriptPubkey) -> [u8; 4] {
+    let hash = Sha256::digest(Sha256::digest(challenge.consensus_serialize()));
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// Converts BTC amount reported by the node into satoshis without floating point arithmetic.
+///
+/// JSON numbers are parsed as floats, but amounts have at most 8 decimal places and never exceed
+/// 21M BTC, so the shortest representation of the parsed float is exactly the decimal amount sent
+/// by the node.
+fn btc_to_sats(value: &Value) -> Option<Sats> {
+    let Value::Number(number) = value else {
+        return None;
+    };
+    let repr = number.to_string();
+    let (mantissa, exp) = match repr.split_once(['e', 'E']) {
+        Some((mantissa, exp)) => (mantissa, exp.parse::<i32>().ok()?),
+        None => (repr.as_str(), 0),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let digits = format!("{int}{frac}").parse::<u64>().ok()?;
+    let shift = exp + 8 - frac.len() as i32;
+    let sats = if shift >= 0 {
+        digits.checked_mul(10u64.checked_pow(shift as u32)?)?
+    } else {
+        let div = 10u64.checked_pow(shift.unsigned_abs())?;
+        if digits % div != 0 {
+            return None;
+        }
+        digits / div
+    };
+    Some(Sats(sats))
+}
+
+impl Indexer for Client {
+    type Error = BitcoindError;
+
+    fn network(&self) -> Result<Network, Self::Error> {
+        let genesis = self.block_hash(0)?;
+        Network::try_from(genesis).map_err(|_| BitcoindError::InvalidGenesis)
+    }
+
+    fn create<K, D: Descriptor<K>, L2: Layer2>(
+        &self,
+        descriptor: &WalletDescr<K, D, L2::Descr>,
+    ) -> MayError<WalletCache<L2::Cache>, Vec<SyncError<Self::Error>>> {
+        let mut cache = WalletCache::new_nonsync();
+        self.update::<K, D, L2>(descriptor, &mut cache).map(|_| cache)
+    }
+
+    fn update_with_progress<K, D: Descriptor<K>, L2: Layer2>(
+        &self,
+        descriptor: &WalletDescr<K, D, L2::Descr>,
+        cache: &mut WalletCache<L2::Cache>,
+        progress: &mut dyn SyncProgress,
+    ) -> MayError<usize, Vec<SyncError<Self::Error>>> {
+        let mut errors = vec![];
+
+        #[cfg(feature = "log")]
+        log::debug!("Updating wallet from Bitcoin Core node");
+
+        // Without a synchronized node we will get empty or incomplete UTXO set, which must not be
+        // confused with an empty wallet.
+        match self.tip() {
+            Ok(tip) => cache.last_block = tip,
+            Err(err) => return MayError::err(0, vec![SyncError::Unreachable(err)]),
+        }
+        // Transactions holding the wallet coins get their status back with the UTXO set scan,
+        // while the status of the other re-orged transactions is requested separately below.
+        let reorged =
+            invalidate_reorged(cache, cache.last_block.height, |height| self.block_hash(height))
+                .is_some();
+
+        let scanned = descriptor.keychains();
+        let mut address_index = BTreeMap::new();
+        let mut scan = Vec::new();
+        for keychain in scanned.iter().copied() {
+            // Since the UTXO set is scanned at once, we can't extend the range on discovery of new
+            // used addresses, so we extend it beyond the last address known to be used instead.
+            let last_used = cache
+                .addr
+                .get(&keychain)
+                .and_then(|addrs| {
+                    addrs.iter().filter(|addr| addr.used > 0).map(|addr| addr.terminal.index).max()
+                })
+                .map(|index| index.index() as usize + 1)
+                .unwrap_or_default();
+            let depth = cache.scan_depth.get(&keychain).copied().unwrap_or_default() as usize;
+            let range =
+                SCAN_RANGE.max(last_used + descriptor.gap_limit(keychain) as usize).max(depth);
+            for derive in descriptor.addresses(keychain).take(range) {
+                scan.push(Value::String(format!("addr({})", derive.addr)));
+                address_index.insert(derive.addr.script_pubkey(), WalletAddr::<i64>::from(derive));
+            }
+        }
+
+        #[cfg(feature = "log")]
+        log::trace!("Scanning UTXO set for {} addresses", scan.len());
+
+        // Each scan reads the whole UTXO set, so all keychains are scanned with a single request,
+        // and the whole range is reported at once.
+        progress.on_address(0, scan.len() as u32);
+        let unspents = match self.call("scantxoutset", json!(["start", scan])) {
+            Ok(res) => res.get("unspents").and_then(Value::as_array).cloned().unwrap_or_default(),
+            Err(err) => return MayError::err(0, vec![SyncError::Unreachable(err)]),
+        };
+        // The initial scan depth has been covered, so the following syncs fall back to the gap
+        // limit.
+        cache.scan_depth.retain(|keychain, _| !scanned.contains(keychain));
+
+        let mut utxos = BTreeSet::new();
+        let mut heights = BTreeMap::new();
+        for unspent in unspents {
+            let Some(txid) =
+                unspent.get("txid").and_then(Value::as_str).and_then(|s| Txid::from_str(s).ok())
+            else {
+                errors.push(SyncError::Tx {
+                    txid: None,
+                    error: BitcoindError::InvalidServerData,
+                });
+                continue;
+            };
+            let (Some(vout), Some(height)) = (
+                unspent.get("vout").and_then(Value::as_u64),
+                unspent.get("height").and_then(Value::as_u64),
+            ) else {
+                errors.push(SyncError::Tx {
+                    txid: Some(txid),
+                    error: BitcoindError::InvalidTx(txid),
+                });
+                continue;
+            };
+            let outpoint = Outpoint::new(txid, vout as u32);
+            // `scantxoutset` sees only the chain state, so we check whether the output is already
+            // spent by some mempool transaction.
+            match self.call("gettxout", json!([txid.to_string(), vout, true])) {
+                Ok(Value::Null) => {
+                    #[cfg(feature = "log")]
+                    log::trace!("- {outpoint} is spent in mempool");
+                }
+                Ok(_) => {
+                    utxos.insert(outpoint);
+                }
+                Err(error) => {
+                    errors.push(SyncError::Tx {
+                        txid: Some(txid),
+                        error,
+                    });
+                    utxos.insert(outpoint);
+                }
+            }
+            heights.insert(txid, height as u32);
+        }
+
+        for (txid, height) in heights {
+            #[cfg(feature = "log")]
+            log::trace!("- {txid}");
+
+            let mut tx = match self.wallet_tx(txid, height) {
+                Ok(tx) => {
+                    progress.on_tx(txid, tx.status.height());
+                    tx
+                }
+                Err(error) => {
+                    errors.push(SyncError::Tx {
+                        txid: Some(txid),
+                        error,
+                    });
+                    continue;
+                }
+            };
+            for debit in &mut tx.outputs {
+                let Some(s) = debit.beneficiary.script_pubkey() else {
+                    continue;
+                };
+                if let Some(wallet_addr) = address_index.get_mut(&s) {
+                    debit.beneficiary = Party::from_wallet_addr(wallet_addr);
+                    wallet_addr.used = wallet_addr.used.saturating_add(1);
+                    wallet_addr.volume.saturating_add_assign(debit.value);
+                    if utxos.contains(&debit.outpoint) {
+                        wallet_addr.balance = wallet_addr
+                            .balance
+                            .saturating_add(debit.value.sats().try_into().expect("sats overflow"));
+                    }
+                } else {
+                    Address::with(&s, descriptor.network())
+                        .map(|addr| {
+                            debit.beneficiary = Party::Counterparty(addr);
+                        })
+                        .ok();
+                }
+            }
+            for credit in &mut tx.inputs {
+                let Some(s) = credit.payer.script_pubkey() else {
+                    continue;
+                };
+                if let Some(wallet_addr) = address_index.get(&s) {
+                    credit.payer = Party::from_wallet_addr(wallet_addr);
+                } else {
+                    Address::with(&s, descriptor.network())
+                        .map(|addr| {
+                            credit.payer = Party::Counterparty(addr);
+                        })
+                        .ok();
+                }
+            }
+            cache.tx.insert(tx.txid, tx);
+        }
+        if reorged {
+            for tx in cache.tx.values_mut().filter(|tx| tx.status == TxStatus::Unknown) {
+                match self.status(tx.txid) {
+                    Ok(status) => tx.status = status,
+                    Err(error) => errors.push(SyncError::Tx {
+                        txid: Some(tx.txid),
+                        error,
+                    }),
+                }
+            }
+        }
+
+        let synced = address_index.len();
+
+        // Node UTXO set is authoritative, so we drop all outputs which were spent since the
+        // previous update - unless their keychain is excluded from the sync.
+        let prev_utxos = std::mem::replace(&mut cache.utxo, utxos);
+        for outpoint in prev_utxos {
+            let keychain = cache
+                .tx
+                .get(&outpoint.txid)
+                .and_then(|tx| tx.outputs.get(outpoint.vout_usize()))
+                .and_then(|out| out.derived_addr())
+                .map(|addr| addr.terminal.keychain);
+            if keychain.is_some_and(|keychain| !scanned.contains(&keychain)) {
+                cache.utxo.insert(outpoint);
+            }
+        }
+        // The node doesn't know transactions which have spent wallet coins, so addresses known to
+        // be used before stay used, even if all of their coins are spent.
+        for wallet_addr in address_index.into_values() {
+            let mut wallet_addr = wallet_addr.expect_transmute();
+            let addrs = cache.addr.entry(wallet_addr.terminal.keychain).or_default();
+            if let Some(prev) = addrs.get(&wallet_addr) {
+                wallet_addr.used = wallet_addr.used.max(prev.used);
+                wallet_addr.volume = wallet_addr.volume.max(prev.volume);
+            }
+            if wallet_addr.used > 0 {
+                addrs.replace(wallet_addr);
+            }
+        }
+
+        if errors.is_empty() {
+            #[cfg(feature = "log")]
+            log::debug!("Wallet update from the indexer successfully complete with no errors");
+            MayError::ok(synced)
+        } else {
+            #[cfg(feature = "log")]
+            {
+                log::error!(
+                    "The following errors has happened during wallet update from the indexer"
+                );
+                for err in &errors {
+                    log::error!("- {err}");
+                }
+            }
+            MayError::err(synced, errors)
+        }
+    }
+
+    fn broadcast(&self, tx: &Tx) -> Result<(), Self::Error> {
+        self.call("sendrawtransaction", json!([tx.to_string()]))?;
+        Ok(())
+    }
+
+    fn status(&self, txid: Txid) -> Result<TxStatus, Self::Error> {
+        // Works for mempool transactions and, if the node has `txindex` enabled, for the mined
+        // ones.
+        let info = match self.call("getrawtransaction", json!([txid.to_string(), 1])) {
+            Ok(info) => info,
+            Err(BitcoindError::Rpc { code, .. }) if code == RPC_INVALID_ADDRESS_OR_KEY => {
+                return Ok(TxStatus::Unknown);
+            }
+            Err(err) => return Err(err),
+        };
+        let Some(block_hash) =
+            info.get("blockhash").and_then(Value::as_str).and_then(|s| BlockHash::from_str(s).ok())
+        else {
+            return Ok(TxStatus::Mempool);
+        };
+        let time =
+            info.get("blocktime").and_then(Value::as_u64).ok_or(BitcoindError::InvalidTx(txid))?;
+        let header = self.call("getblockheader", json!([block_hash.to_string()]))?;
+        let height = header
+            .get("height")
+            .and_then(Value::as_u64)
+            .and_then(|h| u32::try_from(h).ok())
+            .and_then(|h| BlockHeight::try_from(h).ok())
+            .ok_or(BitcoindError::InvalidTx(txid))?;
+        Ok(TxStatus::Mined(MiningInfo {
+            height,
+            time,
+            block_hash,
+        }))
+    }
+
+    fn transaction(&self, txid: Txid) -> Result<Option<Tx>, Self::Error> {
+        // Mined transactions are available only if the node has `txindex` enabled
+        let hex = match self.call("getrawtransaction", json!([txid.to_string(), 0])) {
+            Ok(hex) => hex,
+            Err(BitcoindError::Rpc { code, .. }) if code == RPC_INVALID_ADDRESS_OR_KEY => {
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+        hex.as_str()
+            .and_then(|s| Tx::from_str(s).ok())
+            .map(Some)
+            .ok_or(BitcoindError::InvalidTx(txid))
+    }
+
+    fn block_hash(&self, height: u32) -> Result<BlockHash, Self::Error> {
+        self.call("getblockhash", json!([height]))?
+            .as_str()
+            .and_then(|s| BlockHash::from_str(s).ok())
+            .ok_or(BitcoindError::InvalidServerData)
+    }
+
+    fn tip(&self) -> Result<MiningInfo, Self::Error> { Client::tip(self) }
+
+    fn fee_rate(&self, target_blocks: u16) -> Result<f64, Self::Error> {
+        let estimate = self.call("estimatesmartfee", json!([target_blocks]))?;
+        // Bitcoin Core reports fee rate in BTC per kvB
+        estimate
+            .get("feerate")
+            .and_then(Value::as_f64)
+            .map(|rate| rate * 100_000.0)
+            .ok_or(BitcoindError::NoFeeEstimate(target_blocks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use bpstd::{Keychain, LockTime, TxVer, XpubDerivable};
+    use descriptors::{StdDescr, TrKey};
+
+    use super::*;
+    use crate::{Layer2Empty, NoLayer2};
+
+    const XPUB: &str = "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1;2>/*";
+
+    /// Starts a local JSON-RPC server answering the requests with the `handler` and returns its
+    /// URL. Methods for which the handler returns `None` fail as unknown.
+    fn mock_node(handler: impl Fn(&str, &Value) -> Option<Value> + Send + 'static) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(':') {
+                        _ if line.trim_end().is_empty() => break,
+                        Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                            len = value.trim().parse().unwrap();
+                        }
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0u8; len];
+                stream.read_exact(&mut body).unwrap();
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let method = request["method"].as_str().unwrap();
+                let reply = match handler(method, &request["params"]) {
+                    Some(result) => json!({ "result": result, "error": null, "id": request["id"] }),
+                    None => json!({
+                        "result": null,
+                        "error": { "code": -32601, "message": "Method not found" },
+                        "id": request["id"],
+                    }),
+                }
+                .to_string();
+                write!(
+                    stream.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{reply}",
+                    reply.len()
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    fn blockchain_info() -> Value {
+        json!({
+            "blocks": 200,
+            "headers": 200,
+            "initialblockdownload": false,
+            "bestblockhash": "00".repeat(32),
+            "time": 1_700_000_000,
+        })
+    }
+
+    #[test]
+    fn test_signet_magic() {
+        // Challenge of the default public signet
+        let challenge = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+        let magic = [0x0a, 0x03, 0xcf, 0x40];
+        let script = ScriptPubkey::from_checked(Vec::<u8>::from_hex(challenge).unwrap());
+        assert_eq!(signet_magic(&script), magic);
+
+        let url = mock_node(move |method, _| {
+            let mut info = blockchain_info();
+            info["signet_challenge"] = json!(challenge);
+            (method == "getblockchaininfo").then_some(info)
+        });
+        assert_eq!(Client::new(&url, Auth::None).signet_magic().unwrap(), Some(magic));
+        let url = mock_node(|method, _| (method == "getblockchaininfo").then(blockchain_info));
+        assert_eq!(Client::new(&url, Auth::None).signet_magic().unwrap(), None);
+    }
+
+    #[test]
+    fn test_filtered_keychain_utxos() {
+        let mut descr = WalletDescr::<XpubDerivable, StdDescr>::new_standard(
+            TrKey::from(XpubDerivable::from_str(XPUB).unwrap()).into(),
+            Network::Regtest,
+        );
+        // Output on the third keychain which was unspent during the previous sync
+        let addr = WalletAddr::<Sats>::from(descr.addresses(2u8).next().unwrap());
+        let txid = Txid::from_hex(&"01".repeat(32)).unwrap();
+        let outpoint = Outpoint::new(txid, 0);
+        let mut cache = WalletCache::<Layer2Empty>::new_nonsync();
+        cache.tx.insert(txid, WalletTx {
+            txid,
+            status: TxStatus::Mempool,
+            inputs: vec![],
+            outputs: vec![TxDebit {
+                outpoint,
+                beneficiary: Party::from_wallet_addr(&addr),
+                value: Sats(10_000),
+                spent: None,
+            }],
+            fee: Sats::ZERO,
+            size: 0,
+            weight: 0,
+            version: TxVer::V2,
+            locktime: LockTime::ZERO,
+        });
+        cache.utxo.insert(outpoint);
+
+        let client = Client::new(
+            &mock_node(|method, _| match method {
+                "getblockchaininfo" => Some(blockchain_info()),
+                "scantxoutset" => Some(json!({ "unspents": [] })),
+                _ => None,
+            }),
+            Auth::None,
+        );
+
+        // The keychain is not scanned, so we know nothing about the output being spent
+        descr.restrict_keychains(Some(BTreeSet::from([Keychain::from(0u8), Keychain::from(1u8)])));
+        let res = client.update::<XpubDerivable, StdDescr, NoLayer2>(&descr, &mut cache);
+        assert!(res.err.is_none());
+        assert_eq!(cache.utxo, BTreeSet::from([outpoint]));
+
+        // Once the keychain is scanned, the output missing from the UTXO set is dropped
+        descr.restrict_keychains(None);
+        let res = client.update::<XpubDerivable, StdDescr, NoLayer2>(&descr, &mut cache);
+        assert!(res.err.is_none());
+        assert!(cache.utxo.is_empty());
+    }
+
+    #[test]
+    fn test_spent_addresses() {
+        let descr = WalletDescr::<XpubDerivable, StdDescr>::new_standard(
+            TrKey::from(XpubDerivable::from_str(XPUB).unwrap()).into(),
+            Network::Regtest,
+        );
+        // Address which had a coin during the previous sync
+        let mut addr = WalletAddr::<Sats>::from(descr.addresses(0u8).nth(3).unwrap());
+        addr.used = 1;
+        addr.volume = Sats(10_000);
+        addr.balance = Sats(10_000);
+        let mut cache = WalletCache::<Layer2Empty>::new_nonsync();
+        cache.addr.entry(addr.terminal.keychain).or_default().insert(addr);
+
+        let scans = Arc::new(Mutex::new(Vec::new()));
+        let client = Client::new(
+            &mock_node({
+                let scans = scans.clone();
+                move |method, params| match method {
+                    "getblockchaininfo" => Some(blockchain_info()),
+                    "scantxoutset" => {
+                        scans.lock().unwrap().push(params[1].as_array().unwrap().len());
+                        Some(json!({ "unspents": [] }))
+                    }
+                    _ => None,
+                }
+            }),
+            Auth::None,
+        );
+        let res = client.update::<XpubDerivable, StdDescr, NoLayer2>(&descr, &mut cache);
+        assert!(res.err.is_none());
+        assert_eq!(res.ok, 3 * SCAN_RANGE);
+        // All keychains are scanned at once
+        assert_eq!(*scans.lock().unwrap(), vec![3 * SCAN_RANGE]);
+
+        let addrs = &cache.addr[&addr.terminal.keychain];
+        assert_eq!(addrs.len(), 1);
+        let spent = addrs.first().unwrap();
+        assert_eq!(spent.terminal, addr.terminal);
+        assert_eq!((spent.used, spent.volume, spent.balance), (1, Sats(10_000), Sats::ZERO));
+
+        // The scan depth of an imported wallet extends only the next scan
+        cache.scan_depth.insert(Keychain::from(0u8), 2 * SCAN_RANGE as u32);
+        let res = client.update::<XpubDerivable, StdDescr, NoLayer2>(&descr, &mut cache);
+        assert!(res.err.is_none());
+        assert!(cache.scan_depth.is_empty());
+        let res = client.update::<XpubDerivable, StdDescr, NoLayer2>(&descr, &mut cache);
+        assert!(res.err.is_none());
+        assert_eq!(*scans.lock().unwrap(), vec![3 * SCAN_RANGE, 4 * SCAN_RANGE, 3 * SCAN_RANGE]);
+    }
+
+    #[test]
+    fn test_btc_to_sats() {
+        let sats = |json: &str| btc_to_sats(&serde_json::from_str(json).unwrap());
+        assert_eq!(sats("0.00000001"), Some(Sats(1)));
+        assert_eq!(sats("0.1"), Some(Sats(10_000_000)));
+        assert_eq!(sats("0.29"), Some(Sats(29_000_000)));
+        assert_eq!(sats("1"), Some(Sats(100_000_000)));
+        assert_eq!(sats("1.1e-7"), Some(Sats(11)));
+        assert_eq!(sats("20999999.99999999"), Some(Sats(2_099_999_999_999_999)));
+        assert_eq!(sats("0.123456789"), None);
+        assert_eq!(sats("-0.1"), None);
+        assert_eq!(sats("\"0.1\""), None);
+    }
+}
@@ -19,7 +19,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::num::NonZeroU32;
 use std::str::FromStr;
 
@@ -29,7 +29,10 @@ use electrum::{Client, ElectrumApi, GetHistoryRes, Param};
 pub use electrum::{Config, ConfigBuilder, Error, Socks5Config};
 use serde_json::Value;
 
-use super::BATCH_SIZE;
+use super::{
+    script_status, update_sync_points, verify_sync_points, AddressIndex, GapCounter, SyncError,
+    SyncProgress, UnsupportedRequest,
+};
 use crate::{
     BlockHeight, Indexer, Layer2, MayError, MiningInfo, Network, Party, TxCredit, TxDebit,
     TxStatus, WalletAddr, WalletCache, WalletDescr, WalletTx,
@@ -52,6 +55,8 @@ pub enum ElectrumApiError {
     /// Electrum indexer returned a genesis block hash which doesn't match any of the known
     /// networks.
     InvalidGenesis,
+    /// Electrum indexer is unable to estimate fee rate for the confirmation target of {0} blocks.
+    NoFeeEstimate(u16),
 }
 
 #[derive(Debug, Display, Error, From)]
@@ -61,6 +66,8 @@ pub enum ElectrumError {
     Api(ElectrumApiError),
     #[from]
     Client(Error),
+    #[from]
+    Unsupported(UnsupportedRequest),
 }
 
 impl Indexer for Client {
@@ -74,17 +81,18 @@ impl Indexer for Client {
     fn create<K, D: Descriptor<K>, L2: Layer2>(
         &self,
         descriptor: &WalletDescr<K, D, L2::Descr>,
-    ) -> MayError<WalletCache<L2::Cache>, Vec<Self::Error>> {
+    ) -> MayError<WalletCache<L2::Cache>, Vec<SyncError<Self::Error>>> {
         let mut cache = WalletCache::new_nonsync();
         self.update::<K, D, L2>(descriptor, &mut cache).map(|_| cache)
     }
 
-    fn update<K, D: Descriptor<K>, L2: Layer2>(
+    fn update_with_progress<K, D: Descriptor<K>, L2: Layer2>(
         &self,
         descriptor: &WalletDescr<K, D, L2::Descr>,
         cache: &mut WalletCache<L2::Cache>,
-    ) -> MayError<usize, Vec<Self::Error>> {
-        let mut errors = Vec::<ElectrumError>::new();
+        progress: &mut dyn SyncProgress,
+    ) -> MayError<usize, Vec<SyncError<Self::Error>>> {
+        let mut errors = Vec::<SyncError<ElectrumError>>::new();
 
         #[cfg(feature = "log")]
         log::debug!("Updating wallet from Electrum indexer");
@@ -95,35 +103,49 @@ impl Indexer for Client {
         // cache. We remove old transaction, since its data are now updated (for instance, if a
         // transaction was re-orged, it may have a different height).
 
-        let mut address_index = BTreeMap::new();
+        verify_sync_points(cache, &mut errors, |height| {
+            self.block_header(height as usize).map(|header| header.block_hash())
+        });
+
+        let mut checked = 0usize;
+        let mut failed_keychains = BTreeSet::new();
+        let mut address_index = AddressIndex::new();
         for keychain in descriptor.keychains() {
-            let mut empty_count = 0usize;
-            for derive in descriptor.addresses(keychain) {
+            let sync_height = cache.last_sync.get(&keychain).map(|info| info.height);
+            let depth = cache.scan_depth.get(&keychain).copied().unwrap_or_default();
+            let mut gap = GapCounter::new(descriptor.gap_limit(keychain), depth);
+            for (index, derive) in descriptor.addresses(keychain).enumerate() {
+                progress.on_address(index as u32, gap.estimate(index));
+
                 #[cfg(feature = "log")]
-                log::trace!("Retrieving transaction for {derive}");
+                log::debug!("Retrieving transaction for {derive}");
 
                 let script = derive.addr.script_pubkey();
 
                 let mut txids = Vec::new();
-                let Ok(hres) =
-                    self.script_get_history(&script).map_err(|err| errors.push(err.into()))
-                else {
+                let Ok(hres) = self.script_get_history(&script).map_err(|err| {
+                    errors.push(SyncError::Address {
+                        addr: derive,
+                        error: err.into(),
+                    })
+                }) else {
+                    failed_keychains.insert(keychain);
                     break;
                 };
+                checked += 1;
+                if gap.register(!hres.is_empty()) {
+                    break;
+                }
                 if hres.is_empty() {
-                    empty_count += 1;
-                    if empty_count >= BATCH_SIZE {
-                        break;
-                    }
                     continue;
                 }
 
-                empty_count = 0;
+                let status = script_status(hres.iter().map(|hr| (hr.tx_hash, hr.height as i64)));
+                let unchanged = cache.script_status.get(&script) == Some(&status);
 
-                let mut process_history_entry =
+                let process_history_entry =
                     |hr: GetHistoryRes| -> Result<WalletTx, ElectrumError> {
                         let txid = hr.tx_hash;
-                        txids.push(txid);
 
                         #[cfg(feature = "log")]
                         log::trace!("- {txid}");
@@ -169,6 +191,19 @@ impl Indexer for Client {
                         let mut input_total = Sats::ZERO;
                         let mut inputs = Vec::with_capacity(tx.inputs.len());
                         for input in tx.inputs {
+                            // coinbase input has no previous output transaction
+                            if input.prev_output.txid.is_coinbase() {
+                                inputs.push(TxCredit {
+                                    outpoint: input.prev_output,
+                                    payer: Party::Subsidy,
+                                    sequence: input.sequence,
+                                    coinbase: true,
+                                    script_sig: input.sig_script,
+                                    witness: input.witness,
+                                    value: Sats::ZERO,
+                                });
+                                continue;
+                            }
                             // get value from previous output tx
                             let Some(prev_tx) = self.transaction_get(&input.prev_output.txid)?
                             else {
@@ -217,7 +252,8 @@ impl Indexer for Client {
                             status,
                             inputs,
                             outputs,
-                            fee: input_total - output_total,
+                            // coinbase transactions pay no fee
+                            fee: input_total.saturating_sub(output_total),
                             size: tx_size as u32,
                             weight,
                             version: tx.version,
@@ -226,21 +262,51 @@ impl Indexer for Client {
                     };
 
                 // build wallet transactions from script tx history, collecting indexer errors
+                let mut complete = true;
                 for hr in hres {
+                    let txid = hr.tx_hash;
+                    // Transactions mined before the sync point, as well as all transactions of
+                    // scripts with unchanged status are already known
+                    let synced = hr.height > 0
+                        && sync_height.is_some_and(|sync| hr.height as u32 <= sync.get());
+                    if (unchanged || synced) && cache.tx.contains_key(&txid) {
+                        txids.push(txid);
+                        continue;
+                    }
                     match process_history_entry(hr) {
                         Ok(tx) => {
+                            progress.on_tx(tx.txid, tx.status.height());
                             cache.tx.insert(tx.txid, tx);
                         }
-                        Err(e) => errors.push(e),
+                        Err(error) => {
+                            errors.push(SyncError::Tx {
+                                txid: Some(txid),
+                                error,
+                            });
+                            complete = false;
+                        }
+                    }
+                    // Transactions which failed to be fetched are accounted only if they were
+                    // known from the previous syncs
+                    if cache.tx.contains_key(&txid) {
+                        txids.push(txid);
                     }
                 }
+                if complete {
+                    cache.script_status.insert(script.clone(), status);
+                }
 
                 let wallet_addr = WalletAddr::<i64>::from(derive);
                 address_index.insert(script, (wallet_addr, txids));
             }
         }
 
-        // TODO: Update headers & tip
+        update_sync_points(cache, &descriptor.keychains(), &address_index, &failed_keychains);
+
+        match self.tip() {
+            Ok(tip) => cache.last_block = tip,
+            Err(err) => errors.push(SyncError::Unreachable(err)),
+        }
 
         for (script, (wallet_addr, txids)) in &mut address_index {
             for txid in txids {
@@ -312,7 +378,7 @@ impl Indexer for Client {
         if errors.is_empty() {
             #[cfg(feature = "log")]
             log::debug!("Wallet update from the indexer successfully complete with no errors");
-            MayError::ok(0)
+            MayError::ok(checked)
         } else {
             #[cfg(feature = "log")]
             {
@@ -323,7 +389,7 @@ impl Indexer for Client {
                     log::error!("- {err}");
                 }
             }
-            MayError::err(0, errors)
+            MayError::err(checked, errors)
         }
     }
 
@@ -343,7 +409,8 @@ impl Indexer for Client {
             return Ok(TxStatus::Mempool);
         };
         let last_header = self.block_headers_subscribe()?;
-        let height = last_header.height as u32 - info.confirmations;
+        // A transaction included into the tip block has a single confirmation
+        let height = (last_header.height as u32 + 1).saturating_sub(info.confirmations);
         let height =
             BlockHeight::try_from(height).map_err(|_| ElectrumApiError::ZeroBlockHeight(txid))?;
         Ok(TxStatus::Mined(MiningInfo {
@@ -353,9 +420,32 @@ impl Indexer for Client {
         }))
     }
 
+    fn transaction(&self, txid: Txid) -> Result<Option<Tx>, Self::Error> {
+        Ok(self.transaction_get(&txid)?)
+    }
+
     fn block_hash(&self, height: u32) -> Result<BlockHash, Self::Error> {
-        self.block_header(height as usize)
-            .map(|header| header.block_hash())
-            .map_err(|_| ElectrumApiError::InvalidGenesis.into())
+        let header = self.block_header(height as usize)?;
+        Ok(header.block_hash())
+    }
+
+    fn tip(&self) -> Result<MiningInfo, Self::Error> {
+        let last_header = self.block_headers_subscribe()?;
+        Ok(MiningInfo {
+            height: BlockHeight::try_from(last_header.height as u32)
+                .unwrap_or(BlockHeight::MIN),
+            time: last_header.header.time as u64,
+            block_hash: last_header.header.block_hash(),
+        })
+    }
+
+    fn fee_rate(&self, target_blocks: u16) -> Result<f64, Self::Error> {
+        // Electrum server reports fee rate in BTC per kvB, or a negative value if it doesn't have
+        // an estimation
+        let rate = self.estimate_fee(target_blocks as usize)?;
+        if rate <= 0.0 {
+            return Err(ElectrumApiError::NoFeeEstimate(target_blocks).into());
+        }
+        Ok(rate * 100_000.0)
     }
 }
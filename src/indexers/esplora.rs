@@ -20,26 +20,47 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::num::NonZeroU32;
 use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 use bpstd::{Address, DerivedAddr, LockTime, Outpoint, SeqNo, Tx, TxVer, Txid, Witness};
 use descriptors::Descriptor;
 use esplora::BlockingClient;
 pub use esplora::{Builder, Config, Error};
-
-use super::BATCH_SIZE;
+#[cfg(feature = "async")]
+use futures_util::future::join_all;
+
+#[cfg(feature = "async")]
+use super::retry_async;
+use super::{
+    closest_fee_rate, retry, script_status, synced_txids, update_sync_points, verify_sync_points,
+    AddressIndex, GapCounter, RetryPolicy, SyncError, SyncProgress, UnsupportedRequest,
+};
+#[cfg(feature = "async")]
+use crate::AsyncIndexer;
 use crate::{
     BlockHash, BlockHeight, Indexer, Layer2, MayError, MiningInfo, Network, Party, TxCredit,
     TxDebit, TxStatus, WalletAddr, WalletCache, WalletDescr, WalletTx,
 };
 
+/// Default number of addresses which are queried in parallel during wallet update.
+pub const DEFAULT_PARALLELISM: usize = 4;
+
 /// Represents a client for interacting with the Esplora indexer.
 #[derive(Debug, Clone)]
 pub struct Client {
     pub(crate) inner: BlockingClient,
     pub(crate) kind: ClientKind,
+    pub(crate) retry: RetryPolicy,
+    /// Proxy for the mempool requests made outside of the esplora client.
+    #[cfg(feature = "mempool")]
+    pub(crate) proxy: Option<String>,
+    pub(crate) parallelism: usize,
+    /// Fee estimates received from the server, reused by the subsequent fee rate requests.
+    pub(crate) fee_estimates: Arc<Mutex<Option<BTreeMap<u16, f64>>>>,
 }
 
 impl Deref for Client {
@@ -52,6 +73,28 @@ impl DerefMut for Client {
     fn deref_mut(&mut self) -> &mut Self::Target { &mut self.inner }
 }
 
+/// Represents an asynchronous client for interacting with the Esplora indexer.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone)]
+pub struct AsyncClient {
+    pub(crate) inner: esplora::AsyncClient,
+    pub(crate) kind: ClientKind,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) parallelism: usize,
+}
+
+#[cfg(feature = "async")]
+impl Deref for AsyncClient {
+    type Target = esplora::AsyncClient;
+
+    fn deref(&self) -> &Self::Target { &self.inner }
+}
+
+#[cfg(feature = "async")]
+impl DerefMut for AsyncClient {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.inner }
+}
+
 /// Represents the kind of client used for interacting with the Esplora indexer.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub enum ClientKind {
@@ -73,13 +116,109 @@ impl Client {
     /// Returns an error if the client fails to connect to the Esplora server.
     #[allow(clippy::result_large_err)]
     pub fn new_esplora(url: &str) -> Result<Self, Error> {
-        let inner = esplora::Builder::new(url).build_blocking()?;
+        Self::with_builder(esplora::Builder::new(url), ClientKind::Esplora)
+    }
+
+    /// Creates a new client of a given kind from a pre-configured esplora builder, allowing to
+    /// set up timeouts, proxy and other options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client fails to connect to the server.
+    #[allow(clippy::result_large_err)]
+    pub fn with_builder(builder: Builder, kind: ClientKind) -> Result<Self, Error> {
+        #[cfg(feature = "mempool")]
+        let proxy = builder.proxy.clone();
+        let inner = builder.build_blocking()?;
         let client = Self {
             inner,
-            kind: ClientKind::Esplora,
+            kind,
+            retry: none!(),
+            #[cfg(feature = "mempool")]
+            proxy,
+            parallelism: DEFAULT_PARALLELISM,
+            fee_estimates: none!(),
         };
         Ok(client)
     }
+
+    /// Sets the policy for retrying requests made during wallet update which have failed with
+    /// transient errors (timeouts, connection errors, rate limiting and server-side errors).
+    pub fn with_retries(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Sets the maximal number of addresses which are queried in parallel during wallet update.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncClient {
+    /// Creates a new asynchronous Esplora client with the specified URL.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client fails to connect to the Esplora server.
+    #[allow(clippy::result_large_err)]
+    pub fn new_esplora(url: &str) -> Result<Self, Error> {
+        Self::with_builder(esplora::Builder::new(url), ClientKind::Esplora)
+    }
+
+    /// Creates a new asynchronous client of a given kind from a pre-configured esplora builder.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client fails to connect to the server.
+    #[allow(clippy::result_large_err)]
+    pub fn with_builder(builder: Builder, kind: ClientKind) -> Result<Self, Error> {
+        let inner = builder.build_async()?;
+        Ok(Self {
+            inner,
+            kind,
+            retry: none!(),
+            parallelism: DEFAULT_PARALLELISM,
+        })
+    }
+
+    /// Sets the policy for retrying requests made during wallet update which have failed with
+    /// transient errors (timeouts, connection errors, rate limiting and server-side errors).
+    pub fn with_retries(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Sets the maximal number of addresses which are queried concurrently during wallet update.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+}
+
+/// Detects errors which may disappear if the same request is repeated later.
+fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::Minreq(_) => true,
+        Error::HttpResponse { status, .. } => {
+            *status == 429 || (*status >= 500 && *status != NOT_IMPLEMENTED)
+        }
+        _ => false,
+    }
+}
+
+/// HTTP status of the requests which are not supported by a server.
+const NOT_IMPLEMENTED: u16 = 501;
+
+impl From<UnsupportedRequest> for Error {
+    fn from(err: UnsupportedRequest) -> Self {
+        Error::HttpResponse {
+            status: NOT_IMPLEMENTED,
+            message: err.to_string(),
+        }
+    }
 }
 
 impl From<esplora::TxStatus> for TxStatus {
@@ -146,12 +285,32 @@ impl From<esplora::Tx> for WalletTx {
     }
 }
 
+/// Margin for the inaccuracy of block timestamps when transactions are compared to the wallet
+/// birth time, matching the one used by Bitcoin Core for rescans.
+const BIRTH_TIME_WINDOW: u64 = 2 * 60 * 60;
+
+/// Detects whether a transaction was mined at or before the height up to which the wallet is
+/// already synchronized, or before the wallet birth time.
+fn is_synced(tx: &esplora::Tx, sync_height: Option<BlockHeight>, birth_time: Option<u64>) -> bool {
+    tx.status.block_height.zip(sync_height).is_some_and(|(height, sync)| height <= sync.get())
+        || tx
+            .status
+            .block_time
+            .zip(birth_time)
+            .is_some_and(|(time, birth)| time.saturating_add(BIRTH_TIME_WINDOW) < birth)
+}
+
 /// Retrieves all transactions associated with a given script hash.
 ///
+/// Since the transactions are returned starting from the most recent ones, the retrieval stops
+/// once a transaction mined at or before `sync_height`, or before `birth_time`, is met.
+///
 /// # Arguments
 ///
 /// * `client` - The Esplora client.
 /// * `derive` - The derived address.
+/// * `sync_height` - The height up to which the wallet is already synchronized.
+/// * `birth_time` - The wallet birth time.
 ///
 /// # Errors
 ///
@@ -160,6 +319,8 @@ impl From<esplora::Tx> for WalletTx {
 fn get_scripthash_txs_all(
     client: &Client,
     derive: &DerivedAddr,
+    sync_height: Option<BlockHeight>,
+    birth_time: Option<u64>,
 ) -> Result<Vec<esplora::Tx>, Error> {
     const PAGE_SIZE: usize = 25;
     let mut res = Vec::new();
@@ -167,14 +328,86 @@ fn get_scripthash_txs_all(
     let script = derive.addr.script_pubkey();
 
     loop {
-        let r = match client.kind {
-            ClientKind::Esplora => client.inner.scripthash_txs(&script, last_seen)?,
+        let r = retry(client.retry, is_transient, || match client.kind {
+            ClientKind::Esplora => client.inner.scripthash_txs(&script, last_seen),
             #[cfg(feature = "mempool")]
-            ClientKind::Mempool => client.inner.address_txs(&derive.addr, last_seen)?,
-        };
+            ClientKind::Mempool => client.inner.address_txs(&derive.addr, last_seen),
+        })?;
+        match &r[..] {
+            [a @ .., last]
+                if a.len() >= PAGE_SIZE - 1 && !is_synced(last, sync_height, birth_time) =>
+            {
+                last_seen = Some(last.txid);
+                res.extend(r);
+            }
+            _ => {
+                res.extend(r);
+                break;
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Retrieves transactions for a batch of addresses, querying each of them from a separate thread.
+///
+/// The results are returned in the order of the addresses in the batch; a failure of one of the
+/// requests doesn't affect the others.
+fn get_scripthash_txs_batch(
+    client: &Client,
+    batch: &[(usize, DerivedAddr)],
+    sync_height: Option<BlockHeight>,
+    birth_time: Option<u64>,
+) -> Vec<Result<Vec<esplora::Tx>, Error>> {
+    if let [(_, derive)] = batch {
+        return vec![get_scripthash_txs_all(client, derive, sync_height, birth_time)];
+    }
+    thread::scope(|scope| {
+        let handles = batch
+            .iter()
+            .map(|(_, derive)| {
+                scope.spawn(move || get_scripthash_txs_all(client, derive, sync_height, birth_time))
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("esplora request thread has panicked"))
+            .collect()
+    })
+}
+
+/// Asynchronously retrieves all transactions associated with a given script hash.
+///
+/// # Errors
+///
+/// Returns an error if there was a problem retrieving the transactions.
+#[cfg(feature = "async")]
+#[allow(clippy::result_large_err)]
+async fn get_scripthash_txs_all_async(
+    client: &AsyncClient,
+    derive: &DerivedAddr,
+    sync_height: Option<BlockHeight>,
+    birth_time: Option<u64>,
+) -> Result<Vec<esplora::Tx>, Error> {
+    const PAGE_SIZE: usize = 25;
+    let mut res = Vec::new();
+    let mut last_seen = None;
+    let script = derive.addr.script_pubkey();
+
+    loop {
+        let r = retry_async(client.retry, is_transient, || async {
+            match client.kind {
+                ClientKind::Esplora => client.inner.scripthash_txs(&script, last_seen).await,
+                #[cfg(feature = "mempool")]
+                ClientKind::Mempool => client.inner.address_txs(&derive.addr, last_seen).await,
+            }
+        })
+        .await?;
         match &r[..] {
-            [a @ .., esplora::Tx { txid, .. }] if a.len() >= PAGE_SIZE - 1 => {
-                last_seen = Some(*txid);
+            [a @ .., last]
+                if a.len() >= PAGE_SIZE - 1 && !is_synced(last, sync_height, birth_time) =>
+            {
+                last_seen = Some(last.txid);
                 res.extend(r);
             }
             _ => {
@@ -186,6 +419,99 @@ fn get_scripthash_txs_all(
     Ok(res)
 }
 
+/// Completes wallet update with the transactions retrieved for each of the wallet addresses,
+/// detecting wallet outputs, spendings and counterparties. The number of `checked` addresses is
+/// reported as the update result.
+fn complete_update<K, D: Descriptor<K>, L2: Layer2>(
+    descriptor: &WalletDescr<K, D, L2::Descr>,
+    cache: &mut WalletCache<L2::Cache>,
+    mut address_index: AddressIndex,
+    checked: usize,
+    errors: Vec<SyncError<Error>>,
+) -> MayError<usize, Vec<SyncError<Error>>> {
+    for (script, (wallet_addr, txids)) in &mut address_index {
+        for txid in txids {
+            let mut tx = cache.tx.remove(txid).expect("broken logic");
+            for debit in &mut tx.outputs {
+                let Some(s) = debit.beneficiary.script_pubkey() else {
+                    continue;
+                };
+                if &s == script {
+                    cache.utxo.insert(debit.outpoint);
+                    debit.beneficiary = Party::from_wallet_addr(wallet_addr);
+                    wallet_addr.used = wallet_addr.used.saturating_add(1);
+                    wallet_addr.volume.saturating_add_assign(debit.value);
+                    wallet_addr.balance = wallet_addr
+                        .balance
+                        .saturating_add(debit.value.sats().try_into().expect("sats overflow"));
+                } else if debit.beneficiary.is_unknown() {
+                    Address::with(&s, descriptor.network())
+                        .map(|addr| {
+                            debit.beneficiary = Party::Counterparty(addr);
+                        })
+                        .ok();
+                }
+            }
+            cache.tx.insert(tx.txid, tx);
+        }
+    }
+
+    for (script, (wallet_addr, txids)) in &mut address_index {
+        for txid in txids {
+            let mut tx = cache.tx.remove(txid).expect("broken logic");
+            for credit in &mut tx.inputs {
+                let Some(s) = credit.payer.script_pubkey() else {
+                    continue;
+                };
+                if &s == script {
+                    credit.payer = Party::from_wallet_addr(wallet_addr);
+                    wallet_addr.balance = wallet_addr
+                        .balance
+                        .saturating_sub(credit.value.sats().try_into().expect("sats overflow"));
+                } else if credit.payer.is_unknown() {
+                    Address::with(&s, descriptor.network())
+                        .map(|addr| {
+                            credit.payer = Party::Counterparty(addr);
+                        })
+                        .ok();
+                }
+                if let Some(prev_tx) = cache.tx.get_mut(&credit.outpoint.txid) {
+                    if let Some(txout) =
+                        prev_tx.outputs.get_mut(credit.outpoint.vout_u32() as usize)
+                    {
+                        let outpoint = txout.outpoint;
+                        if tx.status.is_mined() {
+                            cache.utxo.remove(&outpoint);
+                        }
+                        txout.spent = Some(credit.outpoint.into())
+                    };
+                }
+            }
+            cache.tx.insert(tx.txid, tx);
+        }
+        cache
+            .addr
+            .entry(wallet_addr.terminal.keychain)
+            .or_default()
+            .insert(wallet_addr.expect_transmute());
+    }
+
+    if errors.is_empty() {
+        #[cfg(feature = "log")]
+        log::debug!("Wallet update from the indexer successfully complete with no errors");
+        MayError::ok(checked)
+    } else {
+        #[cfg(feature = "log")]
+        {
+            log::error!("The following errors has happened during wallet update from the indexer");
+            for err in &errors {
+                log::error!("- {err}");
+            }
+        }
+        MayError::err(checked, errors)
+    }
+}
+
 impl Indexer for Client {
     type Error = Error;
 
@@ -197,141 +523,107 @@ impl Indexer for Client {
     fn create<K, D: Descriptor<K>, L2: Layer2>(
         &self,
         descriptor: &WalletDescr<K, D, L2::Descr>,
-    ) -> MayError<WalletCache<L2::Cache>, Vec<Self::Error>> {
+    ) -> MayError<WalletCache<L2::Cache>, Vec<SyncError<Self::Error>>> {
         let mut cache = WalletCache::new_nonsync();
         self.update::<K, D, L2>(descriptor, &mut cache).map(|_| cache)
     }
 
-    fn update<K, D: Descriptor<K>, L2: Layer2>(
+    fn update_with_progress<K, D: Descriptor<K>, L2: Layer2>(
         &self,
         descriptor: &WalletDescr<K, D, L2::Descr>,
         cache: &mut WalletCache<L2::Cache>,
-    ) -> MayError<usize, Vec<Self::Error>> {
+        progress: &mut dyn SyncProgress,
+    ) -> MayError<usize, Vec<SyncError<Self::Error>>> {
         let mut errors = vec![];
 
         #[cfg(feature = "log")]
         log::debug!("Updating wallet from Esplora indexer");
 
-        let mut address_index = BTreeMap::new();
-        for keychain in descriptor.keychains() {
-            let mut empty_count = 0usize;
-            for derive in descriptor.addresses(keychain) {
-                #[cfg(feature = "log")]
-                log::trace!("Retrieving transaction for {derive}");
+        verify_sync_points(cache, &mut errors, |height| {
+            retry(self.retry, is_transient, || self.inner.block_hash(height))
+        });
+        let synced = synced_txids(cache);
 
-                let script = derive.addr.script_pubkey();
+        let mut checked = 0usize;
+        let mut failed_keychains = BTreeSet::new();
+        let mut address_index = AddressIndex::new();
+        for keychain in descriptor.keychains() {
+            let sync_height = cache.last_sync.get(&keychain).map(|info| info.height);
+            let depth = cache.scan_depth.get(&keychain).copied().unwrap_or_default();
+            let mut gap = GapCounter::new(descriptor.gap_limit(keychain), depth);
+            let mut addresses = descriptor.addresses(keychain).enumerate();
+            'keychain: while !failed_keychains.contains(&keychain) {
+                let batch = addresses.by_ref().take(self.parallelism).collect::<Vec<_>>();
+                if batch.is_empty() {
+                    break;
+                }
 
-                let mut txids = Vec::new();
-                match get_scripthash_txs_all(self, &derive) {
-                    Err(err) => {
-                        errors.push(err);
-                        break;
-                    }
-                    Ok(txes) if txes.is_empty() => {
-                        empty_count += 1;
-                        if empty_count >= BATCH_SIZE {
-                            break;
+                #[cfg(feature = "log")]
+                log::trace!("Retrieving transactions for {} addresses", batch.len());
+
+                let results = get_scripthash_txs_batch(self, &batch, sync_height, cache.birth_time);
+                for ((index, derive), res) in batch.into_iter().zip(results) {
+                    progress.on_address(index as u32, gap.estimate(index));
+
+                    let script = derive.addr.script_pubkey();
+
+                    let mut txids = Vec::new();
+                    match res {
+                        Err(err) => {
+                            errors.push(SyncError::Address {
+                                addr: derive,
+                                error: err,
+                            });
+                            failed_keychains.insert(keychain);
+                            continue;
+                        }
+                        Ok(txes) if txes.is_empty() => {
+                            checked += 1;
+                            if gap.register(false) {
+                                break 'keychain;
+                            }
+                        }
+                        Ok(txes) => {
+                            checked += 1;
+                            gap.register(true);
+                            txids = txes.iter().map(|tx| tx.txid).collect();
+                            let status = script_status(txes.iter().map(|tx| {
+                                (tx.txid, tx.status.block_height.unwrap_or_default() as i64)
+                            }));
+                            // Scripts with unchanged status have all their transactions known
+                            let unchanged = cache.script_status.get(&script) == Some(&status)
+                                && txids.iter().all(|txid| cache.tx.contains_key(txid));
+                            if !unchanged {
+                                for tx in txes.into_iter().map(WalletTx::from) {
+                                    progress.on_tx(tx.txid, tx.status.height());
+                                    cache.tx.insert(tx.txid, tx);
+                                }
+                                cache.script_status.insert(script.clone(), status);
+                            }
                         }
                     }
-                    Ok(txes) => {
-                        empty_count = 0;
-                        txids = txes.iter().map(|tx| tx.txid).collect();
-                        cache
-                            .tx
-                            .extend(txes.into_iter().map(WalletTx::from).map(|tx| (tx.txid, tx)));
+                    // Transactions mined before the sync point may be not returned by the indexer
+                    for txid in synced.get(&script).into_iter().flatten() {
+                        if !txids.contains(txid) {
+                            txids.push(*txid);
+                        }
                     }
-                }
 
-                let wallet_addr = WalletAddr::<i64>::from(derive);
-                address_index.insert(script, (wallet_addr, txids));
-            }
-        }
-
-        // TODO: Update headers & tip
-
-        for (script, (wallet_addr, txids)) in &mut address_index {
-            for txid in txids {
-                let mut tx = cache.tx.remove(txid).expect("broken logic");
-                for debit in &mut tx.outputs {
-                    let Some(s) = debit.beneficiary.script_pubkey() else {
-                        continue;
-                    };
-                    if &s == script {
-                        cache.utxo.insert(debit.outpoint);
-                        debit.beneficiary = Party::from_wallet_addr(wallet_addr);
-                        wallet_addr.used = wallet_addr.used.saturating_add(1);
-                        wallet_addr.volume.saturating_add_assign(debit.value);
-                        wallet_addr.balance = wallet_addr
-                            .balance
-                            .saturating_add(debit.value.sats().try_into().expect("sats overflow"));
-                    } else if debit.beneficiary.is_unknown() {
-                        Address::with(&s, descriptor.network())
-                            .map(|addr| {
-                                debit.beneficiary = Party::Counterparty(addr);
-                            })
-                            .ok();
-                    }
-                }
-                cache.tx.insert(tx.txid, tx);
-            }
-        }
+                    #[cfg(feature = "log")]
+                    log::debug!("{derive}: {} transaction(s)", txids.len());
 
-        for (script, (wallet_addr, txids)) in &mut address_index {
-            for txid in txids {
-                let mut tx = cache.tx.remove(txid).expect("broken logic");
-                for credit in &mut tx.inputs {
-                    let Some(s) = credit.payer.script_pubkey() else {
-                        continue;
-                    };
-                    if &s == script {
-                        credit.payer = Party::from_wallet_addr(wallet_addr);
-                        wallet_addr.balance = wallet_addr
-                            .balance
-                            .saturating_sub(credit.value.sats().try_into().expect("sats overflow"));
-                    } else if credit.payer.is_unknown() {
-                        Address::with(&s, descriptor.network())
-                            .map(|addr| {
-                                credit.payer = Party::Counterparty(addr);
-                            })
-                            .ok();
-                    }
-                    if let Some(prev_tx) = cache.tx.get_mut(&credit.outpoint.txid) {
-                        if let Some(txout) =
-                            prev_tx.outputs.get_mut(credit.outpoint.vout_u32() as usize)
-                        {
-                            let outpoint = txout.outpoint;
-                            if tx.status.is_mined() {
-                                cache.utxo.remove(&outpoint);
-                            }
-                            txout.spent = Some(credit.outpoint.into())
-                        };
-                    }
+                    let wallet_addr = WalletAddr::<i64>::from(derive);
+                    address_index.insert(script, (wallet_addr, txids));
                 }
-                cache.tx.insert(tx.txid, tx);
             }
-            cache
-                .addr
-                .entry(wallet_addr.terminal.keychain)
-                .or_default()
-                .insert(wallet_addr.expect_transmute());
         }
 
-        if errors.is_empty() {
-            #[cfg(feature = "log")]
-            log::debug!("Wallet update from the indexer successfully complete with no errors");
-            MayError::ok(0)
-        } else {
-            #[cfg(feature = "log")]
-            {
-                log::error!(
-                    "The following errors has happened during wallet update from the indexer"
-                );
-                for err in &errors {
-                    log::error!("- {err}");
-                }
-            }
-            MayError::err(0, errors)
+        update_sync_points(cache, &descriptor.keychains(), &address_index, &failed_keychains);
+        match retry(self.retry, is_transient, || Indexer::tip(self)) {
+            Ok(tip) => cache.last_block = tip,
+            Err(err) => errors.push(SyncError::Unreachable(err)),
         }
+        complete_update::<K, D, L2>(descriptor, cache, address_index, checked, errors)
     }
 
     fn broadcast(&self, tx: &Tx) -> Result<(), Self::Error> { self.inner.broadcast(tx) }
@@ -367,7 +659,215 @@ impl Indexer for Client {
         Ok(TxStatus::Mempool)
     }
 
+    fn transaction(&self, txid: Txid) -> Result<Option<Tx>, Self::Error> {
+        self.inner.tx(&txid)
+    }
+
     fn block_hash(&self, height: u32) -> Result<BlockHash, Self::Error> {
         self.inner.block_hash(height)
     }
+
+    fn tip(&self) -> Result<MiningInfo, Self::Error> {
+        let height = self.inner.height()?;
+        let block_hash = self.inner.block_hash(height)?;
+        let header = self.inner.header_by_hash(&block_hash)?;
+        Ok(MiningInfo {
+            height: BlockHeight::try_from(height).unwrap_or(BlockHeight::MIN),
+            time: header.time as u64,
+            block_hash,
+        })
+    }
+
+    fn fee_rate(&self, target_blocks: u16) -> Result<f64, Self::Error> {
+        #[cfg(feature = "mempool")]
+        if self.kind == ClientKind::Mempool {
+            return Ok(self.recommended_fees()?.for_target(target_blocks));
+        }
+        // Esplora returns estimates for all targets at once, so they are requested only once
+        let mut cache = self.fee_estimates.lock().expect("poisoned fee estimates lock");
+        let estimates = match &*cache {
+            Some(estimates) => estimates.clone(),
+            None => cache.insert(self.inner.fee_estimates()?.into_iter().collect()).clone(),
+        };
+        closest_fee_rate(estimates, target_blocks).ok_or(Error::InvalidServerData)
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncIndexer for AsyncClient {
+    type Error = Error;
+
+    async fn network(&self) -> Result<Network, Self::Error> {
+        let genesis = self.inner.block_hash(0).await?;
+        Network::try_from(genesis).map_err(|_| Error::InvalidServerData)
+    }
+
+    async fn create<K, D: Descriptor<K>, L2: Layer2>(
+        &self,
+        descriptor: &WalletDescr<K, D, L2::Descr>,
+    ) -> MayError<WalletCache<L2::Cache>, Vec<SyncError<Self::Error>>> {
+        let mut cache = WalletCache::new_nonsync();
+        self.update::<K, D, L2>(descriptor, &mut cache).await.map(|_| cache)
+    }
+
+    async fn update_with_progress<K, D: Descriptor<K>, L2: Layer2>(
+        &self,
+        descriptor: &WalletDescr<K, D, L2::Descr>,
+        cache: &mut WalletCache<L2::Cache>,
+        progress: &mut dyn SyncProgress,
+    ) -> MayError<usize, Vec<SyncError<Self::Error>>> {
+        let mut errors = vec![];
+
+        #[cfg(feature = "log")]
+        log::debug!("Asynchronously updating wallet from Esplora indexer");
+
+        let mut sync_hashes = BTreeMap::new();
+        for info in cache.last_sync.values() {
+            let height = info.height.get();
+            let res = retry_async(self.retry, is_transient, || self.inner.block_hash(height)).await;
+            if let Ok(hash) = res {
+                sync_hashes.insert(height, hash);
+            }
+        }
+        verify_sync_points(cache, &mut errors, |height| {
+            sync_hashes.get(&height).copied().ok_or(())
+        });
+        let synced = synced_txids(cache);
+
+        let mut checked = 0usize;
+        let mut failed_keychains = BTreeSet::new();
+        let mut address_index = AddressIndex::new();
+        for keychain in descriptor.keychains() {
+            let sync_height = cache.last_sync.get(&keychain).map(|info| info.height);
+            let depth = cache.scan_depth.get(&keychain).copied().unwrap_or_default();
+            let mut gap = GapCounter::new(descriptor.gap_limit(keychain), depth);
+            let mut addresses = descriptor.addresses(keychain).enumerate();
+            'keychain: while !failed_keychains.contains(&keychain) {
+                let batch = addresses.by_ref().take(self.parallelism).collect::<Vec<_>>();
+                if batch.is_empty() {
+                    break;
+                }
+
+                #[cfg(feature = "log")]
+                log::trace!("Retrieving transactions for {} addresses", batch.len());
+
+                let results = join_all(batch.iter().map(|(_, derive)| {
+                    get_scripthash_txs_all_async(self, derive, sync_height, cache.birth_time)
+                }))
+                .await;
+                for ((index, derive), res) in batch.into_iter().zip(results) {
+                    progress.on_address(index as u32, gap.estimate(index));
+
+                    let script = derive.addr.script_pubkey();
+
+                    let mut txids = Vec::new();
+                    match res {
+                        Err(err) => {
+                            errors.push(SyncError::Address {
+                                addr: derive,
+                                error: err,
+                            });
+                            failed_keychains.insert(keychain);
+                            continue;
+                        }
+                        Ok(txes) if txes.is_empty() => {
+                            checked += 1;
+                            if gap.register(false) {
+                                break 'keychain;
+                            }
+                        }
+                        Ok(txes) => {
+                            checked += 1;
+                            gap.register(true);
+                            txids = txes.iter().map(|tx| tx.txid).collect();
+                            let status = script_status(txes.iter().map(|tx| {
+                                (tx.txid, tx.status.block_height.unwrap_or_default() as i64)
+                            }));
+                            // Scripts with unchanged status have all their transactions known
+                            let unchanged = cache.script_status.get(&script) == Some(&status)
+                                && txids.iter().all(|txid| cache.tx.contains_key(txid));
+                            if !unchanged {
+                                for tx in txes.into_iter().map(WalletTx::from) {
+                                    progress.on_tx(tx.txid, tx.status.height());
+                                    cache.tx.insert(tx.txid, tx);
+                                }
+                                cache.script_status.insert(script.clone(), status);
+                            }
+                        }
+                    }
+                    // Transactions mined before the sync point may be not returned by the indexer
+                    for txid in synced.get(&script).into_iter().flatten() {
+                        if !txids.contains(txid) {
+                            txids.push(*txid);
+                        }
+                    }
+
+                    #[cfg(feature = "log")]
+                    log::debug!("{derive}: {} transaction(s)", txids.len());
+
+                    let wallet_addr = WalletAddr::<i64>::from(derive);
+                    address_index.insert(script, (wallet_addr, txids));
+                }
+            }
+        }
+
+        update_sync_points(cache, &descriptor.keychains(), &address_index, &failed_keychains);
+        match retry_async(self.retry, is_transient, || AsyncIndexer::tip(self)).await {
+            Ok(tip) => cache.last_block = tip,
+            Err(err) => errors.push(SyncError::Unreachable(err)),
+        }
+        complete_update::<K, D, L2>(descriptor, cache, address_index, checked, errors)
+    }
+
+    async fn broadcast(&self, tx: &Tx) -> Result<(), Self::Error> { self.inner.broadcast(tx).await }
+
+    async fn status(&self, txid: Txid) -> Result<TxStatus, Self::Error> {
+        match self.inner.tx(&txid).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return Ok(TxStatus::Unknown),
+            Err(err) => return Err(err),
+        };
+
+        let status = match self.inner.tx_status(&txid).await {
+            Ok(status) => status,
+            Err(_) => return Err(Error::InvalidServerData),
+        };
+
+        if let (Some(height), Some(time), Some(block_hash)) =
+            (status.block_height, status.block_time, status.block_hash)
+        {
+            let height = BlockHeight::try_from(height).map_err(|_| Error::InvalidServerData)?;
+            return Ok(TxStatus::Mined(MiningInfo {
+                height,
+                time,
+                block_hash,
+            }));
+        }
+
+        Ok(TxStatus::Mempool)
+    }
+
+    async fn transaction(&self, txid: Txid) -> Result<Option<Tx>, Self::Error> {
+        self.inner.tx(&txid).await
+    }
+
+    async fn block_hash(&self, height: u32) -> Result<BlockHash, Self::Error> {
+        self.inner.block_hash(height).await
+    }
+
+    async fn tip(&self) -> Result<MiningInfo, Self::Error> {
+        let height = self.inner.height().await?;
+        let block_hash = self.inner.block_hash(height).await?;
+        let header = self.inner.header_by_hash(&block_hash).await?;
+        Ok(MiningInfo {
+            height: BlockHeight::try_from(height).unwrap_or(BlockHeight::MIN),
+            time: header.time as u64,
+            block_hash,
+        })
+    }
+
+    async fn fee_rate(&self, target_blocks: u16) -> Result<f64, Self::Error> {
+        let estimates = self.inner.fee_estimates().await?;
+        closest_fee_rate(estimates, target_blocks).ok_or(Error::InvalidServerData)
+    }
 }
@@ -33,11 +33,61 @@ impl super::esplora::Client {
     /// error occurred.
     #[allow(clippy::result_large_err)]
     pub fn new_mempool(url: &str) -> Result<Self, esplora::Error> {
-        let inner = esplora::Builder::new(url).build_blocking()?;
-        let client = Self {
-            inner,
-            kind: super::esplora::ClientKind::Mempool,
-        };
-        Ok(client)
+        Self::with_builder(esplora::Builder::new(url), super::esplora::ClientKind::Mempool)
+    }
+}
+
+/// Fee rates recommended by a mempool server, in satoshis per virtual byte.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(crate = "serde_crate", rename_all = "camelCase")]
+pub struct RecommendedFees {
+    pub fastest_fee: f64,
+    pub half_hour_fee: f64,
+    pub hour_fee: f64,
+    pub economy_fee: f64,
+    pub minimum_fee: f64,
+}
+
+impl RecommendedFees {
+    /// Selects the recommended fee rate which fits the confirmation target, measured in blocks.
+    pub fn for_target(&self, target_blocks: u16) -> f64 {
+        match target_blocks {
+            0..=1 => self.fastest_fee,
+            2..=3 => self.half_hour_fee,
+            4..=6 => self.hour_fee,
+            _ => self.economy_fee,
+        }
+    }
+}
+
+impl super::esplora::Client {
+    /// Retrieves fee rates recommended by the mempool server using its
+    /// `/v1/fees/recommended` endpoint, which is not a part of the Esplora API.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server is not reachable or doesn't support the endpoint.
+    #[allow(clippy::result_large_err)]
+    pub fn recommended_fees(&self) -> Result<RecommendedFees, esplora::Error> {
+        let url = format!("{}/v1/fees/recommended", self.inner.url().trim_end_matches('/'));
+        let mut request = minreq::get(&url);
+        if let Some(proxy) = &self.proxy {
+            let proxy = minreq::Proxy::new(proxy).map_err(esplora::Error::Minreq)?;
+            request = request.with_proxy(proxy);
+        }
+        #[cfg(feature = "log")]
+        log::trace!("Request to {}", super::redact_url(&url));
+        let resp = request.send().map_err(esplora::Error::Minreq)?;
+        let body = resp.as_str().map_err(esplora::Error::Minreq)?;
+        #[cfg(feature = "log")]
+        log::trace!("Response {}: {body}", resp.status_code);
+        if resp.status_code != 200 {
+            return Err(esplora::Error::HttpResponse {
+                status: resp.status_code as u16,
+                message: body.to_owned(),
+            });
+        }
+        serde_json::from_str(body).map_err(|_| esplora::Error::InvalidServerData)
     }
 }
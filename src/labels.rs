@@ -0,0 +1,333 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet labels and their import and export in BIP-329 format.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bpstd::{Address, Outpoint, Txid};
+
+use crate::{Inpoint, Layer2Data, WalletData};
+
+/// Type of a wallet item a BIP-329 label is attached to.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(crate = "serde_crate", rename_all = "lowercase")
+)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Display)]
+#[display(lowercase)]
+pub enum LabelType {
+    Tx,
+    Addr,
+    Pubkey,
+    Input,
+    Output,
+    Xpub,
+}
+
+/// Label record in BIP-329 format.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Label {
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub ty: LabelType,
+    /// Reference to the labelled item: transaction id, address, `txid:vin` of the spending
+    /// transaction for inputs, or outpoint for outputs.
+    #[cfg_attr(feature = "serde", serde(rename = "ref"))]
+    pub reference: String,
+    pub label: String,
+    /// Abbreviated descriptor of the wallet the item belongs to; it is not kept by the wallet
+    /// when the labels are imported.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub origin: Option<String>,
+}
+
+impl Label {
+    pub fn new(ty: LabelType, reference: impl ToString, label: impl ToString) -> Self {
+        Label {
+            ty,
+            reference: reference.to_string(),
+            label: label.to_string(),
+            origin: None,
+        }
+    }
+
+    /// Parses the label reference into the wallet item, returning `None` for the items which
+    /// can't be labelled in the wallet or invalid references.
+    pub fn item(&self) -> Option<LabelRef> {
+        let reference = self.reference.as_str();
+        match self.ty {
+            LabelType::Tx => Txid::from_str(reference).ok().map(LabelRef::Tx),
+            LabelType::Addr => Address::from_str(reference).ok().map(LabelRef::Addr),
+            LabelType::Input => {
+                let (txid, vin) = reference.split_once(':')?;
+                let inpoint = Inpoint::new(Txid::from_str(txid).ok()?, vin.parse().ok()?);
+                Some(LabelRef::Input(inpoint))
+            }
+            LabelType::Output => Outpoint::from_str(reference).ok().map(LabelRef::Output),
+            LabelType::Pubkey | LabelType::Xpub => None,
+        }
+    }
+}
+
+/// Wallet item which can be labelled; displays as the BIP-329 reference of the item.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum LabelRef {
+    Tx(Txid),
+    Addr(Address),
+    /// Transaction input, referenced by the spending transaction and the input number.
+    Input(Inpoint),
+    Output(Outpoint),
+}
+
+impl Display for LabelRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelRef::Tx(txid) => Display::fmt(txid, f),
+            LabelRef::Addr(addr) => Display::fmt(addr, f),
+            LabelRef::Input(inpoint) => write!(f, "{}:{}", inpoint.txid, inpoint.vin),
+            LabelRef::Output(outpoint) => Display::fmt(outpoint, f),
+        }
+    }
+}
+
+impl LabelRef {
+    pub fn label_type(&self) -> LabelType {
+        match self {
+            LabelRef::Tx(_) => LabelType::Tx,
+            LabelRef::Addr(_) => LabelType::Addr,
+            LabelRef::Input(_) => LabelType::Input,
+            LabelRef::Output(_) => LabelType::Output,
+        }
+    }
+
+    /// Constructs BIP-329 record with the correct type and reference for the item.
+    pub fn to_label(&self, label: impl ToString) -> Label {
+        Label::new(self.label_type(), self, label)
+    }
+}
+
+/// Label which was not imported since the wallet already has a different label for the same
+/// item.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LabelConflict {
+    pub label: Label,
+    /// Label the wallet has for the item.
+    pub existing: String,
+}
+
+/// Report on the labels import.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct LabelImport {
+    /// Number of labels added to the wallet or replacing the existing ones.
+    pub added: usize,
+    /// Number of labels which were already known to the wallet.
+    pub unchanged: usize,
+    /// Labels for the items which can't be labelled in the wallet, or having invalid references.
+    pub skipped: Vec<Label>,
+    /// Labels which conflict with the existing ones and were not imported.
+    pub conflicts: Vec<LabelConflict>,
+}
+
+/// Outcome of merging a single label into a map of existing labels.
+enum Merge {
+    Added,
+    Unchanged,
+    Conflict(String),
+}
+
+fn merge<K: Ord>(labels: &mut BTreeMap<K, String>, key: K, label: &str, overwrite: bool) -> Merge {
+    match labels.get(&key) {
+        Some(existing) if existing == label => Merge::Unchanged,
+        Some(existing) if !overwrite => Merge::Conflict(existing.clone()),
+        _ => {
+            labels.insert(key, label.to_owned());
+            Merge::Added
+        }
+    }
+}
+
+impl<L2: Layer2Data> WalletData<L2> {
+    /// Returns label attached to the wallet item.
+    pub fn label(&self, item: &LabelRef) -> Option<&str> {
+        match item {
+            LabelRef::Tx(txid) => self.tx_annotations.get(txid),
+            LabelRef::Addr(addr) => self.addr_annotations.get(addr),
+            LabelRef::Input(inpoint) => self.txin_annotations.get(inpoint),
+            LabelRef::Output(outpoint) => self.txout_annotations.get(outpoint),
+        }
+        .map(String::as_str)
+    }
+
+    /// Attaches label to the wallet item, or removes the label if `None` is given. Returns the
+    /// previous label of the item.
+    pub fn set_label(&mut self, item: LabelRef, label: Option<String>) -> Option<String> {
+        fn set<K: Ord>(
+            labels: &mut BTreeMap<K, String>,
+            key: K,
+            label: Option<String>,
+        ) -> Option<String> {
+            match label {
+                Some(label) => labels.insert(key, label),
+                None => labels.remove(&key),
+            }
+        }
+        match item {
+            LabelRef::Tx(txid) => set(&mut self.tx_annotations, txid, label),
+            LabelRef::Addr(addr) => set(&mut self.addr_annotations, addr, label),
+            LabelRef::Input(inpoint) => set(&mut self.txin_annotations, inpoint, label),
+            LabelRef::Output(outpoint) => set(&mut self.txout_annotations, outpoint, label),
+        }
+    }
+
+    /// Lists all wallet labels as BIP-329 records, ordered by their type and reference.
+    pub fn labels(&self) -> Vec<Label> {
+        let tx =
+            self.tx_annotations.iter().map(|(txid, label)| LabelRef::Tx(*txid).to_label(label));
+        let addr =
+            self.addr_annotations.iter().map(|(addr, label)| LabelRef::Addr(*addr).to_label(label));
+        let input = self
+            .txin_annotations
+            .iter()
+            .map(|(inpoint, label)| LabelRef::Input(*inpoint).to_label(label));
+        let output = self
+            .txout_annotations
+            .iter()
+            .map(|(outpoint, label)| LabelRef::Output(*outpoint).to_label(label));
+        tx.chain(addr).chain(input).chain(output).collect()
+    }
+
+    /// Merges labels with the existing wallet labels.
+    ///
+    /// Labels which differ from the ones already present in the wallet for the same item are
+    /// reported as conflicts and are not imported, unless `overwrite` is set. Labels for public
+    /// keys and extended public keys are not supported by the wallet and are skipped.
+    pub fn import_labels(
+        &mut self,
+        labels: impl IntoIterator<Item = Label>,
+        overwrite: bool,
+    ) -> LabelImport {
+        let mut report = LabelImport::default();
+        for label in labels {
+            let text = label.label.as_str();
+            let merged = label.item().map(|item| match item {
+                LabelRef::Tx(txid) => merge(&mut self.tx_annotations, txid, text, overwrite),
+                LabelRef::Addr(addr) => merge(&mut self.addr_annotations, addr, text, overwrite),
+                LabelRef::Input(inpoint) => {
+                    merge(&mut self.txin_annotations, inpoint, text, overwrite)
+                }
+                LabelRef::Output(outpoint) => {
+                    merge(&mut self.txout_annotations, outpoint, text, overwrite)
+                }
+            });
+            match merged {
+                None => report.skipped.push(label),
+                Some(Merge::Added) => report.added += 1,
+                Some(Merge::Unchanged) => report.unchanged += 1,
+                Some(Merge::Conflict(existing)) => {
+                    report.conflicts.push(LabelConflict { label, existing })
+                }
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd";
+
+    #[test]
+    fn test_import_conflicts() {
+        let mut data = WalletData::new_layer1();
+        let report = data.import_labels(
+            [
+                Label::new(LabelType::Tx, TXID, "rent"),
+                Label::new(LabelType::Output, format!("{TXID}:1"), "change"),
+                Label::new(LabelType::Xpub, "xpub", "main"),
+                Label::new(LabelType::Tx, "invalid", "nothing"),
+            ],
+            false,
+        );
+        assert_eq!(report.added, 2);
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(data.labels().len(), 2);
+
+        let report = data.import_labels(
+            [Label::new(LabelType::Tx, TXID, "rent"), Label::new(LabelType::Tx, TXID, "food")],
+            false,
+        );
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(report.conflicts, vec![LabelConflict {
+            label: Label::new(LabelType::Tx, TXID, "food"),
+            existing: "rent".to_owned(),
+        }]);
+        assert_eq!(data.tx_annotations.values().next().unwrap(), "rent");
+
+        let report = data.import_labels([Label::new(LabelType::Tx, TXID, "food")], true);
+        assert_eq!(report.added, 1);
+        assert_eq!(data.tx_annotations.values().next().unwrap(), "food");
+    }
+
+    #[test]
+    fn test_set_label() {
+        let mut data = WalletData::new_layer1();
+        let output = LabelRef::Output(Outpoint::from_str(&format!("{TXID}:1")).unwrap());
+        let input = LabelRef::Input(Inpoint::new(Txid::from_str(TXID).unwrap(), 1));
+        assert_eq!(data.set_label(output, Some(s!("change"))), None);
+        assert_eq!(data.label(&output), Some("change"));
+        assert_eq!(data.label(&input), None);
+
+        let labels = data.labels();
+        assert_eq!(labels, vec![Label::new(LabelType::Output, format!("{TXID}:1"), "change")]);
+        assert_eq!(labels[0].item(), Some(output));
+
+        assert_eq!(data.set_label(output, None), Some(s!("change")));
+        assert!(data.labels().is_empty());
+    }
+
+    #[test]
+    fn test_input_reference() {
+        let mut data = WalletData::new_layer1();
+        let label = Label::new(LabelType::Input, format!("{TXID}:3"), "paid rent");
+        let input = LabelRef::Input(Inpoint::new(Txid::from_str(TXID).unwrap(), 3));
+        assert_eq!(label.item(), Some(input));
+        assert_eq!(input.to_label("paid rent"), label);
+        assert_eq!(Label::new(LabelType::Input, format!("{TXID}.3"), "rent").item(), None);
+
+        assert_eq!(data.import_labels([label.clone()], false).added, 1);
+        assert_eq!(
+            data.txin_annotations.keys().collect::<Vec<_>>(),
+            vec![&Inpoint::new(Txid::from_str(TXID).unwrap(), 3)]
+        );
+        assert_eq!(data.labels(), vec![label]);
+    }
+}
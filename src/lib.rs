@@ -37,31 +37,68 @@ mod data;
 mod rows;
 mod wallet;
 mod layer2;
+pub mod batch;
+pub mod checksum;
 pub mod coinselect;
+pub mod export;
+pub mod fees;
+pub mod finalize;
+pub mod labels;
 #[cfg(feature = "cli")]
 pub mod cli;
 #[cfg(feature = "signers")]
 pub mod hot;
 mod bip43;
+pub mod bip21;
+#[cfg(feature = "base64")]
+pub mod bip322;
+#[cfg(feature = "serde_json")]
+pub mod slip132;
+#[cfg(feature = "hwi")]
+pub mod hwi;
 #[cfg(feature = "fs")]
 pub mod fs;
 
+pub use batch::{check_duplicate_outputs, Batch, BatchError, DuplicatePolicy};
+pub use bip21::{Invoice, InvoiceError};
+#[cfg(all(feature = "base64", feature = "signers"))]
+pub use bip322::sign_message;
+#[cfg(feature = "base64")]
+pub use bip322::{message_hash, verify_message, Bip322Error};
 pub use bip43::{Bip43, DerivationStandard, ParseBip43Error};
 pub use bpstd::*;
 pub use data::{
-    BlockHeight, BlockInfo, MiningInfo, Party, TxCredit, TxDebit, TxStatus, WalletAddr, WalletTx,
-    WalletUtxo,
+    AddrInfo, AddrUsage, Balance, BlockHeight, BlockInfo, Inpoint, InpointParseError, MiningInfo,
+    Party, TxCredit, TxDebit, TxStatus, UtxoInfo, WalletAddr, WalletTx, WalletUtxo,
+    COINBASE_MATURITY,
 };
+#[cfg(feature = "serde_json")]
+pub use export::ElectrumImportError;
+pub use export::{CoreDescriptor, CoreImport, CoreImportError, CoreTimestamp, HistoryFilter};
+#[cfg(feature = "signers")]
+pub use hot::{sign_psbt, MnemonicError, MnemonicSeed, Seed, SeedType, SignReport, SoftSignError};
 #[cfg(feature = "hot")]
 pub use hot::{HotArgs, HotCommand};
-#[cfg(feature = "signers")]
-pub use hot::{Seed, SeedType};
-pub use indexers::Indexer;
-#[cfg(any(feature = "electrum", feature = "esplora", feature = "mempool"))]
+#[cfg(feature = "async")]
+pub use indexers::AsyncIndexer;
+#[cfg(any(
+    feature = "bitcoind",
+    feature = "electrum",
+    feature = "esplora",
+    feature = "mempool"
+))]
 pub use indexers::{AnyIndexer, AnyIndexerError};
+pub use indexers::{
+    BroadcastError, Indexer, RetryPolicy, SyncError, SyncProgress, SyncReport, TipCheck,
+    UnsupportedRequest,
+};
 pub use layer2::{
     Layer2, Layer2Cache, Layer2Coin, Layer2Data, Layer2Descriptor, Layer2Empty, Layer2Tx, NoLayer2,
 };
 pub use rows::{CoinRow, Counterparty, OpType, TxRow};
 pub use util::MayError;
-pub use wallet::{Wallet, WalletCache, WalletCacheProvider, WalletData, WalletDescr};
+pub use wallet::{
+    BuildTxError, BuiltTx, BumpFeeError, ChangePolicy, CpfpError, CpfpTx, NonWalletItem, OpReturn,
+    OpReturnError, SpendParams, SweepFilter, TxEstimate, TxOrder, Wallet, WalletCache,
+    WalletCacheProvider, WalletData, WalletDescr, DEFAULT_GAP_LIMIT, MAX_OP_RETURN_SIZE,
+};
@@ -39,6 +39,10 @@ pub enum OpType {
     Credit,
     #[display("-")]
     Debit,
+    /// Transaction spending only wallet outputs and paying only to the wallet addresses, like a
+    /// consolidation of the wallet UTXOs.
+    #[display("=")]
+    Transfer,
 }
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug, From)]
@@ -107,7 +111,8 @@ impl FromStr for Counterparty {
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct TxRow<L2: Layer2Tx = Layer2Empty> {
     pub height: TxStatus<BlockHeight>,
-    // TODO: Add date/time
+    /// Timestamp of the block mining the transaction; `None` for unconfirmed transactions.
+    pub time: Option<u64>,
     pub operation: OpType,
     pub our_inputs: Vec<u32>,
     pub counterparties: Vec<(Counterparty, i64)>,
@@ -119,9 +124,27 @@ pub struct TxRow<L2: Layer2Tx = Layer2Empty> {
     pub total: Sats,
     pub amount: Sats,
     pub balance: Sats,
+    /// Label attached to the transaction.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub label: Option<String>,
     pub layer2: L2,
 }
 
+impl<L2: Layer2Tx> TxRow<L2> {
+    /// Change of the wallet balance made by the transaction: the sum of the transaction outputs
+    /// owned by the wallet less the sum of the wallet outputs it spends.
+    pub fn net_amount(&self) -> i64 { self.own.iter().map(|(_, value)| value).sum() }
+
+    /// Fee paid by the wallet, if the transaction spends wallet outputs.
+    pub fn our_fee(&self) -> Option<Sats> {
+        if self.our_inputs.is_empty() {
+            None
+        } else {
+            Some(self.fee)
+        }
+    }
+}
+
 #[cfg_attr(
     feature = "serde",
     derive(serde::Serialize, serde::Deserialize),
@@ -141,6 +164,9 @@ pub struct CoinRow<L2: Layer2Coin> {
     pub address: DerivedAddr,
     pub outpoint: Outpoint,
     pub amount: Sats,
+    /// Label attached to the output or, if there is none, to the address of the output.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub label: Option<String>,
     pub layer2: Vec<L2>,
 }
 
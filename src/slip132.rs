@@ -0,0 +1,151 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SLIP-132 extended public keys (`ypub`, `zpub`, `Zpub` etc.), which encode the script type of
+//! the wallet in the key version prefix.
+
+use bpstd::base58;
+
+/// Length of the serialized extended key, without the base58check checksum.
+const XKEY_LEN: usize = 78;
+
+const VERSION_XPUB: [u8; 4] = [0x04, 0x88, 0xB2, 0x1E];
+const VERSION_TPUB: [u8; 4] = [0x04, 0x35, 0x87, 0xCF];
+
+/// Errors converting SLIP-132 extended public keys.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Slip132Error {
+    /// invalid base58 encoding of the extended key - {0}.
+    #[from]
+    Base58(base58::Error),
+
+    /// extended key has invalid length {0}.
+    InvalidLength(usize),
+
+    /// extended key has unknown version {0:#010x}; only public key versions are supported.
+    UnknownVersion(u32),
+}
+
+/// Script type of the wallet, as defined by the SLIP-132 extended public key version.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+pub enum KeyApplication {
+    /// P2PKH and P2SH multisig (`xpub`, `tpub`).
+    #[display("legacy")]
+    Legacy,
+
+    /// P2WPKH nested in P2SH (`ypub`, `upub`).
+    #[display("nested-wpkh")]
+    NestedWpkh,
+
+    /// P2WPKH (`zpub`, `vpub`).
+    #[display("wpkh")]
+    Wpkh,
+
+    /// P2WSH multisig nested in P2SH (`Ypub`, `Upub`).
+    #[display("nested-wsh")]
+    NestedWsh,
+
+    /// P2WSH multisig (`Zpub`, `Vpub`).
+    #[display("wsh")]
+    Wsh,
+}
+
+/// Extended public key with SLIP-132 version converted into the standard `xpub` or `tpub` one.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Slip132Key {
+    /// Standard base58 representation of the key.
+    pub xpub: String,
+    pub application: KeyApplication,
+    pub testnet: bool,
+}
+
+impl Slip132Key {
+    /// Parses extended public key with any of the SLIP-132 public key versions.
+    pub fn from_base58(s: &str) -> Result<Self, Slip132Error> {
+        let data = base58::decode_check(s)?;
+        if data.len() != XKEY_LEN {
+            return Err(Slip132Error::InvalidLength(data.len()));
+        }
+        let version = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let (application, testnet) = match version {
+            0x0488_B21E => (KeyApplication::Legacy, false),
+            0x049D_7CB2 => (KeyApplication::NestedWpkh, false),
+            0x04B2_4746 => (KeyApplication::Wpkh, false),
+            0x0295_B43F => (KeyApplication::NestedWsh, false),
+            0x02AA_7ED3 => (KeyApplication::Wsh, false),
+            0x0435_87CF => (KeyApplication::Legacy, true),
+            0x044A_5262 => (KeyApplication::NestedWpkh, true),
+            0x045F_1CF6 => (KeyApplication::Wpkh, true),
+            0x0242_89EF => (KeyApplication::NestedWsh, true),
+            0x0257_5483 => (KeyApplication::Wsh, true),
+            _ => return Err(Slip132Error::UnknownVersion(version)),
+        };
+        let mut xkey = data;
+        xkey[..4].copy_from_slice(if testnet { &VERSION_TPUB } else { &VERSION_XPUB });
+        Ok(Slip132Key {
+            xpub: base58::encode_check(&xkey),
+            application,
+            testnet,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XPUB: &str = "xpub6DJ2dNUysrn5Vt36jH2KLBT2i1auw1tTSSomg8PhqNiUtx8QX2SvC9nrHu81fT41fvDUnhMjEzQgXnQjKEu3oaqMSzhSrHMxyyoEAmUHQbY";
+
+    #[test]
+    fn test_slip132() {
+        let key = Slip132Key::from_base58(XPUB).unwrap();
+        assert_eq!(key.xpub, XPUB);
+        assert_eq!(key.application, KeyApplication::Legacy);
+
+        let key = Slip132Key::from_base58("zpub6rxZEhppBDs3CURLPzbZkMe33wsopFsTGfrDEvBUbPUF19ks2Ln3SH78LK3BfGMrVCT6HeYrAK7nJMdrkdj5Q4CZBg6J26zwXRvWwtzrWqR").unwrap();
+        assert_eq!(key.xpub, XPUB);
+        assert_eq!(key.application, KeyApplication::Wpkh);
+        assert!(!key.testnet);
+
+        let key = Slip132Key::from_base58("Zpub73reMwZFkBRQd3aiLf4YaRyqmjv52cZ3awVtABT1yAJedLKmnkAKXPy491zfDhakifX5AEYy3XWHRXFd3rt2YYKE29ChRWTwRACPXnLSWEE").unwrap();
+        assert_eq!(key.xpub, XPUB);
+        assert_eq!(key.application, KeyApplication::Wsh);
+
+        let key = Slip132Key::from_base58("vpub5ZdW2399aVh7oHes4ZT4v1G2N5J23muTcDmL7Lbw5MxinkVx1i7nx2UaFVCqfdkArdysHkAcKfhamDBbsr52D7U9iKJbgTizSXfwPcvWznn").unwrap();
+        assert_eq!(
+            key.xpub,
+            "tpubDDff9cJfb8jXmgDxBU1QY6nQ38gZvQPWz5Q52gSrBHUNeEo7bFJ1LbTgXwCoBx1FTpkPQGsbn6EjDxuLi6kHh2HeWbSkXp2CZwPdvhpER9t"
+        );
+        assert_eq!(key.application, KeyApplication::Wpkh);
+        assert!(key.testnet);
+
+        assert!(matches!(
+            Slip132Key::from_base58(&XPUB.replace('Q', "R")),
+            Err(Slip132Error::Base58(base58::Error::BadChecksum(..)))
+        ));
+        assert_eq!(
+            Slip132Key::from_base58("xpub0"),
+            Err(Slip132Error::Base58(base58::Error::BadByte(b'0')))
+        );
+    }
+}
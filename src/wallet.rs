@@ -22,25 +22,47 @@
 
 use std::borrow::Cow;
 use std::cmp;
-use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Display;
 use std::marker::PhantomData;
-use std::ops::{AddAssign, Deref};
+use std::ops::{AddAssign, Deref, Range};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::RwLock;
 
+use amplify::hex::FromHex;
 use bpstd::{
-    Address, AddressNetwork, DerivedAddr, Descriptor, Idx, IdxBase, Keychain, Network, NormalIndex,
-    Outpoint, Sats, ScriptPubkey, Txid, Vout,
+    Address, AddressNetwork, ConsensusEncode, DerivedAddr, DerivedScript, Descriptor, Idx, IdxBase,
+    Keychain, LockTime, Network, NormalIndex, Outpoint, Sats, ScriptPubkey, Terminal, Tx, Txid,
+    Vout, Weight,
 };
 use nonasync::persistence::{
     CloneNoPersistence, Persistence, PersistenceError, PersistenceProvider, Persisting,
 };
-use psbt::{Psbt, PsbtConstructor, PsbtMeta, Utxo};
+use psbt::{
+    Beneficiary, ConstructionError, Payment, Psbt, PsbtConstructor, PsbtMeta, PsbtVer, TxParams,
+    Utxo,
+};
 
+use crate::batch::{Batch, BatchError};
+use crate::coinselect::{self, CoinSelect, DustPolicy, SelectionParams, Strategy};
+use crate::data::Inpoint;
+use crate::fees::FeePolicy;
+use crate::indexers::{BroadcastError, SyncError, SyncReport, TipCheck};
+use crate::labels::{Label, LabelImport, LabelRef};
+#[cfg(feature = "async")]
+use crate::AsyncIndexer;
 use crate::{
-    BlockInfo, CoinRow, Counterparty, Indexer, Layer2, Layer2Cache, Layer2Data, Layer2Descriptor,
-    Layer2Empty, MayError, MiningInfo, NoLayer2, OpType, Party, TxCredit, TxDebit, TxRow, TxStatus,
-    WalletAddr, WalletTx, WalletUtxo,
+    fees, AddrInfo, AddrUsage, Balance, BlockHeight, BlockInfo, CoinRow, Counterparty, Indexer,
+    Layer2, Layer2Cache, Layer2Data, Layer2Descriptor, Layer2Empty, MayError, MiningInfo, NoLayer2,
+    OpType, Party, SyncProgress, TxCredit, TxDebit, TxRow, TxStatus, UtxoInfo, WalletAddr,
+    WalletTx, WalletUtxo,
 };
 
+/// Default number of consecutive unused addresses after which the address discovery in a keychain
+/// stops.
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
 #[display(doc_comments)]
 pub enum NonWalletItem {
@@ -54,11 +76,357 @@ pub enum NonWalletItem {
     Spent(Outpoint),
 }
 
+/// Errors constructing a fee-bumping replacement for a wallet transaction.
+#[derive(Clone, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum BumpFeeError {
+    /// transaction {0} is not known to the wallet.
+    UnknownTx(Txid),
+
+    /// transaction {0} is already mined and can't be replaced.
+    Mined(Txid),
+
+    /// transaction {0} doesn't signal replaceability (BIP-125).
+    NotReplaceable(Txid),
+
+    /// transaction input spending {0} doesn't belong to the wallet, so the replacement can't be
+    /// signed.
+    ForeignInput(Outpoint),
+
+    /// transaction output #{0} has a non-standard script which can't be paid to by the
+    /// replacement.
+    NonStandardOutput(u32),
+
+    /// transaction output #{0} is recorded as a block subsidy, which indicates inconsistent wallet
+    /// cache.
+    SubsidyOutput(u32),
+
+    /// replacement fee {0} is too low; it must be at least {1} to exceed the fees of the original
+    /// transaction and its unconfirmed descendants by the minimal relay fee.
+    FeeTooLow(Sats, Sats),
+
+    /// insufficient funds to pay the increased fee: the wallet has {0} available, while {1} is
+    /// required.
+    InsufficientFunds(Sats, Sats),
+
+    /// reduced change of {0} doesn't exceed the dust limit of {1} and can't be absorbed into the
+    /// fee without making it absurdly high.
+    DustChange(Sats, Sats),
+
+    #[from]
+    #[display(inner)]
+    Construction(ConstructionError),
+}
+
+/// Errors constructing a child transaction accelerating its parent with [`Wallet::cpfp`].
+#[derive(Clone, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum CpfpError {
+    /// transaction {0} is not known to the wallet.
+    UnknownTx(Txid),
+
+    /// transaction {0} is already mined and doesn't need acceleration.
+    Mined(Txid),
+
+    /// transaction {0} has no unspent outputs belonging to the wallet.
+    NoWalletOutput(Txid),
+
+    /// wallet output of {0} can't pay the child transaction fee of {1} leaving a non-dust change.
+    InsufficientValue(Sats, Sats),
+
+    #[from]
+    #[display(inner)]
+    Construction(ConstructionError),
+}
+
+/// Child transaction constructed by [`Wallet::cpfp`].
+#[derive(Clone, Debug)]
+pub struct CpfpTx {
+    pub psbt: Psbt,
+    pub meta: PsbtMeta,
+    /// Virtual size of the parent and the child transactions together.
+    pub package_vsize: u32,
+    /// Fee rate of the parent and the child transactions together, in satoshis per virtual byte.
+    pub package_fee_rate: f64,
+}
+
+/// Errors constructing a transaction with [`Wallet::build_tx`].
+#[derive(Clone, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum BuildTxError {
+    /// insufficient funds: the wallet has {0} available, while {1} is required.
+    InsufficientFunds(Sats, Sats),
+
+    /// coin selection produced change of {0}, which doesn't exceed the minimal change amount of
+    /// {1}.
+    DustChange(Sats, Sats),
+
+    /// transaction fee rate of {0} ṩ/vbyte exceeds the maximal fee rate of {1} ṩ/vbyte.
+    AbsurdFeeRate(f64, f64),
+
+    /// transaction fee rate of {0} ṩ/vbyte is below the minimal relay fee rate of {1} ṩ/vbyte,
+    /// so the transaction won't propagate through the network.
+    FeeRateTooLow(f64, f64),
+
+    /// no spendable wallet outputs match the sweep criteria.
+    NothingToSweep,
+
+    /// the amount of {0} remaining for the output after the fee is below its dust limit of {1}.
+    DustOutput(Sats, Sats),
+
+    /// change address with derivation index {0} is already used or reserved.
+    ChangeIndexUsed(NormalIndex),
+
+    /// change address with derivation index {0} is beyond the gap limit of {2} addresses after
+    /// the next unused index {1}, so the change won't be found by the wallet sync.
+    ChangeIndexBeyondGap(NormalIndex, NormalIndex, u32),
+
+    /// the fee of {1} exceeds the total amount of {0} being swept.
+    SweepFeeTooHigh(Sats, Sats),
+
+    /// only {0} wallet outputs match the consolidation criteria, while at least {1} are required.
+    NothingToConsolidate(usize, usize),
+
+    /// consolidation is not worth it: the fee of {1} is not less than the total value of {0} of
+    /// the consolidated outputs.
+    ConsolidationTooExpensive(Sats, Sats),
+
+    /// the minimal number of inputs {0} exceeds the maximal number of inputs {1}.
+    InputLimits(usize, usize),
+
+    /// only {0} wallet outputs are available for spending, while at least {1} inputs are
+    /// required.
+    TooFewInputs(usize, usize),
+
+    /// the payment can't be funded with at most {max} inputs: the largest {max} wallet outputs
+    /// have {available}, while {required} is required.
+    TooManyInputs {
+        max: usize,
+        available: Sats,
+        required: Sats,
+    },
+
+    /// coin selection has found no wallet outputs matching the selection parameters, although
+    /// the wallet has {0} available, while {1} is required.
+    NoSelection(Sats, Sats),
+
+    #[from]
+    #[display(inner)]
+    Batch(BatchError),
+
+    #[from]
+    #[display(inner)]
+    Construction(ConstructionError),
+}
+
+/// Restrictions on the UTXOs spent by [`Wallet::sweep`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct SweepFilter {
+    /// Sweep only UTXOs on the addresses of this keychain.
+    pub keychain: Option<Keychain>,
+    /// Sweep only UTXOs with value below this threshold, for instance to get rid of the dust.
+    pub below: Option<Sats>,
+}
+
+impl SweepFilter {
+    pub fn matches(&self, utxo: &WalletUtxo) -> bool {
+        self.keychain.is_none_or(|keychain| utxo.terminal.keychain == keychain)
+            && self.below.is_none_or(|below| utxo.value < below)
+    }
+}
+
+/// Maximal size of the data carried by a standard `OP_RETURN` output.
+pub const MAX_OP_RETURN_SIZE: usize = 80;
+
+/// Errors creating [`OpReturn`] data.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum OpReturnError {
+    /// OP_RETURN data of {0} bytes exceed the standardness limit of 80 bytes.
+    TooLarge(usize),
+
+    /// OP_RETURN data must be a hex-encoded string.
+    InvalidHex,
+}
+
+/// Data committed to by an `OP_RETURN` output of zero value, not exceeding the standardness
+/// limit of [`MAX_OP_RETURN_SIZE`] bytes.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct OpReturn {
+    len: u8,
+    data: [u8; MAX_OP_RETURN_SIZE],
+}
+
+impl OpReturn {
+    pub fn new(data: &[u8]) -> Result<Self, OpReturnError> {
+        if data.len() > MAX_OP_RETURN_SIZE {
+            return Err(OpReturnError::TooLarge(data.len()));
+        }
+        let mut buf = [0u8; MAX_OP_RETURN_SIZE];
+        buf[..data.len()].copy_from_slice(data);
+        Ok(OpReturn {
+            len: data.len() as u8,
+            data: buf,
+        })
+    }
+
+    pub fn as_slice(&self) -> &[u8] { &self.data[..self.len as usize] }
+
+    pub fn script_pubkey(&self) -> ScriptPubkey { ScriptPubkey::op_return(self.as_slice()) }
+}
+
+impl FromStr for OpReturn {
+    type Err = OpReturnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let data = Vec::<u8>::from_hex(s).map_err(|_| OpReturnError::InvalidHex)?;
+        OpReturn::new(&data)
+    }
+}
+
+/// Parameters of a transaction constructed by [`Wallet::build_tx`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SpendParams {
+    pub fee: FeePolicy,
+    /// Whether the transaction signals replaceability according to BIP-125.
+    pub rbf: bool,
+    /// Transaction lock time. Input sequence numbers always enable lock time, whether the
+    /// transaction signals replaceability or not.
+    pub lock_time: Option<LockTime>,
+    pub version: PsbtVer,
+    /// Spend only UTXOs created by mined transactions.
+    pub confirmed_only: bool,
+    /// See [`SelectionParams::changeless_window`].
+    pub changeless_window: Option<Sats>,
+    /// Handling of the change below the dust limit of the change output.
+    pub dust_policy: DustPolicy,
+    /// Maximal fee rate of the transaction, in satoshis per virtual byte.
+    pub max_fee_rate: f64,
+    /// Skip checking that the fee rate is within the minimal relay fee rate and
+    /// [`SpendParams::max_fee_rate`].
+    pub allow_absurd_fee: bool,
+    /// Data of a zero-value `OP_RETURN` output added after the other transaction outputs.
+    pub op_return: Option<OpReturn>,
+    /// Order of the transaction inputs and outputs.
+    pub order: TxOrder,
+    /// Derivation index of the change output.
+    pub change: ChangePolicy,
+    /// Minimal number of inputs, forcing consolidation of additional coins into the change.
+    pub min_inputs: Option<usize>,
+    /// Maximal number of inputs.
+    pub max_inputs: Option<usize>,
+}
+
+impl SpendParams {
+    pub fn with(fee: FeePolicy) -> Self {
+        SpendParams {
+            fee,
+            rbf: true,
+            lock_time: None,
+            version: PsbtVer::V0,
+            confirmed_only: false,
+            changeless_window: None,
+            dust_policy: DustPolicy::Burn,
+            max_fee_rate: fees::MAX_FEE_RATE,
+            allow_absurd_fee: false,
+            op_return: None,
+            order: TxOrder::AsGiven,
+            change: ChangePolicy::NextUnused,
+            min_inputs: None,
+            max_inputs: None,
+        }
+    }
+
+    /// Constructs PSBT construction parameters for a transaction paying `fee`.
+    pub fn tx_params(&self, fee: Sats) -> TxParams {
+        let mut tx_params = TxParams::with(fee);
+        tx_params.seq_no = fees::seq_no(self.rbf);
+        tx_params.lock_time = self.lock_time;
+        tx_params
+    }
+}
+
+/// Choice of the derivation index for the change output of a transaction constructed by
+/// [`Wallet::build_tx`]. The change always goes to the internal keychain of the wallet, or to the
+/// default keychain if the wallet has no internal one.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum ChangePolicy {
+    /// Use the next index which was neither used by the known transactions nor reserved.
+    #[default]
+    NextUnused,
+
+    /// Use a specific index, which must not be used or reserved yet. Subsequent change outputs
+    /// with [`ChangePolicy::NextUnused`] use the indexes following it.
+    Fixed(NormalIndex),
+}
+
+/// Order of the inputs and outputs of a transaction constructed by [`Wallet::build_tx`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum TxOrder {
+    /// Inputs are in the order chosen by the coin selection; outputs follow the order of the
+    /// beneficiaries, with the change and `OP_RETURN` outputs added last.
+    #[default]
+    AsGiven,
+
+    /// Inputs and outputs are sorted lexicographically according to BIP-69, such that their
+    /// order doesn't reveal which output is the change.
+    Bip69,
+}
+
+/// Transaction constructed by [`Wallet::build_tx`].
+#[derive(Clone, Debug)]
+pub struct BuiltTx {
+    pub psbt: Psbt,
+    pub meta: PsbtMeta,
+    /// Coin selection strategy which has chosen the inputs; `None` if all spendable UTXOs are
+    /// spent or the inputs were chosen by a custom coin selection algorithm.
+    pub strategy: Option<Strategy>,
+    /// Amount of the change output; zero if the transaction has no change.
+    pub change: Sats,
+    /// Change below the dust limit, which was added to the fee instead of creating a change
+    /// output; see [`coinselect::Selection::dropped_change`].
+    pub dropped_change: Sats,
+    /// Estimated size of the signed transaction, in virtual bytes.
+    pub vsize: u32,
+    /// Fee rate of the signed transaction, in satoshis per virtual byte.
+    pub fee_rate: f64,
+}
+
+/// Cost of a transaction estimated by [`Wallet::estimate_tx`] without constructing it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TxEstimate {
+    /// Wallet UTXOs which would be spent, in the order of the transaction inputs.
+    pub inputs: Vec<Outpoint>,
+    /// Total value of the spent UTXOs.
+    pub input_value: Sats,
+    /// Absolute transaction fee.
+    pub fee: Sats,
+    /// See [`BuiltTx::strategy`].
+    pub strategy: Option<Strategy>,
+    /// Amount of the change output; zero if the transaction would have no change.
+    pub change: Sats,
+    /// See [`BuiltTx::dropped_change`].
+    pub dropped_change: Sats,
+    /// Estimated size of the signed transaction, in virtual bytes.
+    pub vsize: u32,
+    /// Fee rate of the signed transaction, in satoshis per virtual byte.
+    pub fee_rate: f64,
+}
+
+/// Addresses derived from a wallet descriptor, keyed by their keychain and derivation index.
+///
+/// Derivation of an address from an extended public key repeats the elliptic curve operations
+/// for the keychain and the index each time; the cache keeps the results, so syncs, UTXO listings
+/// and address lookups iterating over the same keychains don't re-derive them.
+type DerivationCache = RwLock<BTreeMap<(Keychain, NormalIndex), Vec<Address>>>;
+
 pub struct AddrIter<'descr, K, D: Descriptor<K>> {
     generator: &'descr D,
+    derived: &'descr DerivationCache,
     network: AddressNetwork,
     keychain: Keychain,
     index: NormalIndex,
+    end: Option<NormalIndex>,
     remainder: VecDeque<DerivedAddr>,
     _phantom: PhantomData<K>,
 }
@@ -71,9 +439,21 @@ impl<K, D: Descriptor<K>> Iterator for AddrIter<'_, K, D> {
             if let Some(derived) = self.remainder.pop_front() {
                 return Some(derived);
             }
-            self.remainder = self
-                .generator
-                .derive_address(self.network, self.keychain, self.index)
+            if self.end.is_some_and(|end| self.index >= end) {
+                return None;
+            }
+            let key = (self.keychain, self.index);
+            let cached = self.derived.read().expect("poisoned cache lock").get(&key).cloned();
+            let addrs = cached.unwrap_or_else(|| {
+                let addrs = self
+                    .generator
+                    .derive_address(self.network, self.keychain, self.index)
+                    .collect::<Vec<_>>();
+                self.derived.write().expect("poisoned cache lock").insert(key, addrs.clone());
+                addrs
+            });
+            self.remainder = addrs
+                .into_iter()
                 .map(|addr| DerivedAddr::new(addr, self.keychain, self.index))
                 .collect();
             self.index.checked_inc_assign()?;
@@ -106,6 +486,18 @@ where
     generator: D,
     #[getter(as_copy)]
     network: Network,
+    /// Gap limits configured for specific keychains; see [`WalletDescr::gap_limit`].
+    #[getter(skip)]
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "BTreeMap::is_empty"))]
+    gap_limit: BTreeMap<Keychain, u32>,
+    /// Keychains to which the wallet operations are restricted; see [`WalletDescr::keychains`].
+    #[getter(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    keychain_filter: Option<BTreeSet<Keychain>>,
+    /// Addresses already derived from the descriptor; see [`DerivationCache`].
+    #[getter(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    derived: DerivationCache,
     layer2: L2,
     #[cfg_attr(feature = "serde", serde(skip))]
     _phantom: PhantomData<K>,
@@ -117,6 +509,9 @@ impl<K, D: Descriptor<K>> WalletDescr<K, D, Layer2Empty> {
             persistence: None,
             generator: descr,
             network,
+            gap_limit: none!(),
+            keychain_filter: None,
+            derived: none!(),
             layer2: none!(),
             _phantom: PhantomData,
         }
@@ -129,30 +524,88 @@ impl<K, D: Descriptor<K>, L2: Layer2Descriptor> WalletDescr<K, D, L2> {
             persistence: None,
             generator: descr,
             network,
+            gap_limit: none!(),
+            keychain_filter: None,
+            derived: none!(),
             layer2,
             _phantom: PhantomData,
         }
     }
 
-    pub fn addresses(&self, keychain: impl Into<Keychain>) -> AddrIter<K, D> {
+    pub fn addresses(&self, keychain: impl Into<Keychain>) -> AddrIter<'_, K, D> {
+        self.addresses_from(keychain, NormalIndex::ZERO)
+    }
+
+    /// Iterates over the addresses of the `keychain` starting from the derivation index `from`,
+    /// without deriving the addresses with the lower indexes.
+    pub fn addresses_from(
+        &self,
+        keychain: impl Into<Keychain>,
+        from: NormalIndex,
+    ) -> AddrIter<'_, K, D> {
         AddrIter {
             generator: &self.generator,
+            derived: &self.derived,
             network: self.network.into(),
             keychain: keychain.into(),
-            index: NormalIndex::ZERO,
+            index: from,
+            end: None,
             remainder: VecDeque::new(),
             _phantom: PhantomData,
         }
     }
 
+    /// Iterates over the addresses of the `keychain` with the derivation indexes from the
+    /// `range`, deriving no addresses outside of it.
+    pub fn addresses_range(
+        &self,
+        keychain: impl Into<Keychain>,
+        range: Range<NormalIndex>,
+    ) -> AddrIter<'_, K, D> {
+        AddrIter {
+            end: Some(range.end),
+            ..self.addresses_from(keychain, range.start)
+        }
+    }
+
     pub fn with_descriptor<T, E>(
         &mut self,
         f: impl FnOnce(&mut D) -> Result<T, E>,
     ) -> Result<T, E> {
         let res = f(&mut self.generator)?;
+        self.derived.get_mut().expect("poisoned cache lock").clear();
         self.mark_dirty();
         Ok(res)
     }
+
+    /// Number of consecutive unused addresses after the last used one which are checked in a
+    /// keychain during the address discovery.
+    pub fn gap_limit(&self, keychain: impl Into<Keychain>) -> u32 {
+        self.gap_limit.get(&keychain.into()).copied().unwrap_or(DEFAULT_GAP_LIMIT)
+    }
+
+    pub fn set_gap_limit(&mut self, keychain: impl Into<Keychain>, gap_limit: u32) {
+        self.gap_limit.insert(keychain.into(), gap_limit);
+        self.mark_dirty();
+    }
+
+    /// Keychains used by the wallet operations, like sync, balance computation and coin listing.
+    ///
+    /// These are all the keychains of the descriptor, unless the operations are restricted to
+    /// some of them with [`Self::restrict_keychains`].
+    pub fn keychains(&self) -> BTreeSet<Keychain> {
+        let keychains = self.generator.keychains();
+        match &self.keychain_filter {
+            Some(filter) => keychains.intersection(filter).copied().collect(),
+            None => keychains,
+        }
+    }
+
+    /// Restricts the wallet operations to the given subset of the descriptor keychains, or
+    /// removes the restriction if `None` is given. The restriction is not persisted.
+    pub fn restrict_keychains(&mut self, keychains: Option<BTreeSet<Keychain>>) {
+        self.keychain_filter = keychains;
+    }
 }
 
 impl<K, D: Descriptor<K>, L2: Layer2Descriptor> Deref for WalletDescr<K, D, L2> {
@@ -167,6 +620,9 @@ impl<K, D: Descriptor<K>, L2: Layer2Descriptor> CloneNoPersistence for WalletDes
             persistence: None,
             generator: self.generator.clone(),
             network: self.network,
+            gap_limit: self.gap_limit.clone(),
+            keychain_filter: self.keychain_filter.clone(),
+            derived: RwLock::new(self.derived.read().expect("poisoned cache lock").clone()),
             layer2: self.layer2.clone(),
             _phantom: PhantomData,
         }
@@ -215,8 +671,22 @@ pub struct WalletData<L2: Layer2Data> {
     pub name: String,
     pub tx_annotations: BTreeMap<Txid, String>,
     pub txout_annotations: BTreeMap<Outpoint, String>,
-    pub txin_annotations: BTreeMap<Outpoint, String>,
+    /// Annotations of the wallet transaction inputs, keyed by the spending transaction input.
+    #[cfg_attr(feature = "serde", serde(with = "crate::data::inpoint_keys"))]
+    pub txin_annotations: BTreeMap<Inpoint, String>,
     pub addr_annotations: BTreeMap<Address, String>,
+    /// Wallet outputs which must not be spent by the wallet unless explicitly requested, together
+    /// with the optional reason of the lock. The reason is kept apart from the output annotation.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub locked_utxos: BTreeMap<Outpoint, Option<String>>,
+    /// Encrypted extended private key account file which can sign for the wallet descriptor.
+    /// Wallets without it are watch-only.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub signing_account: Option<PathBuf>,
+    /// Time of the wallet creation known from the wallet import, used as the blockchain rescan
+    /// start while the wallet has no confirmed transactions.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub birth_time: Option<u64>,
     pub last_used: BTreeMap<Keychain, NormalIndex>,
     pub layer2: L2,
 }
@@ -231,6 +701,9 @@ impl<L2: Layer2Data> CloneNoPersistence for WalletData<L2> {
             txout_annotations: self.txout_annotations.clone(),
             txin_annotations: self.txin_annotations.clone(),
             addr_annotations: self.addr_annotations.clone(),
+            locked_utxos: self.locked_utxos.clone(),
+            signing_account: self.signing_account.clone(),
+            birth_time: self.birth_time,
             layer2: self.layer2.clone(),
             last_used: self.last_used.clone(),
         }
@@ -256,6 +729,9 @@ impl WalletData<Layer2Empty> {
             txout_annotations: empty!(),
             txin_annotations: empty!(),
             addr_annotations: empty!(),
+            locked_utxos: empty!(),
+            signing_account: None,
+            birth_time: None,
             layer2: none!(),
             last_used: empty!(),
         }
@@ -263,6 +739,10 @@ impl WalletData<Layer2Empty> {
 }
 
 impl<L2: Layer2Data> WalletData<L2> {
+    /// Detects whether the wallet has no signing keys and can't sign transactions by itself.
+    #[inline]
+    pub fn is_watch_only(&self) -> bool { self.signing_account.is_none() }
+
     pub fn new_layer2() -> Self
     where L2: Default {
         WalletData {
@@ -273,6 +753,9 @@ impl<L2: Layer2Data> WalletData<L2> {
             txout_annotations: empty!(),
             txin_annotations: empty!(),
             addr_annotations: empty!(),
+            locked_utxos: empty!(),
+            signing_account: None,
+            birth_time: None,
             layer2: none!(),
             last_used: empty!(),
         }
@@ -322,6 +805,7 @@ pub trait WalletCacheProvider<L2C: Layer2Cache>: Persisting {
                 outpoint,
                 address: out.derived_addr().expect("cache data inconsistency"),
                 amount: out.value,
+                label: None,
                 layer2: none!(), // TODO: Add support to WalletTx
             }
         })
@@ -332,6 +816,10 @@ pub trait WalletCacheProvider<L2C: Layer2Cache>: Persisting {
             let (credit, debit) = tx.credited_debited();
             let mut row = TxRow {
                 height: tx.status.map(|info| info.height),
+                time: match tx.status {
+                    TxStatus::Mined(info) => Some(info.time),
+                    _ => None,
+                },
                 operation: OpType::Credit,
                 our_inputs: tx
                     .inputs
@@ -348,6 +836,7 @@ pub trait WalletCacheProvider<L2C: Layer2Cache>: Persisting {
                 total: tx.total_moved(),
                 amount: Sats::ZERO,
                 balance: Sats::ZERO,
+                label: None,
                 layer2: none!(), // TODO: Add support to WalletTx
             };
             // TODO: Add balance calculation
@@ -382,6 +871,8 @@ pub trait WalletCacheProvider<L2C: Layer2Cache>: Persisting {
                 });
                 row.operation = OpType::Debit;
                 row.amount = debit;
+            } else {
+                row.operation = OpType::Transfer;
             }
             row
         })
@@ -425,6 +916,16 @@ pub trait WalletCacheProvider<L2C: Layer2Cache>: Persisting {
     fn register_psbt(&mut self, psbt: &Psbt, meta: &PsbtMeta) {
         let unsigned_tx = psbt.to_unsigned_tx();
         let txid = unsigned_tx.txid();
+        // The PSBT constructor doesn't compute the transaction weight, so the weight of the signed
+        // transaction is estimated, allowing to bump its fee before the next sync
+        let input_scripts =
+            psbt.inputs().map(|input| input.prev_txout().script_pubkey.clone()).collect::<Vec<_>>();
+        let output_scripts = psbt.outputs().map(|output| &output.script);
+        let vsize = fees::estimate_vsize(&input_scripts, output_scripts);
+        let weight = match meta.weight {
+            0 => vsize * 4,
+            weight => weight,
+        };
         let wallet_tx = WalletTx {
             txid,
             status: TxStatus::Mempool,
@@ -435,13 +936,20 @@ pub trait WalletCacheProvider<L2C: Layer2Cache>: Persisting {
                     TxCredit {
                         outpoint: input.previous_outpoint,
                         payer: match (self.utxo(&input.previous_outpoint), addr) {
-                            (Some(_), Some(addr)) => {
-                                let (keychain, index) = self
-                                    .addr_by_address(&addr)
-                                    .map(|(keychain, a)| (keychain, a.terminal.index))
-                                    .expect("address cache inconsistency");
-                                Party::Wallet(DerivedAddr::new(addr, keychain, index))
-                            }
+                            // Outputs of the transactions registered before the next sync are
+                            // not in the address cache yet
+                            (Some(_), Some(addr)) => self
+                                .addr_by_address(&addr)
+                                .map(|(keychain, a)| {
+                                    DerivedAddr::new(addr, keychain, a.terminal.index)
+                                })
+                                .or_else(|| {
+                                    let prevout = input.previous_outpoint;
+                                    let tx = self.tx(&prevout.txid)?;
+                                    tx.outputs.get(prevout.vout.to_usize())?.derived_addr()
+                                })
+                                .map(Party::Wallet)
+                                .unwrap_or(Party::Counterparty(addr)),
                             (_, Some(addr)) => Party::Counterparty(addr),
                             _ => Party::Unknown(input.prev_txout().script_pubkey.clone()),
                         },
@@ -478,7 +986,7 @@ pub trait WalletCacheProvider<L2C: Layer2Cache>: Persisting {
                 .collect(),
             fee: meta.fee,
             size: meta.size,
-            weight: meta.weight,
+            weight,
             version: unsigned_tx.version,
             locktime: unsigned_tx.lock_time,
         };
@@ -507,6 +1015,23 @@ pub struct WalletCache<L2: Layer2Cache> {
     #[cfg_attr(feature = "serde", serde(skip))]
     pub id: Option<String>,
     pub last_block: MiningInfo,
+    /// Most recent block with wallet transactions known for each keychain at the moment of the
+    /// last sync; transactions mined before it are not requested again from the indexer.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub last_sync: BTreeMap<Keychain, MiningInfo>,
+    /// Status of the wallet address scripts at the moment of the last sync, used to skip
+    /// retrieval of transactions for the scripts whose history has not changed.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub script_status: BTreeMap<ScriptPubkey, String>,
+    /// Number of addresses to check in each keychain during the next sync regardless of the gap
+    /// limit. Set when the wallet is imported from another software which has watched that many
+    /// addresses, and cleared once the keychain is synced.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub scan_depth: BTreeMap<Keychain, u32>,
+    /// Time of the wallet creation, copied from [`WalletData::birth_time`] before each sync:
+    /// indexers may skip retrieval of the transactions mined before it.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub birth_time: Option<u64>,
     pub last_change: NormalIndex,
     pub headers: BTreeSet<BlockInfo>,
     pub tx: BTreeMap<Txid, WalletTx>,
@@ -553,6 +1078,10 @@ impl<L2C: Layer2Cache> WalletCache<L2C> {
             persistence: None,
             id: None,
             last_block: MiningInfo::genesis(),
+            last_sync: none!(),
+            script_status: none!(),
+            scan_depth: none!(),
+            birth_time: None,
             last_change: NormalIndex::ZERO,
             headers: none!(),
             tx: none!(),
@@ -565,7 +1094,7 @@ impl<L2C: Layer2Cache> WalletCache<L2C> {
     pub fn with<I: Indexer, K, D: Descriptor<K>, L2: Layer2<Cache = L2C>>(
         descriptor: &WalletDescr<K, D, L2::Descr>,
         indexer: &I,
-    ) -> MayError<Self, Vec<I::Error>> {
+    ) -> MayError<Self, Vec<SyncError<I::Error>>> {
         indexer.create::<K, D, L2>(descriptor)
     }
 
@@ -573,7 +1102,7 @@ impl<L2C: Layer2Cache> WalletCache<L2C> {
         &mut self,
         descriptor: &WalletDescr<K, D, L2::Descr>,
         indexer: &I,
-    ) -> MayError<usize, Vec<I::Error>> {
+    ) -> MayError<usize, Vec<SyncError<I::Error>>> {
         let res = indexer.update::<K, D, L2>(descriptor, self);
         self.mark_dirty();
         res
@@ -582,6 +1111,16 @@ impl<L2C: Layer2Cache> WalletCache<L2C> {
     pub fn register_psbt(&mut self, psbt: &Psbt, meta: &PsbtMeta) {
         let unsigned_tx = psbt.to_unsigned_tx();
         let txid = unsigned_tx.txid();
+        // The PSBT constructor doesn't compute the transaction weight, so the weight of the signed
+        // transaction is estimated, allowing to bump its fee before the next sync
+        let input_scripts =
+            psbt.inputs().map(|input| input.prev_txout().script_pubkey.clone()).collect::<Vec<_>>();
+        let output_scripts = psbt.outputs().map(|output| &output.script);
+        let vsize = fees::estimate_vsize(&input_scripts, output_scripts);
+        let weight = match meta.weight {
+            0 => vsize * 4,
+            weight => weight,
+        };
         let wallet_tx = WalletTx {
             txid,
             status: TxStatus::Mempool,
@@ -592,18 +1131,23 @@ impl<L2C: Layer2Cache> WalletCache<L2C> {
                     TxCredit {
                         outpoint: input.previous_outpoint,
                         payer: match (self.utxo.get(&input.previous_outpoint), addr) {
-                            (Some(_), Some(addr)) => {
-                                let (keychain, index) = self
-                                    .addr
-                                    .iter()
-                                    .flat_map(|(keychain, addrs)| {
-                                        addrs.iter().map(|a| (*keychain, a))
-                                    })
-                                    .find(|(_, a)| a.addr == addr)
-                                    .map(|(keychain, a)| (keychain, a.terminal.index))
-                                    .expect("address cache inconsistency");
-                                Party::Wallet(DerivedAddr::new(addr, keychain, index))
-                            }
+                            // Outputs of the transactions registered before the next sync are
+                            // not in the address cache yet
+                            (Some(_), Some(addr)) => self
+                                .addr
+                                .iter()
+                                .flat_map(|(keychain, addrs)| addrs.iter().map(|a| (*keychain, a)))
+                                .find(|(_, a)| a.addr == addr)
+                                .map(|(keychain, a)| {
+                                    DerivedAddr::new(addr, keychain, a.terminal.index)
+                                })
+                                .or_else(|| {
+                                    let prevout = input.previous_outpoint;
+                                    let tx = self.tx.get(&prevout.txid)?;
+                                    tx.outputs.get(prevout.vout.to_usize())?.derived_addr()
+                                })
+                                .map(Party::Wallet)
+                                .unwrap_or(Party::Counterparty(addr)),
                             (_, Some(addr)) => Party::Counterparty(addr),
                             _ => Party::Unknown(input.prev_txout().script_pubkey.clone()),
                         },
@@ -640,7 +1184,7 @@ impl<L2C: Layer2Cache> WalletCache<L2C> {
                 .collect(),
             fee: meta.fee,
             size: meta.size,
-            weight: meta.weight,
+            weight,
             version: unsigned_tx.version,
             locktime: unsigned_tx.lock_time,
         };
@@ -650,6 +1194,107 @@ impl<L2C: Layer2Cache> WalletCache<L2C> {
         }
     }
 
+    /// Registers a transaction published by the wallet as an unconfirmed one, such that the
+    /// balance reflects it before the next sync. Wallet outputs spent by the transaction are
+    /// removed from the UTXO set; transactions already known to the cache, like the ones
+    /// registered with [`Self::register_psbt`], are not re-created.
+    pub fn register_tx(&mut self, tx: &Tx, network: AddressNetwork) {
+        let txid = tx.txid();
+        for (vin, input) in tx.inputs.iter().enumerate() {
+            let prevout = input.prev_output;
+            if let Some(debit) = self
+                .tx
+                .get_mut(&prevout.txid)
+                .and_then(|prev_tx| prev_tx.outputs.get_mut(prevout.vout.into_usize()))
+            {
+                debit.spent = Some(Inpoint::new(txid, vin as u32));
+                self.utxo.remove(&prevout);
+            }
+        }
+        if self.tx.contains_key(&txid) {
+            return;
+        }
+
+        let inputs = tx
+            .inputs
+            .iter()
+            .map(|input| {
+                let prevout = input.prev_output;
+                let debit = self
+                    .tx
+                    .get(&prevout.txid)
+                    .and_then(|prev_tx| prev_tx.outputs.get(prevout.vout.into_usize()));
+                TxCredit {
+                    outpoint: prevout,
+                    payer: debit
+                        .map(|debit| debit.beneficiary.clone())
+                        .unwrap_or_else(|| Party::Unknown(none!())),
+                    sequence: input.sequence,
+                    coinbase: false,
+                    script_sig: input.sig_script.clone(),
+                    witness: input.witness.clone(),
+                    value: debit.map(|debit| debit.value).unwrap_or_default(),
+                }
+            })
+            .collect::<Vec<_>>();
+        let outputs = tx
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(vout, output)| {
+                let outpoint = Outpoint::new(txid, Vout::from_u32(vout as u32));
+                let wallet_addr = self
+                    .addr
+                    .values()
+                    .flatten()
+                    .find(|a| a.addr.script_pubkey() == output.script_pubkey);
+                let beneficiary = match wallet_addr {
+                    Some(a) => {
+                        self.utxo.insert(outpoint);
+                        Party::Wallet(DerivedAddr::new(
+                            a.addr,
+                            a.terminal.keychain,
+                            a.terminal.index,
+                        ))
+                    }
+                    None => Address::with(&output.script_pubkey, network)
+                        .map(Party::Counterparty)
+                        .unwrap_or_else(|_| Party::Unknown(output.script_pubkey.clone())),
+                };
+                TxDebit {
+                    outpoint,
+                    beneficiary,
+                    value: output.value,
+                    spent: None,
+                }
+            })
+            .collect::<Vec<_>>();
+        // Fee is known only if all the spent outputs are known to the wallet
+        let fee = if inputs.iter().all(|input| !input.payer.is_unknown()) {
+            let credit = inputs.iter().map(|input| input.value).sum::<Sats>();
+            let debit = outputs.iter().map(|output| output.value).sum::<Sats>();
+            if credit > debit {
+                credit - debit
+            } else {
+                Sats::ZERO
+            }
+        } else {
+            Sats::ZERO
+        };
+        let wallet_tx = WalletTx {
+            txid,
+            status: TxStatus::Mempool,
+            inputs,
+            outputs,
+            fee,
+            size: tx.consensus_serialize().len() as u32,
+            weight: tx.weight_units().to_u32(),
+            version: tx.version,
+            locktime: tx.lock_time,
+        };
+        self.tx.insert(txid, wallet_tx);
+    }
+
     pub fn addresses_on(&self, keychain: Keychain) -> &BTreeSet<WalletAddr> {
         self.addr.get(&keychain).unwrap_or_else(|| {
             panic!("keychain #{keychain} is not supported by the wallet descriptor")
@@ -721,6 +1366,10 @@ impl<L2: Layer2Cache> CloneNoPersistence for WalletCache<L2> {
             persistence: None,
             id: self.id.clone(),
             last_block: self.last_block,
+            last_sync: self.last_sync.clone(),
+            script_status: self.script_status.clone(),
+            scan_depth: self.scan_depth.clone(),
+            birth_time: self.birth_time,
             last_change: self.last_change,
             headers: self.headers.clone(),
             tx: self.tx.clone(),
@@ -753,6 +1402,45 @@ impl<L2: Layer2Cache> Drop for WalletCache<L2> {
     }
 }
 
+/// Index of the wallet scripts met in the cached transactions and addresses.
+///
+/// The index only grows: transactions and addresses already indexed are skipped, so extending it
+/// after a sync or a new transaction doesn't re-process the rest of the cache.
+#[derive(Clone, Default, Debug)]
+struct ScriptIndex {
+    scripts: HashMap<ScriptPubkey, Terminal>,
+    txids: HashSet<Txid>,
+    terminals: HashSet<Terminal>,
+}
+
+impl ScriptIndex {
+    fn get(&self, script_pubkey: &ScriptPubkey) -> Option<Terminal> {
+        self.scripts.get(script_pubkey).copied()
+    }
+
+    fn add_tx(&mut self, tx: &WalletTx) {
+        if !self.txids.insert(tx.txid) {
+            return;
+        }
+        let parties = tx
+            .inputs
+            .iter()
+            .map(|credit| &credit.payer)
+            .chain(tx.outputs.iter().map(|debit| &debit.beneficiary));
+        for party in parties {
+            if let Party::Wallet(derived) = party {
+                self.scripts.insert(derived.addr.script_pubkey(), derived.terminal);
+            }
+        }
+    }
+
+    fn add_addr(&mut self, addr: &WalletAddr) {
+        if self.terminals.insert(addr.terminal) {
+            self.scripts.insert(addr.addr.script_pubkey(), addr.terminal);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Wallet<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2 = NoLayer2>
 {
@@ -760,6 +1448,9 @@ pub struct Wallet<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2
     data: WalletData<L2::Data>,
     cache: Cache,
     layer2: L2,
+    /// Index of the wallet scripts met in the cached transactions, allowing to check whether an
+    /// output belongs to the wallet without deriving the keys.
+    scripts: ScriptIndex,
 }
 
 impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2> Deref
@@ -783,6 +1474,7 @@ impl<
             data: self.data.clone_no_persistence(),
             cache: self.cache.clone_no_persistence(),
             layer2: self.layer2.clone_no_persistence(),
+            scripts: self.scripts.clone(),
         }
     }
 }
@@ -816,6 +1508,7 @@ impl<K, D: Descriptor<K>, Cache: Persisting + WalletCacheProvider<L2::Cache>, L2
     fn after_construct_psbt(&mut self, psbt: &Psbt, meta: &PsbtMeta) {
         debug_assert_eq!(AddressNetwork::from(self.network), meta.network);
         self.cache.register_psbt(psbt, meta);
+        self.index_tx(psbt.txid());
     }
 }
 
@@ -826,6 +1519,7 @@ impl<K, D: Descriptor<K>> Wallet<K, D, WalletCache<Layer2Empty>> {
             data: WalletData::new_layer1(),
             descr: WalletDescr::new_standard(descr, network),
             layer2: none!(),
+            scripts: none!(),
         }
     }
 }
@@ -837,6 +1531,7 @@ impl<K, D: Descriptor<K>, L2: Layer2> Wallet<K, D, WalletCache<L2::Cache>, L2> {
             data: WalletData::new_layer2(),
             descr: WalletDescr::new_layer2(descr, l2_descr, network),
             layer2,
+            scripts: none!(),
         }
     }
 
@@ -852,11 +1547,32 @@ impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2>
         cache: Cache,
         layer2: L2,
     ) -> Self {
-        Self {
+        let mut wallet = Self {
             descr,
             data,
             cache,
             layer2,
+            scripts: none!(),
+        };
+        wallet.index_scripts();
+        wallet
+    }
+
+    /// Extends the index of the wallet scripts with the transactions and addresses added to the
+    /// wallet cache since the last indexing.
+    fn index_scripts(&mut self) {
+        for (_, tx) in self.cache.txs() {
+            self.scripts.add_tx(&tx);
+        }
+        for (_, addr) in self.cache.addrs() {
+            self.scripts.add_addr(&addr);
+        }
+    }
+
+    /// Adds the scripts of the cached transaction `txid` to the index of the wallet scripts.
+    fn index_tx(&mut self, txid: Txid) {
+        if let Some(tx) = self.cache.tx(&txid) {
+            self.scripts.add_tx(&tx);
         }
     }
 
@@ -870,6 +1586,20 @@ impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2>
         self.data.mark_dirty();
     }
 
+    /// Attaches an encrypted extended private key account file to the wallet, or makes the wallet
+    /// watch-only if `None` is given.
+    pub fn set_signing_account(&mut self, account: Option<PathBuf>) {
+        self.data.signing_account = account;
+        self.data.mark_dirty();
+    }
+
+    /// Sets the time of the wallet creation, known from the wallet import. Indexers may skip
+    /// transactions mined before it during the wallet sync.
+    pub fn set_birth_time(&mut self, time: Option<u64>) {
+        self.data.birth_time = time;
+        self.data.mark_dirty();
+    }
+
     pub fn with_descriptor<T, E>(
         &mut self,
         f: impl FnOnce(&mut D) -> Result<T, E>,
@@ -877,6 +1607,17 @@ impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2>
         self.descr.with_descriptor(f)
     }
 
+    pub fn set_gap_limit(&mut self, keychain: impl Into<Keychain>, gap_limit: u32) {
+        self.descr.set_gap_limit(keychain, gap_limit)
+    }
+
+    /// Restricts the wallet operations to a subset of the descriptor keychains; see
+    /// [`WalletDescr::restrict_keychains`].
+    pub fn restrict_keychains(&mut self, keychains: Option<BTreeSet<Keychain>>) {
+        self.descr.restrict_keychains(keychains)
+    }
+
+    pub fn data(&self) -> &WalletData<L2::Data> { &self.data }
     pub fn data_l2(&self) -> &L2::Data { &self.data.layer2 }
     pub fn cache_l2(&self) -> &L2::Cache { self.cache.layer2() }
 
@@ -889,6 +1630,54 @@ impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2>
         Ok(res)
     }
 
+    /// Returns label attached to the wallet item.
+    pub fn get_label(&self, item: &LabelRef) -> Option<&str> { self.data.label(item) }
+
+    /// Attaches label to the wallet item, or removes the label if `None` is given. Returns the
+    /// previous label of the item.
+    pub fn set_label(&mut self, item: LabelRef, label: Option<String>) -> Option<String> {
+        let prev = self.data.set_label(item, label);
+        self.data.mark_dirty();
+        prev
+    }
+
+    /// Merges BIP-329 labels with the existing wallet labels; see
+    /// [`WalletData::import_labels`] for the details.
+    pub fn import_labels(
+        &mut self,
+        labels: impl IntoIterator<Item = Label>,
+        overwrite: bool,
+    ) -> LabelImport {
+        let report = self.data.import_labels(labels, overwrite);
+        self.data.mark_dirty();
+        report
+    }
+
+    /// Locks a wallet output from being spent by the default coin selection, optionally
+    /// attaching a label with the reason of the lock; the output annotation is left intact.
+    /// Locks are kept in the wallet data and survive re-syncs.
+    pub fn lock_utxo(
+        &mut self,
+        outpoint: Outpoint,
+        label: Option<String>,
+    ) -> Result<(), NonWalletItem> {
+        if !self.cache.has_outpoint(outpoint) {
+            return Err(NonWalletItem::NonWalletUtxo(outpoint));
+        }
+        self.data.locked_utxos.insert(outpoint, label);
+        self.data.mark_dirty();
+        Ok(())
+    }
+
+    /// Unlocks previously locked wallet output, returning whether it was locked.
+    pub fn unlock_utxo(&mut self, outpoint: Outpoint) -> bool {
+        let unlocked = self.data.locked_utxos.remove(&outpoint).is_some();
+        if unlocked {
+            self.data.mark_dirty();
+        }
+        unlocked
+    }
+
     pub fn with_data_l2<T, E>(
         &mut self,
         f: impl FnOnce(&mut L2::Data) -> Result<T, E>,
@@ -933,16 +1722,142 @@ impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2>
         cmp::max(last_index, self.last_published_derivation_index(keychain))
     }
 
-    pub fn next_address(&mut self, keychain: impl Into<Keychain>, shift: bool) -> Address {
+    /// Returns the next unused address of the `keychain` and reserves it, such that subsequent
+    /// calls return different addresses even before any funds are received on this one.
+    ///
+    /// The reservation is recorded in the wallet data and is persisted together with it.
+    pub fn next_address(&mut self, keychain: impl Into<Keychain>) -> DerivedAddr {
+        let keychain = keychain.into();
+        let index = self.next_derivation_index(keychain, true);
+        self.derived_address(keychain, index)
+    }
+
+    /// Returns the address which will be returned by the next call to [`Self::next_address`],
+    /// without reserving it.
+    pub fn peek_address(&self, keychain: impl Into<Keychain>) -> DerivedAddr {
         let keychain = keychain.into();
-        let index = self.next_derivation_index(keychain, shift);
-        self.addresses(keychain)
-            .nth(index.index() as usize)
+        self.derived_address(keychain, self.last_derivation_index(keychain))
+    }
+
+    fn derived_address(&self, keychain: Keychain, index: NormalIndex) -> DerivedAddr {
+        self.addresses_from(keychain, index)
+            .next()
             .expect("address iterator always can produce address")
-            .addr
     }
 
-    pub fn balance(&self) -> Sats { self.cache.coins().map(|utxo| utxo.amount).sum::<Sats>() }
+    /// Searches the wallet keychains for the derivation producing `addr`.
+    ///
+    /// On each keychain the addresses are derived up to the last used or reserved one, followed
+    /// by the keychain gap limit and `extra_depth` more addresses. Returns `None` if the address
+    /// is not produced by any of the checked derivations.
+    pub fn find_address(&self, addr: &Address, extra_depth: u32) -> Option<DerivedAddr> {
+        self.descr.keychains().into_iter().find_map(|keychain| {
+            let depth = self
+                .last_derivation_index(keychain)
+                .index()
+                .saturating_add(self.descr.gap_limit(keychain))
+                .saturating_add(extra_depth);
+            self.addresses(keychain).take(depth as usize).find(|derived| derived.addr == *addr)
+        })
+    }
+
+    /// Checks whether the output script belongs to the wallet, returning the derivation of the
+    /// script.
+    ///
+    /// Scripts of the transactions known from the last wallet sync are looked up in an index
+    /// without deriving any keys; other scripts are searched with [`Self::find_address`] up to
+    /// the gap limit of each keychain. Doesn't require access to an indexer.
+    pub fn owns_output(&self, script_pubkey: &ScriptPubkey) -> Option<Terminal> {
+        let indexed = self.scripts.get(script_pubkey);
+        if let Some(terminal) = indexed.filter(|t| self.descr.keychains().contains(&t.keychain)) {
+            return Some(terminal);
+        }
+        let addr = Address::with(script_pubkey, AddressNetwork::from(self.descr.network)).ok()?;
+        self.find_address(&addr, 0).map(|derived| derived.terminal)
+    }
+
+    /// Derives addresses of the `keychain` with the derivation indexes from the `range`, marking
+    /// the addresses which were used by the transactions known from the last wallet sync.
+    ///
+    /// Addresses outside the range are not derived, so large ranges far from the start of the
+    /// keychain are cheap to produce.
+    pub fn addresses_in(
+        &self,
+        keychain: impl Into<Keychain>,
+        range: Range<NormalIndex>,
+    ) -> impl Iterator<Item = AddrInfo> + '_ {
+        let keychain = keychain.into();
+        let used = self
+            .cache
+            .addrs()
+            .filter(|(k, addr)| *k == keychain && addr.used > 0)
+            .map(|(_, addr)| addr.terminal.index)
+            .collect::<BTreeSet<_>>();
+        self.addresses_range(keychain, range).map(move |derived| AddrInfo {
+            terminal: derived.terminal,
+            script_pubkey: derived.addr.script_pubkey(),
+            addr: derived.addr,
+            used: used.contains(&derived.terminal.index),
+        })
+    }
+
+    /// Derivation paths of the keys used by the address at the `terminal`, one for each key of
+    /// the wallet descriptor.
+    pub fn derivation_paths(&self, terminal: Terminal) -> Vec<String> {
+        self.descr
+            .generator
+            .xpubs()
+            .map(|xpub| format!("{}/{}/{}", xpub.origin(), terminal.keychain, terminal.index))
+            .collect()
+    }
+
+    /// Lists unspent outputs controlled by the wallet descriptor, ordered by their outpoints.
+    ///
+    /// Outputs on scripts which are not derivable from the descriptor are skipped, as well as
+    /// outputs spent by any transaction known to the wallet, even if the spending transaction was
+    /// retrieved during a sync which has failed for the address the output belongs to.
+    pub fn list_utxos(&self) -> Vec<UtxoInfo> {
+        let keychains = self.descr.keychains();
+        let spent = self
+            .cache
+            .txs()
+            .flat_map(|(_, tx)| tx.inputs.iter().map(|input| input.outpoint).collect::<Vec<_>>())
+            .collect::<BTreeSet<_>>();
+        let mut utxos = vec![];
+        for outpoint in self.cache.utxos() {
+            if spent.contains(&outpoint) {
+                continue;
+            }
+            let Some(tx) = self.cache.tx(&outpoint.txid) else {
+                continue;
+            };
+            let Some(debit) = tx.outputs.get(outpoint.vout_usize()) else {
+                continue;
+            };
+            let Some(derived) = debit.derived_addr().filter(|_| debit.spent.is_none()) else {
+                continue;
+            };
+            let terminal = derived.terminal;
+            if !keychains.contains(&terminal.keychain)
+                || self.derived_address(terminal.keychain, terminal.index).addr != derived.addr
+            {
+                continue;
+            }
+            utxos.push(UtxoInfo {
+                outpoint,
+                value: debit.value,
+                script_pubkey: derived.addr.script_pubkey(),
+                terminal,
+                height: tx.status.height(),
+                label: self.output_label(outpoint, &derived.addr),
+                locked: self.is_locked(outpoint),
+            });
+        }
+        utxos.sort_by_key(|utxo| utxo.outpoint);
+        utxos
+    }
+
+    pub fn balance(&self) -> Sats { self.coins().map(|utxo| utxo.amount).sum::<Sats>() }
 
     #[inline]
     pub fn transactions(&self) -> impl Iterator<Item = (Txid, Cow<'_, WalletTx>)> + '_ {
@@ -951,7 +1866,19 @@ impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2>
 
     #[inline]
     pub fn coins(&self) -> impl Iterator<Item = CoinRow<<L2::Cache as Layer2Cache>::Coin>> + '_ {
-        self.cache.coins()
+        let keychains = self.descr.keychains();
+        self.cache
+            .coins()
+            .filter(move |coin| keychains.contains(&coin.address.terminal.keychain))
+            .map(|mut coin| {
+                coin.label = self.output_label(coin.outpoint, &coin.address.addr);
+                coin
+            })
+    }
+
+    fn output_label(&self, outpoint: Outpoint, addr: &Address) -> Option<String> {
+        let data = &self.data;
+        data.txout_annotations.get(&outpoint).or_else(|| data.addr_annotations.get(addr)).cloned()
     }
 
     pub fn address_coins(
@@ -969,8 +1896,17 @@ impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2>
     }
 
     #[inline]
+    /// Lists wallet transactions in chronological order, i.e. ordered by the height of the block
+    /// mining them, followed by the unconfirmed transactions.
+    ///
+    /// The history is computed from the wallet cache and doesn't require access to an indexer.
     pub fn history(&self) -> impl Iterator<Item = TxRow<<L2::Cache as Layer2Cache>::Tx>> + '_ {
-        self.cache.history()
+        let mut rows = self.cache.history().collect::<Vec<_>>();
+        rows.sort_by_key(|row| (!row.height.is_mined(), row.height, row.time));
+        rows.into_iter().map(|mut row| {
+            row.label = self.data.tx_annotations.get(&row.txid).cloned();
+            row
+        })
     }
 
     pub fn has_outpoint(&self, outpoint: Outpoint) -> bool { self.cache.has_outpoint(outpoint) }
@@ -984,12 +1920,13 @@ impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2>
     }
 
     pub fn utxos(&self) -> impl Iterator<Item = WalletUtxo> + '_ {
-        self.cache.utxos().flat_map(|outpoint| {
+        let keychains = self.descr.keychains();
+        self.cache.utxos().flat_map(move |outpoint| {
             let tx = self.cache.tx(&outpoint.txid).expect("cache data inconsistency");
             let debit = tx.outputs.get(outpoint.vout_usize()).expect("cache data inconsistency");
             let terminal =
                 debit.derived_addr().expect("UTXO doesn't belong to the wallet").terminal;
-            if debit.spent.is_some() {
+            if debit.spent.is_some() || !keychains.contains(&terminal.keychain) {
                 None
             } else {
                 Some(WalletUtxo {
@@ -1002,13 +1939,27 @@ impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache>, L2: Layer2>
         })
     }
 
+    pub fn is_locked(&self, outpoint: Outpoint) -> bool {
+        self.data.locked_utxos.contains_key(&outpoint)
+    }
+
+    /// Label given to the wallet output when it was locked, if any.
+    pub fn lock_label(&self, outpoint: Outpoint) -> Option<&str> {
+        self.data.locked_utxos.get(&outpoint)?.as_deref()
+    }
+
+    /// Unspent outputs which are not locked and can be used by the coin selection.
+    pub fn spendable_utxos(&self) -> impl Iterator<Item = WalletUtxo> + '_ {
+        self.utxos().filter(|utxo| !self.is_locked(utxo.outpoint))
+    }
+
     pub fn coinselect<'a>(
         &'a self,
         up_to: Sats,
         selector: impl Fn(&WalletUtxo) -> bool + 'a,
     ) -> impl Iterator<Item = Outpoint> + 'a {
         let mut selected = Sats::ZERO;
-        self.utxos()
+        self.spendable_utxos()
             .filter(selector)
             .take_while(move |utxo| {
                 if selected <= up_to {
@@ -1031,39 +1982,1009 @@ impl<K, D: Descriptor<K>, L2: Layer2> Wallet<K, D, WalletCache<L2::Cache>, L2> {
 
 impl<K, D: Descriptor<K>, L2: Layer2> Wallet<K, D, WalletCache<L2::Cache>, L2> {
     #[must_use]
-    pub fn update<I: Indexer>(&mut self, indexer: &I) -> MayError<(), Vec<I::Error>> {
-        indexer.update::<K, D, L2>(&self.descr, &mut self.cache).map(drop)
+    pub fn update<I: Indexer>(&mut self, indexer: &I) -> SyncReport<I::Error> {
+        self.cache.birth_time = self.data.birth_time;
+        let res = indexer.update::<K, D, L2>(&self.descr, &mut self.cache);
+        self.cache.mark_dirty();
+        self.index_scripts();
+        SyncReport::from(res)
     }
-}
 
-impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache> + Persisting, L2: Layer2>
-    Wallet<K, D, Cache, L2>
-{
-    pub fn load<P>(
-        provider: P,
-        autosave: bool,
-    ) -> Result<Wallet<K, D, Cache, L2>, PersistenceError>
-    where
-        P: Clone
-            + PersistenceProvider<WalletDescr<K, D, L2::Descr>>
-            + PersistenceProvider<WalletData<L2::Data>>
-            + PersistenceProvider<Cache>
-            + PersistenceProvider<L2>
-            + 'static,
-    {
-        let descr = WalletDescr::<K, D, L2::Descr>::load(provider.clone(), autosave)?;
-        let data = WalletData::<L2::Data>::load(provider.clone(), autosave)?;
-        let cache = Cache::load(provider.clone(), autosave)?;
-        let layer2 = L2::load(provider, autosave)?;
+    #[must_use]
+    pub fn update_with_progress<I: Indexer>(
+        &mut self,
+        indexer: &I,
+        progress: &mut dyn SyncProgress,
+    ) -> SyncReport<I::Error> {
+        self.cache.birth_time = self.data.birth_time;
+        let res = indexer.update_with_progress::<K, D, L2>(&self.descr, &mut self.cache, progress);
+        self.cache.mark_dirty();
+        self.index_scripts();
+        SyncReport::from(res)
+    }
 
-        Ok(Wallet {
-            descr,
-            data,
-            cache,
-            layer2,
+    #[cfg(feature = "async")]
+    #[must_use]
+    pub async fn update_async<I: AsyncIndexer>(&mut self, indexer: &I) -> SyncReport<I::Error> {
+        self.cache.birth_time = self.data.birth_time;
+        let res = indexer.update::<K, D, L2>(&self.descr, &mut self.cache).await;
+        self.cache.mark_dirty();
+        self.index_scripts();
+        SyncReport::from(res)
+    }
+
+    /// Makes the next sync check at least `depth` addresses of the `keychain` regardless of its
+    /// gap limit, for instance to discover all the addresses watched by another wallet software
+    /// from which the wallet was imported.
+    pub fn set_scan_depth(&mut self, keychain: impl Into<Keychain>, depth: u32) {
+        self.cache.scan_depth.insert(keychain.into(), depth);
+        self.cache.mark_dirty();
+    }
+
+    /// Forgets blocks up to which the wallet was synchronized and the address script statuses,
+    /// such that the next update re-requests the whole wallet history from the indexer.
+    pub fn reset_sync(&mut self) {
+        self.cache.last_sync.clear();
+        self.cache.script_status.clear();
+        self.cache.mark_dirty();
+    }
+
+    /// Returns the height of the most recent block known from the last wallet sync, if the wallet
+    /// was ever synchronized.
+    pub fn last_sync_height(&self) -> Option<BlockHeight> {
+        self.cache
+            .last_sync
+            .values()
+            .map(|info| info.height)
+            .chain(Some(self.cache.last_block.height).filter(|h| *h > BlockHeight::MIN))
+            .max()
+    }
+
+    /// Returns the chain tip reported by the indexer during the last wallet sync, if the wallet
+    /// was ever synchronized.
+    pub fn last_block(&self) -> Option<MiningInfo> {
+        Some(self.cache.last_block).filter(|info| info.height > BlockHeight::MIN)
+    }
+
+    /// Chain height against which the confirmations of the wallet transactions are counted: the
+    /// tip known from the last sync, unless the cache has transactions mined above it, which
+    /// happens if the tip was not retrieved during the sync.
+    fn confirmation_tip(&self) -> BlockHeight {
+        self.cache
+            .txs()
+            .filter_map(|(_, tx)| tx.status.height())
+            .chain(self.last_sync_height())
+            .max()
+            .unwrap_or(BlockHeight::MIN)
+    }
+
+    /// Lists all addresses of each keychain up to the last used or reserved one, annotated with
+    /// their usage and balance.
+    ///
+    /// The information is computed from the wallet cache and reflects the state of the last
+    /// wallet sync.
+    pub fn address_usage(&self) -> Vec<AddrUsage> {
+        let tip = self.confirmation_tip();
+        let mut tx_count = BTreeMap::<Terminal, u32>::new();
+        for (_, tx) in self.cache.txs() {
+            let terminals = tx
+                .inputs
+                .iter()
+                .filter_map(TxCredit::derived_addr)
+                .chain(tx.outputs.iter().filter_map(TxDebit::derived_addr))
+                .map(|derived| derived.terminal)
+                .collect::<BTreeSet<_>>();
+            for terminal in terminals {
+                *tx_count.entry(terminal).or_default() += 1;
+            }
+        }
+        let mut balances = BTreeMap::<Terminal, Balance>::new();
+        for outpoint in self.cache.utxos() {
+            let tx = self.cache.tx(&outpoint.txid).expect("cache data inconsistency");
+            let debit = tx.outputs.get(outpoint.vout_usize()).expect("cache data inconsistency");
+            if debit.spent.is_some() {
+                continue;
+            }
+            let terminal =
+                debit.derived_addr().expect("UTXO doesn't belong to the wallet").terminal;
+            balances.entry(terminal).or_default().add_coin(
+                debit.value,
+                tx.status.confirmations(tip),
+                tx.is_coinbase(),
+                1,
+            );
+        }
+
+        let mut usage = vec![];
+        for keychain in self.descr.keychains() {
+            let reserved = self.data.last_used.get(&keychain).copied().unwrap_or_default();
+            let last = self.last_derivation_index(keychain);
+            for derived in self.addresses(keychain).take_while(|d| d.terminal.index < last) {
+                let terminal = derived.terminal;
+                usage.push(AddrUsage {
+                    terminal,
+                    script_pubkey: derived.addr.script_pubkey(),
+                    addr: derived.addr,
+                    derivation: self.derivation_paths(terminal),
+                    tx_count: tx_count.get(&terminal).copied().unwrap_or_default(),
+                    balance: balances.get(&terminal).copied().unwrap_or_default(),
+                    reserved: terminal.index < reserved,
+                    label: self.data.addr_annotations.get(&derived.addr).cloned(),
+                });
+            }
+        }
+        usage
+    }
+
+    /// Computes balance of each of the wallet keychains, split by the confirmation status of the
+    /// coins. Coins with less than `min_confirmations` confirmations are counted as unconfirmed;
+    /// coins which are not mined yet are always unconfirmed.
+    pub fn balance_breakdown(&self, min_confirmations: u32) -> BTreeMap<Keychain, Balance> {
+        let tip = self.confirmation_tip();
+        let keychains = self.descr.keychains();
+        let mut breakdown = BTreeMap::<Keychain, Balance>::new();
+        for outpoint in self.cache.utxos() {
+            let tx = self.cache.tx(&outpoint.txid).expect("cache data inconsistency");
+            let debit = tx.outputs.get(outpoint.vout_usize()).expect("cache data inconsistency");
+            if debit.spent.is_some() {
+                continue;
+            }
+            let terminal =
+                debit.derived_addr().expect("UTXO doesn't belong to the wallet").terminal;
+            if !keychains.contains(&terminal.keychain) {
+                continue;
+            }
+            let balance = breakdown.entry(terminal.keychain).or_default();
+            if self.is_locked(outpoint) {
+                balance.locked.saturating_add_assign(debit.value);
+                continue;
+            }
+            balance.add_coin(
+                debit.value,
+                tx.status.confirmations(tip),
+                tx.is_coinbase(),
+                min_confirmations,
+            );
+        }
+        breakdown
+    }
+
+    /// Checks that the chain known to the `indexer` is consistent with the chain tip stored during
+    /// the last wallet sync. Must be called before the next sync, which updates the stored tip.
+    pub fn check_tip<I: Indexer>(&self, indexer: &I) -> Result<TipCheck, I::Error> {
+        let Some(stored) = self.last_block() else {
+            return Ok(TipCheck::Unknown);
+        };
+        let tip = indexer.tip()?;
+        if tip.height < stored.height {
+            return Ok(TipCheck::with(stored, tip, stored.block_hash));
+        }
+        let block_hash = indexer.block_hash(stored.height.get())?;
+        Ok(TipCheck::with(stored, tip, block_hash))
+    }
+
+    /// Asynchronous version of [`Wallet::check_tip`].
+    #[cfg(feature = "async")]
+    pub async fn check_tip_async<I: AsyncIndexer>(
+        &self,
+        indexer: &I,
+    ) -> Result<TipCheck, I::Error> {
+        let Some(stored) = self.last_block() else {
+            return Ok(TipCheck::Unknown);
+        };
+        let tip = indexer.tip().await?;
+        if tip.height < stored.height {
+            return Ok(TipCheck::with(stored, tip, stored.block_hash));
+        }
+        let block_hash = indexer.block_hash(stored.height.get()).await?;
+        Ok(TipCheck::with(stored, tip, block_hash))
+    }
+}
+
+impl<K, D: Descriptor<K>, L2: Layer2> Wallet<K, D, WalletCache<L2::Cache>, L2> {
+    /// Constructs PSBT paying to the `beneficiaries` from the spendable wallet UTXOs chosen by
+    /// the `coin_select` algorithm. The change, if any, is sent to a new address of the change
+    /// keychain.
+    ///
+    /// If none of the beneficiaries is paid a fixed amount, all spendable UTXOs are spent, and the
+    /// beneficiaries paid with [`Payment::Max`] receive the whole amount remaining after the fee.
+    /// Locked UTXOs are never spent, as well as unconfirmed ones if
+    /// [`SpendParams::confirmed_only`] is set.
+    pub fn build_tx(
+        &mut self,
+        beneficiaries: &[Beneficiary],
+        params: SpendParams,
+        coin_select: &impl CoinSelect,
+    ) -> Result<BuiltTx, BuildTxError> {
+        let candidates = self
+            .spendable_utxos()
+            .filter(|utxo| !params.confirmed_only || coinselect::confirmed(utxo))
+            .collect::<Vec<_>>();
+        self.build_tx_from(candidates, beneficiaries, params, coin_select)
+    }
+
+    /// Estimates the cost of the transaction which [`Wallet::build_tx`] would construct with the
+    /// same arguments, without constructing it. The wallet state is not modified, and no change
+    /// address gets reserved.
+    pub fn estimate_tx(
+        &self,
+        beneficiaries: &[Beneficiary],
+        params: SpendParams,
+        coin_select: &impl CoinSelect,
+    ) -> Result<TxEstimate, BuildTxError> {
+        let candidates = self
+            .spendable_utxos()
+            .filter(|utxo| !params.confirmed_only || coinselect::confirmed(utxo))
+            .collect::<Vec<_>>();
+        self.estimate_tx_from(&candidates, beneficiaries, params, coin_select)
+    }
+
+    /// Constructs a single PSBT paying all the `batch` payments.
+    ///
+    /// Before the coin selection, the total amount of the payments together with the fee of a
+    /// transaction having just one input is checked against the spendable wallet balance.
+    pub fn pay_batch(
+        &mut self,
+        batch: &Batch,
+        params: SpendParams,
+        coin_select: &impl CoinSelect,
+    ) -> Result<BuiltTx, BuildTxError> {
+        let total = batch.total()?;
+        let candidates = self
+            .spendable_utxos()
+            .filter(|utxo| !params.confirmed_only || coinselect::confirmed(utxo))
+            .collect::<Vec<_>>();
+        let available = candidates.iter().map(|utxo| utxo.value).sum::<Sats>();
+        let outputs = batch
+            .payments()
+            .map(|(address, _)| address.script_pubkey())
+            .chain(params.op_return.map(|op_return| op_return.script_pubkey()))
+            .collect::<Vec<_>>();
+        let min_fee = match params.fee {
+            FeePolicy::Rate(fee_rate) => candidates
+                .iter()
+                .map(|utxo| {
+                    let script = self.utxo_script(utxo);
+                    fees::fee_for_rate(fees::estimate_vsize([&script], &outputs), fee_rate)
+                })
+                .min()
+                .unwrap_or_default(),
+            FeePolicy::Absolute(fee) => fee,
+        };
+        let required = total.checked_add(min_fee).ok_or(BatchError::Overflow)?;
+        if available < required {
+            return Err(BuildTxError::InsufficientFunds(available, required));
+        }
+        self.build_tx_from(candidates, &batch.beneficiaries(), params, coin_select)
+    }
+
+    /// Constructs PSBT spending all spendable wallet UTXOs matching the `filter` to a single
+    /// `address`, which receives the whole amount remaining after the fee. The transaction never
+    /// has a change output.
+    ///
+    /// Locked UTXOs are never swept, as well as unconfirmed ones if
+    /// [`SpendParams::confirmed_only`] is set. With a fee rate, UTXOs worth less than the fee for
+    /// spending them are left unspent, since they would reduce the swept amount. The fee for an
+    /// input doesn't depend on the other inputs, so a single pass over the UTXOs reaches the fixed
+    /// point of the selection.
+    ///
+    /// Fails if the amount remaining after the fee is below the dust limit of the `address`.
+    pub fn sweep(
+        &mut self,
+        address: Address,
+        params: SpendParams,
+        filter: SweepFilter,
+    ) -> Result<BuiltTx, BuildTxError> {
+        let mut candidates = self.sweep_candidates(params, filter);
+        if candidates.is_empty() {
+            return Err(BuildTxError::NothingToSweep);
+        }
+        if let FeePolicy::Rate(fee_rate) = params.fee {
+            let input_fee = |utxo: &WalletUtxo| {
+                fees::fee_for_rate(fees::input_vsize(&self.utxo_script(utxo)), fee_rate)
+            };
+            let economic = candidates
+                .iter()
+                .copied()
+                .filter(|utxo| utxo.value > input_fee(utxo))
+                .collect::<Vec<_>>();
+            // If no UTXO is worth spending, the transaction construction reports the fee problem
+            if !economic.is_empty() {
+                candidates = economic;
+            }
+        }
+        self.sweep_from(candidates, address, params)
+    }
+
+    /// Constructs PSBT consolidating spendable wallet UTXOs matching the `filter` into a single
+    /// output on a new address of the internal (change) keychain, which receives the whole amount
+    /// remaining after the fee.
+    ///
+    /// Fails if fewer than `min_inputs` UTXOs match the filter, or if the fee is not less than
+    /// the total value of the consolidated UTXOs. The address is reserved only if the PSBT is
+    /// constructed.
+    pub fn consolidate(
+        &mut self,
+        params: SpendParams,
+        filter: SweepFilter,
+        min_inputs: usize,
+    ) -> Result<BuiltTx, BuildTxError> {
+        let candidates = self.sweep_candidates(params, filter);
+        if candidates.is_empty() || candidates.len() < min_inputs {
+            return Err(BuildTxError::NothingToConsolidate(candidates.len(), min_inputs.max(1)));
+        }
+        let keychain = self.change_keychain();
+        let address = self.peek_address(keychain).addr;
+        let built = self.sweep_from(candidates, address, params).map_err(|err| match err {
+            BuildTxError::SweepFeeTooHigh(total, fee) => {
+                BuildTxError::ConsolidationTooExpensive(total, fee)
+            }
+            err => err,
+        })?;
+        self.next_address(keychain);
+        Ok(built)
+    }
+
+    /// Keychain receiving the change: the internal keychain, if the wallet has one, or the default
+    /// keychain otherwise. The keychain filter is not taken into account, such that restricting
+    /// the wallet to the receiving keychain doesn't send change to receiving addresses.
+    pub fn change_keychain(&self) -> Keychain {
+        let keychains = self.descr.generator.keychains();
+        keychains
+            .into_iter()
+            .find(|keychain| *keychain == Keychain::from(1u8))
+            .unwrap_or_else(|| self.descr.default_keychain())
+    }
+
+    /// Reserves derivation index for a change output according to the `policy`, recording it in
+    /// the wallet data, such that it is not used for a change again.
+    pub fn reserve_change(
+        &mut self,
+        policy: ChangePolicy,
+    ) -> Result<(Keychain, NormalIndex), BuildTxError> {
+        let (keychain, index) = self.change_index(policy)?;
+        self.record_change(keychain, index);
+        Ok((keychain, index))
+    }
+
+    /// Chooses derivation index for a change output according to the `policy` without reserving
+    /// it.
+    fn change_index(&self, policy: ChangePolicy) -> Result<(Keychain, NormalIndex), BuildTxError> {
+        let keychain = self.change_keychain();
+        let next = self.last_derivation_index(keychain);
+        let index = match policy {
+            ChangePolicy::NextUnused => next,
+            ChangePolicy::Fixed(index) if index < next => {
+                return Err(BuildTxError::ChangeIndexUsed(index));
+            }
+            ChangePolicy::Fixed(index) => {
+                let gap_limit = self.descr.gap_limit(keychain);
+                if index.index() - next.index() >= gap_limit {
+                    return Err(BuildTxError::ChangeIndexBeyondGap(index, next, gap_limit));
+                }
+                index
+            }
+        };
+        Ok((keychain, index))
+    }
+
+    fn record_change(&mut self, keychain: Keychain, index: NormalIndex) {
+        self.data.last_used.insert(keychain, index.saturating_add(1u32));
+        self.data.mark_dirty();
+    }
+
+    /// Script of the first address of the change keychain, which is used to estimate the size of
+    /// the change output.
+    fn change_script(&self) -> ScriptPubkey {
+        self.addresses(self.change_keychain()).next().expect("wallet address").addr.script_pubkey()
+    }
+
+    /// Script locking the wallet UTXO, which defines the size of the input spending it.
+    fn utxo_script(&self, utxo: &WalletUtxo) -> DerivedScript {
+        self.derived_script(utxo.terminal)
+    }
+
+    /// Script derived by the wallet descriptor at the `terminal`.
+    fn derived_script(&self, terminal: Terminal) -> DerivedScript {
+        self.descr
+            .generator
+            .derive(terminal.keychain, terminal.index)
+            .next()
+            .expect("descriptor derives a script")
+    }
+
+    fn sweep_candidates(&self, params: SpendParams, filter: SweepFilter) -> Vec<WalletUtxo> {
+        self.spendable_utxos()
+            .filter(|utxo| !params.confirmed_only || coinselect::confirmed(utxo))
+            .filter(|utxo| filter.matches(utxo))
+            .collect()
+    }
+
+    fn sweep_from(
+        &mut self,
+        candidates: Vec<WalletUtxo>,
+        address: Address,
+        params: SpendParams,
+    ) -> Result<BuiltTx, BuildTxError> {
+        let beneficiary = Beneficiary::new(address, Payment::Max);
+        // All coins are spent to the single beneficiary, so coin selection is not used
+        let coin_select = Strategy::LargestFirst;
+        let res = self.build_tx_from(candidates, &[beneficiary], params, &coin_select);
+        res.map_err(|err| match err {
+            BuildTxError::InsufficientFunds(total, fee) => {
+                BuildTxError::SweepFeeTooHigh(total, fee)
+            }
+            err => err,
         })
     }
 
+    fn build_tx_from(
+        &mut self,
+        candidates: Vec<WalletUtxo>,
+        beneficiaries: &[Beneficiary],
+        params: SpendParams,
+        coin_select: &impl CoinSelect,
+    ) -> Result<BuiltTx, BuildTxError> {
+        let TxEstimate {
+            inputs: coins,
+            fee,
+            strategy,
+            change,
+            dropped_change,
+            vsize,
+            fee_rate,
+            ..
+        } = self.estimate_tx_from(&candidates, beneficiaries, params, coin_select)?;
+
+        let mut tx_params = params.tx_params(fee);
+        // The change index is reserved only once the transaction is constructed, such that a
+        // failure doesn't waste it
+        let change_index = if change > Sats::ZERO {
+            let (keychain, index) = self.change_index(params.change)?;
+            tx_params.change_keychain = keychain;
+            Some(index)
+        } else {
+            None
+        };
+        // The transaction is registered in the cache only once it has all its outputs in the
+        // final order, since they define its txid
+        let mut deferred = Deferred {
+            wallet: self,
+            change_index,
+        };
+        let (mut psbt, mut meta) =
+            deferred.construct_psbt(coins, beneficiaries.iter().copied(), tx_params)?;
+        if let Some(index) = change_index {
+            self.record_change(tx_params.change_keychain, index);
+        }
+        if let Some(op_return) = params.op_return {
+            psbt.append_output_expect(op_return.script_pubkey(), Sats::ZERO);
+        }
+        if params.order == TxOrder::Bip69 {
+            sort_outputs_bip69(&mut psbt, &mut meta);
+        }
+        self.after_construct_psbt(&psbt, &meta);
+        psbt.version = params.version;
+        Ok(BuiltTx {
+            psbt,
+            meta,
+            strategy,
+            change,
+            dropped_change,
+            vsize,
+            fee_rate,
+        })
+    }
+
+    fn estimate_tx_from(
+        &self,
+        candidates: &[WalletUtxo],
+        beneficiaries: &[Beneficiary],
+        params: SpendParams,
+        coin_select: &impl CoinSelect,
+    ) -> Result<TxEstimate, BuildTxError> {
+        let change_script = self.change_script();
+        let candidate_scripts = candidates
+            .iter()
+            .map(|utxo| (utxo.outpoint, self.utxo_script(utxo)))
+            .collect::<BTreeMap<_, _>>();
+        let outputs = beneficiaries
+            .iter()
+            .map(|beneficiary| beneficiary.address.script_pubkey())
+            .chain(params.op_return.map(|op_return| op_return.script_pubkey()))
+            .collect::<Vec<_>>();
+        let available = candidates.iter().map(|utxo| utxo.value).sum::<Sats>();
+        let total_amount = beneficiaries.iter().try_fold(Sats::ZERO, |sats, b| match b.amount {
+            Payment::Max => Err(()),
+            Payment::Fixed(s) => sats.checked_add(s).ok_or(()),
+        });
+        let (coins, fee, strategy, change, dropped_change) = match total_amount {
+            Ok(sats) if sats > Sats::ZERO => {
+                let (fee_rate, fixed_fee) = match params.fee {
+                    FeePolicy::Rate(fee_rate) => (fee_rate, Sats::ZERO),
+                    FeePolicy::Absolute(fee) => (0.0, fee),
+                };
+                // Coin selection sizes all inputs alike, so the largest input is assumed, and the
+                // fee is corrected for the actual size of the selected inputs afterwards
+                let input_vsize =
+                    candidate_scripts.values().map(fees::input_vsize).max().unwrap_or_default();
+                let selection_params = SelectionParams {
+                    target: sats + fixed_fee,
+                    fee_rate,
+                    base_vsize: fees::TX_OVERHEAD_VBYTES
+                        + 1
+                        + outputs.iter().map(fees::output_vsize).sum::<u32>(),
+                    input_vsize,
+                    change_vsize: fees::output_vsize(&change_script),
+                    min_change: fees::dust_limit(&change_script),
+                    changeless_window: params.changeless_window,
+                    dust_policy: params.dust_policy,
+                    min_inputs: params.min_inputs,
+                    max_inputs: params.max_inputs,
+                };
+                check_input_limits(candidates, &selection_params)?;
+                let mut selection =
+                    coin_select.select(candidates, &selection_params).ok_or_else(|| {
+                        let fee = selection_params.fee(candidates.len(), false);
+                        let required = selection_params.target + fee;
+                        if available < required {
+                            return BuildTxError::InsufficientFunds(available, required);
+                        }
+                        // The limit is reported only if even the largest coins can't fit into it
+                        if let Some(max) = selection_params.max_inputs {
+                            let mut values =
+                                candidates.iter().map(|utxo| utxo.value).collect::<Vec<_>>();
+                            values.sort_by_key(|value| cmp::Reverse(*value));
+                            let largest = values.into_iter().take(max).sum::<Sats>();
+                            let required =
+                                selection_params.target + selection_params.fee(max, false);
+                            if largest < required {
+                                return BuildTxError::TooManyInputs {
+                                    max,
+                                    available: largest,
+                                    required,
+                                };
+                            }
+                        }
+                        BuildTxError::NoSelection(available, required)
+                    })?;
+                // Library strategies drop such change into the fee, but custom ones may not
+                if selection.change > Sats::ZERO && selection.change <= selection_params.min_change {
+                    return Err(BuildTxError::DustChange(
+                        selection.change,
+                        selection_params.min_change,
+                    ));
+                }
+                if let FeePolicy::Rate(fee_rate) = params.fee {
+                    let scripts =
+                        selection.inputs.iter().map(|outpoint| &candidate_scripts[outpoint]);
+                    let mut output_scripts = outputs.iter().collect::<Vec<_>>();
+                    if selection.has_change() {
+                        output_scripts.push(&change_script);
+                    }
+                    let vsize = fees::estimate_vsize(scripts, output_scripts);
+                    let required = fees::fee_for_rate(vsize, fee_rate);
+                    // The overestimate goes to the change, if there is one; otherwise all the fee
+                    // above the required one is the dropped change
+                    let excess = selection.fee.saturating_sub(required);
+                    if selection.has_change() {
+                        selection.fee = required;
+                        selection.change.saturating_add_assign(excess);
+                    } else {
+                        selection.dropped_change = excess;
+                    }
+                }
+                (
+                    selection.inputs,
+                    fixed_fee + selection.fee,
+                    selection.strategy,
+                    selection.change,
+                    selection.dropped_change,
+                )
+            }
+            _ => {
+                let inputs = candidates.iter().map(|utxo| &candidate_scripts[&utxo.outpoint]);
+                let fee = match params.fee {
+                    FeePolicy::Rate(fee_rate) => {
+                        fees::fee_for_rate(fees::estimate_vsize(inputs, &outputs), fee_rate)
+                    }
+                    FeePolicy::Absolute(fee) => fee,
+                };
+                // Nothing would be left for the outputs if the fee takes all the funds
+                if available <= fee {
+                    return Err(BuildTxError::InsufficientFunds(available, fee));
+                }
+                let is_max = |b: &&Beneficiary| matches!(b.amount, Payment::Max);
+                let max_count = beneficiaries.iter().filter(is_max).count();
+                if max_count > 0 {
+                    let fixed = beneficiaries
+                        .iter()
+                        .filter_map(|b| match b.amount {
+                            Payment::Fixed(sats) => Some(sats.sats()),
+                            Payment::Max => None,
+                        })
+                        .sum::<u64>();
+                    let remaining = (available.sats() - fee.sats()).saturating_sub(fixed);
+                    let share = Sats(remaining / max_count as u64);
+                    for beneficiary in beneficiaries.iter().filter(is_max) {
+                        let limit = fees::dust_limit(&beneficiary.address.script_pubkey());
+                        if share < limit {
+                            return Err(BuildTxError::DustOutput(share, limit));
+                        }
+                    }
+                }
+                let coins = candidates.iter().map(|utxo| utxo.outpoint).collect();
+                (coins, fee, None, Sats::ZERO, Sats::ZERO)
+            }
+        };
+
+        let mut coins = coins;
+        if params.order == TxOrder::Bip69 {
+            // Txids are compared in the reversed byte order, as they are displayed
+            coins.sort_by_key(|outpoint| (outpoint.txid.to_string(), outpoint.vout.into_u32()));
+        }
+        let (input_values, input_scripts): (Vec<_>, Vec<_>) = coins
+            .iter()
+            .map(|outpoint| self.cache.outpoint_by(*outpoint).expect("wallet UTXO").0)
+            .map(|utxo| (utxo.value, self.utxo_script(&utxo)))
+            .unzip();
+        let mut output_scripts = outputs.iter().collect::<Vec<_>>();
+        if change > Sats::ZERO {
+            output_scripts.push(&change_script);
+        }
+        let vsize = fees::estimate_vsize(&input_scripts, output_scripts);
+        let fee_rate = fee.sats() as f64 / vsize as f64;
+        if !params.allow_absurd_fee {
+            if fee_rate > params.max_fee_rate {
+                return Err(BuildTxError::AbsurdFeeRate(fee_rate, params.max_fee_rate));
+            }
+            // Requested fee rate is checked instead of the effective one, which may differ from
+            // it due to the fee rounding
+            let min_rate = match params.fee {
+                FeePolicy::Rate(rate) => rate,
+                FeePolicy::Absolute(_) => fee_rate,
+            };
+            if min_rate < fees::MIN_RELAY_FEE_RATE {
+                return Err(BuildTxError::FeeRateTooLow(min_rate, fees::MIN_RELAY_FEE_RATE));
+            }
+        }
+
+        Ok(TxEstimate {
+            inputs: coins,
+            input_value: input_values.into_iter().sum(),
+            fee,
+            strategy,
+            change,
+            dropped_change,
+            vsize,
+            fee_rate,
+        })
+    }
+
+    /// Publishes the signed transaction via the indexer and registers it in the wallet cache as
+    /// unconfirmed, such that the wallet balance reflects it without a re-sync. A transaction
+    /// already known to the indexer or a node is treated as successfully published.
+    pub fn broadcast<I: Indexer>(&mut self, indexer: &I, tx: &Tx) -> Result<Txid, BroadcastError>
+    where I::Error: Display {
+        if let Err(err) = indexer.broadcast(tx) {
+            if let Some(err) = BroadcastError::classify(err) {
+                return Err(err);
+            }
+        }
+        let network = AddressNetwork::from(self.descr.network);
+        self.cache.register_tx(tx, network);
+        self.cache.mark_dirty();
+        self.index_tx(tx.txid());
+        Ok(tx.txid())
+    }
+
+    /// Constructs PSBT for a BIP-125 replacement of the unconfirmed wallet transaction `txid`,
+    /// paying `fee_rate` satoshis per virtual byte.
+    ///
+    /// The replacement spends all inputs of the original transaction and pays the same amounts
+    /// to the same beneficiaries, taking the increased fee from the change. If the change is not
+    /// sufficient, other confirmed wallet UTXOs are added, starting from the largest ones, since
+    /// a replacement can't spend new unconfirmed outputs (BIP-125 rule 2). Change reduced below
+    /// the dust limit is absorbed into the fee.
+    ///
+    /// Unconfirmed wallet transactions spending outputs of the original one get evicted together
+    /// with it, so their outputs are not spent by the replacement, and the replacement pays for
+    /// their fees as well (BIP-125 rule 3).
+    pub fn bump_fee(
+        &mut self,
+        txid: Txid,
+        fee_rate: f64,
+    ) -> Result<(Psbt, PsbtMeta), BumpFeeError> {
+        let tx = self.cache.tx.get(&txid).ok_or(BumpFeeError::UnknownTx(txid))?.clone();
+        if tx.status.is_mined() {
+            return Err(BumpFeeError::Mined(txid));
+        }
+        if !tx.inputs.iter().any(|input| fees::signals_rbf(input.sequence)) {
+            return Err(BumpFeeError::NotReplaceable(txid));
+        }
+
+        let mut coins = Vec::with_capacity(tx.inputs.len());
+        let mut input_scripts = Vec::with_capacity(tx.inputs.len());
+        let mut payer_script = None;
+        let mut input_sum = Sats::ZERO;
+        for input in &tx.inputs {
+            let Party::Wallet(derive) = input.payer else {
+                return Err(BumpFeeError::ForeignInput(input.outpoint));
+            };
+            coins.push(input.outpoint);
+            input_scripts.push(self.derived_script(derive.terminal));
+            payer_script.get_or_insert_with(|| derive.addr.script_pubkey());
+            input_sum += input.value;
+        }
+        let reused = coins.iter().copied().collect::<BTreeSet<_>>();
+
+        // Outputs to our own keychains other than the default one are change, which gets
+        // re-created by the PSBT constructor; all other outputs are kept as they are.
+        let network = AddressNetwork::from(self.descr.network);
+        let mut beneficiaries = Vec::with_capacity(tx.outputs.len());
+        let mut output_scripts = Vec::with_capacity(tx.outputs.len() + 1);
+        let mut change_script = None;
+        let mut payment_sum = Sats::ZERO;
+        for (vout, output) in tx.outputs.iter().enumerate() {
+            let address = match &output.beneficiary {
+                Party::Wallet(derive)
+                    if derive.terminal.keychain != self.descr.default_keychain() =>
+                {
+                    change_script = Some(derive.addr.script_pubkey());
+                    continue;
+                }
+                Party::Wallet(derive) => derive.addr,
+                Party::Counterparty(addr) => *addr,
+                Party::Unknown(script) => Address::with(script, network)
+                    .map_err(|_| BumpFeeError::NonStandardOutput(vout as u32))?,
+                Party::Subsidy => return Err(BumpFeeError::SubsidyOutput(vout as u32)),
+            };
+            output_scripts.push(address.script_pubkey());
+            beneficiaries.push(Beneficiary::new(address, Payment::Fixed(output.value)));
+            payment_sum += output.value;
+        }
+        output_scripts.push(change_script.or(payer_script).expect("transaction has inputs"));
+
+        let evicted = self.unconfirmed_descendants(txid);
+        let replaced_fee = tx.fee + evicted.iter().map(|txid| self.cache.tx[txid].fee).sum();
+        let mut candidates = self
+            .spendable_utxos()
+            .filter(coinselect::confirmed)
+            .filter(|utxo| !reused.contains(&utxo.outpoint))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|utxo| cmp::Reverse(utxo.value));
+        let mut candidates = candidates.into_iter();
+        let (mut fee, min_fee) = loop {
+            let vsize = fees::estimate_vsize(&input_scripts, &output_scripts);
+            let fee = fees::fee_for_rate(vsize, fee_rate);
+            let min_fee =
+                replaced_fee + fees::fee_for_rate(vsize, fees::INCREMENTAL_RELAY_FEE_RATE);
+            let required = payment_sum + cmp::max(fee, min_fee);
+            if input_sum >= required {
+                break (fee, min_fee);
+            }
+            let Some(utxo) = candidates.next() else {
+                return Err(BumpFeeError::InsufficientFunds(input_sum, required));
+            };
+            coins.push(utxo.outpoint);
+            input_scripts.push(self.utxo_script(&utxo));
+            input_sum += utxo.value;
+        };
+        if fee < min_fee {
+            return Err(BumpFeeError::FeeTooLow(fee, min_fee));
+        }
+
+        // Change not exceeding the dust limit of its script is dropped and absorbed into the fee,
+        // unless this makes the fee absurdly high
+        let change = input_sum - payment_sum - fee;
+        let dust_limit = fees::dust_limit(output_scripts.last().expect("change script"));
+        if change > Sats::ZERO && change <= dust_limit {
+            output_scripts.pop();
+            let vsize = fees::estimate_vsize(&input_scripts, &output_scripts);
+            let absorbed = input_sum - payment_sum;
+            if absorbed.sats() as f64 / vsize as f64 > fees::MAX_FEE_RATE {
+                return Err(BumpFeeError::DustChange(change, dust_limit));
+            }
+            fee = absorbed;
+        }
+
+        let mut params = TxParams::with(fee);
+        params.seq_no = fees::seq_no(true);
+        let mut replacement = Replacement {
+            wallet: self,
+            reused,
+        };
+        Ok(replacement.construct_psbt(coins, beneficiaries, params)?)
+    }
+
+    /// Unconfirmed wallet transactions spending outputs of the transaction `txid`, directly or
+    /// through other unconfirmed transactions.
+    fn unconfirmed_descendants(&self, txid: Txid) -> BTreeSet<Txid> {
+        let mut descendants = BTreeSet::new();
+        let mut queue = vec![txid];
+        while let Some(parent) = queue.pop() {
+            for (child, tx) in &self.cache.tx {
+                if !tx.status.is_mined()
+                    && !descendants.contains(child)
+                    && tx.inputs.iter().any(|input| input.outpoint.txid == parent)
+                {
+                    descendants.insert(*child);
+                    queue.push(*child);
+                }
+            }
+        }
+        descendants
+    }
+
+    /// Constructs PSBT for a child transaction spending the largest unspent wallet output of the
+    /// unconfirmed transaction `parent` back to the change keychain, such that the fee rate of
+    /// both transactions together reaches `fee_rate` satoshis per virtual byte (CPFP).
+    pub fn cpfp(&mut self, parent: Txid, fee_rate: f64) -> Result<CpfpTx, CpfpError> {
+        let tx = self.cache.tx.get(&parent).ok_or(CpfpError::UnknownTx(parent))?;
+        if tx.status.is_mined() {
+            return Err(CpfpError::Mined(parent));
+        }
+        let parent_fee = tx.fee;
+        let parent_vsize = tx.weight.div_ceil(4);
+
+        let (utxo, script) = tx
+            .outputs
+            .iter()
+            .filter_map(|debit| self.cache.outpoint_by(debit.outpoint).ok())
+            .max_by_key(|(utxo, _)| utxo.value)
+            .ok_or(CpfpError::NoWalletOutput(parent))?;
+
+        let vsize = fees::estimate_vsize([&self.utxo_script(&utxo)], [&script]);
+        let package_vsize = parent_vsize + vsize;
+        let package_fee = fees::fee_for_rate(package_vsize, fee_rate);
+        let fee = cmp::max(
+            if package_fee > parent_fee { package_fee - parent_fee } else { Sats::ZERO },
+            fees::fee_for_rate(vsize, fees::MIN_RELAY_FEE_RATE),
+        );
+        // The child output goes to the change keychain of the same wallet descriptor
+        if utxo.value <= fee + fees::dust_limit(&script) {
+            return Err(CpfpError::InsufficientValue(utxo.value, fee));
+        }
+
+        let mut params = TxParams::with(fee);
+        params.seq_no = fees::seq_no(true);
+        let (psbt, meta) =
+            self.construct_psbt([utxo.outpoint], Vec::<Beneficiary>::new(), params)?;
+        let package_fee_rate = (parent_fee + fee).sats() as f64 / package_vsize as f64;
+        Ok(CpfpTx {
+            psbt,
+            meta,
+            package_vsize,
+            package_fee_rate,
+        })
+    }
+}
+
+/// PSBT constructor which is able to re-spend inputs of a transaction being replaced.
+struct Replacement<'wallet, K, D: Descriptor<K>, L2: Layer2> {
+    wallet: &'wallet mut Wallet<K, D, WalletCache<L2::Cache>, L2>,
+    reused: BTreeSet<Outpoint>,
+}
+
+impl<K, D: Descriptor<K>, L2: Layer2> PsbtConstructor for Replacement<'_, K, D, L2> {
+    type Key = K;
+    type Descr = D;
+
+    fn descriptor(&self) -> &D { self.wallet.descriptor() }
+
+    fn utxo(&self, outpoint: Outpoint) -> Option<(Utxo, ScriptPubkey)> {
+        if !self.reused.contains(&outpoint) {
+            return self.wallet.utxo(outpoint);
+        }
+        let tx = self.wallet.cache.tx.get(&outpoint.txid)?;
+        let debit = tx.outputs.get(outpoint.vout.into_usize())?;
+        let terminal = debit.derived_addr()?.terminal;
+        let utxo = Utxo {
+            outpoint,
+            value: debit.value,
+            terminal,
+        };
+        Some((utxo, debit.beneficiary.script_pubkey()?))
+    }
+
+    fn network(&self) -> Network { self.wallet.network() }
+
+    fn next_derivation_index(&mut self, keychain: impl Into<Keychain>, shift: bool) -> NormalIndex {
+        self.wallet.next_derivation_index(keychain, shift)
+    }
+
+    fn after_construct_psbt(&mut self, psbt: &Psbt, meta: &PsbtMeta) {
+        self.wallet.after_construct_psbt(psbt, meta)
+    }
+}
+
+/// Sorts PSBT outputs by their amounts and then scripts according to BIP-69, updating the
+/// position of the change output in the PSBT metadata.
+fn sort_outputs_bip69(psbt: &mut Psbt, meta: &mut PsbtMeta) {
+    let bip69_key = |output: &psbt::Output| (output.value(), output.script.to_vec());
+    let change_key = meta.change.map(|change| {
+        let output = psbt.outputs().nth(change.vout.into_usize()).expect("change output");
+        bip69_key(output)
+    });
+    psbt.sort_outputs_by(bip69_key).expect("constructed PSBT outputs are modifiable");
+    if let (Some(change), Some(key)) = (&mut meta.change, change_key) {
+        let vout = psbt.outputs().position(|output| bip69_key(output) == key);
+        change.vout = Vout::from_u32(vout.expect("change output") as u32);
+    }
+}
+
+/// Checks that the input count limits of the coin selection are consistent with each other and
+/// with the number of the `candidates`.
+fn check_input_limits(
+    candidates: &[WalletUtxo],
+    params: &SelectionParams,
+) -> Result<(), BuildTxError> {
+    if let (Some(min), Some(max)) = (params.min_inputs, params.max_inputs) {
+        if min > max {
+            return Err(BuildTxError::InputLimits(min, max));
+        }
+    }
+    match params.min_inputs {
+        Some(min) if candidates.len() < min => {
+            Err(BuildTxError::TooFewInputs(candidates.len(), min))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// PSBT constructor which leaves registration of the constructed PSBT in the wallet cache to
+/// the caller, allowing to modify the PSBT outputs before that.
+struct Deferred<'wallet, K, D: Descriptor<K>, L2: Layer2> {
+    wallet: &'wallet mut Wallet<K, D, WalletCache<L2::Cache>, L2>,
+    /// Derivation index of the change output chosen by the caller.
+    change_index: Option<NormalIndex>,
+}
+
+impl<K, D: Descriptor<K>, L2: Layer2> PsbtConstructor for Deferred<'_, K, D, L2> {
+    type Key = K;
+    type Descr = D;
+
+    fn descriptor(&self) -> &D { self.wallet.descriptor() }
+
+    fn utxo(&self, outpoint: Outpoint) -> Option<(Utxo, ScriptPubkey)> {
+        self.wallet.utxo(outpoint)
+    }
+
+    fn network(&self) -> Network { self.wallet.network() }
+
+    fn next_derivation_index(&mut self, keychain: impl Into<Keychain>, shift: bool) -> NormalIndex {
+        match self.change_index {
+            Some(index) => index,
+            None => self.wallet.next_derivation_index(keychain, shift),
+        }
+    }
+
+    fn after_construct_psbt(&mut self, _: &Psbt, _: &PsbtMeta) {}
+}
+
+impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache> + Persisting, L2: Layer2>
+    Wallet<K, D, Cache, L2>
+{
+    pub fn load<P>(
+        provider: P,
+        autosave: bool,
+    ) -> Result<Wallet<K, D, Cache, L2>, PersistenceError>
+    where
+        P: Clone
+            + PersistenceProvider<WalletDescr<K, D, L2::Descr>>
+            + PersistenceProvider<WalletData<L2::Data>>
+            + PersistenceProvider<Cache>
+            + PersistenceProvider<L2>
+            + 'static,
+    {
+        let descr = WalletDescr::<K, D, L2::Descr>::load(provider.clone(), autosave)?;
+        let data = WalletData::<L2::Data>::load(provider.clone(), autosave)?;
+        let cache = Cache::load(provider.clone(), autosave)?;
+        let layer2 = L2::load(provider, autosave)?;
+
+        Ok(Wallet::bind(descr, data, cache, layer2))
+    }
+
     pub fn make_persistent<P>(
         &mut self,
         provider: P,
@@ -1095,3 +3016,1161 @@ impl<K, D: Descriptor<K>, Cache: WalletCacheProvider<L2::Cache> + Persisting, L2
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bpstd::{
+        BlockHash, DeriveScripts, SeqNo, SigScript, TxIn, TxOut, TxVer, VarIntArray, XpubDerivable,
+    };
+    use descriptors::{Pkh, StdDescr, TrKey};
+
+    use crate::coinselect::Selection;
+    use crate::indexers::{SyncProgress, UnsupportedRequest};
+
+    use super::*;
+
+    type TestWallet = Wallet<XpubDerivable, StdDescr, WalletCache<Layer2Empty>>;
+
+    fn test_xpub() -> XpubDerivable {
+        XpubDerivable::from_str(
+            "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1>/*",
+        )
+        .unwrap()
+    }
+
+    /// Wallet having confirmed coins of the given `values` on its first receiving addresses.
+    fn funded_wallet(values: &[u64]) -> TestWallet {
+        funded_wallet_with(TrKey::from(test_xpub()).into(), values)
+    }
+
+    /// Wallet with the `descr` descriptor, funded like [`funded_wallet`].
+    fn funded_wallet_with(descr: StdDescr, values: &[u64]) -> TestWallet {
+        let mut wallet = TestWallet::new_layer1(descr, Network::Mainnet);
+        let txid = wallet_funding_txid();
+        let mut outputs = vec![];
+        for (no, value) in values.iter().copied().enumerate() {
+            let derived = wallet.addresses(0u8).nth(no).unwrap();
+            let outpoint = Outpoint::new(txid, no as u32);
+            let mut addr = WalletAddr::from(derived);
+            addr.used = 1;
+            addr.volume = Sats(value);
+            addr.balance = Sats(value);
+            wallet.cache.addr.entry(derived.terminal.keychain).or_default().insert(addr);
+            wallet.cache.utxo.insert(outpoint);
+            outputs.push(TxDebit {
+                outpoint,
+                beneficiary: Party::Wallet(derived),
+                value: Sats(value),
+                spent: None,
+            });
+        }
+        let mined = |height| MiningInfo {
+            height: BlockHeight::new(height).unwrap(),
+            time: 1_700_000_000,
+            block_hash: BlockHash::from_hex(&"22".repeat(32)).unwrap(),
+        };
+        wallet.cache.tx.insert(txid, WalletTx {
+            txid,
+            status: TxStatus::Mined(mined(100)),
+            inputs: vec![],
+            outputs,
+            fee: Sats::ZERO,
+            size: 0,
+            weight: 0,
+            version: TxVer::V2,
+            locktime: LockTime::ZERO,
+        });
+        wallet.cache.last_block = mined(110);
+        wallet.index_scripts();
+        wallet
+    }
+
+    fn wallet_funding_txid() -> Txid { Txid::from_hex(&"11".repeat(32)).unwrap() }
+
+    fn payment(sats: u64) -> Beneficiary {
+        let address =
+            Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").expect("address");
+        Beneficiary::new(address, Payment::Fixed(Sats(sats)))
+    }
+
+    #[test]
+    fn test_bip86_addresses() {
+        // Test vectors from BIP-86 for the `abandon abandon ... about` mnemonic
+        let xpub = XpubDerivable::from_str(
+            "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1>/*",
+        )
+        .unwrap();
+        let descr = WalletDescr::<XpubDerivable, StdDescr>::new_standard(
+            TrKey::from(xpub).into(),
+            Network::Mainnet,
+        );
+        let receive = descr
+            .addresses(0u8)
+            .take(2)
+            .map(|derived| derived.addr.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(receive, [
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+            "bc1p4qhjn9zdvkux4e44uhx8tc55attvtyu358kutcqkudyccelu0was9fqzwh"
+        ]);
+        let change = descr.addresses(1u8).next().unwrap();
+        assert_eq!(
+            change.addr.to_string(),
+            "bc1p3qkhfews2uk44qtvauqyr2ttdsw7svhkl9nkm9s9c3x4ax5h60wqwruhk7"
+        );
+    }
+
+    #[test]
+    fn test_network_address_prefixes() {
+        let xpub = XpubDerivable::from_str(
+            "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1>/*",
+        )
+        .unwrap();
+        let address = |network| {
+            let descr = WalletDescr::<XpubDerivable, StdDescr>::new_standard(
+                TrKey::from(xpub.clone()).into(),
+                network,
+            );
+            descr.addresses(0u8).next().unwrap().addr.to_string()
+        };
+        let mainnet = address(Network::Mainnet);
+        assert!(mainnet.starts_with("bc1p"));
+        let testnet = address(Network::Testnet3);
+        assert!(testnet.starts_with("tb1p"));
+        assert_eq!(address(Network::Testnet4), testnet);
+        assert_eq!(address(Network::Signet), testnet);
+        assert!(address(Network::Regtest).starts_with("bcrt1p"));
+        // The same key is used on all networks, only the encoding differs
+        assert_eq!(mainnet[4..mainnet.len() - 6], testnet[4..testnet.len() - 6]);
+    }
+
+    #[test]
+    fn test_change_index() {
+        let xpub = XpubDerivable::from_str(
+            "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1>/*",
+        )
+        .unwrap();
+        let mut wallet = Wallet::<XpubDerivable, StdDescr, WalletCache<Layer2Empty>>::new_layer1(
+            TrKey::from(xpub).into(),
+            Network::Mainnet,
+        );
+        let change = Keychain::from(1u8);
+        assert_eq!(wallet.change_keychain(), change);
+        wallet.restrict_keychains(Some(BTreeSet::from([Keychain::from(0u8)])));
+        assert_eq!(wallet.change_keychain(), change);
+        wallet.restrict_keychains(None);
+
+        // Two transactions in a row get different change addresses
+        let first = wallet.reserve_change(ChangePolicy::NextUnused).unwrap();
+        let second = wallet.reserve_change(ChangePolicy::NextUnused).unwrap();
+        assert_eq!(first, (change, NormalIndex::normal(0)));
+        assert_eq!(second, (change, NormalIndex::normal(1)));
+
+        let fixed = ChangePolicy::Fixed(NormalIndex::normal(10));
+        assert_eq!(wallet.reserve_change(fixed).unwrap(), (change, NormalIndex::normal(10)));
+        assert!(matches!(
+            wallet.reserve_change(fixed),
+            Err(BuildTxError::ChangeIndexUsed(index)) if index == NormalIndex::normal(10)
+        ));
+        assert!(matches!(
+            wallet.reserve_change(ChangePolicy::Fixed(NormalIndex::normal(1))),
+            Err(BuildTxError::ChangeIndexUsed(_))
+        ));
+        let next = wallet.reserve_change(ChangePolicy::NextUnused).unwrap();
+        assert_eq!(next, (change, NormalIndex::normal(11)));
+        // Change beyond the gap limit won't be found by the sync
+        assert!(matches!(
+            wallet.reserve_change(ChangePolicy::Fixed(NormalIndex::normal(32))),
+            Err(BuildTxError::ChangeIndexBeyondGap(_, next, 20)) if next == NormalIndex::normal(12)
+        ));
+        assert!(wallet.reserve_change(ChangePolicy::Fixed(NormalIndex::normal(31))).is_ok());
+        // Receiving addresses are not affected
+        assert_eq!(wallet.last_derivation_index(0u8), NormalIndex::ZERO);
+    }
+
+    #[test]
+    fn test_multiple_keychains() {
+        let xpub = XpubDerivable::from_str(
+            "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1;2>/*",
+        )
+        .unwrap();
+        let mut descr = WalletDescr::<XpubDerivable, StdDescr>::new_standard(
+            TrKey::from(xpub).into(),
+            Network::Mainnet,
+        );
+        let all = [0u8, 1, 2].map(Keychain::from);
+        assert_eq!(descr.keychains(), BTreeSet::from(all));
+        let first = all.map(|keychain| descr.addresses(keychain).next().unwrap().addr);
+        assert_ne!(first[0], first[2]);
+        assert_ne!(first[1], first[2]);
+
+        descr.restrict_keychains(Some(BTreeSet::from([all[0], all[2], Keychain::from(5u8)])));
+        assert_eq!(descr.keychains(), BTreeSet::from([all[0], all[2]]));
+        descr.restrict_keychains(None);
+        assert_eq!(descr.keychains(), BTreeSet::from(all));
+    }
+
+    #[test]
+    fn test_find_address() {
+        let xpub = XpubDerivable::from_str(
+            "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1>/*",
+        )
+        .unwrap();
+        let mut wallet = Wallet::<XpubDerivable, StdDescr, WalletCache<Layer2Empty>>::new_layer1(
+            TrKey::from(xpub).into(),
+            Network::Mainnet,
+        );
+        let change =
+            Address::from_str("bc1p3qkhfews2uk44qtvauqyr2ttdsw7svhkl9nkm9s9c3x4ax5h60wqwruhk7")
+                .unwrap();
+        let found = wallet.find_address(&change, 0).unwrap();
+        assert_eq!(found.terminal, Terminal::new(1u8, NormalIndex::ZERO));
+
+        let far = wallet.addresses(0u8).nth(30).unwrap();
+        assert_eq!(wallet.find_address(&far.addr, 0), None);
+        assert_eq!(wallet.find_address(&far.addr, 20), Some(far));
+
+        // Reserved addresses extend the search
+        for _ in 0..20 {
+            wallet.next_address(0u8);
+        }
+        assert_eq!(wallet.find_address(&far.addr, 0), Some(far));
+
+        let foreign = Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").unwrap();
+        assert_eq!(wallet.find_address(&foreign, 100), None);
+    }
+
+    #[test]
+    fn test_owns_output() {
+        let xpub = XpubDerivable::from_str(
+            "[73c5da0a/86h/0h/0h]xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1>/*",
+        )
+        .unwrap();
+        let mut wallet = Wallet::<XpubDerivable, StdDescr, WalletCache<Layer2Empty>>::new_layer1(
+            TrKey::from(xpub).into(),
+            Network::Mainnet,
+        );
+        let near = wallet.addresses(1u8).nth(3).unwrap();
+        let far = wallet.addresses(0u8).nth(100).unwrap();
+        assert_eq!(wallet.owns_output(&near.addr.script_pubkey()), Some(near.terminal));
+        assert_eq!(wallet.owns_output(&far.addr.script_pubkey()), None);
+
+        // Scripts of the synced transactions are found regardless of the search depth
+        let txid = Txid::from_hex(&"01".repeat(32)).unwrap();
+        wallet.cache.tx.insert(txid, WalletTx {
+            txid,
+            status: TxStatus::Mempool,
+            inputs: vec![],
+            outputs: vec![TxDebit {
+                outpoint: Outpoint::new(txid, Vout::from_u32(0)),
+                beneficiary: Party::Wallet(far),
+                value: Sats(1000),
+                spent: None,
+            }],
+            fee: Sats::ZERO,
+            size: 0,
+            weight: 0,
+            version: TxVer::V2,
+            locktime: LockTime::ZERO,
+        });
+        wallet.index_scripts();
+        assert_eq!(wallet.owns_output(&far.addr.script_pubkey()), Some(far.terminal));
+
+        let foreign = Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").unwrap();
+        assert_eq!(wallet.owns_output(&foreign.script_pubkey()), None);
+    }
+
+    #[test]
+    fn test_script_index_extension() {
+        let mut wallet = funded_wallet(&[60_000]);
+        assert_eq!(wallet.scripts.txids.len(), 1);
+
+        // Change of the constructed transaction is indexed without re-indexing the cache
+        let built = wallet
+            .build_tx(
+                &[payment(10_000)],
+                SpendParams::with(FeePolicy::Rate(1.0)),
+                &Strategy::LargestFirst,
+            )
+            .unwrap();
+        let change = built.meta.change.unwrap();
+        let output = built.psbt.outputs().nth(change.vout.into_usize()).unwrap();
+        let change_script = output.script.clone();
+        assert_eq!(wallet.scripts.get(&change_script), Some(change.terminal));
+        assert_eq!(wallet.scripts.txids.len(), 2);
+
+        // Already indexed transactions are not processed again
+        wallet.scripts.scripts.clear();
+        wallet.index_scripts();
+        assert_eq!(wallet.scripts.get(&change_script), None);
+    }
+
+    #[test]
+    fn test_rbf_sequence() {
+        let params = SpendParams::with(FeePolicy::Absolute(Sats(1000)));
+        let tx_params = params.tx_params(Sats(1000));
+        assert_eq!(tx_params.seq_no.to_consensus_u32(), 0xFFFF_FFFD);
+        assert_eq!(tx_params.lock_time, None);
+
+        let params = SpendParams {
+            rbf: false,
+            ..params
+        };
+        let tx_params = params.tx_params(Sats(1000));
+        assert_eq!(tx_params.seq_no.to_consensus_u32(), 0xFFFF_FFFE);
+        assert!(!fees::signals_rbf(tx_params.seq_no));
+    }
+
+    #[test]
+    fn test_sweep_filter() {
+        let utxo = |terminal: &str, value: u64| WalletUtxo {
+            outpoint: Outpoint::coinbase(),
+            value: Sats(value),
+            terminal: Terminal::from_str(terminal).unwrap(),
+            status: TxStatus::Mempool,
+        };
+        let receive = utxo("&0/3", 500);
+        let change = utxo("&1/0", 50_000);
+
+        let all = SweepFilter::default();
+        assert!(all.matches(&receive) && all.matches(&change));
+
+        let keychain = SweepFilter {
+            keychain: Some(Keychain::from(1u8)),
+            below: None,
+        };
+        assert!(!keychain.matches(&receive) && keychain.matches(&change));
+
+        let dust = SweepFilter {
+            keychain: None,
+            below: Some(Sats(1000)),
+        };
+        assert!(dust.matches(&receive) && !dust.matches(&change));
+    }
+
+    #[test]
+    fn test_sweep() {
+        let funding = wallet_funding_txid();
+        let address = payment(0).address;
+        let params = SpendParams::with(FeePolicy::Absolute(Sats(1_000)));
+        let mut wallet = funded_wallet(&[50_000, 30_000, 700]);
+        let last_used = wallet.data.last_used.clone();
+        let built = wallet.sweep(address, params, SweepFilter::default()).unwrap();
+        assert_eq!(built.psbt.inputs().count(), 3);
+        let outputs = built.psbt.outputs().collect::<Vec<_>>();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].script, address.script_pubkey());
+        assert_eq!(outputs[0].value(), Sats(79_700));
+        assert_eq!(built.meta.change, None);
+        // No change address is reserved
+        assert_eq!(wallet.data.last_used, last_used);
+
+        // Locked and filtered out coins are left unspent
+        let mut wallet = funded_wallet(&[50_000, 30_000, 700]);
+        wallet.lock_utxo(Outpoint::new(funding, 0u32), None).unwrap();
+        let filter = SweepFilter {
+            keychain: None,
+            below: Some(Sats(40_000)),
+        };
+        let built = wallet.sweep(address, params, filter).unwrap();
+        let inputs = built.psbt.inputs().map(|input| input.previous_outpoint).collect::<Vec<_>>();
+        assert_eq!(inputs, vec![Outpoint::new(funding, 1u32), Outpoint::new(funding, 2u32)]);
+
+        let filter = SweepFilter {
+            keychain: Some(Keychain::INNER),
+            below: None,
+        };
+        let err = wallet.sweep(address, params, filter).unwrap_err();
+        assert!(matches!(err, BuildTxError::NothingToSweep));
+        let filter = SweepFilter {
+            keychain: None,
+            below: Some(Sats(1_000)),
+        };
+        let err = wallet.sweep(address, params, filter).unwrap_err();
+        assert!(matches!(err, BuildTxError::SweepFeeTooHigh(..)));
+    }
+
+    #[test]
+    fn test_sweep_uneconomic() {
+        let address = payment(0).address;
+        let params = SpendParams::with(FeePolicy::Rate(5.0));
+
+        // Coin worth less than the fee for spending it is left unspent
+        let mut wallet = funded_wallet(&[50_000, 200]);
+        let built = wallet.sweep(address, params, SweepFilter::default()).unwrap();
+        let inputs = built.psbt.inputs().map(|input| input.previous_outpoint).collect::<Vec<_>>();
+        assert_eq!(inputs, vec![Outpoint::new(wallet_funding_txid(), 0u32)]);
+        assert_eq!(built.psbt.outputs().next().unwrap().value(), Sats(50_000) - built.meta.fee);
+
+        // If no coin is worth spending, the fee problem is reported
+        let mut wallet = funded_wallet(&[200]);
+        let err = wallet.sweep(address, params, SweepFilter::default()).unwrap_err();
+        assert!(matches!(err, BuildTxError::SweepFeeTooHigh(..)));
+
+        // Amount remaining after the fee must not be dust
+        let mut wallet = funded_wallet(&[390]);
+        let params = SpendParams::with(FeePolicy::Rate(1.0));
+        let err = wallet.sweep(address, params, SweepFilter::default()).unwrap_err();
+        assert!(matches!(err, BuildTxError::DustOutput(_, limit) if limit == Sats(294)));
+    }
+
+    #[test]
+    fn test_consolidate() {
+        let params = SpendParams::with(FeePolicy::Rate(1.0));
+        let mut wallet = funded_wallet(&[10_000, 20_000, 30_000]);
+        let change = wallet.peek_address(Keychain::INNER);
+        let built = wallet.consolidate(params, SweepFilter::default(), 2).unwrap();
+        assert_eq!(built.psbt.inputs().count(), 3);
+        let outputs = built.psbt.outputs().collect::<Vec<_>>();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].script, change.addr.script_pubkey());
+        assert_eq!(outputs[0].value(), Sats(60_000) - built.meta.fee);
+        // The consolidation address is reserved
+        assert_ne!(wallet.peek_address(Keychain::INNER), change);
+
+        let mut wallet = funded_wallet(&[10_000, 20_000, 30_000]);
+        let err = wallet.consolidate(params, SweepFilter::default(), 4).unwrap_err();
+        assert!(matches!(err, BuildTxError::NothingToConsolidate(3, 4)));
+
+        let mut wallet = funded_wallet(&[300, 300]);
+        let params = SpendParams::with(FeePolicy::Rate(10.0));
+        let err = wallet.consolidate(params, SweepFilter::default(), 2).unwrap_err();
+        assert!(
+            matches!(err, BuildTxError::ConsolidationTooExpensive(total, _) if total == Sats(600))
+        );
+        // Failed consolidation doesn't waste the address
+        assert_eq!(wallet.peek_address(Keychain::INNER), change);
+    }
+
+    #[test]
+    fn test_check_input_limits() {
+        let utxo = WalletUtxo {
+            outpoint: Outpoint::coinbase(),
+            value: Sats(10_000),
+            terminal: Terminal::from_str("&0/0").unwrap(),
+            status: TxStatus::Mempool,
+        };
+        let candidates = [utxo, utxo];
+        let limits = |min_inputs, max_inputs| SelectionParams {
+            target: Sats(1000),
+            fee_rate: 1.0,
+            base_vsize: 10,
+            input_vsize: 68,
+            change_vsize: 31,
+            min_change: Sats(546),
+            changeless_window: None,
+            dust_policy: DustPolicy::Burn,
+            min_inputs,
+            max_inputs,
+        };
+        assert!(check_input_limits(&candidates, &limits(None, None)).is_ok());
+        assert!(check_input_limits(&candidates, &limits(Some(2), Some(2))).is_ok());
+        assert!(matches!(
+            check_input_limits(&candidates, &limits(Some(3), Some(2))),
+            Err(BuildTxError::InputLimits(3, 2))
+        ));
+        assert!(matches!(
+            check_input_limits(&candidates, &limits(Some(3), None)),
+            Err(BuildTxError::TooFewInputs(2, 3))
+        ));
+    }
+
+    #[test]
+    fn test_max_inputs() {
+        let mut wallet = funded_wallet(&[50_000, 30_000, 10_000, 5_000]);
+        let params = SpendParams {
+            max_inputs: Some(2),
+            ..SpendParams::with(FeePolicy::Rate(1.0))
+        };
+
+        // Smallest coins don't fit into the limit, while the largest ones do
+        let built = wallet.build_tx(&[payment(60_000)], params, &Strategy::SmallestFirst).unwrap();
+        assert_eq!(built.psbt.inputs().count(), 2);
+
+        assert!(matches!(
+            wallet.build_tx(&[payment(85_000)], params, &Strategy::SmallestFirst),
+            Err(BuildTxError::TooManyInputs { max: 2, available: Sats(80_000), .. })
+        ));
+    }
+
+    #[test]
+    fn test_build_tx() {
+        let mut wallet = funded_wallet(&[50_000, 30_000, 20_000]);
+        let params = SpendParams::with(FeePolicy::Rate(2.0));
+        let built = wallet.build_tx(&[payment(60_000)], params, &Strategy::LargestFirst).unwrap();
+        let psbt = &built.psbt;
+        assert_eq!(psbt.version, params.version);
+        assert_eq!(psbt.inputs().count(), 2);
+        assert_eq!(psbt.input_sum(), Sats(80_000));
+        for input in psbt.inputs() {
+            assert_eq!(input.previous_outpoint.txid, wallet_funding_txid());
+            assert!(input.witness_utxo.is_some());
+            assert!(input.tap_internal_key.is_some());
+            assert_eq!(input.tap_bip32_derivation.len(), 1);
+        }
+
+        // The payment and the change, which goes to the change keychain
+        assert_eq!(psbt.outputs().count(), 2);
+        let script = payment(0).address.script_pubkey();
+        assert!(psbt.outputs().any(|output| output.script == script && output.value() == 60_000));
+        let change = built.meta.change.expect("change output");
+        assert_eq!(change.terminal.keychain, Keychain::INNER);
+        let output = psbt.outputs().nth(change.vout.into_usize()).unwrap();
+        assert_eq!(output.value(), built.change);
+        assert_eq!(output.tap_bip32_derivation.len(), 1);
+        assert_eq!(psbt.input_sum(), Sats(60_000) + built.change + built.meta.fee);
+        assert_eq!(built.strategy, Some(Strategy::LargestFirst));
+
+        assert!(matches!(
+            funded_wallet(&[50_000, 30_000, 20_000]).build_tx(
+                &[payment(100_000)],
+                params,
+                &Strategy::LargestFirst
+            ),
+            Err(BuildTxError::InsufficientFunds(..))
+        ));
+    }
+
+    #[test]
+    fn test_fee_by_input_scripts() {
+        let rate = 3.0;
+        let pkh = StdDescr::from(Pkh::from(test_xpub()));
+        for mut wallet in
+            [funded_wallet(&[60_000, 70_000]), funded_wallet_with(pkh, &[60_000, 70_000])]
+        {
+            let script = wallet.addresses(0u8).next().unwrap().addr.script_pubkey();
+            let built = wallet
+                .build_tx(
+                    &[payment(100_000)],
+                    SpendParams::with(FeePolicy::Rate(rate)),
+                    &Strategy::LargestFirst,
+                )
+                .unwrap();
+            assert!(built.change > Sats::ZERO);
+            let outputs = [payment(0).address.script_pubkey(), script.clone()];
+            let vsize = fees::estimate_vsize([&script, &script], &outputs);
+            assert_eq!(built.vsize, vsize);
+            // The fee pays for the actual inputs, leaving no overestimate to miners
+            assert_eq!(built.psbt.fee(), Some(fees::fee_for_rate(vsize, rate)));
+        }
+    }
+
+    #[test]
+    fn test_estimate_matches_build() {
+        let mut wallet = funded_wallet(&[60_000, 70_000, 5_000]);
+        let params = || SpendParams::with(FeePolicy::Rate(2.0));
+        let last_used = wallet.data.last_used.clone();
+        let estimate =
+            wallet.estimate_tx(&[payment(100_000)], params(), &Strategy::LargestFirst).unwrap();
+        // Estimation doesn't reserve the change address
+        assert_eq!(wallet.data.last_used, last_used);
+
+        let built =
+            wallet.build_tx(&[payment(100_000)], params(), &Strategy::LargestFirst).unwrap();
+        assert_ne!(wallet.data.last_used, last_used);
+        let inputs = built.psbt.inputs().map(|input| input.previous_outpoint).collect::<Vec<_>>();
+        assert_eq!(estimate.inputs, inputs);
+        assert_eq!(estimate.input_value, Sats(130_000));
+        assert_eq!(Some(estimate.fee), built.psbt.fee());
+        assert_eq!(estimate.strategy, built.strategy);
+        assert_eq!(estimate.change, built.change);
+        assert_eq!(estimate.dropped_change, built.dropped_change);
+        assert_eq!(estimate.vsize, built.vsize);
+        assert_eq!(estimate.fee_rate, built.fee_rate);
+    }
+
+    #[test]
+    fn test_confirmations_without_tip() {
+        let mut wallet = funded_wallet(&[10_000]);
+        let balance = |wallet: &TestWallet| wallet.balance_breakdown(6)[&Keychain::OUTER];
+        assert_eq!(balance(&wallet).confirmed, Sats(10_000));
+
+        // The tip was not retrieved during the sync, but the keychains were synced to block 110
+        let synced = wallet.cache.last_block;
+        wallet.cache.last_block = MiningInfo::genesis();
+        wallet.cache.last_sync.insert(Keychain::OUTER, synced);
+        assert_eq!(balance(&wallet).confirmed, Sats(10_000));
+
+        // Without any sync information the transaction has at least a single confirmation
+        wallet.cache.last_sync.clear();
+        assert_eq!(balance(&wallet).unconfirmed, Sats(10_000));
+        assert_eq!(wallet.balance_breakdown(1)[&Keychain::OUTER].confirmed, Sats(10_000));
+    }
+
+    #[test]
+    fn test_dust_change() {
+        /// Selection of all the coins leaving a dust change, which library strategies never do.
+        struct DustSelect;
+        impl CoinSelect for DustSelect {
+            fn select(
+                &self,
+                candidates: &[WalletUtxo],
+                params: &SelectionParams,
+            ) -> Option<Selection> {
+                Some(Selection {
+                    inputs: candidates.iter().map(|utxo| utxo.outpoint).collect(),
+                    fee: params.fee(candidates.len(), true),
+                    change: Sats(1),
+                    dropped_change: Sats::ZERO,
+                    strategy: None,
+                })
+            }
+        }
+
+        let mut wallet = funded_wallet(&[50_000]);
+        let params = SpendParams::with(FeePolicy::Rate(1.0));
+        assert!(matches!(
+            wallet.build_tx(&[payment(40_000)], params, &DustSelect),
+            Err(BuildTxError::DustChange(Sats(1), _))
+        ));
+        // Nothing got reserved by the failed build
+        assert_eq!(wallet.spendable_utxos().count(), 1);
+    }
+
+    #[test]
+    fn test_register_tx() {
+        let mut wallet = funded_wallet(&[50_000, 30_000]);
+        let funding = wallet_funding_txid();
+        let change = wallet.addresses(0u8).nth(1).unwrap();
+        let tx = Tx {
+            version: TxVer::V2,
+            inputs: VarIntArray::from_checked(vec![TxIn {
+                prev_output: Outpoint::new(funding, 0),
+                sig_script: SigScript::new(),
+                sequence: SeqNo::from_consensus_u32(fees::SEQ_NO_RBF),
+                witness: none!(),
+            }]),
+            outputs: VarIntArray::from_checked(vec![
+                TxOut::new(payment(0).address.script_pubkey(), Sats(40_000)),
+                TxOut::new(change.addr.script_pubkey(), Sats(9_000)),
+            ]),
+            lock_time: LockTime::ZERO,
+        };
+        let txid = tx.txid();
+        wallet.cache.register_tx(&tx, AddressNetwork::Mainnet);
+
+        // The spent coin leaves the UTXO set, and the change joins it
+        let utxos = wallet.cache.utxo.iter().copied().collect::<BTreeSet<_>>();
+        assert_eq!(utxos, bset![Outpoint::new(funding, 1), Outpoint::new(txid, 1)]);
+        let spent = wallet.cache.tx[&funding].outputs[0].spent;
+        assert_eq!(spent, Some(Inpoint::new(txid, 0)));
+
+        let registered = &wallet.cache.tx[&txid];
+        assert_eq!(registered.status, TxStatus::Mempool);
+        assert_eq!(registered.fee, Sats(1_000));
+        assert_eq!(registered.inputs[0].value, Sats(50_000));
+        assert!(matches!(registered.inputs[0].payer, Party::Wallet(_)));
+        assert!(matches!(registered.outputs[0].beneficiary, Party::Counterparty(_)));
+        assert_eq!(registered.outputs[1].beneficiary, Party::Wallet(change));
+
+        // Registering the same transaction again changes nothing
+        let (txs, utxos) = (wallet.cache.tx.clone(), wallet.cache.utxo.clone());
+        wallet.cache.register_tx(&tx, AddressNetwork::Mainnet);
+        assert_eq!(wallet.cache.tx, txs);
+        assert_eq!(wallet.cache.utxo, utxos);
+    }
+
+    #[test]
+    fn test_pay_batch() {
+        let mut wallet = funded_wallet(&[50_000, 30_000, 20_000]);
+        let payments = [
+            ("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq", 25_000),
+            ("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", 10_000),
+            ("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", 15_000),
+        ]
+        .map(|(address, sats)| (Address::from_str(address).unwrap(), Sats(sats)));
+        let batch = Batch::with(payments, crate::DuplicatePolicy::Reject).unwrap();
+        let params = SpendParams {
+            order: TxOrder::Bip69,
+            ..SpendParams::with(FeePolicy::Rate(2.0))
+        };
+        let built = wallet.pay_batch(&batch, params, &Strategy::LargestFirst).unwrap();
+        let psbt = &built.psbt;
+
+        // Each payment gets its own output, and the change takes the rest
+        for (address, amount) in &payments {
+            let script = address.script_pubkey();
+            let output = psbt.outputs().find(|output| output.script == script).unwrap();
+            assert_eq!(output.value(), *amount);
+        }
+        assert_eq!(psbt.outputs().count(), payments.len() + 1);
+        let change = built.meta.change.expect("change output");
+        assert_eq!(psbt.outputs().nth(change.vout.into_usize()).unwrap().value(), built.change);
+        let paid = psbt.outputs().map(|output| output.value()).sum::<Sats>();
+        assert_eq!(paid, Sats(50_000) + built.change);
+        assert_eq!(psbt.input_sum(), paid + built.meta.fee);
+
+        // BIP-69 order: inputs by txid and vout, outputs by amount and script
+        let inputs = psbt.inputs().map(|input| input.previous_outpoint).collect::<Vec<_>>();
+        assert!(inputs.windows(2).all(|pair| pair[0].vout < pair[1].vout));
+        let outputs = psbt
+            .outputs()
+            .map(|output| (output.value(), output.script.to_vec()))
+            .collect::<Vec<_>>();
+        assert!(outputs.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let batch = Batch::with(
+            payments.map(|(address, _)| (address, Sats(40_000))),
+            crate::DuplicatePolicy::Reject,
+        )
+        .unwrap();
+        assert!(matches!(
+            funded_wallet(&[50_000, 30_000, 20_000]).pay_batch(
+                &batch,
+                params,
+                &Strategy::LargestFirst
+            ),
+            Err(BuildTxError::InsufficientFunds(..))
+        ));
+    }
+
+    /// Indexer which re-syncs wallets to a fixed snapshot of the blockchain.
+    struct SnapshotIndexer(TestWallet);
+
+    impl Indexer for SnapshotIndexer {
+        type Error = UnsupportedRequest;
+
+        fn network(&self) -> Result<Network, Self::Error> { Ok(Network::Mainnet) }
+
+        fn create<K, D: Descriptor<K>, L2: Layer2>(
+            &self,
+            descr: &WalletDescr<K, D, L2::Descr>,
+        ) -> MayError<WalletCache<L2::Cache>, Vec<SyncError<Self::Error>>> {
+            let mut cache = WalletCache::new_nonsync();
+            let res = self.update::<K, D, L2>(descr, &mut cache);
+            MayError {
+                ok: cache,
+                err: res.err,
+            }
+        }
+
+        fn update_with_progress<K, D: Descriptor<K>, L2: Layer2>(
+            &self,
+            _descr: &WalletDescr<K, D, L2::Descr>,
+            cache: &mut WalletCache<L2::Cache>,
+            _progress: &mut dyn SyncProgress,
+        ) -> MayError<usize, Vec<SyncError<Self::Error>>> {
+            let snapshot = &self.0.cache;
+            cache.last_block = snapshot.last_block;
+            cache.tx = snapshot.tx.clone();
+            cache.utxo = snapshot.utxo.clone();
+            cache.addr = snapshot.addr.clone();
+            MayError::ok(cache.addr.values().map(BTreeSet::len).sum())
+        }
+
+        fn broadcast(&self, _tx: &Tx) -> Result<(), Self::Error> {
+            Err(UnsupportedRequest("broadcast"))
+        }
+
+        fn status(&self, _txid: Txid) -> Result<TxStatus, Self::Error> {
+            Err(UnsupportedRequest("status"))
+        }
+
+        fn block_hash(&self, _height: u32) -> Result<BlockHash, Self::Error> {
+            Err(UnsupportedRequest("block hash"))
+        }
+    }
+
+    #[test]
+    fn test_addresses_in() {
+        let mut wallet = funded_wallet(&[1_000, 2_000]);
+        let range = NormalIndex::ZERO..NormalIndex::from(4u8);
+        let used = wallet.addresses_in(0u8, range).map(|info| info.used).collect::<Vec<_>>();
+        assert_eq!(used, [true, true, false, false]);
+
+        let cached = |wallet: &TestWallet| wallet.descr.derived.read().unwrap().len();
+        let before = cached(&wallet);
+        let range = NormalIndex::from(100u8)..NormalIndex::from(200u8);
+        let addrs = wallet.addresses_in(1u8, range.clone()).collect::<Vec<_>>();
+        assert_eq!(addrs.len(), 100);
+        for info in &addrs {
+            assert_eq!(info.terminal.keychain, Keychain::INNER);
+            let derived = wallet
+                .descr
+                .generator
+                .derive_address(AddressNetwork::Mainnet, 1u8, info.terminal.index)
+                .next()
+                .unwrap();
+            assert_eq!(info.addr, derived);
+            assert_eq!(info.script_pubkey, derived.script_pubkey());
+            assert!(!info.used);
+        }
+        // Only the addresses from the range are derived, and they are not derived again
+        assert_eq!(cached(&wallet), before + 100);
+        assert_eq!(wallet.addresses_in(1u8, range.clone()).collect::<Vec<_>>(), addrs);
+        assert_eq!(cached(&wallet), before + 100);
+
+        let other = funded_wallet_with(Pkh::from(test_xpub()).into(), &[]);
+        wallet
+            .descr
+            .with_descriptor(|descr| {
+                *descr = other.descr.generator.clone();
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!(cached(&wallet), 0);
+        let info = wallet.addresses_in(1u8, range).next().unwrap();
+        assert!(info.script_pubkey.is_p2pkh());
+    }
+
+    #[test]
+    fn test_lock_keeps_annotation() {
+        let mut wallet = funded_wallet(&[10_000, 20_000]);
+        let labeled = Outpoint::new(wallet_funding_txid(), 0);
+        let unlabeled = Outpoint::new(wallet_funding_txid(), 1);
+        wallet.data.txout_annotations.insert(labeled, s!("salary"));
+
+        wallet.lock_utxo(labeled, Some(s!("reserved for rent"))).unwrap();
+        wallet.lock_utxo(unlabeled, None).unwrap();
+        assert_eq!(wallet.data.txout_annotations.get(&labeled).map(String::as_str), Some("salary"));
+        assert!(!wallet.data.txout_annotations.contains_key(&unlabeled));
+        assert_eq!(wallet.lock_label(labeled), Some("reserved for rent"));
+        assert_eq!(wallet.lock_label(unlabeled), None);
+
+        let indexer = SnapshotIndexer(funded_wallet(&[10_000, 20_000]));
+        wallet.cache = WalletCache::new_nonsync();
+        assert!(wallet.update(&indexer).is_complete());
+        let utxos = wallet.list_utxos();
+        assert_eq!(utxos.len(), 2);
+        assert!(utxos.iter().all(|utxo| utxo.locked));
+        assert_eq!(utxos[0].label.as_deref(), Some("salary"));
+        assert_eq!(wallet.lock_label(labeled), Some("reserved for rent"));
+        assert_eq!(wallet.spendable_utxos().count(), 0);
+
+        assert!(wallet.unlock_utxo(labeled));
+        assert!(!wallet.unlock_utxo(labeled));
+        assert_eq!(wallet.lock_label(labeled), None);
+        assert_eq!(wallet.data.txout_annotations.get(&labeled).map(String::as_str), Some("salary"));
+        assert_eq!(wallet.spendable_utxos().count(), 1);
+    }
+
+    #[test]
+    fn test_locked_coins_never_selected() {
+        const STRATEGIES: [Strategy; 4] = [
+            Strategy::LargestFirst,
+            Strategy::SmallestFirst,
+            Strategy::BranchAndBound,
+            Strategy::OldestFirst,
+        ];
+
+        // Deterministic pseudo-random generator, so failures are reproducible
+        let mut seed = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = move |max: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % max
+        };
+
+        for _ in 0..50 {
+            let values = (0..next(8) + 1).map(|_| next(100_000) + 1_000).collect::<Vec<_>>();
+            let locked = values.iter().map(|_| next(2) == 0).collect::<Vec<_>>();
+            let unlocked =
+                values.iter().zip(&locked).filter(|(_, l)| !**l).map(|(v, _)| v).sum::<u64>();
+            let locked_wallet = || {
+                let mut wallet = funded_wallet(&values);
+                for (no, _) in locked.iter().enumerate().filter(|(_, l)| **l) {
+                    let outpoint = Outpoint::new(wallet_funding_txid(), no as u32);
+                    wallet.lock_utxo(outpoint, None).unwrap();
+                }
+                wallet
+            };
+            let params = SpendParams::with(FeePolicy::Rate(next(20) as f64 + 1.0));
+            let amount = next(values.iter().sum::<u64>()) + 1_000;
+            for strategy in STRATEGIES {
+                // The whole wallet is given to the build, which must exclude locked coins itself
+                let mut wallet = locked_wallet();
+                let Ok(built) = wallet.build_tx(&[payment(amount)], params, &strategy) else {
+                    continue;
+                };
+                let mut inputs = built.psbt.inputs();
+                assert!(inputs.all(|input| !wallet.is_locked(input.previous_outpoint)));
+                assert!(built.psbt.input_sum().sats() <= unlocked);
+            }
+            if amount > unlocked {
+                assert!(matches!(
+                    locked_wallet().build_tx(&[payment(amount)], params, &Strategy::LargestFirst),
+                    Err(BuildTxError::InsufficientFunds(..))
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_op_return() {
+        let op_return = OpReturn::from_str("deadbeef").unwrap();
+        assert_eq!(op_return.as_slice(), &[0xde, 0xad, 0xbe, 0xef]);
+        let script = op_return.script_pubkey();
+        assert_eq!(script.as_slice(), &[0x6a, 0x04, 0xde, 0xad, 0xbe, 0xef]);
+        // Data output is accounted in the transaction size
+        assert_eq!(fees::output_vsize(&script), 8 + 1 + 6);
+
+        assert!(OpReturn::new(&[0u8; MAX_OP_RETURN_SIZE]).is_ok());
+        assert_eq!(
+            OpReturn::new(&[0u8; MAX_OP_RETURN_SIZE + 1]),
+            Err(OpReturnError::TooLarge(MAX_OP_RETURN_SIZE + 1))
+        );
+        assert_eq!(OpReturn::from_str("xyz"), Err(OpReturnError::InvalidHex));
+    }
+
+    #[test]
+    fn test_rbf_lock_time() {
+        let lock_time = LockTime::from_consensus_u32(840_000);
+        for rbf in [true, false] {
+            let params = SpendParams {
+                rbf,
+                lock_time: Some(lock_time),
+                ..SpendParams::with(FeePolicy::Rate(1.0))
+            };
+            let tx_params = params.tx_params(Sats(1000));
+            assert_eq!(tx_params.lock_time, Some(lock_time));
+            assert_eq!(fees::signals_rbf(tx_params.seq_no), rbf);
+            // Final sequence number would disable the lock time
+            assert_ne!(tx_params.seq_no.to_consensus_u32(), 0xFFFF_FFFF);
+        }
+    }
+
+    #[test]
+    fn test_fixed_change() {
+        let mut wallet = funded_wallet(&[50_000, 30_000]);
+        let change = wallet.change_keychain();
+        let params = SpendParams {
+            change: ChangePolicy::Fixed(NormalIndex::normal(5)),
+            ..SpendParams::with(FeePolicy::Rate(2.0))
+        };
+
+        // Failed construction doesn't reserve the index
+        assert!(matches!(
+            wallet.build_tx(&[payment(100_000)], params, &Strategy::LargestFirst),
+            Err(BuildTxError::InsufficientFunds(..))
+        ));
+        assert_eq!(wallet.last_derivation_index(change), NormalIndex::ZERO);
+
+        let built = wallet.build_tx(&[payment(20_000)], params, &Strategy::LargestFirst).unwrap();
+        let change_info = built.meta.change.unwrap();
+        assert_eq!(change_info.terminal, Terminal::new(change, NormalIndex::normal(5)));
+        assert_eq!(wallet.last_derivation_index(change), NormalIndex::normal(6));
+
+        // The same change index can't be used twice
+        assert!(matches!(
+            wallet.build_tx(&[payment(20_000)], params, &Strategy::LargestFirst),
+            Err(BuildTxError::ChangeIndexUsed(index)) if index == NormalIndex::normal(5)
+        ));
+        assert_eq!(wallet.last_derivation_index(change), NormalIndex::normal(6));
+    }
+
+    #[test]
+    fn test_dropped_change() {
+        let mut wallet = funded_wallet(&[50_000]);
+        let params = SpendParams::with(FeePolicy::Rate(2.0));
+        // Single taproot input with P2WPKH output takes 100 vbytes, leaving 100 sats of change
+        let estimate =
+            wallet.estimate_tx(&[payment(49_700)], params, &Strategy::LargestFirst).unwrap();
+        assert_eq!(estimate.vsize, 100);
+        assert_eq!(estimate.change, Sats::ZERO);
+        assert_eq!(estimate.dropped_change, Sats(100));
+        assert_eq!(estimate.fee, Sats(300));
+
+        let built = wallet.build_tx(&[payment(49_700)], params, &Strategy::LargestFirst).unwrap();
+        assert_eq!(built.dropped_change, Sats(100));
+        assert_eq!(built.meta.fee, Sats(300));
+    }
+
+    #[test]
+    fn test_multisig_input_size() {
+        use amplify::confinement::Confined;
+        use amplify::num::u4;
+        use descriptors::ShMulti;
+
+        let descr = StdDescr::from(ShMulti {
+            threshold: u4::with(2),
+            keys: Confined::try_from(vec![test_xpub(); 3]).unwrap(),
+        });
+        let mut wallet = funded_wallet_with(descr, &[50_000]);
+        let params = SpendParams::with(FeePolicy::Rate(10.0));
+        let built = wallet.build_tx(&[payment(20_000)], params, &Strategy::LargestFirst).unwrap();
+        // 2-of-3 P2SH multisig input takes 297 vbytes, P2WPKH payment 31 and P2SH change 32
+        assert_eq!(built.vsize, 10 + 297 + 31 + 32);
+        assert_eq!(built.meta.fee, fees::fee_for_rate(built.vsize, 10.0));
+    }
+
+    #[test]
+    fn test_bump_fee() {
+        let mut wallet = funded_wallet(&[50_000, 30_000]);
+        let params = SpendParams::with(FeePolicy::Rate(2.0));
+        let built = wallet.build_tx(&[payment(20_000)], params, &Strategy::LargestFirst).unwrap();
+        let txid = built.psbt.to_unsigned_tx().txid();
+        let original_fee = built.meta.fee;
+
+        let (psbt, meta) = wallet.bump_fee(txid, 10.0).unwrap();
+        assert!(meta.fee > original_fee);
+        assert!(psbt.outputs().any(|output| output.value() == Sats(20_000)));
+        assert_eq!(psbt.inputs().count(), 1);
+
+        assert!(matches!(wallet.bump_fee(txid, 2.0), Err(BumpFeeError::FeeTooLow(..))));
+        let mined = wallet.cache.tx.keys().copied().find(|id| *id != txid).unwrap();
+        assert!(
+            matches!(wallet.bump_fee(mined, 10.0), Err(BumpFeeError::Mined(id)) if id == mined)
+        );
+    }
+
+    #[test]
+    fn test_bump_fee_confirmed_inputs() {
+        let mut wallet = funded_wallet(&[50_000, 30_000]);
+        let funding = wallet_funding_txid();
+        // Unconfirmed incoming coin larger than any of the confirmed ones
+        let incoming = Txid::from_hex(&"44".repeat(32)).unwrap();
+        let unconfirmed = Outpoint::new(incoming, 0u32);
+        let derived = wallet.addresses(0u8).nth(5).unwrap();
+        let mut addr = WalletAddr::from(derived);
+        addr.used = 1;
+        addr.volume = Sats(100_000);
+        addr.balance = Sats(100_000);
+        wallet.cache.addr.entry(derived.terminal.keychain).or_default().insert(addr);
+        wallet.cache.tx.insert(incoming, WalletTx {
+            txid: incoming,
+            status: TxStatus::Mempool,
+            inputs: vec![],
+            outputs: vec![TxDebit {
+                outpoint: unconfirmed,
+                beneficiary: Party::Wallet(derived),
+                value: Sats(100_000),
+                spent: None,
+            }],
+            fee: Sats(1_000),
+            size: 200,
+            weight: 600,
+            version: TxVer::V2,
+            locktime: LockTime::ZERO,
+        });
+        wallet.cache.utxo.insert(unconfirmed);
+
+        let mut params = SpendParams::with(FeePolicy::Rate(2.0));
+        params.confirmed_only = true;
+        let built = wallet.build_tx(&[payment(49_000)], params, &Strategy::LargestFirst).unwrap();
+        let txid = built.psbt.to_unsigned_tx().txid();
+
+        // The change can't pay the increased fee, so the largest confirmed coin gets added
+        let (psbt, _) = wallet.bump_fee(txid, 20.0).unwrap();
+        let inputs = psbt.inputs().map(|input| input.previous_outpoint).collect::<Vec<_>>();
+        assert_eq!(inputs, vec![Outpoint::new(funding, 0u32), Outpoint::new(funding, 1u32)]);
+    }
+
+    #[test]
+    fn test_bump_fee_dust_change() {
+        let params = SpendParams::with(FeePolicy::Rate(2.0));
+        let bump = |amount: u64| {
+            let mut wallet = funded_wallet(&[50_000, 30_000]);
+            let built =
+                wallet.build_tx(&[payment(amount)], params, &Strategy::LargestFirst).unwrap();
+            wallet.bump_fee(built.psbt.to_unsigned_tx().txid(), 10.0).unwrap()
+        };
+        let (_, meta) = bump(20_000);
+        let fee = meta.fee.sats();
+
+        // Taproot change above its dust limit of 330 sats is kept, even though it is below the
+        // dust limit of P2PKH outputs
+        let (psbt, meta) = bump(50_000 - fee - 400);
+        assert_eq!(meta.fee.sats(), fee);
+        assert_eq!(psbt.outputs().count(), 2);
+        assert!(psbt.outputs().any(|output| output.value() == Sats(400)));
+
+        let (psbt, meta) = bump(50_000 - fee - 300);
+        assert_eq!(meta.fee.sats(), fee + 300);
+        assert_eq!(psbt.outputs().count(), 1);
+        assert_eq!(meta.change, None);
+    }
+
+    #[test]
+    fn test_bump_fee_descendants() {
+        let mut wallet = funded_wallet(&[50_000, 30_000]);
+        let params = SpendParams::with(FeePolicy::Rate(2.0));
+        let built = wallet.build_tx(&[payment(45_000)], params, &Strategy::LargestFirst).unwrap();
+        let parent = built.psbt.to_unsigned_tx().txid();
+        let change = Outpoint::new(parent, built.meta.change.unwrap().vout);
+
+        // Unconfirmed child spends the parent change together with a large external coin
+        let child = Txid::from_hex(&"33".repeat(32)).unwrap();
+        let child_output = Outpoint::new(child, 0);
+        let derived = wallet.addresses(1u8).nth(5).unwrap();
+        let external = Address::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq").unwrap();
+        let credit = |outpoint, payer, value| TxCredit {
+            outpoint,
+            payer,
+            sequence: fees::seq_no(true),
+            coinbase: false,
+            script_sig: none!(),
+            witness: none!(),
+            value,
+        };
+        let change_addr = wallet.cache.tx[&parent].outputs[change.vout_usize()].beneficiary.clone();
+        wallet.cache.tx.insert(child, WalletTx {
+            txid: child,
+            status: TxStatus::Mempool,
+            inputs: vec![
+                credit(change, change_addr, Sats(4_000)),
+                credit(Outpoint::coinbase(), Party::Counterparty(external), Sats(100_000)),
+            ],
+            outputs: vec![TxDebit {
+                outpoint: child_output,
+                beneficiary: Party::Wallet(derived),
+                value: Sats(100_000),
+                spent: None,
+            }],
+            fee: Sats(3_000),
+            size: 200,
+            weight: 600,
+            version: TxVer::V2,
+            locktime: LockTime::ZERO,
+        });
+        let debit = &mut wallet.cache.tx.get_mut(&parent).unwrap().outputs[change.vout_usize()];
+        debit.spent = Some(Inpoint::new(child, 0u32));
+        wallet.cache.utxo.remove(&change);
+        wallet.cache.utxo.insert(child_output);
+
+        // The replacement has to pay for the evicted child as well
+        let Err(BumpFeeError::FeeTooLow(_, min_fee)) = wallet.bump_fee(parent, 3.0) else {
+            panic!("child fee is not accounted");
+        };
+        assert!(min_fee > built.meta.fee + Sats(3_000));
+
+        // Output of the evicted child can't fund the replacement
+        let (psbt, meta) = wallet.bump_fee(parent, 40.0).unwrap();
+        let inputs = psbt.inputs().map(|input| input.previous_outpoint).collect::<Vec<_>>();
+        assert!(!inputs.contains(&child_output));
+        assert!(inputs.contains(&Outpoint::new(wallet_funding_txid(), 1u32)));
+        assert!(meta.fee >= built.meta.fee + Sats(3_000));
+    }
+    #[test]
+    fn test_cpfp() {
+        let mut wallet = funded_wallet(&[50_000, 30_000]);
+        let params = SpendParams::with(FeePolicy::Rate(1.0));
+        let built = wallet.build_tx(&[payment(20_000)], params, &Strategy::LargestFirst).unwrap();
+        let parent = built.psbt.to_unsigned_tx().txid();
+        let change = Outpoint::new(parent, built.meta.change.unwrap().vout);
+        let parent_vsize = wallet.cache.tx[&parent].weight.div_ceil(4);
+        assert!(parent_vsize > 0);
+
+        // Child spends the parent change alone, lifting the package to the requested fee rate
+        let cpfp = wallet.cpfp(parent, 10.0).unwrap();
+        let inputs = cpfp.psbt.inputs().map(|input| input.previous_outpoint).collect::<Vec<_>>();
+        assert_eq!(inputs, vec![change]);
+        assert_eq!(cpfp.psbt.outputs().count(), 1);
+        assert_eq!(cpfp.meta.change.map(|change| change.terminal.keychain), Some(Keychain::INNER));
+        assert!(cpfp.package_vsize > parent_vsize);
+        assert!((10.0..10.1).contains(&cpfp.package_fee_rate));
+        let package_fee = built.meta.fee + cpfp.meta.fee;
+        assert_eq!(package_fee, fees::fee_for_rate(cpfp.package_vsize, 10.0));
+
+        assert!(matches!(
+            wallet.cpfp(parent, 1_000.0),
+            Err(CpfpError::InsufficientValue(value, _)) if value == built.change
+        ));
+        let funding = wallet_funding_txid();
+        assert!(matches!(wallet.cpfp(funding, 10.0), Err(CpfpError::Mined(id)) if id == funding));
+        let unknown = Txid::from_hex(&"44".repeat(32)).unwrap();
+        assert!(
+            matches!(wallet.cpfp(unknown, 10.0), Err(CpfpError::UnknownTx(id)) if id == unknown)
+        );
+    }
+}